@@ -4,5 +4,5 @@ pub mod state;
 pub mod sync;
 pub mod watcher;
 
-pub use state::{AppState, EntryManager, PeerManager};
+pub use state::{AppState, EntryManager, PeerManager, VersionStore};
 pub use sync::Synchronizer;
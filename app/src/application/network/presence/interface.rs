@@ -1,4 +1,4 @@
-use std::net::IpAddr;
+use std::net::{IpAddr, SocketAddr};
 use tokio::io;
 use uuid::Uuid;
 
@@ -10,7 +10,8 @@ use uuid::Uuid;
 /// learn of the disconnect immediately rather than waiting for a
 /// timeout.
 ///
-/// The production adapter is `MdnsAdapter`.
+/// The production adapter is `PresenceAdapter`, which merges every
+/// discovery source.
 pub trait PresenceInterface {
     /// Starts announcing this instance to peers.
     async fn advertise(&self) -> io::Result<()>;
@@ -21,15 +22,46 @@ pub trait PresenceInterface {
     async fn shutdown(&self);
 }
 
+/// A discovery source that may be turned off. `None` announces nothing
+/// and its `next` ends at once, so a `MergedPresence` simply carries on
+/// with the other source.
+impl<P: PresenceInterface> PresenceInterface for Option<P> {
+    async fn advertise(&self) -> io::Result<()> {
+        match self {
+            Some(presence) => presence.advertise().await,
+            None => Ok(()),
+        }
+    }
+
+    async fn next(&self) -> io::Result<Option<PresenceEvent>> {
+        match self {
+            Some(presence) => presence.next().await,
+            None => Ok(None),
+        }
+    }
+
+    async fn shutdown(&self) {
+        if let Some(presence) = self {
+            presence.shutdown().await;
+        }
+    }
+}
+
 /// Discovery-layer event delivered by `PresenceInterface::next`.
 pub enum PresenceEvent {
     /// A peer announced itself (or reconfirmed liveness). A change in
     /// `instance_id` for the same `id` indicates the peer restarted.
+    /// `addrs` are where the peer's transport listens, most preferred
+    /// first; never empty. `cluster` is the peer's `Cluster::tag`.
     Ping {
         id: Uuid,
-        addr: IpAddr,
+        addrs: Vec<SocketAddr>,
         instance_id: Uuid,
+        cluster: Option<String>,
     },
     /// A peer explicitly retracted its advertisement.
     Disconnect(Uuid),
+    /// This host's own addresses changed. `local_ip` is the address
+    /// outbound traffic now leaves from, if there is one.
+    NetworkChanged { local_ip: Option<IpAddr> },
 }
@@ -0,0 +1,147 @@
+use crate::{
+    application::{AppState, PeerManager},
+    domain::TransportChannelData,
+};
+use std::{sync::Arc, time::Duration};
+use tokio::{
+    io,
+    sync::mpsc::Sender,
+    time::{Instant, MissedTickBehavior, interval_at},
+};
+use tracing::trace;
+
+/// Application service that keeps the peer map honest between
+/// discovery events.
+///
+/// Peers not heard from for the configured `peer_timeout` are evicted;
+/// presence pings and every inbound message count as hearing from a
+/// peer. Every `anti_entropy_interval`, which eviction caps at a third
+/// of the timeout, live peers are handshaked again,
+/// so a `Metadata` message that was lost is repaired by the entry map
+/// comparison rather than waiting for a restart. One handshake repairs
+/// both sides, so only the device with the lower id sends it, as on
+/// discovery; the other steps in for a peer it has not heard from for
+/// two intervals. The answering `HandshakeAck` also refreshes the peer,
+/// which keeps peers alive that presence never pings again.
+pub struct LivenessService {
+    state: Arc<AppState>,
+    peer_manager: Arc<PeerManager>,
+    sender_tx: Sender<TransportChannelData>,
+}
+
+impl LivenessService {
+    pub fn new(
+        state: Arc<AppState>,
+        peer_manager: Arc<PeerManager>,
+        sender_tx: Sender<TransportChannelData>,
+    ) -> Self {
+        Self {
+            state,
+            peer_manager,
+            sender_tx,
+        }
+    }
+
+    /// Runs eviction and anti-entropy until the outbound channel
+    /// closes. A timing set to `0` never fires.
+    pub async fn run(&self) -> io::Result<()> {
+        tokio::select!(
+            res = self.evict_loop() => res,
+            res = self.anti_entropy_loop() => res,
+        )
+    }
+
+    async fn evict_loop(&self) -> io::Result<()> {
+        let Some(timeout) = self.state.liveness().peer_timeout() else {
+            return std::future::pending().await;
+        };
+
+        let period = (timeout / 4).max(Duration::from_secs(1));
+        let mut ticks = interval_at(Instant::now() + period, period);
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            ticks.tick().await;
+            self.peer_manager.evict_silent(timeout).await;
+        }
+    }
+
+    async fn anti_entropy_loop(&self) -> io::Result<()> {
+        let Some(period) = self.state.liveness().anti_entropy_interval() else {
+            return std::future::pending().await;
+        };
+
+        let mut ticks = interval_at(Instant::now() + period, period);
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            ticks.tick().await;
+            self.anti_entropy(period).await?;
+        }
+    }
+
+    /// Queues a `HandshakeSyn` to every peer this device re-handshakes.
+    async fn anti_entropy(&self, period: Duration) -> io::Result<()> {
+        let local_id = self.state.local_id();
+
+        for peer in self.peer_manager.list().await {
+            let quiet = peer
+                .last_seen
+                .elapsed()
+                .is_ok_and(|silence| silence > period * 2);
+            if local_id > peer.id && !quiet {
+                continue;
+            }
+
+            trace!(peer = %peer.id, "anti-entropy handshake");
+            self.sender_tx
+                .send(TransportChannelData::HandshakeSyn(peer.transport_addr()))
+                .await
+                .map_err(io::Error::other)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::Peer;
+    use std::{
+        net::{Ipv4Addr, SocketAddr},
+        time::SystemTime,
+    };
+    use tokio::sync::mpsc;
+    use uuid::Uuid;
+
+    fn peer(id: Uuid, last: u8, last_seen: SystemTime) -> Peer {
+        let addr = SocketAddr::new(Ipv4Addr::new(10, 0, 0, last).into(), 42882);
+        let mut peer = Peer::new(id, addr, "host".into(), Uuid::new_v4(), vec![]);
+        peer.last_seen = last_seen;
+        peer
+    }
+
+    #[tokio::test]
+    async fn anti_entropy_handshakes_higher_ids_and_quiet_lower_ones() {
+        let env = crate::utils::test_support::test_env().await;
+        let pm = PeerManager::new(env.state.clone());
+        let (tx, mut rx) = mpsc::channel(16);
+        let service = LivenessService::new(env.state.clone(), pm.clone(), tx);
+
+        let now = SystemTime::now();
+        let long_ago = now - Duration::from_secs(600);
+        pm.insert(peer(Uuid::max(), 1, now)).await;
+        pm.insert(peer(Uuid::nil(), 2, now)).await;
+        pm.insert(peer(Uuid::from_u128(1), 3, long_ago)).await;
+
+        service.anti_entropy(Duration::from_secs(60)).await.unwrap();
+
+        let mut targets = Vec::new();
+        while let Ok(TransportChannelData::HandshakeSyn(target)) = rx.try_recv() {
+            targets.push(target.ip());
+        }
+        targets.sort();
+        assert_eq!(
+            targets,
+            vec![Ipv4Addr::new(10, 0, 0, 1), Ipv4Addr::new(10, 0, 0, 3)]
+        );
+    }
+}
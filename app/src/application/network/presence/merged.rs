@@ -0,0 +1,225 @@
+use crate::application::network::presence::interface::{PresenceEvent, PresenceInterface};
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{
+        Mutex, PoisonError,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+use tokio::io;
+use uuid::Uuid;
+
+/// How long a `Ping` repeating the last one yielded for the same device
+/// is held back.
+const DEDUPE_WINDOW: Duration = Duration::from_secs(5);
+
+/// The last `Ping` yielded for a device, and when.
+struct LastPing {
+    addrs: Vec<SocketAddr>,
+    instance_id: Uuid,
+    at: Instant,
+}
+
+/// Two discovery adapters behind one `PresenceInterface`, so several
+/// discovery sources feed the same `PresenceService`.
+///
+/// `next` yields whichever adapter's event arrives first and keeps
+/// going with the other once one of them ends; it ends when both have.
+/// A `Ping` that repeats the last one yielded for the device within
+/// `DEDUPE_WINDOW`, as when both adapters hear the same announcement,
+/// is dropped; one that changes the addresses or instance always goes
+/// through. Both adapters' `next` must be cancel-safe, since the
+/// one that loses a race is dropped mid-await. An error from either
+/// adapter is returned as is.
+pub struct MergedPresence<A: PresenceInterface, B: PresenceInterface> {
+    first: A,
+    second: B,
+    first_done: AtomicBool,
+    second_done: AtomicBool,
+    last_pings: Mutex<HashMap<Uuid, LastPing>>,
+}
+
+impl<A: PresenceInterface, B: PresenceInterface> MergedPresence<A, B> {
+    pub fn new(first: A, second: B) -> Self {
+        Self {
+            first,
+            second,
+            first_done: AtomicBool::new(false),
+            second_done: AtomicBool::new(false),
+            last_pings: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Records `event` and tells whether it is new.
+    fn is_fresh(&self, event: &PresenceEvent) -> bool {
+        let mut last_pings = self
+            .last_pings
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        match event {
+            PresenceEvent::Ping {
+                id,
+                addrs,
+                instance_id,
+                ..
+            } => {
+                let now = Instant::now();
+                let repeat = last_pings.get(id).is_some_and(|last| {
+                    last.addrs == *addrs
+                        && last.instance_id == *instance_id
+                        && now.duration_since(last.at) < DEDUPE_WINDOW
+                });
+                if !repeat {
+                    let last = LastPing {
+                        addrs: addrs.clone(),
+                        instance_id: *instance_id,
+                        at: now,
+                    };
+                    last_pings.insert(*id, last);
+                }
+                !repeat
+            }
+            PresenceEvent::Disconnect(id) => {
+                last_pings.remove(id);
+                true
+            }
+            PresenceEvent::NetworkChanged { .. } => true,
+        }
+    }
+}
+
+impl<A: PresenceInterface, B: PresenceInterface> PresenceInterface for MergedPresence<A, B> {
+    async fn advertise(&self) -> io::Result<()> {
+        self.first.advertise().await?;
+        self.second.advertise().await
+    }
+
+    async fn next(&self) -> io::Result<Option<PresenceEvent>> {
+        loop {
+            let first_done = self.first_done.load(Ordering::Relaxed);
+            let second_done = self.second_done.load(Ordering::Relaxed);
+
+            let (event, done) = tokio::select! {
+                event = self.first.next(), if !first_done => (event?, &self.first_done),
+                event = self.second.next(), if !second_done => (event?, &self.second_done),
+                else => return Ok(None),
+            };
+
+            match event {
+                Some(event) if self.is_fresh(&event) => return Ok(Some(event)),
+                Some(_) => continue,
+                None => done.store(true, Ordering::Relaxed),
+            }
+        }
+    }
+
+    async fn shutdown(&self) {
+        self.first.shutdown().await;
+        self.second.shutdown().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{IpAddr, Ipv4Addr};
+
+    struct Scripted {
+        events: tokio::sync::Mutex<Vec<PresenceEvent>>,
+        advertised: AtomicBool,
+    }
+
+    impl Scripted {
+        fn new(events: Vec<PresenceEvent>) -> Self {
+            Self {
+                events: tokio::sync::Mutex::new(events),
+                advertised: AtomicBool::new(false),
+            }
+        }
+    }
+
+    impl PresenceInterface for Scripted {
+        async fn advertise(&self) -> io::Result<()> {
+            self.advertised.store(true, Ordering::Relaxed);
+            Ok(())
+        }
+
+        async fn next(&self) -> io::Result<Option<PresenceEvent>> {
+            let mut events = self.events.lock().await;
+            Ok((!events.is_empty()).then(|| events.remove(0)))
+        }
+
+        async fn shutdown(&self) {}
+    }
+
+    fn ping(id: Uuid) -> PresenceEvent {
+        ping_from(id, Uuid::new_v4())
+    }
+
+    fn ping_from(id: Uuid, instance_id: Uuid) -> PresenceEvent {
+        PresenceEvent::Ping {
+            id,
+            addrs: vec![SocketAddr::new(
+                IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+                42882,
+            )],
+            instance_id,
+            cluster: None,
+        }
+    }
+
+    fn id_of(event: PresenceEvent) -> Uuid {
+        match event {
+            PresenceEvent::Ping { id, .. } | PresenceEvent::Disconnect(id) => id,
+            PresenceEvent::NetworkChanged { .. } => panic!("no network change scripted"),
+        }
+    }
+
+    #[tokio::test]
+    async fn yields_every_event_of_both_adapters_then_ends() {
+        let (a, b, c) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let merged = MergedPresence::new(
+            Scripted::new(vec![ping(a), PresenceEvent::Disconnect(a)]),
+            Scripted::new(vec![ping(b), ping(c)]),
+        );
+
+        merged.advertise().await.unwrap();
+        let mut seen = Vec::new();
+        while let Some(event) = merged.next().await.unwrap() {
+            seen.push(id_of(event));
+        }
+
+        assert!(merged.first.advertised.load(Ordering::Relaxed));
+        assert!(merged.second.advertised.load(Ordering::Relaxed));
+        seen.sort();
+        let mut expected = vec![a, a, b, c];
+        expected.sort();
+        assert_eq!(seen, expected);
+        assert!(merged.next().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn repeated_pings_are_yielded_once() {
+        let (id, instance, restarted) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let merged = MergedPresence::new(
+            Scripted::new(vec![ping_from(id, instance), ping_from(id, restarted)]),
+            Scripted::new(vec![ping_from(id, instance), ping_from(id, instance)]),
+        );
+
+        let mut instances = Vec::new();
+        while let Some(event) = merged.next().await.unwrap() {
+            if let PresenceEvent::Ping { instance_id, .. } = event {
+                instances.push(instance_id);
+            }
+        }
+
+        // Each change of instance goes through; repeats of the last one
+        // yielded do not.
+        assert!(instances.len() >= 2 && instances.len() <= 3);
+        assert_eq!(instances.iter().filter(|i| **i == restarted).count(), 1);
+        assert!(instances.windows(2).all(|pair| pair[0] != pair[1]));
+    }
+}
@@ -1,2 +1,4 @@
 pub mod interface;
+pub mod liveness;
+pub mod merged;
 pub mod service;
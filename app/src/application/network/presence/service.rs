@@ -4,20 +4,30 @@ use crate::{
         PeerManager,
         network::presence::interface::{PresenceEvent, PresenceInterface},
     },
-    domain::TransportChannelData,
+    domain::{PendingDevice, ServerEvent, TransportChannelData},
+};
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
 };
-use std::{net::IpAddr, sync::Arc};
 use tokio::{io, sync::mpsc::Sender};
-use tracing::{trace, warn};
+use tracing::{info, trace, warn};
 use uuid::Uuid;
 
 /// Application service that turns `PresenceEvent`s from the discovery
 /// adapter into peer-manager updates and outbound handshakes.
 ///
-/// New pings trigger a `HandshakeSyn`; disconnects evict the peer
-/// from the manager. The service also calls `shutdown` on the adapter
-/// during graceful shutdown so peers see the retraction before the
-/// presence advert times out.
+/// Pings from devices of another `Cluster` are ignored. New pings from
+/// trusted devices trigger a `HandshakeSyn`, as do pings showing that a
+/// connected peer moved; pings from unknown devices park them in the
+/// pending list until the user approves them. Disconnects
+/// evict the peer from the manager. When this host's own network
+/// changes, the service advertises again with the new address, so
+/// each peer sees this device move, and handshakes the peers whose own
+/// address changed. The
+/// service also calls `shutdown` on the adapter during graceful
+/// shutdown so peers see the retraction before the presence advert
+/// times out.
 pub struct PresenceService<P: PresenceInterface> {
     adapter: P,
     state: Arc<AppState>,
@@ -49,26 +59,62 @@ impl<P: PresenceInterface> PresenceService<P> {
             match event {
                 PresenceEvent::Ping {
                     id,
-                    addr,
+                    addrs,
                     instance_id,
+                    cluster,
                 } => {
-                    self.handle_ping(id, addr, instance_id).await?;
+                    if !self.state.cluster().accepts_tag(cluster.as_deref()) {
+                        trace!(peer = %id, "ignoring ping from another cluster");
+                        continue;
+                    }
+                    self.handle_ping(id, addrs, instance_id).await?;
                 }
 
                 PresenceEvent::Disconnect(id) => {
                     self.handle_disconnect(id).await?;
                 }
+
+                PresenceEvent::NetworkChanged { local_ip } => {
+                    self.handle_network_change(local_ip).await?;
+                }
             }
         }
         warn!("Presence adapter channel closed");
         Ok(())
     }
 
-    async fn handle_ping(&self, id: Uuid, addr: IpAddr, instance_id: Uuid) -> io::Result<()> {
-        trace!(peer = %id, %addr, "presence ping");
+    async fn handle_ping(
+        &self,
+        id: Uuid,
+        addrs: Vec<SocketAddr>,
+        instance_id: Uuid,
+    ) -> io::Result<()> {
+        trace!(peer = %id, ?addrs, "presence ping");
+        let Some(addr) = addrs.first().copied() else {
+            return Ok(());
+        };
+        let moved = self.peer_manager.add_addrs(id, &addrs).await;
+
+        if !self.peer_manager.is_trusted(&id).await {
+            self.peer_manager
+                .add_pending(PendingDevice {
+                    id,
+                    addr: addr.ip(),
+                    transport_port: addr.port(),
+                    hostname: None,
+                })
+                .await;
+            return Ok(());
+        }
+
+        self.peer_manager.touch(&id).await;
         let seen = self.peer_manager.seen(&id, &instance_id).await;
 
-        if !seen && self.state.local_id() < id {
+        if moved {
+            info!(peer = %id, ?addrs, "peer moved");
+        }
+
+        if (!seen || moved) && self.state.local_id() < id {
             self.sender_tx
                 .send(TransportChannelData::HandshakeSyn(addr))
                 .await
@@ -82,6 +128,36 @@ impl<P: PresenceInterface> PresenceService<P> {
         Ok(())
     }
 
+    /// Takes the new local IP, advertises again and handshakes the
+    /// peers whose address changed, on the same lower-id rule as
+    /// pings. A failed advertisement is only logged, since the network
+    /// may be gone altogether; the next change advertises again.
+    async fn handle_network_change(&self, local_ip: Option<IpAddr>) -> io::Result<()> {
+        if let Some(ip) = local_ip {
+            self.state.set_local_ip(ip).await;
+        }
+        let local_ip = self.state.local_ip().await;
+        info!(%local_ip, "local network changed");
+
+        if let Err(err) = self.adapter.advertise().await {
+            warn!("failed to advertise after a network change: {err}");
+        }
+        let _ = self
+            .state
+            .sse_sender()
+            .send(ServerEvent::NetworkChanged { local_ip });
+
+        for (id, addr) in self.peer_manager.moved().await {
+            if self.state.local_id() < id {
+                self.sender_tx
+                    .send(TransportChannelData::HandshakeSyn(addr))
+                    .await
+                    .map_err(io::Error::other)?;
+            }
+        }
+        Ok(())
+    }
+
     pub async fn shutdown(&self) {
         self.adapter.shutdown().await;
     }
@@ -169,11 +245,13 @@ mod tests {
 
         let addr = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 100));
         let larger_id = Uuid::from_u128(u128::MAX);
+        peer_manager.load_trusted(vec![larger_id]).await;
 
         let ping_event = PresenceEvent::Ping {
             id: larger_id,
-            addr,
+            addrs: vec![SocketAddr::new(addr, 42001)],
             instance_id: Uuid::new_v4(),
+            cluster: None,
         };
 
         let adapter = MockPresenceAdapter::new(vec![ping_event]);
@@ -187,7 +265,7 @@ mod tests {
 
         assert!(received.is_ok(), "Should receive handshake message");
         if let Ok(Some(TransportChannelData::HandshakeSyn(received_addr))) = received {
-            assert_eq!(received_addr, addr);
+            assert_eq!(received_addr, SocketAddr::new(addr, 42001));
         } else {
             panic!("Expected HandshakeSyn message");
         }
@@ -199,11 +277,13 @@ mod tests {
 
         let addr = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 100));
         let smaller_id = Uuid::from_u128(0);
+        peer_manager.load_trusted(vec![smaller_id]).await;
 
         let ping_event = PresenceEvent::Ping {
             id: smaller_id,
-            addr,
+            addrs: vec![SocketAddr::new(addr, 42882)],
             instance_id: Uuid::new_v4(),
+            cluster: None,
         };
 
         let adapter = MockPresenceAdapter::new(vec![ping_event]);
@@ -232,22 +312,27 @@ mod tests {
         let remote_id = Uuid::from_u128(u128::MAX);
         let instance_id = Uuid::new_v4();
         let addr = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 100));
+        peer_manager.load_trusted(vec![remote_id]).await;
 
         peer_manager
             .insert(Peer {
                 id: remote_id,
                 instance_id,
                 addr,
+                transport_port: 42882,
                 hostname: "test-peer".to_string(),
                 last_seen: SystemTime::now(),
                 sync_dirs: Default::default(),
+                protocol_version: 0,
+                capabilities: Default::default(),
             })
             .await;
 
         let ping_event = PresenceEvent::Ping {
             id: remote_id,
-            addr,
+            addrs: vec![SocketAddr::new(addr, 42882)],
             instance_id,
+            cluster: None,
         };
         let adapter = MockPresenceAdapter::new(vec![ping_event]);
         let service = PresenceService::new(adapter, state, peer_manager, sender_tx);
@@ -265,6 +350,170 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_handle_ping_sends_handshake_when_known_peer_moved() {
+        use crate::domain::Peer;
+
+        let (_env, state, peer_manager, sender_tx, mut sender_rx) = create_test_components().await;
+
+        let remote_id = Uuid::from_u128(u128::MAX);
+        let instance_id = Uuid::new_v4();
+        let old_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 100)), 42882);
+        let new_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 5)), 42882);
+        peer_manager.load_trusted(vec![remote_id]).await;
+        peer_manager
+            .insert(Peer::new(
+                remote_id,
+                old_addr,
+                "test-peer".to_string(),
+                instance_id,
+                vec![],
+            ))
+            .await;
+
+        let ping_event = PresenceEvent::Ping {
+            id: remote_id,
+            addrs: vec![new_addr],
+            instance_id,
+            cluster: None,
+        };
+        let adapter = MockPresenceAdapter::new(vec![ping_event]);
+        let peer_manager_clone = peer_manager.clone();
+        let service = PresenceService::new(adapter, state, peer_manager, sender_tx);
+        tokio::spawn(async move {
+            let _ = service.run().await;
+        });
+
+        let received =
+            tokio::time::timeout(tokio::time::Duration::from_millis(100), sender_rx.recv()).await;
+
+        assert!(
+            matches!(received, Ok(Some(TransportChannelData::HandshakeSyn(addr))) if addr == new_addr),
+            "Should handshake the peer at its new address"
+        );
+        assert_eq!(
+            peer_manager_clone.candidates(old_addr).await,
+            vec![new_addr, old_addr]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_network_change_advertises_again_and_handshakes_moved_peers() {
+        use crate::domain::Peer;
+
+        let (_env, state, peer_manager, sender_tx, mut sender_rx) = create_test_components().await;
+
+        // Only the moved peer with the larger id is ours to handshake.
+        let addr = |last| SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 168, 1, last)), 42882);
+        let moved_to = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 5)), 42882);
+        for (id, last, moved) in [
+            (Uuid::nil(), 1, true),
+            (Uuid::max(), 2, true),
+            (Uuid::from_u128(u128::MAX - 1), 3, false),
+        ] {
+            peer_manager
+                .insert(Peer::new(
+                    id,
+                    addr(last),
+                    "peer".into(),
+                    Uuid::new_v4(),
+                    vec![],
+                ))
+                .await;
+            peer_manager.add_addrs(id, &[addr(last)]).await;
+            if moved {
+                assert!(peer_manager.add_addrs(id, &[moved_to]).await);
+            }
+        }
+
+        let mut sse_rx = state.sse_subscribe();
+        let adapter = MockPresenceAdapter::new(vec![]);
+        let service = PresenceService::new(adapter, state.clone(), peer_manager, sender_tx);
+        let new_ip = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 9));
+        service.handle_network_change(Some(new_ip)).await.unwrap();
+
+        assert_eq!(state.local_ip().await, new_ip);
+        assert!(service.adapter.advertise_was_called().await);
+        assert!(matches!(
+            sse_rx.try_recv(),
+            Ok(ServerEvent::NetworkChanged { local_ip }) if local_ip == new_ip
+        ));
+
+        let mut handshaked = Vec::new();
+        while let Ok(TransportChannelData::HandshakeSyn(target)) = sender_rx.try_recv() {
+            handshaked.push(target);
+        }
+        assert_eq!(handshaked, vec![moved_to]);
+    }
+
+    #[tokio::test]
+    async fn test_handle_ping_from_unknown_device_marks_it_pending_without_handshake() {
+        let (_env, state, peer_manager, sender_tx, mut sender_rx) = create_test_components().await;
+
+        let addr = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 100));
+        let unknown_id = Uuid::from_u128(u128::MAX);
+
+        let ping_event = PresenceEvent::Ping {
+            id: unknown_id,
+            addrs: vec![SocketAddr::new(addr, 42882)],
+            instance_id: Uuid::new_v4(),
+            cluster: None,
+        };
+
+        let adapter = MockPresenceAdapter::new(vec![ping_event]);
+        let peer_manager_clone = peer_manager.clone();
+        let service = PresenceService::new(adapter, state, peer_manager, sender_tx);
+        tokio::spawn(async move {
+            let _ = service.run().await;
+        });
+
+        let received =
+            tokio::time::timeout(tokio::time::Duration::from_millis(100), sender_rx.recv()).await;
+
+        assert!(
+            matches!(received, Err(_) | Ok(None)),
+            "Should not handshake with an unapproved device"
+        );
+        let pending = peer_manager_clone.list_pending().await;
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].id, unknown_id);
+        assert_eq!(pending[0].addr, addr);
+        assert_eq!(pending[0].transport_port, 42882);
+    }
+
+    #[tokio::test]
+    async fn test_ping_from_another_cluster_is_ignored() {
+        let (_env, state, peer_manager, sender_tx, mut sender_rx) = create_test_components().await;
+
+        let trusted = Uuid::from_u128(u128::MAX);
+        let stranger = Uuid::from_u128(u128::MAX - 1);
+        peer_manager.load_trusted(vec![trusted]).await;
+        let tag = crate::domain::Cluster::new(Some("another team".into())).tag();
+
+        let events = [trusted, stranger]
+            .into_iter()
+            .map(|id| PresenceEvent::Ping {
+                id,
+                addrs: vec![SocketAddr::new(
+                    IpAddr::V4(Ipv4Addr::new(192, 168, 1, 100)),
+                    42882,
+                )],
+                instance_id: Uuid::new_v4(),
+                cluster: tag.clone(),
+            })
+            .collect();
+        let adapter = MockPresenceAdapter::new(events);
+        let peer_manager_clone = peer_manager.clone();
+        let service = PresenceService::new(adapter, state, peer_manager, sender_tx);
+        service.run().await.unwrap();
+
+        assert!(matches!(
+            sender_rx.try_recv(),
+            Err(mpsc::error::TryRecvError::Empty)
+        ));
+        assert!(peer_manager_clone.list_pending().await.is_empty());
+    }
+
     #[tokio::test]
     async fn test_handle_disconnect_removes_peer() {
         use crate::domain::Peer;
@@ -281,9 +530,12 @@ mod tests {
                 id: peer_id,
                 instance_id,
                 addr,
+                transport_port: 42882,
                 hostname: "test-peer".to_string(),
                 last_seen: SystemTime::now(),
                 sync_dirs: Default::default(),
+                protocol_version: 0,
+                capabilities: Default::default(),
             })
             .await;
 
@@ -330,18 +582,21 @@ mod tests {
         let peer2_id = Uuid::from_u128(u128::MAX - 1);
         let addr1 = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 100));
         let addr2 = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 101));
+        peer_manager.load_trusted(vec![peer1_id, peer2_id]).await;
 
         let events = vec![
             PresenceEvent::Disconnect(peer2_id),
             PresenceEvent::Ping {
                 id: peer2_id,
-                addr: addr2,
+                addrs: vec![SocketAddr::new(addr2, 42882)],
                 instance_id: Uuid::new_v4(),
+                cluster: None,
             },
             PresenceEvent::Ping {
                 id: peer1_id,
-                addr: addr1,
+                addrs: vec![SocketAddr::new(addr1, 42882)],
                 instance_id: Uuid::new_v4(),
+                cluster: None,
             },
         ];
 
@@ -1,5 +1,5 @@
 use crate::domain::{TransportData, TransportEvent};
-use std::net::IpAddr;
+use std::net::SocketAddr;
 use tokio::io::{self};
 
 /// Port for the peer-to-peer transport that carries handshakes,
@@ -7,8 +7,10 @@ use tokio::io::{self};
 ///
 /// Implementations are bidirectional: `recv` blocks until the next
 /// inbound `TransportEvent` is available; `send` delivers a
-/// `TransportData` payload to a specific peer by address. The
-/// production adapter is `TcpAdapter`.
+/// `TransportData` payload to a specific peer at the address its
+/// transport listens on, which carries the peer's own port. The
+/// production adapters are `TcpAdapter` and `QuicAdapter`, chosen by
+/// the `transport` setting in `config.toml`.
 ///
 /// `recv` errors after a connection is accepted are treated as bad
 /// peer messages by the caller and the loop continues — a corrupt
@@ -20,7 +22,7 @@ pub trait TransportInterface {
 
     /// Sends `data` to `target`. Returns once the payload has been
     /// written to the wire.
-    async fn send(&self, target: IpAddr, data: TransportData) -> TransportResult<()>;
+    async fn send(&self, target: SocketAddr, data: TransportData) -> TransportResult<()>;
 }
 
 /// Result alias for fallible transport calls.
@@ -4,15 +4,14 @@ use crate::{
         persistence::interface::PersistenceInterface,
     },
     domain::{
-        EntryInfo, MutexChannel, Peer, ServerEvent, TransportChannelData, TransportData,
-        TransportEvent, VersionCmp,
+        EntryInfo, MutexChannel, Peer, PendingDevice, ProtocolInfo, ServerEvent,
+        TransportChannelData, TransportData, TransportEvent, TransportMetadata, VersionCmp,
     },
-    utils::fs::is_git_path,
+    utils::fs::{compute_hash, is_git_path, is_synche_path},
 };
-use futures::TryFutureExt;
-use std::{net::IpAddr, sync::Arc};
+use std::{net::SocketAddr, sync::Arc};
 use tokio::{fs, io, sync::mpsc::Sender};
-use tracing::{error, info, warn};
+use tracing::{info, warn};
 use uuid::Uuid;
 
 /// Inbound side of the transport service.
@@ -20,9 +19,13 @@ use uuid::Uuid;
 /// Pulls events off the adapter and dispatches them onto two internal
 /// queues — `control_chan` for handshake/metadata/request messages
 /// and `transfer_chan` for entry bytes — so a slow file write cannot
-/// starve protocol traffic. Handlers reconcile peer state, persist
-/// metadata, and either accept, write, or conflict-resolve incoming
-/// entries before re-broadcasting.
+/// starve protocol traffic. Handshakes without a valid proof of this
+/// device's `Cluster` are rejected outright, and disconnect the sender
+/// if it was a peer. Events from devices the user has not approved
+/// never reach a queue: handshakes put the sender on the pending list
+/// and everything else is dropped. Handlers reconcile
+/// peer state, persist metadata, and either accept, write, or
+/// conflict-resolve incoming entries before re-broadcasting.
 pub struct TransportReceiver<T: TransportInterface, P: PersistenceInterface> {
     adapter: Arc<T>,
     state: Arc<AppState>,
@@ -63,6 +66,25 @@ impl<T: TransportInterface, P: PersistenceInterface> TransportReceiver<T, P> {
     async fn recv(&self) -> io::Result<()> {
         loop {
             let event = self.adapter.recv().await?;
+
+            if self.is_foreign_handshake(&event) {
+                warn!(peer = %event.metadata.source_id, "Rejecting handshake from another cluster");
+                self.peer_manager
+                    .remove_peer(event.metadata.source_id)
+                    .await;
+                continue;
+            }
+
+            if !self
+                .peer_manager
+                .is_trusted(&event.metadata.source_id)
+                .await
+            {
+                self.handle_untrusted(event).await;
+                continue;
+            }
+            self.peer_manager.touch(&event.metadata.source_id).await;
+
             match event.payload {
                 TransportData::Transfer(_) => {
                     self.transfer_chan
@@ -113,6 +135,53 @@ impl<T: TransportInterface, P: PersistenceInterface> TransportReceiver<T, P> {
         Ok(())
     }
 
+    /// Whether `event` is a handshake from outside this device's
+    /// cluster.
+    fn is_foreign_handshake(&self, event: &TransportEvent) -> bool {
+        match &event.payload {
+            TransportData::HandshakeSyn(hs_data) | TransportData::HandshakeAck(hs_data) => !self
+                .state
+                .cluster()
+                .verifies(event.metadata.source_id, hs_data.cluster_proof.as_deref()),
+            _ => false,
+        }
+    }
+
+    /// The transport port a handshake announced. Peers that predate
+    /// per-peer ports listen on the same one as this device.
+    fn announced_port(&self, port: Option<u16>) -> u16 {
+        port.unwrap_or(self.state.ports().transport)
+    }
+
+    /// Where to answer the sender of an event: its address on the
+    /// transport port its handshake announced.
+    async fn reply_addr(&self, metadata: &TransportMetadata) -> SocketAddr {
+        let port = self
+            .peer_manager
+            .transport_port(&metadata.source_id)
+            .await
+            .unwrap_or(self.state.ports().transport);
+        SocketAddr::new(metadata.source_ip, port)
+    }
+
+    #[tracing::instrument(skip_all, fields(peer = %event.metadata.source_id))]
+    async fn handle_untrusted(&self, event: TransportEvent) {
+        match event.payload {
+            TransportData::HandshakeSyn(hs_data) | TransportData::HandshakeAck(hs_data) => {
+                self.peer_manager
+                    .add_pending(PendingDevice {
+                        id: event.metadata.source_id,
+                        addr: event.metadata.source_ip,
+                        transport_port: self.announced_port(hs_data.transport_port),
+                        hostname: Some(hs_data.hostname),
+                    })
+                    .await;
+            }
+
+            _ => warn!("Dropping message from unapproved device"),
+        }
+    }
+
     #[tracing::instrument(skip_all, fields(peer = %event.metadata.source_id))]
     async fn handle_handshake(&self, event: TransportEvent) -> io::Result<()> {
         let (hs_data, is_syn) = match event.payload {
@@ -121,49 +190,86 @@ impl<T: TransportInterface, P: PersistenceInterface> TransportReceiver<T, P> {
             _ => unreachable!(),
         };
 
+        if let Some(reason) = hs_data.protocol.incompatibility(&ProtocolInfo::local()) {
+            warn!("Not connecting to incompatible peer: {reason}");
+            let _ = self.state.sse_sender().send(ServerEvent::PeerIncompatible {
+                id: event.metadata.source_id,
+                addr: event.metadata.source_ip,
+                hostname: hs_data.hostname,
+                protocol_version: hs_data.protocol.version,
+                reason,
+            });
+            return Ok(());
+        }
+
+        let addr = SocketAddr::new(
+            event.metadata.source_ip,
+            self.announced_port(hs_data.transport_port),
+        );
         let peer = Peer::new(
             event.metadata.source_id,
-            event.metadata.source_ip,
+            addr,
             hs_data.hostname,
             hs_data.instance_id,
             hs_data.sync_dirs,
-        );
+        )
+        .with_protocol(&hs_data.protocol);
         self.peer_manager.insert(peer.clone()).await;
 
         if is_syn {
-            // Can't use send_tx because Response must be sent strictly BEFORE syncing
-            let data = self.entry_manager.get_handshake_data().await?;
-            self.try_send(
-                || {
-                    self.adapter
-                        .send(peer.addr, TransportData::HandshakeAck(data.clone()))
-                        .map_err(|e| e.into())
-                },
-                peer.addr,
-            )
-            .await;
+            // Queued ahead of every Request below. It goes out on the
+            // sender's handshake lane, so streaming our entry map does
+            // not hold up this loop.
+            self.send_tx
+                .send(TransportChannelData::HandshakeAck(peer.transport_addr()))
+                .await
+                .map_err(io::Error::other)?;
         }
 
         info!(peer = ?peer.id, "syncing peer");
 
-        let entries_to_request = self
-            .entry_manager
-            .get_entries_to_request(&peer, hs_data.entries)
-            .await?;
+        let mut entries = hs_data.entries;
+        let mut tombstones = self.entry_manager.local_tombstones().await?;
 
-        for entry in entries_to_request {
-            if entry.is_file() {
-                self.broadcast_sync_started(event.metadata.source_id, &entry);
-                self.send_tx
-                    .send(TransportChannelData::Request((peer.addr, entry)))
-                    .await
-                    .map_err(io::Error::other)?;
-            } else {
-                self.create_received_dir(event.metadata.source_id, entry)
-                    .await?;
+        while let Some(page) = entries.next_page().await {
+            let page = match page {
+                Ok(page) => page,
+                Err(err) => {
+                    warn!("Handshake entry map broke off: {err}");
+                    return Ok(());
+                }
+            };
+
+            let entries_to_request = self
+                .entry_manager
+                .get_entries_to_request(&peer, page, &mut tombstones)
+                .await?;
+
+            for entry in entries_to_request {
+                if self.is_send_only(&entry).await {
+                    self.keep_local(event.metadata.source_id, entry).await?;
+                } else if entry.is_removed() {
+                    self.remove_received_entry(event.metadata.source_id, entry)
+                        .await?;
+                } else if entry.is_file() {
+                    self.broadcast_sync_started(event.metadata.source_id, &entry);
+                    self.send_tx
+                        .send(TransportChannelData::Request((
+                            peer.transport_addr(),
+                            entry,
+                        )))
+                        .await
+                        .map_err(io::Error::other)?;
+                } else {
+                    self.create_received_dir(event.metadata.source_id, entry)
+                        .await?;
+                }
             }
         }
-        Ok(())
+
+        self.entry_manager
+            .acknowledge_tombstones(peer.id, tombstones)
+            .await
     }
 
     #[tracing::instrument(skip_all, fields(peer = %event.metadata.source_id))]
@@ -183,15 +289,16 @@ impl<T: TransportInterface, P: PersistenceInterface> TransportReceiver<T, P> {
             .await?
         {
             VersionCmp::KeepOther => {
-                if peer_entry.is_removed() {
-                    self.remove_entry(&peer_entry.name).await
+                if self.is_send_only(&peer_entry).await {
+                    self.keep_local(event.metadata.source_id, peer_entry).await
+                } else if peer_entry.is_removed() {
+                    self.remove_received_entry(event.metadata.source_id, peer_entry)
+                        .await
                 } else if peer_entry.is_file() {
                     self.broadcast_sync_started(event.metadata.source_id, &peer_entry);
+                    let target = self.reply_addr(&event.metadata).await;
                     self.send_tx
-                        .send(TransportChannelData::Request((
-                            event.metadata.source_ip,
-                            peer_entry,
-                        )))
+                        .send(TransportChannelData::Request((target, peer_entry)))
                         .await
                         .map_err(io::Error::other)
                 } else {
@@ -220,13 +327,17 @@ impl<T: TransportInterface, P: PersistenceInterface> TransportReceiver<T, P> {
         match self.entry_manager.get_entry(&requested_entry.name).await? {
             Some(local_entry)
                 if local_entry.is_file()
+                    && !local_entry.is_removed()
                     && matches!(local_entry.compare(&requested_entry), VersionCmp::Equal) =>
             {
+                if self.has_unrecorded_change(&local_entry).await {
+                    info!(entry = %local_entry.name, "Not serving a locally changed receive-only file");
+                    return Ok(());
+                }
+
+                let target = self.reply_addr(&event.metadata).await;
                 self.send_tx
-                    .send(TransportChannelData::Transfer((
-                        event.metadata.source_ip,
-                        local_entry,
-                    )))
+                    .send(TransportChannelData::Transfer((target, local_entry)))
                     .await
                     .map_err(io::Error::other)
             }
@@ -244,10 +355,12 @@ impl<T: TransportInterface, P: PersistenceInterface> TransportReceiver<T, P> {
 
         if is_git_path(&received_entry.name)
             || !self.is_in_configured_sync_dir(&received_entry).await
+            || self.is_send_only(&received_entry).await
         {
             return Ok(());
         }
 
+        let peer_version = received_entry.version.clone();
         let Some(entry) = self
             .entry_manager
             .insert_peer_entry(event.metadata.source_id, received_entry)
@@ -257,6 +370,10 @@ impl<T: TransportInterface, P: PersistenceInterface> TransportReceiver<T, P> {
         };
 
         self.broadcast_sync_completed(event.metadata.source_id, &entry);
+        let entry = self
+            .entry_manager
+            .merge_received(entry, &peer_version)
+            .await?;
 
         self.send_tx
             .send(TransportChannelData::Metadata(entry))
@@ -265,11 +382,39 @@ impl<T: TransportInterface, P: PersistenceInterface> TransportReceiver<T, P> {
     }
 
     /// Returns true if `entry`'s top-level component is one of the
-    /// directories the local user has opted in to syncing. Acts as a
-    /// scope guard for inbound Metadata / Request / Transfer so a peer
-    /// cannot push or pull data outside the configured sync set.
+    /// directories the local user has opted in to syncing, and the entry
+    /// is not in its `SYNCHE_DIR`. Acts as a scope guard for inbound
+    /// Metadata / Request / Transfer so a peer cannot push or pull data
+    /// outside the configured sync set.
     async fn is_in_configured_sync_dir(&self, entry: &EntryInfo) -> bool {
-        self.state.contains_sync_dir(&entry.get_sync_dir()).await
+        self.state.contains_sync_dir(&entry.get_sync_dir()).await && !is_synche_path(&entry.name)
+    }
+
+    /// Returns true if `entry` is in a send-only directory, whose local
+    /// copies never give way to a peer's.
+    async fn is_send_only(&self, entry: &EntryInfo) -> bool {
+        self.state
+            .directory_mode(&entry.get_sync_dir())
+            .await
+            .is_send_only()
+    }
+
+    /// Returns true if `entry` is in a receive-only directory and the
+    /// file on disk no longer holds it. Local edits there are never
+    /// recorded, so serving the disk copy would fail the requester's
+    /// hash check.
+    async fn has_unrecorded_change(&self, entry: &EntryInfo) -> bool {
+        if !self
+            .state
+            .directory_mode(&entry.get_sync_dir())
+            .await
+            .is_receive_only()
+        {
+            return false;
+        }
+
+        let path = entry.name.to_canonical(self.state.home_path());
+        compute_hash(&path).await.ok() != entry.hash
     }
 
     fn broadcast_sync_started(&self, peer: Uuid, entry: &EntryInfo) {
@@ -291,6 +436,20 @@ impl<T: TransportInterface, P: PersistenceInterface> TransportReceiver<T, P> {
             });
     }
 
+    /// Records a peer's winning version of an entry in a send-only
+    /// directory without applying it, then re-announces our copy, which
+    /// now supersedes the peer's.
+    async fn keep_local(&self, peer_id: Uuid, entry: EntryInfo) -> io::Result<()> {
+        let Some(local) = self.entry_manager.keep_local(peer_id, &entry).await? else {
+            return Ok(());
+        };
+
+        self.send_tx
+            .send(TransportChannelData::Metadata(local))
+            .await
+            .map_err(io::Error::other)
+    }
+
     async fn create_received_dir(&self, peer_id: Uuid, dir: EntryInfo) -> io::Result<()> {
         let Some(dir) = self.entry_manager.insert_peer_entry(peer_id, dir).await? else {
             return Ok(());
@@ -305,39 +464,24 @@ impl<T: TransportInterface, P: PersistenceInterface> TransportReceiver<T, P> {
             .map_err(io::Error::other)
     }
 
-    async fn remove_entry(&self, entry_name: &str) -> io::Result<()> {
-        let _ = self.entry_manager.remove_entry(entry_name).await?;
-
-        let path = self.state.home_path().join(entry_name);
-
-        if path.is_dir() {
-            fs::remove_dir_all(path).await?;
-        } else if path.is_file() {
-            fs::remove_file(path).await?;
-        }
-        Ok(())
-    }
-
-    async fn try_send<F, Fut>(&self, mut op: F, addr: IpAddr)
-    where
-        F: FnMut() -> Fut,
-        Fut: Future<Output = io::Result<()>>,
-    {
-        for _ in 0..3 {
-            if let Err(err) = op().await {
-                error!(peer = ?addr, "Transport send error: {err}");
-            } else {
-                return;
-            }
+    /// Applies a peer's tombstone and moves the entry from disk into the
+    /// trash, then re-broadcasts the tombstone so every other peer — the
+    /// sender included — can count us as holding it.
+    async fn remove_received_entry(&self, peer_id: Uuid, entry: EntryInfo) -> io::Result<()> {
+        let Some(tombstone) = self
+            .entry_manager
+            .apply_peer_tombstone(peer_id, entry)
+            .await?
+        else {
+            return Ok(());
+        };
 
-            if !self.peer_manager.exists(addr).await {
-                warn!("cancelled transport send: peer disconnected mid-op");
-                return;
-            }
-        }
+        self.entry_manager.trash_entry(peer_id, &tombstone).await?;
 
-        error!(peer = ?addr, "Disconnecting peer after 3 Transport send attempts.");
-        self.peer_manager.remove_peer_by_addr(addr).await;
+        self.send_tx
+            .send(TransportChannelData::Metadata(tombstone))
+            .await
+            .map_err(io::Error::other)
     }
 }
 
@@ -346,7 +490,10 @@ mod tests {
     use super::*;
     use crate::{
         application::network::transport::test_support::RecordingTransport,
-        domain::{EntryKind, TransportMetadata},
+        domain::{
+            Capability, Cluster, DirectoryMode, EntryKind, HandshakeData, HandshakeEntries,
+            SyncDirectory, TransportMetadata,
+        },
         infra::persistence::sqlite::SqliteDb,
     };
     use std::{
@@ -388,6 +535,8 @@ mod tests {
             kind: EntryKind::File,
             hash: Some("hash".to_string()),
             version: HashMap::from([(Uuid::new_v4(), 1)]),
+            modified: None,
+            removed_at: None,
         }
     }
 
@@ -441,6 +590,8 @@ mod tests {
             kind: EntryKind::File,
             hash: Some("hash".to_string()),
             version: HashMap::from([(Uuid::new_v4(), 1)]),
+            modified: None,
+            removed_at: None,
         }
     }
 
@@ -523,6 +674,8 @@ mod tests {
             // Peer reports its own axis AND a claim about `third`'s
             // counter — only the peer's own axis must be persisted.
             version: HashMap::from([(peer, 3), (third, 99)]),
+            modified: None,
+            removed_at: None,
         };
 
         let evt = TransportEvent {
@@ -551,6 +704,8 @@ mod tests {
             kind: EntryKind::File,
             hash: Some("hash".into()),
             version: HashMap::from([(peer, u64::MAX)]),
+            modified: None,
+            removed_at: None,
         };
 
         let evt = TransportEvent {
@@ -607,6 +762,8 @@ mod tests {
             kind: EntryKind::File,
             hash: Some("hash".to_string()),
             version: HashMap::from([(peer, 1)]),
+            modified: None,
+            removed_at: None,
         };
 
         let evt = TransportEvent {
@@ -631,4 +788,368 @@ mod tests {
             other => panic!("unexpected event: {other:?}"),
         }
     }
+
+    #[tokio::test]
+    async fn send_only_dir_reannounces_the_local_copy_instead_of_taking_the_peers() {
+        let (_env, receiver, entry_manager, mut send_rx) = setup().await;
+        let dir = SyncDirectory {
+            mode: DirectoryMode::SendOnly,
+            ..SyncDirectory::new("sync".into())
+        };
+        assert!(entry_manager.update_sync_dir(dir).await);
+        let local = entry_manager
+            .entry_created(&"sync/a.txt".into(), EntryKind::File, Some("mine".into()))
+            .await
+            .unwrap();
+        let peer = Uuid::new_v4();
+        let theirs = EntryInfo {
+            hash: Some("theirs".to_string()),
+            version: HashMap::from([(peer, 3)]),
+            ..local.clone()
+        };
+
+        receiver
+            .handle_metadata(event_from(peer, TransportData::Metadata(theirs.clone())))
+            .await
+            .unwrap();
+
+        let Ok(TransportChannelData::Metadata(announced)) = send_rx.try_recv() else {
+            panic!("expected the local copy to be announced");
+        };
+        assert_eq!(announced.hash, local.hash);
+        assert_eq!(announced.version[&peer], 3);
+        assert!(matches!(announced.compare(&theirs), VersionCmp::KeepSelf));
+
+        receiver
+            .handle_transfer(event_from(peer, TransportData::Transfer(theirs)))
+            .await
+            .unwrap();
+        assert!(matches!(send_rx.try_recv(), Err(TryRecvError::Empty)));
+        let stored = entry_manager
+            .get_entry("sync/a.txt")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.hash, local.hash);
+    }
+
+    #[tokio::test]
+    async fn receive_only_dir_serves_only_files_matching_the_stored_entry() {
+        let (env, receiver, entry_manager, mut send_rx) = setup().await;
+        let dir = SyncDirectory {
+            mode: DirectoryMode::ReceiveOnly,
+            ..SyncDirectory::new("sync".into())
+        };
+        assert!(entry_manager.update_sync_dir(dir).await);
+        let name: crate::domain::RelativePath = "sync/a.txt".into();
+        let path = name.to_canonical(env.home_path());
+        std::fs::create_dir_all(env.home_path().join("sync")).unwrap();
+        std::fs::write(&path, "cluster").unwrap();
+        let stored = entry_manager
+            .insert_entry(EntryInfo {
+                hash: Some(compute_hash(&path).await.unwrap()),
+                ..file_entry("sync/a.txt")
+            })
+            .await
+            .unwrap();
+
+        receiver
+            .handle_request(event(TransportData::Request(stored.clone())))
+            .await
+            .unwrap();
+        assert!(matches!(
+            send_rx.try_recv(),
+            Ok(TransportChannelData::Transfer(_))
+        ));
+
+        // A local edit the store never saw must not be served.
+        std::fs::write(&path, "local").unwrap();
+        receiver
+            .handle_request(event(TransportData::Request(stored)))
+            .await
+            .unwrap();
+        assert!(matches!(send_rx.try_recv(), Err(TryRecvError::Empty)));
+    }
+
+    async fn setup_with_transport() -> (
+        crate::utils::test_support::TestEnv,
+        TransportReceiver<RecordingTransport, SqliteDb>,
+        Arc<PeerManager>,
+        Arc<EntryManager<SqliteDb>>,
+        tokio::sync::mpsc::UnboundedSender<
+            crate::application::network::transport::interface::TransportResult<TransportEvent>,
+        >,
+        tokio::sync::mpsc::Receiver<TransportChannelData>,
+    ) {
+        let env = crate::utils::test_support::test_env_with_dirs(&["sync"]).await;
+        let state = env.state.clone();
+        let db = SqliteDb::new(":memory:").await.unwrap();
+        let entry_manager = EntryManager::new(db, state.clone());
+        let peer_manager = PeerManager::new(state.clone());
+        let transport = RecordingTransport::new();
+        let push = transport.push_handle();
+        let (send_tx, send_rx) = tokio::sync::mpsc::channel(4);
+        let receiver = TransportReceiver::new(
+            Arc::new(transport),
+            state,
+            peer_manager.clone(),
+            entry_manager.clone(),
+            send_tx,
+        );
+
+        (env, receiver, peer_manager, entry_manager, push, send_rx)
+    }
+
+    fn event_from(source_id: Uuid, payload: TransportData) -> TransportEvent {
+        TransportEvent {
+            payload,
+            metadata: TransportMetadata {
+                source_id,
+                source_ip: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn recv_parks_unapproved_handshake_as_pending_and_drops_its_metadata() {
+        let (_env, receiver, peer_manager, entry_manager, push, mut send_rx) =
+            setup_with_transport().await;
+        let stranger = Uuid::new_v4();
+        let entry = EntryInfo {
+            name: "sync/payload.bin".into(),
+            kind: EntryKind::File,
+            hash: Some("hash".into()),
+            version: HashMap::from([(stranger, 1)]),
+            modified: None,
+            removed_at: None,
+        };
+
+        push.send(Ok(event_from(
+            stranger,
+            TransportData::HandshakeSyn(HandshakeData {
+                hostname: "stranger".into(),
+                instance_id: Uuid::new_v4(),
+                transport_port: None,
+                cluster_proof: None,
+                sync_dirs: vec![],
+                entries: HandshakeEntries::from_map(HashMap::from([(
+                    entry.name.clone(),
+                    entry.clone(),
+                )])),
+                protocol: Default::default(),
+            }),
+        )))
+        .unwrap();
+        push.send(Ok(event_from(
+            stranger,
+            TransportData::Metadata(entry.clone()),
+        )))
+        .unwrap();
+
+        let _ = tokio::time::timeout(std::time::Duration::from_millis(200), receiver.run()).await;
+
+        let pending = peer_manager.list_pending().await;
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].hostname.as_deref(), Some("stranger"));
+        assert!(peer_manager.list().await.is_empty());
+        assert!(
+            entry_manager
+                .get_entry(&entry.name)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert!(matches!(send_rx.try_recv(), Err(TryRecvError::Empty)));
+    }
+
+    #[tokio::test]
+    async fn recv_rejects_handshake_from_another_cluster() {
+        let (_env, receiver, peer_manager, _entry_manager, push, mut send_rx) =
+            setup_with_transport().await;
+        let (trusted, stranger) = (Uuid::new_v4(), Uuid::new_v4());
+        peer_manager.load_trusted(vec![trusted]).await;
+
+        let elsewhere = Cluster::new(Some("another team".into()));
+        for id in [trusted, stranger] {
+            let hs_data = HandshakeData {
+                hostname: "elsewhere".into(),
+                instance_id: Uuid::new_v4(),
+                transport_port: None,
+                cluster_proof: elsewhere.proof(id),
+                sync_dirs: vec![],
+                entries: HandshakeEntries::from_map(HashMap::new()),
+                protocol: ProtocolInfo::local(),
+            };
+            push.send(Ok(event_from(id, TransportData::HandshakeSyn(hs_data))))
+                .unwrap();
+        }
+
+        let _ = tokio::time::timeout(std::time::Duration::from_millis(200), receiver.run()).await;
+
+        assert!(peer_manager.list().await.is_empty());
+        assert!(peer_manager.list_pending().await.is_empty());
+        assert!(matches!(send_rx.try_recv(), Err(TryRecvError::Empty)));
+    }
+
+    #[tokio::test]
+    async fn recv_dispatches_metadata_from_approved_device() {
+        let (_env, receiver, peer_manager, _entry_manager, push, mut send_rx) =
+            setup_with_transport().await;
+        let peer = Uuid::new_v4();
+        peer_manager.load_trusted(vec![peer]).await;
+        let entry = EntryInfo {
+            name: "sync/payload.bin".into(),
+            kind: EntryKind::File,
+            hash: Some("hash".into()),
+            version: HashMap::from([(peer, 1)]),
+            modified: None,
+            removed_at: None,
+        };
+
+        push.send(Ok(event_from(peer, TransportData::Metadata(entry.clone()))))
+            .unwrap();
+
+        let _ = tokio::time::timeout(std::time::Duration::from_millis(200), receiver.run()).await;
+
+        assert!(matches!(
+            send_rx.try_recv(),
+            Ok(TransportChannelData::Request((_, requested))) if requested.name == entry.name
+        ));
+    }
+
+    #[tokio::test]
+    async fn handle_handshake_applies_peer_tombstone_and_rebroadcasts_it() {
+        let (env, receiver, entry_manager, mut send_rx) = setup().await;
+        let peer = Uuid::new_v4();
+        let mut entry = file_entry("sync/gone.txt");
+        entry.version = HashMap::from([(peer, 1)]);
+        entry_manager.insert_entry(entry.clone()).await.unwrap();
+        let path = env.home_path().join("sync/gone.txt");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, "bytes").unwrap();
+
+        let mut tombstone = entry.clone();
+        tombstone.version = HashMap::from([(peer, 2)]);
+        tombstone.set_removed_hash();
+
+        receiver
+            .handle_handshake(event_from(
+                peer,
+                TransportData::HandshakeAck(HandshakeData {
+                    hostname: "peer".into(),
+                    instance_id: Uuid::new_v4(),
+                    transport_port: None,
+                    cluster_proof: None,
+                    sync_dirs: vec![],
+                    entries: HandshakeEntries::from_map(HashMap::from([(
+                        entry.name.clone(),
+                        tombstone,
+                    )])),
+                    protocol: Default::default(),
+                }),
+            ))
+            .await
+            .unwrap();
+
+        assert!(!path.exists());
+        let trashed = entry_manager.list_trash().await.unwrap();
+        assert!(
+            matches!(&trashed[..], [record] if record.path == entry.name && record.peer == peer)
+        );
+        match send_rx.try_recv() {
+            Ok(TransportChannelData::Metadata(sent)) => {
+                assert_eq!(sent.name, entry.name);
+                assert!(sent.is_removed());
+            }
+            _ => panic!("expected tombstone metadata"),
+        }
+    }
+
+    #[tokio::test]
+    async fn handle_handshake_syn_queues_ack_ahead_of_requests() {
+        let (_env, receiver, _entry_manager, mut send_rx) = setup().await;
+        let peer = Uuid::new_v4();
+        let mut entry = file_entry("sync/new.txt");
+        entry.version = HashMap::from([(peer, 1)]);
+
+        receiver
+            .handle_handshake(event_from(
+                peer,
+                TransportData::HandshakeSyn(HandshakeData {
+                    hostname: "peer".into(),
+                    instance_id: Uuid::new_v4(),
+                    transport_port: None,
+                    cluster_proof: None,
+                    sync_dirs: vec![],
+                    entries: HandshakeEntries::from_map(HashMap::from([(
+                        entry.name.clone(),
+                        entry.clone(),
+                    )])),
+                    protocol: ProtocolInfo::local(),
+                }),
+            ))
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            send_rx.try_recv(),
+            Ok(TransportChannelData::HandshakeAck(_))
+        ));
+        assert!(matches!(
+            send_rx.try_recv(),
+            Ok(TransportChannelData::Request((_, requested))) if requested.name == entry.name
+        ));
+    }
+
+    fn handshake_with(protocol: ProtocolInfo) -> TransportData {
+        TransportData::HandshakeAck(HandshakeData {
+            hostname: "peer".into(),
+            instance_id: Uuid::new_v4(),
+            transport_port: None,
+            cluster_proof: None,
+            sync_dirs: vec![],
+            entries: HandshakeEntries::from_map(HashMap::new()),
+            protocol,
+        })
+    }
+
+    #[tokio::test]
+    async fn handle_handshake_stores_negotiated_capabilities_on_peer() {
+        let (env, receiver, _entry_manager, _send_rx) = setup().await;
+        let peer = Uuid::new_v4();
+
+        receiver
+            .handle_handshake(event_from(peer, handshake_with(ProtocolInfo::local())))
+            .await
+            .unwrap();
+
+        assert!(env.state.peer_supports(&peer, Capability::Delta).await);
+        assert!(env.state.peer_supports(&peer, Capability::Resume).await);
+    }
+
+    #[tokio::test]
+    async fn handle_handshake_rejects_incompatible_peer_with_sse_error() {
+        let (env, receiver, _entry_manager, _send_rx) = setup().await;
+        let mut sse_rx = env.state.sse_subscribe();
+        let peer = Uuid::new_v4();
+        let future = ProtocolInfo {
+            version: u32::MAX,
+            min_version: u32::MAX,
+            capabilities: Default::default(),
+        };
+
+        receiver
+            .handle_handshake(event_from(peer, handshake_with(future)))
+            .await
+            .unwrap();
+
+        assert!(receiver.peer_manager.list().await.is_empty());
+        match sse_rx.try_recv() {
+            Ok(ServerEvent::PeerIncompatible { id, reason, .. }) => {
+                assert_eq!(id, peer);
+                assert!(reason.contains("or newer"), "{reason}");
+            }
+            other => panic!("expected PeerIncompatible, got {other:?}"),
+        }
+    }
 }
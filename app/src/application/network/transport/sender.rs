@@ -7,7 +7,7 @@ use crate::{
     utils::fs::is_git_path,
 };
 use futures::TryFutureExt;
-use std::{net::IpAddr, sync::Arc};
+use std::{net::SocketAddr, sync::Arc};
 use tokio::{
     io,
     sync::{Mutex, mpsc::Receiver},
@@ -17,17 +17,22 @@ use tracing::{error, warn};
 /// Outbound side of the transport service.
 ///
 /// Reads `TransportChannelData` items off the shared outbound channel
-/// and splits them across two priority lanes — `control_chan` for
-/// handshakes/metadata/requests, `transfer_chan` for bulk entry
-/// transfers — so the latter cannot delay protocol messages.
+/// and splits them across three priority lanes — `handshake_chan` for
+/// handshakes, `control_chan` for metadata/requests, `transfer_chan`
+/// for bulk entry transfers — so neither a large entry map nor a large
+/// file can delay protocol messages.
+///
+/// A message to a device goes to each of its `PeerAddrs` in turn until
+/// one takes it; that address is remembered and tried first next time.
 pub struct TransportSender<T: TransportInterface, P: PersistenceInterface> {
     adapter: Arc<T>,
     state: Arc<AppState>,
     peer_manager: Arc<PeerManager>,
     entry_manager: Arc<EntryManager<P>>,
     send_rx: Mutex<Receiver<TransportChannelData>>,
+    handshake_chan: MutexChannel<(SocketAddr, bool)>,
     control_chan: MutexChannel<TransportChannelData>,
-    transfer_chan: MutexChannel<(IpAddr, EntryInfo)>,
+    transfer_chan: MutexChannel<(SocketAddr, EntryInfo)>,
 }
 
 impl<T: TransportInterface, P: PersistenceInterface> TransportSender<T, P> {
@@ -44,6 +49,7 @@ impl<T: TransportInterface, P: PersistenceInterface> TransportSender<T, P> {
             peer_manager,
             entry_manager,
             send_rx,
+            handshake_chan: MutexChannel::new(16),
             control_chan: MutexChannel::new(100),
             transfer_chan: MutexChannel::new(16),
         }
@@ -52,6 +58,7 @@ impl<T: TransportInterface, P: PersistenceInterface> TransportSender<T, P> {
     pub async fn run(&self) -> io::Result<()> {
         tokio::select!(
             res = self.send() => res,
+            res = self.send_handshakes() => res,
             res = self.send_control() => res,
             res = self.send_files() => res
         )
@@ -60,6 +67,22 @@ impl<T: TransportInterface, P: PersistenceInterface> TransportSender<T, P> {
     async fn send(&self) -> io::Result<()> {
         while let Some(data) = self.send_rx.lock().await.recv().await {
             match data {
+                TransportChannelData::HandshakeSyn(target) => {
+                    self.handshake_chan
+                        .tx
+                        .send((target, true))
+                        .await
+                        .map_err(io::Error::other)?;
+                }
+
+                TransportChannelData::HandshakeAck(target) => {
+                    self.handshake_chan
+                        .tx
+                        .send((target, false))
+                        .await
+                        .map_err(io::Error::other)?;
+                }
+
                 TransportChannelData::Transfer(data) => {
                     self.transfer_chan
                         .tx
@@ -81,17 +104,17 @@ impl<T: TransportInterface, P: PersistenceInterface> TransportSender<T, P> {
         Ok(())
     }
 
+    async fn send_handshakes(&self) -> io::Result<()> {
+        while let Some((target, is_syn)) = self.handshake_chan.recv().await {
+            self.send_handshake(target, is_syn).await?;
+        }
+        warn!("Transport Send Handshake channel closed");
+        Ok(())
+    }
+
     async fn send_control(&self) -> io::Result<()> {
         while let Some(data) = self.control_chan.recv().await {
             match data {
-                TransportChannelData::HandshakeSyn(target) => {
-                    self.send_handshake(target, true).await?;
-                }
-
-                TransportChannelData::_HandshakeAck(target) => {
-                    self.send_handshake(target, false).await?;
-                }
-
                 TransportChannelData::Metadata(entry) => {
                     self.send_metadata(entry).await?;
                 }
@@ -108,18 +131,17 @@ impl<T: TransportInterface, P: PersistenceInterface> TransportSender<T, P> {
     }
 
     #[tracing::instrument(skip_all, fields(target = %target, is_syn))]
-    async fn send_handshake(&self, target: IpAddr, is_syn: bool) -> io::Result<()> {
-        let data = self.entry_manager.get_handshake_data().await?;
-
+    async fn send_handshake(&self, target: SocketAddr, is_syn: bool) -> io::Result<()> {
         self.try_send(
-            || {
+            |addr| async move {
+                let data = self.entry_manager.get_handshake_data().await?;
                 let data = if is_syn {
-                    TransportData::HandshakeSyn(data.clone())
+                    TransportData::HandshakeSyn(data)
                 } else {
-                    TransportData::HandshakeAck(data.clone())
+                    TransportData::HandshakeAck(data)
                 };
 
-                self.adapter.send(target, data).map_err(|e| e.into())
+                self.adapter.send(addr, data).await.map_err(|e| e.into())
             },
             target,
         )
@@ -136,9 +158,9 @@ impl<T: TransportInterface, P: PersistenceInterface> TransportSender<T, P> {
 
         for target in self.peer_manager.get_peers_to_send_metadata(&entry).await {
             self.try_send(
-                || {
+                |addr| {
                     self.adapter
-                        .send(target, TransportData::Metadata(entry.clone()))
+                        .send(addr, TransportData::Metadata(entry.clone()))
                         .map_err(|e| e.into())
                 },
                 target,
@@ -149,15 +171,15 @@ impl<T: TransportInterface, P: PersistenceInterface> TransportSender<T, P> {
     }
 
     #[tracing::instrument(skip_all, fields(target = %target, entry = %entry.name))]
-    async fn send_request(&self, target: IpAddr, entry: EntryInfo) -> io::Result<()> {
+    async fn send_request(&self, target: SocketAddr, entry: EntryInfo) -> io::Result<()> {
         if is_git_path(&entry.name) {
             return Ok(());
         }
 
         self.try_send(
-            || {
+            |addr| {
                 self.adapter
-                    .send(target, TransportData::Request(entry.clone()))
+                    .send(addr, TransportData::Request(entry.clone()))
                     .map_err(|e| e.into())
             },
             target,
@@ -179,9 +201,9 @@ impl<T: TransportInterface, P: PersistenceInterface> TransportSender<T, P> {
             }
 
             self.try_send(
-                || {
+                |addr| {
                     self.adapter
-                        .send(target, TransportData::Transfer(entry.clone()))
+                        .send(addr, TransportData::Transfer(entry.clone()))
                         .map_err(|e| e.into())
                 },
                 target,
@@ -192,26 +214,35 @@ impl<T: TransportInterface, P: PersistenceInterface> TransportSender<T, P> {
         Ok(())
     }
 
-    async fn try_send<F, Fut>(&self, mut op: F, addr: IpAddr)
+    /// Runs `op` against each candidate address of the device at
+    /// `target` until one succeeds. Three rounds without success
+    /// disconnect the peer.
+    async fn try_send<F, Fut>(&self, mut op: F, target: SocketAddr)
     where
-        F: FnMut() -> Fut,
+        F: FnMut(SocketAddr) -> Fut,
         Fut: Future<Output = io::Result<()>>,
     {
+        let candidates = self.peer_manager.candidates(target).await;
+
         for _ in 0..3 {
-            if let Err(err) = op().await {
-                error!(peer = ?addr, "Transport send error: {err}");
-            } else {
-                return;
+            for addr in &candidates {
+                match op(*addr).await {
+                    Ok(()) => {
+                        self.peer_manager.remember(*addr).await;
+                        return;
+                    }
+                    Err(err) => error!(peer = ?addr, "Transport send error: {err}"),
+                }
             }
 
-            if !self.peer_manager.exists(addr).await {
+            if !self.peer_manager.exists(target).await {
                 warn!("cancelled transport send: peer disconnected mid-op");
                 return;
             }
         }
 
-        error!(peer = ?addr, "Disconnecting peer after 3 Transport send attempts.");
-        self.peer_manager.remove_peer_by_addr(addr).await;
+        error!(peer = ?target, "Disconnecting peer after 3 Transport send attempts.");
+        self.peer_manager.remove_peer_by_addr(target).await;
     }
 }
 
@@ -225,7 +256,7 @@ mod tests {
     };
     use std::{
         collections::HashMap,
-        net::{IpAddr, Ipv4Addr},
+        net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
     };
     use uuid::Uuid;
 
@@ -262,7 +293,7 @@ mod tests {
         }
     }
 
-    async fn add_peer(pm: &PeerManager, addr: IpAddr, sync_dirs: Vec<SyncDirectory>) -> Uuid {
+    async fn add_peer(pm: &PeerManager, addr: SocketAddr, sync_dirs: Vec<SyncDirectory>) -> Uuid {
         let id = Uuid::new_v4();
         pm.insert(Peer::new(
             id,
@@ -281,6 +312,8 @@ mod tests {
             kind: EntryKind::File,
             hash: Some("h".into()),
             version: HashMap::from([(Uuid::new_v4(), 1)]),
+            modified: None,
+            removed_at: None,
         }
     }
 
@@ -289,7 +322,7 @@ mod tests {
     #[tokio::test]
     async fn send_handshake_dispatches_handshake_syn_to_target() {
         let h = setup().await;
-        let target = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        let target = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), 42882);
 
         h.sender.send_handshake(target, true).await.unwrap();
 
@@ -307,13 +340,11 @@ mod tests {
     #[tokio::test]
     async fn send_metadata_skips_git_paths() {
         let h = setup().await;
-        let addr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+        let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)), 42882);
         add_peer(
             &h.peer_manager,
             addr,
-            vec![SyncDirectory {
-                name: "sync".into(),
-            }],
+            vec![SyncDirectory::new("sync".into())],
         )
         .await;
 
@@ -330,7 +361,7 @@ mod tests {
     #[tokio::test]
     async fn send_request_skips_git_paths() {
         let h = setup().await;
-        let target = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 6));
+        let target = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 6)), 42882);
 
         h.sender
             .send_request(target, entry("sync/.git/HEAD"))
@@ -341,27 +372,23 @@ mod tests {
     }
 
     /// Metadata broadcasts only to peers that share the entry's
-    /// top-level sync directory.
+    /// top-level sync directory, on the port each peer listens on.
     #[tokio::test]
     async fn send_metadata_broadcasts_only_to_peers_sharing_sync_dir() {
         let h = setup().await;
-        let sharing = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 3));
-        let other = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 4));
+        let sharing = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 3)), 5000);
+        let other = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 4)), 42882);
 
         add_peer(
             &h.peer_manager,
             sharing,
-            vec![SyncDirectory {
-                name: "Default Folder".into(),
-            }],
+            vec![SyncDirectory::new("Default Folder".into())],
         )
         .await;
         add_peer(
             &h.peer_manager,
             other,
-            vec![SyncDirectory {
-                name: "Other Dir".into(),
-            }],
+            vec![SyncDirectory::new("Other Dir".into())],
         )
         .await;
 
@@ -375,13 +402,33 @@ mod tests {
         assert_eq!(recorded[0].0, sharing);
     }
 
+    /// A device is tried at each of its addresses in turn, and the one
+    /// that takes the message is tried first from then on.
+    #[tokio::test]
+    async fn send_falls_back_to_the_next_address_and_remembers_it() {
+        let h = setup().await;
+        let ipv4 = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 7)), 42882);
+        let ipv6 = SocketAddr::new(IpAddr::V6(Ipv6Addr::LOCALHOST), 42882);
+        let id = add_peer(&h.peer_manager, ipv4, vec![]).await;
+        h.peer_manager.add_addrs(id, &[ipv6]).await;
+        h.adapter.set_unreachable(ipv4);
+
+        h.sender.send_handshake(ipv4, true).await.unwrap();
+
+        let recorded = h.adapter.sends.lock().await;
+        assert_eq!(recorded.len(), 1);
+        assert_eq!(recorded[0].0, ipv6);
+        assert_eq!(h.peer_manager.candidates(ipv4).await, vec![ipv6, ipv4]);
+        assert_eq!(h.peer_manager.list().await[0].transport_addr(), ipv6);
+    }
+
     /// Three consecutive `send` failures must evict the peer via
     /// `PeerManager::remove_peer_by_addr` — the disconnect contract that
     /// keeps a dead TCP target from blocking the sender forever.
     #[tokio::test]
     async fn send_disconnects_peer_after_three_consecutive_failures() {
         let h = setup().await;
-        let addr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 5));
+        let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 5)), 42882);
         add_peer(&h.peer_manager, addr, vec![]).await;
         h.adapter.set_fail_sends(true);
 
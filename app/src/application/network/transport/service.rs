@@ -66,12 +66,15 @@ mod tests {
     use super::*;
     use crate::{
         application::network::transport::test_support::RecordingTransport,
-        domain::{EntryInfo, EntryKind, HandshakeData, TransportEvent, TransportMetadata},
+        domain::{
+            EntryInfo, EntryKind, HandshakeData, HandshakeEntries, TransportEvent,
+            TransportMetadata,
+        },
         infra::persistence::sqlite::SqliteDb,
     };
     use std::{
         collections::HashMap,
-        net::{IpAddr, Ipv4Addr},
+        net::{IpAddr, Ipv4Addr, SocketAddr},
         time::Duration,
     };
     use uuid::Uuid;
@@ -81,7 +84,7 @@ mod tests {
         service: TransportService<RecordingTransport, SqliteDb>,
         sender_tx: tokio::sync::mpsc::Sender<TransportChannelData>,
         peer_manager: Arc<PeerManager>,
-        sends: Arc<tokio::sync::Mutex<Vec<(IpAddr, crate::domain::TransportData)>>>,
+        sends: Arc<tokio::sync::Mutex<Vec<(SocketAddr, crate::domain::TransportData)>>>,
         push: tokio::sync::mpsc::UnboundedSender<
             crate::application::network::transport::interface::TransportResult<TransportEvent>,
         >,
@@ -116,8 +119,11 @@ mod tests {
             payload: crate::domain::TransportData::HandshakeSyn(HandshakeData {
                 hostname: "remote".into(),
                 instance_id: Uuid::new_v4(),
+                transport_port: Some(5000),
+                cluster_proof: None,
                 sync_dirs: Vec::new(),
-                entries: HashMap::new(),
+                entries: HandshakeEntries::from_map(HashMap::new()),
+                protocol: Default::default(),
             }),
             metadata: TransportMetadata {
                 source_id,
@@ -160,7 +166,7 @@ mod tests {
     async fn new_returns_usable_sender_channel() {
         let h = setup().await;
 
-        let target = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 50));
+        let target = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 50)), 42882);
         h.sender_tx
             .send(TransportChannelData::HandshakeSyn(target))
             .await
@@ -188,6 +194,7 @@ mod tests {
 
         let source_id = Uuid::new_v4();
         let source_ip = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 7));
+        h.peer_manager.load_trusted(vec![source_id]).await;
         h.push
             .send(Ok(handshake_event(source_id, source_ip)))
             .unwrap();
@@ -201,6 +208,7 @@ mod tests {
         assert_eq!(peers.len(), 1);
         assert_eq!(peers[0].id, source_id);
         assert_eq!(peers[0].addr, source_ip);
+        assert_eq!(peers[0].transport_port, 5000);
     }
 
     /// Inbound metadata for an unknown entry routes through `EntryManager`
@@ -211,11 +219,14 @@ mod tests {
         let h = setup().await;
 
         let peer_id = Uuid::new_v4();
+        h.peer_manager.load_trusted(vec![peer_id]).await;
         let entry = EntryInfo {
             name: "Default Folder/file.txt".into(),
             kind: EntryKind::File,
             hash: Some("h".into()),
             version: HashMap::from([(peer_id, 1)]),
+            modified: None,
+            removed_at: None,
         };
 
         h.push
@@ -2,10 +2,12 @@
 //!
 //! `RecordingTransport` is an in-memory `TransportInterface` that lets
 //! tests seed inbound events, capture outbound sends, and toggle a
-//! permanent-failure mode for retry tests.
+//! permanent-failure mode for retry tests, for every address or for
+//! some.
 
 use std::{
-    net::IpAddr,
+    collections::HashSet,
+    net::SocketAddr,
     sync::{
         Arc,
         atomic::{AtomicBool, Ordering},
@@ -21,10 +23,11 @@ use crate::{
 };
 
 pub(super) struct RecordingTransport {
-    pub sends: Arc<Mutex<Vec<(IpAddr, TransportData)>>>,
+    pub sends: Arc<Mutex<Vec<(SocketAddr, TransportData)>>>,
     pub recv_tx: mpsc::UnboundedSender<TransportResult<TransportEvent>>,
     pub recv_rx: Mutex<mpsc::UnboundedReceiver<TransportResult<TransportEvent>>>,
     pub fail_sends: Arc<AtomicBool>,
+    pub unreachable: std::sync::Mutex<HashSet<SocketAddr>>,
 }
 
 impl RecordingTransport {
@@ -35,6 +38,7 @@ impl RecordingTransport {
             recv_tx,
             recv_rx: Mutex::new(recv_rx),
             fail_sends: Arc::new(AtomicBool::new(false)),
+            unreachable: Default::default(),
         }
     }
 
@@ -51,6 +55,11 @@ impl RecordingTransport {
     pub fn set_fail_sends(&self, fail: bool) {
         self.fail_sends.store(fail, Ordering::SeqCst);
     }
+
+    /// Makes every send to `addr` fail.
+    pub fn set_unreachable(&self, addr: SocketAddr) {
+        self.unreachable.lock().unwrap().insert(addr);
+    }
 }
 
 impl TransportInterface for RecordingTransport {
@@ -63,8 +72,10 @@ impl TransportInterface for RecordingTransport {
             .unwrap_or_else(|| Err(TransportError::new("recv channel closed")))
     }
 
-    async fn send(&self, target: IpAddr, data: TransportData) -> TransportResult<()> {
-        if self.fail_sends.load(Ordering::SeqCst) {
+    async fn send(&self, target: SocketAddr, data: TransportData) -> TransportResult<()> {
+        if self.fail_sends.load(Ordering::SeqCst)
+            || self.unreachable.lock().unwrap().contains(&target)
+        {
             return Err(TransportError::new("simulated send failure"));
         }
         self.sends.lock().await.push((target, data));
@@ -1,12 +1,20 @@
 use tokio::io;
+use uuid::Uuid;
 
-use crate::domain::EntryInfo;
+use crate::domain::{ConflictRecord, EntryInfo, MergeBase, Snapshot, TrashRecord, VersionVector};
 
 /// Port for entry-metadata persistence.
 ///
 /// Implementations store and retrieve `EntryInfo` keyed by their
-/// `RelativePath` string. The interface is intentionally small —
-/// callers never query or mutate version vectors directly; they
+/// `RelativePath` string — removed entries included, as tombstones —
+/// plus the ids of devices the user approved for pairing, which of
+/// them have acknowledged each tombstone, the conflicts awaiting the
+/// user, the entries in the trash, the snapshots the user took with
+/// the entries each one holds, the hash and version each entry last
+/// had in common with a peer, and the conflicts waiting on a peer's
+/// version to be merged. The interface is
+/// intentionally small — callers never query or mutate version vectors
+/// directly; they
 /// `insert_or_replace_entry` after merging in memory.
 ///
 /// All methods are `async` because the default adapter (`SqliteDb`)
@@ -22,8 +30,85 @@ pub trait PersistenceInterface: Send + Sync + 'static {
     /// Returns every persisted entry. Used at startup to rehydrate the
     /// in-memory view.
     async fn list_all_entries(&self) -> PersistenceResult<Vec<EntryInfo>>;
+    /// Returns up to `limit` entries whose names sort after `after`
+    /// (from the first entry when `None`), in ascending name order.
+    /// Lets callers walk the whole table without loading it at once.
+    async fn list_entries_after(
+        &self,
+        after: Option<&str>,
+        limit: usize,
+    ) -> PersistenceResult<Vec<EntryInfo>>;
+    /// Like `list_entries_after`, but only the entry named `dir` and
+    /// the entries below it.
+    async fn list_entries_within(
+        &self,
+        dir: &str,
+        after: Option<&str>,
+        limit: usize,
+    ) -> PersistenceResult<Vec<EntryInfo>>;
     /// Deletes an entry by name. Deleting a missing entry must not error.
     async fn delete_entry(&self, name: &str) -> PersistenceResult<()>;
+    /// Records `id` as an approved device. Inserting a known id must
+    /// not error.
+    async fn insert_trusted_device(&self, id: &Uuid) -> PersistenceResult<()>;
+    /// Returns every approved device id. Used at startup to rebuild
+    /// the in-memory trusted set.
+    async fn list_trusted_devices(&self) -> PersistenceResult<Vec<Uuid>>;
+    /// Records that `device_id` holds the tombstone for `name`.
+    /// Recording the same pair twice must not error.
+    async fn insert_tombstone_ack(&self, name: &str, device_id: &Uuid) -> PersistenceResult<()>;
+    /// Returns the devices that acknowledged the tombstone for `name`.
+    async fn list_tombstone_acks(&self, name: &str) -> PersistenceResult<Vec<Uuid>>;
+    /// Forgets every acknowledgement for `name`. Deleting none must
+    /// not error.
+    async fn delete_tombstone_acks(&self, name: &str) -> PersistenceResult<()>;
+    /// Records an unresolved conflict.
+    async fn insert_conflict(&self, conflict: &ConflictRecord) -> PersistenceResult<()>;
+    /// Returns every unresolved conflict, oldest first.
+    async fn list_conflicts(&self) -> PersistenceResult<Vec<ConflictRecord>>;
+    /// Forgets a resolved conflict. Deleting a missing one must not
+    /// error.
+    async fn delete_conflict(&self, id: &Uuid) -> PersistenceResult<()>;
+    /// Records an entry moved into the trash.
+    async fn insert_trash(&self, record: &TrashRecord) -> PersistenceResult<()>;
+    /// Returns every entry in the trash, oldest first.
+    async fn list_trash(&self) -> PersistenceResult<Vec<TrashRecord>>;
+    /// Forgets a restored or purged entry. Deleting a missing one must
+    /// not error.
+    async fn delete_trash(&self, id: &Uuid) -> PersistenceResult<()>;
+    /// Records a snapshot along with the entries it holds.
+    async fn insert_snapshot(
+        &self,
+        snapshot: &Snapshot,
+        entries: &[EntryInfo],
+    ) -> PersistenceResult<()>;
+    /// Returns every snapshot, oldest first.
+    async fn list_snapshots(&self) -> PersistenceResult<Vec<Snapshot>>;
+    /// Returns the entries snapshot `id` holds, in ascending name order.
+    async fn list_snapshot_entries(&self, id: &Uuid) -> PersistenceResult<Vec<EntryInfo>>;
+    /// Forgets a snapshot and its entries. Deleting a missing one must
+    /// not error.
+    async fn delete_snapshot(&self, id: &Uuid) -> PersistenceResult<()>;
+    /// Records `hash` at `version` as the merge base of `name`,
+    /// replacing any other.
+    async fn set_merge_base(
+        &self,
+        name: &str,
+        hash: &str,
+        version: &VersionVector,
+    ) -> PersistenceResult<()>;
+    /// Returns the merge base of `name`, if any.
+    async fn get_merge_base(&self, name: &str) -> PersistenceResult<Option<MergeBase>>;
+    /// Forgets the merge base of `name`. Deleting none must not error.
+    async fn delete_merge_base(&self, name: &str) -> PersistenceResult<()>;
+    /// Whether any entry still has `hash` as its merge base.
+    async fn is_merge_base(&self, hash: &str) -> PersistenceResult<bool>;
+    /// Records conflict `id` on `name` as waiting for the peer's
+    /// version, replacing any other.
+    async fn set_pending_merge(&self, name: &str, id: &Uuid) -> PersistenceResult<()>;
+    /// Forgets and returns the conflict on `name` waiting for the
+    /// peer's version, if any.
+    async fn take_pending_merge(&self, name: &str) -> PersistenceResult<Option<Uuid>>;
 }
 
 /// Result alias for fallible persistence calls.
@@ -1,20 +1,29 @@
 use crate::{
     domain::{
-        AppPorts, BroadcastChannel, CanonicalPath, Config, ConfigDirectory, Peer, RelativePath,
-        ServerEvent, SyncDirectory,
+        AppPorts, BroadcastChannel, CanonicalPath, Capability, Cluster, Config, ConfigDirectory,
+        ConfigPeer, DeviceKey, DigestTree, DirectoryMode, DiscoveryConfig, LivenessConfig, Peer,
+        PeerAddrs, PendingDevice, RelativePath, ServerEvent, SyncDirectory, TransportProtocol,
     },
     utils::dirs::SyncheDirs,
 };
-use std::{collections::HashMap, net::IpAddr, path::PathBuf, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::PathBuf,
+    sync::Arc,
+};
 use tokio::{
-    fs, io,
-    sync::{RwLock, broadcast},
+    fs,
+    io::{self, AsyncWriteExt},
+    sync::{RwLock, RwLockReadGuard, broadcast},
 };
+use tracing::{info, warn};
 use uuid::Uuid;
 
 pub const DEFAULT_HTTP_PORT: u16 = 42880;
 pub const DEFAULT_PRESENCE_PORT: u16 = 42881;
 pub const DEFAULT_TRANSPORT_PORT: u16 = 42882;
+pub const DEFAULT_BROADCAST_PORT: u16 = 42883;
 
 /// Returns the production port assignment. Tests inject their own
 /// `AppPorts { http: 0, ... }` to avoid collisions with a running
@@ -24,16 +33,22 @@ pub fn default_ports() -> AppPorts {
         http: DEFAULT_HTTP_PORT,
         presence: DEFAULT_PRESENCE_PORT,
         transport: DEFAULT_TRANSPORT_PORT,
+        broadcast: DEFAULT_BROADCAST_PORT,
     }
 }
 
 /// Process-wide runtime hub shared as `Arc<AppState>` across every
 /// subsystem.
 ///
-/// Holds the device's identities (`local_id` persists across
-/// restarts; `instance_id` is regenerated per process), the active
-/// `home_path` and port assignments, the live peer and sync-dir maps,
-/// and the SSE broadcast channel used to push events to the GUI.
+/// Holds the device's identities (`device_key` and the `local_id`
+/// derived from it persist across restarts; `instance_id` is
+/// regenerated per process), the active `home_path`, port assignments,
+/// the cluster, liveness timings, discovery switches and configured
+/// `[[peer]]` list, the live peer and sync-dir maps, the addresses each
+/// device was announced or reached at, the pairing state (trusted,
+/// pending and rejected device ids), the digest tree `EntryManager`
+/// keeps over the entry map, the SSE broadcast channel used to push
+/// events to the GUI, and the channel announcing peers that went away.
 ///
 /// All on-disk paths are resolved through the injected `SyncheDirs`
 /// rather than global statics — see `CLAUDE.md` (Runtime / data
@@ -41,14 +56,26 @@ pub fn default_ports() -> AppPorts {
 pub struct AppState {
     dirs: SyncheDirs,
     ports: AppPorts,
+    device_key: DeviceKey,
     local_id: Uuid,
     instance_id: Uuid,
     hostname: String,
     home_path: CanonicalPath,
+    cluster: Cluster,
+    transport: TransportProtocol,
+    static_peers: Vec<ConfigPeer>,
+    liveness: LivenessConfig,
+    discovery: DiscoveryConfig,
     local_ip: RwLock<IpAddr>,
     sse_broadcast: BroadcastChannel<ServerEvent>,
+    pub(super) peer_removals: BroadcastChannel<Uuid>,
     pub(super) peers: RwLock<HashMap<Uuid, Peer>>,
+    pub(super) peer_addrs: RwLock<HashMap<Uuid, PeerAddrs>>,
     pub(super) sync_dirs: RwLock<HashMap<RelativePath, SyncDirectory>>,
+    pub(super) trusted_devices: RwLock<HashSet<Uuid>>,
+    pub(super) pending_devices: RwLock<HashMap<Uuid, PendingDevice>>,
+    pub(super) rejected_devices: RwLock<HashSet<Uuid>>,
+    pub(super) digest_tree: RwLock<DigestTree>,
 }
 
 impl AppState {
@@ -60,8 +87,10 @@ impl AppState {
     pub async fn new(dirs: SyncheDirs, ports: AppPorts) -> Arc<Self> {
         let config = Config::init(&dirs).await.unwrap();
 
-        let local_ip = local_ip_address::local_ip().unwrap();
-        let (local_id, instance_id) = Self::init_ids(&dirs).await.unwrap();
+        // Offline at startup; the network watch fills it in later.
+        let local_ip = local_ip_address::local_ip().unwrap_or(Ipv4Addr::LOCALHOST.into());
+        let (device_key, instance_id) = Self::init_ids(&dirs).await.unwrap();
+        let local_id = device_key.device_id();
 
         let hostname = hostname::get().unwrap().to_string_lossy().to_string();
         let hostname = hostname
@@ -77,15 +106,34 @@ impl AppState {
                 .collect(),
         );
 
+        if config.liveness.is_anti_entropy_adjusted() {
+            warn!(
+                "anti_entropy_interval must be set to at most a third of peer_timeout; handshaking every {:?} instead",
+                config.liveness.anti_entropy_interval().unwrap_or_default()
+            );
+        }
+
         Arc::new(Self {
             dirs,
             ports,
             hostname,
+            device_key,
             local_id,
             instance_id,
             peers: Default::default(),
+            peer_addrs: Default::default(),
+            trusted_devices: Default::default(),
+            pending_devices: Default::default(),
+            rejected_devices: Default::default(),
+            digest_tree: Default::default(),
             sse_broadcast: BroadcastChannel::new(100),
+            peer_removals: BroadcastChannel::new(100),
             home_path: config.home_path,
+            cluster: Cluster::new(config.cluster),
+            transport: config.transport,
+            static_peers: config.peer,
+            liveness: config.liveness,
+            discovery: config.discovery,
             local_ip: RwLock::new(local_ip),
             sync_dirs,
         })
@@ -103,10 +151,38 @@ impl AppState {
         self.local_id
     }
 
+    pub fn device_key(&self) -> &DeviceKey {
+        &self.device_key
+    }
+
     pub fn instance_id(&self) -> Uuid {
         self.instance_id
     }
 
+    /// The `cluster` of `config.toml` as read at startup.
+    pub fn cluster(&self) -> &Cluster {
+        &self.cluster
+    }
+
+    pub fn transport(&self) -> TransportProtocol {
+        self.transport
+    }
+
+    /// The `[liveness]` timings of `config.toml` as read at startup.
+    pub fn liveness(&self) -> LivenessConfig {
+        self.liveness
+    }
+
+    /// The `[discovery]` switches of `config.toml` as read at startup.
+    pub fn discovery(&self) -> DiscoveryConfig {
+        self.discovery
+    }
+
+    /// The `[[peer]]` entries of `config.toml` as read at startup.
+    pub fn static_peers(&self) -> &[ConfigPeer] {
+        &self.static_peers
+    }
+
     pub fn hostname(&self) -> &String {
         &self.hostname
     }
@@ -119,6 +195,10 @@ impl AppState {
         *self.local_ip.read().await
     }
 
+    pub async fn set_local_ip(&self, ip: IpAddr) {
+        *self.local_ip.write().await = ip;
+    }
+
     pub fn sse_sender(&self) -> broadcast::Sender<ServerEvent> {
         self.sse_broadcast.sender()
     }
@@ -127,19 +207,86 @@ impl AppState {
         self.sse_broadcast.subscribe()
     }
 
-    async fn init_ids(dirs: &SyncheDirs) -> io::Result<(Uuid, Uuid)> {
-        let file = dirs.device_id_file();
-
-        let local_id = if !file.exists() {
-            let id = Uuid::new_v4();
-            fs::write(&file, id.to_string()).await?;
-            id
+    /// Ids of peers `PeerManager` removes from the live map, so
+    /// adapters can let go of whatever they keep open for them.
+    pub fn subscribe_peer_removals(&self) -> broadcast::Receiver<Uuid> {
+        self.peer_removals.subscribe()
+    }
+
+    /// Returns `true` if `id` has been approved by the user. Adapters
+    /// call this to refuse traffic from unpaired devices before any
+    /// payload is read.
+    pub async fn is_trusted_device(&self, id: &Uuid) -> bool {
+        self.trusted_devices.read().await.contains(id)
+    }
+
+    /// Returns `true` if the connected peer `id` negotiated
+    /// `capability` in its handshake. Adapters call this before using
+    /// an optional wire feature; unknown peers support nothing.
+    pub async fn peer_supports(&self, id: &Uuid, capability: Capability) -> bool {
+        self.peers
+            .read()
+            .await
+            .get(id)
+            .is_some_and(|peer| peer.supports(capability))
+    }
+
+    /// The connected peer whose transport listens at `addr`, which may
+    /// be any of its `PeerAddrs`. Adapters use it to find the
+    /// connection a device already has.
+    pub async fn peer_at(&self, addr: SocketAddr) -> Option<Uuid> {
+        let peers = self.peers.read().await;
+        let addrs = self.peer_addrs.read().await;
+
+        peers
+            .values()
+            .find(|peer| {
+                let known = addrs.get(&peer.id);
+                peer.transport_addr() == addr || known.is_some_and(|known| known.contains(addr))
+            })
+            .map(|peer| peer.id)
+    }
+
+    /// Read access to the digest tree over the entry map. Adapters
+    /// use it to compare subtrees with a peer during a handshake; only
+    /// `EntryManager` writes to it.
+    pub async fn digest_tree(&self) -> RwLockReadGuard<'_, DigestTree> {
+        self.digest_tree.read().await
+    }
+
+    /// Loads the device key, generating and persisting one on first
+    /// run. The `device_id` file is rewritten whenever it disagrees
+    /// with the id derived from the key — this migrates installs that
+    /// predate key-derived ids.
+    async fn init_ids(dirs: &SyncheDirs) -> io::Result<(DeviceKey, Uuid)> {
+        let key_file = dirs.device_key_file();
+
+        let device_key = if !key_file.exists() {
+            let key = DeviceKey::generate();
+            write_secret(&key_file, &key.to_bytes()).await?;
+            key
         } else {
-            let id = fs::read_to_string(&file).await?;
-            Uuid::parse_str(&id).map_err(io::Error::other)?
+            let bytes = fs::read(&key_file).await?;
+            DeviceKey::from_bytes(&bytes).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "Malformed device_key file")
+            })?
         };
 
-        Ok((local_id, Uuid::new_v4()))
+        let local_id = device_key.device_id();
+        let id_file = dirs.device_id_file();
+        let stored_id = match fs::read_to_string(&id_file).await {
+            Ok(id) => Uuid::parse_str(id.trim()).ok(),
+            Err(_) => None,
+        };
+
+        if stored_id != Some(local_id) {
+            if let Some(old) = stored_id {
+                info!("Device id changed from {old} to key-derived {local_id}");
+            }
+            fs::write(&id_file, local_id.to_string()).await?;
+        }
+
+        Ok((device_key, Uuid::new_v4()))
     }
 
     /// Adds `name` to `config.toml` and the in-memory `sync_dirs`
@@ -156,13 +303,16 @@ impl AppState {
             dirs.values().map(|d| d.to_config()).collect()
         };
 
-        directory.push(ConfigDirectory {
-            name: name.to_owned(),
-        });
+        directory.push(ConfigDirectory::new(name));
 
         self.write_config(&Config {
             directory,
             home_path: self.home_path.clone(),
+            cluster: self.cluster.name().map(str::to_owned),
+            transport: self.transport,
+            liveness: self.liveness,
+            discovery: self.discovery,
+            peer: self.static_peers.clone(),
         })
         .await
         .map(|_| true)
@@ -187,6 +337,11 @@ impl AppState {
         self.write_config(&Config {
             directory,
             home_path: self.home_path.clone(),
+            cluster: self.cluster.name().map(str::to_owned),
+            transport: self.transport,
+            liveness: self.liveness,
+            discovery: self.discovery,
+            peer: self.static_peers.clone(),
         })
         .await
     }
@@ -210,6 +365,11 @@ impl AppState {
         self.write_config(&Config {
             directory,
             home_path: new_home_path,
+            cluster: self.cluster.name().map(str::to_owned),
+            transport: self.transport,
+            liveness: self.liveness,
+            discovery: self.discovery,
+            peer: self.static_peers.clone(),
         })
         .await
     }
@@ -237,12 +397,28 @@ impl AppState {
         fs::write(self.dirs.config_file(), contents).await
     }
 
+    /// Names of the configured sync directories.
+    pub async fn sync_dir_names(&self) -> Vec<RelativePath> {
+        self.sync_dirs.read().await.keys().cloned().collect()
+    }
+
     /// Returns `true` if `name` is an exact match for a configured
     /// sync directory.
     pub async fn contains_sync_dir(&self, name: &RelativePath) -> bool {
         self.sync_dirs.read().await.contains_key(name)
     }
 
+    /// The mode of the sync directory `name`; `SendReceive` if it is not
+    /// configured.
+    pub async fn directory_mode(&self, name: &RelativePath) -> DirectoryMode {
+        self.sync_dirs
+            .read()
+            .await
+            .get(name)
+            .map(|dir| dir.mode)
+            .unwrap_or_default()
+    }
+
     /// Returns `true` if `path` falls under any configured sync
     /// directory — the boundary check that decides whether a watcher
     /// event is relevant.
@@ -256,6 +432,20 @@ impl AppState {
     }
 }
 
+/// Writes key material to a new file, readable by the owner only
+/// where the platform supports it. The mode is set when the file is
+/// created, so the key is never readable by others in between.
+async fn write_secret(path: &CanonicalPath, contents: &[u8]) -> io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+
+    let mut file = options.open(path).await?;
+    file.write_all(contents).await?;
+    file.flush().await
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -300,6 +490,52 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn local_id_is_derived_from_device_key() {
+        let env = test_env().await;
+
+        assert_eq!(env.state.local_id(), env.state.device_key().device_id());
+        let on_disk = tokio::fs::read_to_string(env.dirs.device_id_file())
+            .await
+            .unwrap();
+        assert_eq!(on_disk, env.state.local_id().to_string());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn device_key_file_is_readable_by_the_owner_only() {
+        use std::os::unix::fs::PermissionsExt;
+        let env = test_env().await;
+
+        let meta = tokio::fs::metadata(env.dirs.device_key_file())
+            .await
+            .unwrap();
+        assert_eq!(meta.permissions().mode() & 0o777, 0o600);
+    }
+
+    /// Installs from before key-derived ids only have a random
+    /// `device_id`; startup must replace it with the derived one.
+    #[tokio::test]
+    async fn legacy_device_id_is_replaced_by_key_derived_id() {
+        let env = test_env().await;
+        tokio::fs::remove_file(env.dirs.device_key_file())
+            .await
+            .unwrap();
+        let legacy = Uuid::new_v4();
+        tokio::fs::write(env.dirs.device_id_file(), legacy.to_string())
+            .await
+            .unwrap();
+
+        let state = AppState::new(env.dirs.clone(), default_ports()).await;
+
+        assert_ne!(state.local_id(), legacy);
+        assert_eq!(state.local_id(), state.device_key().device_id());
+        let on_disk = tokio::fs::read_to_string(env.dirs.device_id_file())
+            .await
+            .unwrap();
+        assert_eq!(on_disk, state.local_id().to_string());
+    }
+
     #[tokio::test]
     async fn test_validate_home_path_creates_missing_dir() {
         let temp = TempDir::new().unwrap();
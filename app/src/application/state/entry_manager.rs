@@ -1,14 +1,22 @@
-use super::{app_state::AppState, ignore::IgnoreHandler};
+use super::{
+    app_state::AppState, ignore::IgnoreHandler, merge_bases::MergeBases, snapshots::SnapshotStore,
+    trash::Trash, versions::VersionStore,
+};
 use crate::{
     application::persistence::interface::PersistenceInterface,
     domain::{
-        CanonicalPath, EntryInfo, EntryKind, HandshakeData, MAX_TRUSTED_COUNTER, Peer,
-        RelativePath, SyncDirectory, VersionCmp,
+        CanonicalPath, ConflictPolicy, ConflictRecord, ConflictResolution, DigestTree, EntryDiff,
+        EntryInfo, EntryKind, EntryPage, EntryPager, EntryScope, FileVersion, HandshakeData,
+        HandshakeEntries, MAX_MERGE_BYTES, MAX_TRUSTED_COUNTER, Peer, ProtocolInfo, RelativePath,
+        ServerEvent, Snapshot, SyncDirectory, TrashExpiry, TrashRecord, VersionCmp, VersionVector,
+        as_text, three_way_merge,
+    },
+    utils::fs::{
+        compute_hash, hash_bytes, is_ds_store, is_git_path, is_synche_path, modified_secs,
     },
-    utils::fs::{compute_hash, is_ds_store, is_git_path},
 };
 use std::{
-    collections::{HashMap, VecDeque},
+    collections::{HashMap, HashSet, VecDeque},
     sync::Arc,
     time::{SystemTime, UNIX_EPOCH},
 };
@@ -16,10 +24,18 @@ use tokio::{
     fs::{self},
     io,
 };
-use tracing::{trace, warn};
+use tracing::{info, trace, warn};
 use uuid::Uuid;
 use walkdir::WalkDir;
 
+/// How many entries one handshake page reads from the store.
+const HANDSHAKE_PAGE_ENTRIES: usize = 1000;
+
+/// How long a tombstone is kept after its removal, even once every
+/// trusted device has acknowledged it, so that a device paired later
+/// still learns of the deletion instead of resurrecting the entry.
+const TOMBSTONE_MIN_AGE_SECS: u64 = 30 * 24 * 60 * 60;
+
 /// Owns the lifecycle of synchronized filesystem entries.
 ///
 /// Combines a `PersistenceInterface` (durable metadata store), the
@@ -27,11 +43,30 @@ use walkdir::WalkDir;
 /// `IgnoreHandler` (`.gitignore` rules) to scan the home directory at
 /// startup, react to local file events, and reconcile metadata that
 /// arrives from peers — including materializing conflict files when
-/// `VersionCmp::Conflict` is detected.
+/// `VersionCmp::Conflict` is detected, and recording them as
+/// `ConflictRecord`s until the user resolves them.
+///
+/// Deletions are kept as tombstones (`REMOVED_HASH` plus the bumped
+/// version vector) so a peer that was offline when the entry was
+/// removed still learns about it at its next handshake. A tombstone is
+/// garbage-collected once it is `TOMBSTONE_MIN_AGE_SECS` old and every
+/// trusted device, if any, has acknowledged it.
+///
+/// The contents of each text file as last held in common with a peer
+/// are kept in `MergeBases`, so that concurrent edits to it can be
+/// three-way merged once the peer's version arrives; see
+/// `merge_received`. Contents replaced by peers are kept in the
+/// `VersionStore`, and entries deleted by peers in the `Trash`, from
+/// which the user can restore them. Snapshots the user takes of a sync
+/// directory keep their contents in the `SnapshotStore`.
 pub struct EntryManager<P: PersistenceInterface> {
     db: P,
     state: Arc<AppState>,
     ignore_handler: IgnoreHandler,
+    merge_bases: MergeBases,
+    versions: VersionStore,
+    trash: Trash,
+    snapshots: SnapshotStore,
 }
 
 impl<P: PersistenceInterface> EntryManager<P> {
@@ -39,15 +74,31 @@ impl<P: PersistenceInterface> EntryManager<P> {
         Arc::new(Self {
             db,
             ignore_handler: IgnoreHandler::new(state.clone()),
+            merge_bases: MergeBases::new(state.clone()),
+            versions: VersionStore::new(state.clone()),
+            trash: Trash::new(state.clone()),
+            snapshots: SnapshotStore::new(state.clone()),
             state,
         })
     }
 
     /// Scans every configured sync directory, then reconciles the
     /// on-disk view with the persisted entries — creating missing
-    /// directories, hashing files, and seeding version vectors for
-    /// fresh entries. Called once at startup.
+    /// directories, hashing files, seeding version vectors for fresh
+    /// entries, and tombstoning entries deleted while we were not
+    /// running. Called once at startup, and loads the digest tree that
+    /// every later write keeps current, prunes the kept versions,
+    /// empties the trash of expired entries and collects the tombstones
+    /// due. What changed on disk in a receive-only directory is left
+    /// unrecorded; see `local_changes`.
     pub async fn init(&self) -> io::Result<()> {
+        self.load_digest_tree().await?;
+        self.versions.prune_all().await?;
+        self.expire_trash().await?;
+        for name in self.local_tombstones().await? {
+            self.collect_tombstone(&name).await?;
+        }
+
         let mut filesystem_entries = HashMap::new();
 
         for dir in self.state.sync_dirs.read().await.values() {
@@ -97,7 +148,10 @@ impl<P: PersistenceInterface> EntryManager<P> {
             let canonical = CanonicalPath::new(entry.path())?;
             let relative = RelativePath::new(&canonical, self.state.home_path())?;
 
-            if is_git_path(&relative) || self.is_ignored(&canonical, &relative).await {
+            if is_git_path(&relative)
+                || is_synche_path(&relative)
+                || self.is_ignored(&canonical, &relative).await
+            {
                 continue;
             }
 
@@ -109,6 +163,8 @@ impl<P: PersistenceInterface> EntryManager<P> {
                         kind: EntryKind::File,
                         hash: Some(compute_hash(&canonical).await?),
                         version: HashMap::from([(self.state.local_id(), 0)]),
+                        modified: modified_secs(&canonical).await,
+                        removed_at: None,
                     },
                 );
             } else if canonical.is_dir() {
@@ -119,6 +175,8 @@ impl<P: PersistenceInterface> EntryManager<P> {
                         kind: EntryKind::Directory,
                         hash: None,
                         version: HashMap::from([(self.state.local_id(), 0)]),
+                        modified: None,
+                        removed_at: None,
                     },
                 );
 
@@ -145,9 +203,18 @@ impl<P: PersistenceInterface> EntryManager<P> {
 
         let sync_dirs = { self.state.sync_dirs.read().await.clone() };
 
+        let receive_only = |name: &RelativePath| {
+            sync_dirs
+                .get(&name.sync_dir())
+                .is_some_and(|dir| dir.mode.is_receive_only())
+        };
+
         for (name, entry) in &mut db_entries {
             if !sync_dirs.contains_key(&entry.get_sync_dir()) {
-                self.db.delete_entry(name).await?;
+                self.forget_entry(name).await?;
+                continue;
+            }
+            if receive_only(name) {
                 continue;
             }
 
@@ -155,18 +222,19 @@ impl<P: PersistenceInterface> EntryManager<P> {
                 Some(fs_entry) if fs_entry.hash != entry.hash => {
                     bump_local_counter(&mut entry.version, self.state.local_id())?;
 
-                    self.db
-                        .insert_or_replace_entry(&EntryInfo {
-                            name: name.clone(),
-                            version: entry.version.clone(),
-                            kind: fs_entry.kind.clone(),
-                            hash: fs_entry.hash.clone(),
-                        })
-                        .await?;
+                    self.store_entry(&EntryInfo {
+                        name: name.clone(),
+                        version: entry.version.clone(),
+                        kind: fs_entry.kind.clone(),
+                        hash: fs_entry.hash.clone(),
+                        modified: fs_entry.modified,
+                        removed_at: None,
+                    })
+                    .await?;
                 }
 
-                None => {
-                    self.db.delete_entry(name).await?;
+                None if !entry.is_removed() => {
+                    self.delete_and_update_entry(entry.clone()).await?;
                 }
 
                 _ => {}
@@ -174,52 +242,90 @@ impl<P: PersistenceInterface> EntryManager<P> {
         }
 
         for (name, fs_entry) in filesystem_entries {
-            if !db_entries.contains_key(&name) {
-                self.db.insert_or_replace_entry(&fs_entry).await?;
+            if !db_entries.contains_key(&name) && !receive_only(&name) {
+                self.store_entry(&fs_entry).await?;
             }
         }
         Ok(())
     }
 
-    pub async fn add_sync_dir(&self, name: RelativePath) -> io::Result<()> {
-        let path = name.to_canonical(self.state.home_path());
+    /// Starts syncing `dir`, recording what it already holds unless it
+    /// is receive-only.
+    pub async fn add_sync_dir(&self, dir: SyncDirectory) -> io::Result<()> {
+        let path = dir.name.to_canonical(self.state.home_path());
         fs::create_dir_all(&path).await?;
 
         let dir_entries = self.build_dir(path.clone()).await?;
 
-        for (_, info) in dir_entries {
-            self.insert_entry(info).await?;
+        if !dir.mode.is_receive_only() {
+            for (_, info) in dir_entries {
+                self.insert_entry(info).await?;
+            }
         }
 
         self.state
             .sync_dirs
             .write()
             .await
-            .insert(name.clone(), SyncDirectory { name });
+            .insert(dir.name.clone(), dir);
         Ok(())
     }
 
+    /// Stops syncing `name`. Its entries are forgotten rather than
+    /// tombstoned: leaving a sync directory must not delete it on peers.
     pub async fn remove_sync_dir(&self, name: &RelativePath) -> io::Result<bool> {
         if self.state.sync_dirs.write().await.remove(name).is_some() {
-            self.remove_dir(name).await?;
+            for entry in self.db.list_all_entries().await? {
+                if entry.name.starts_with_dir(name) {
+                    self.forget_entry(&entry.name).await?;
+                }
+            }
             Ok(true)
         } else {
             Ok(false)
         }
     }
 
+    /// Applies the settings of `dir` to the synced directory of the
+    /// same name, if there is one. Returns `true` if any changed.
+    pub async fn update_sync_dir(&self, dir: SyncDirectory) -> bool {
+        match self.state.sync_dirs.write().await.get_mut(&dir.name) {
+            Some(current) if *current != dir => {
+                *current = dir;
+                true
+            }
+            _ => false,
+        }
+    }
+
     pub async fn list_dirs(&self) -> HashMap<RelativePath, SyncDirectory> {
         self.state.sync_dirs.read().await.clone()
     }
 
+    /// Whether `relative` is kept out of sync: matched by a
+    /// `.gitignore`, or the copy of a conflict still awaiting the user.
     pub async fn is_ignored(&self, path: &CanonicalPath, relative: &RelativePath) -> bool {
         self.ignore_handler.is_ignored(path, relative).await
+            || self.is_conflict_copy(relative).await
+    }
+
+    async fn is_conflict_copy(&self, relative: &RelativePath) -> bool {
+        if !relative.contains("_CONFLICT_") {
+            return false;
+        }
+        match self.db.list_conflicts().await {
+            Ok(conflicts) => conflicts.iter().any(|c| c.conflict_path == *relative),
+            Err(err) => {
+                warn!(path = %relative, "failed to list conflicts: {err}");
+                false
+            }
+        }
     }
 
     pub async fn insert_entry(&self, mut entry: EntryInfo) -> io::Result<EntryInfo> {
         entry.version.entry(self.state.local_id()).or_insert(0);
         trace!(entry = %entry.name, "inserting entry");
-        self.db.insert_or_replace_entry(&entry).await?;
+        self.store_entry(&entry).await?;
         Ok(entry)
     }
 
@@ -260,21 +366,40 @@ impl<P: PersistenceInterface> EntryManager<P> {
         version.entry(self.state.local_id()).or_insert(0);
         entry.version = version;
         trace!(entry = %entry.name, peer = %peer_id, "inserting peer entry");
-        self.db.insert_or_replace_entry(&entry).await?;
+        self.store_entry(&entry).await?;
         Ok(Some(entry))
     }
 
+    /// Records a newly created entry. Recreating a path that still has
+    /// a tombstone continues the tombstone's version vector, so the new
+    /// entry dominates the deletion on every peer instead of losing to
+    /// it.
     pub async fn entry_created(
         &self,
         name: &RelativePath,
         kind: EntryKind,
         hash: Option<String>,
     ) -> io::Result<EntryInfo> {
+        let version = match self.get_entry(name).await? {
+            Some(tombstone) if tombstone.is_removed() => {
+                let mut version = tombstone.version;
+                bump_local_counter(&mut version, self.state.local_id())?;
+                version
+            }
+            _ => HashMap::from([(self.state.local_id(), 0)]),
+        };
+        let modified = match kind {
+            EntryKind::File => modified_secs(&name.to_canonical(self.state.home_path())).await,
+            EntryKind::Directory => None,
+        };
+
         self.insert_entry(EntryInfo {
             name: name.to_owned(),
             kind,
             hash,
-            version: HashMap::from([(self.state.local_id(), 0)]),
+            version,
+            modified,
+            removed_at: None,
         })
         .await
     }
@@ -285,9 +410,12 @@ impl<P: PersistenceInterface> EntryManager<P> {
         hash: Option<String>,
     ) -> io::Result<EntryInfo> {
         entry.hash = hash;
+        if entry.is_file() {
+            entry.modified = modified_secs(&entry.name.to_canonical(self.state.home_path())).await;
+        }
         bump_local_counter(&mut entry.version, self.state.local_id())?;
 
-        self.db.insert_or_replace_entry(&entry).await?;
+        self.store_entry(&entry).await?;
         Ok(entry)
     }
 
@@ -296,38 +424,95 @@ impl<P: PersistenceInterface> EntryManager<P> {
         Ok(entry)
     }
 
-    /// Given a peer's full entry map (typically delivered in a
+    /// Names of every local tombstone, read page by page. A handshake
+    /// starts from this set and strikes out each name the peer still
+    /// holds live; see `acknowledge_tombstones`.
+    pub async fn local_tombstones(&self) -> io::Result<HashSet<RelativePath>> {
+        let mut tombstones = HashSet::new();
+        let mut after: Option<RelativePath> = None;
+
+        loop {
+            let page = self
+                .db
+                .list_entries_after(after.as_deref(), HANDSHAKE_PAGE_ENTRIES)
+                .await?;
+            let last = page.len() < HANDSHAKE_PAGE_ENTRIES;
+            after = page.last().map(|e| e.name.clone());
+
+            tombstones.extend(
+                page.into_iter()
+                    .filter(EntryInfo::is_removed)
+                    .map(|e| e.name),
+            );
+
+            if last {
+                return Ok(tombstones);
+            }
+        }
+    }
+
+    /// Given one page of a peer's entry map (typically delivered in a
     /// handshake), returns the subset that we should request from
     /// them — entries we don't have, or entries where the peer's
-    /// version dominates ours after conflict resolution.
+    /// version dominates ours after conflict resolution. Peer
+    /// tombstones are only returned when they remove something we
+    /// still have. In a send-only directory, a peer version that
+    /// dominates ours is returned for the caller to hand to
+    /// `keep_local` rather than request; see `settle_send_only`.
+    ///
+    /// Strikes every name the peer holds live from `tombstones`, so
+    /// that once the last page is in, what is left are the local
+    /// tombstones the peer's map no longer contradicts.
     pub async fn get_entries_to_request(
         &self,
         peer: &Peer,
-        peer_entries: HashMap<RelativePath, EntryInfo>,
+        peer_entries: Vec<EntryInfo>,
+        tombstones: &mut HashSet<RelativePath>,
     ) -> io::Result<Vec<EntryInfo>> {
         let mut to_request = Vec::new();
 
         let dirs = { self.state.sync_dirs.read().await.clone() };
 
-        for (name, peer_entry) in peer_entries {
-            if is_git_path(&name) {
+        for peer_entry in peer_entries {
+            if !peer_entry.is_removed() {
+                tombstones.remove(&peer_entry.name);
+            }
+
+            if is_git_path(&peer_entry.name) {
                 continue;
             }
 
-            if dirs.contains_key(&peer_entry.get_sync_dir()) {
+            if let Some(dir) = dirs.get(&peer_entry.get_sync_dir())
+                && !is_synche_path(&peer_entry.name)
+            {
+                let peer_version = peer_entry.version.clone();
                 let Some(peer_entry) = Self::sanitize_peer_entry(peer.id, &peer_entry) else {
                     continue;
                 };
 
-                if let Some(mut local_entry) = self.get_entry(&name).await? {
+                if let Some(mut local_entry) = self.get_entry(&peer_entry.name).await? {
+                    if dir.mode.is_send_only()
+                        && let Some(cmp) = self
+                            .settle_send_only(&mut local_entry, &peer_entry, peer.id)
+                            .await?
+                    {
+                        if matches!(cmp, VersionCmp::KeepOther) {
+                            to_request.push(peer_entry);
+                        }
+                        continue;
+                    }
+
                     let cmp = self
                         .compare_and_resolve_conflict(&mut local_entry, &peer_entry, peer.id)
                         .await?;
+                    if local_entry.hash == peer_entry.hash {
+                        self.record_merge_base(&local_entry, &peer_version).await?;
+                    }
 
                     if matches!(cmp, VersionCmp::KeepOther) {
                         to_request.push(peer_entry);
                     }
-                } else {
+                } else if !peer_entry.is_removed() {
                     to_request.push(peer_entry);
                 }
             }
@@ -336,6 +521,21 @@ impl<P: PersistenceInterface> EntryManager<P> {
         Ok(to_request)
     }
 
+    /// Records the peer's acknowledgement of each tombstone left in
+    /// `tombstones` after its whole map went through
+    /// `get_entries_to_request` — the entry is either absent or removed
+    /// on its side too. Must not be called when the map was cut short.
+    pub async fn acknowledge_tombstones(
+        &self,
+        peer_id: Uuid,
+        tombstones: HashSet<RelativePath>,
+    ) -> io::Result<()> {
+        for name in tombstones {
+            self.acknowledge_tombstone(peer_id, &name).await?;
+        }
+        Ok(())
+    }
+
     /// Compares the local and peer copies of an entry and, if the
     /// result is `Conflict`, defers to `handle_conflict` to decide a
     /// winner (and possibly write a conflict file). When the local
@@ -367,10 +567,16 @@ impl<P: PersistenceInterface> EntryManager<P> {
     /// Resolves a true concurrent-edit conflict.
     ///
     /// Removal-vs-live takes a fixed tiebreak (the live side wins);
-    /// otherwise the lower `local_id` wins to give a deterministic,
-    /// peer-agnostic choice. If the local side must give way, the
-    /// existing file is copied to `<stem>_CONFLICT_<unix>_<id>.<ext>`
-    /// so no user data is lost before the peer's version is adopted.
+    /// otherwise the `ConflictPolicy` of the entry's sync directory
+    /// picks the winner, the same way on both peers. If the local side
+    /// must give way, the existing file is copied to
+    /// `<stem>_CONFLICT_<unix>_<id>.<ext>` so no user data is lost
+    /// before the peer's version is adopted. Under `KeepBoth` the copy
+    /// is left for the watcher to sync as a new file; otherwise the
+    /// conflict is recorded and announced as `ConflictDetected` for
+    /// the user to resolve with `resolve_conflict`. When the file has a
+    /// text merge base, the announcement waits for the peer's version,
+    /// which `merge_received` may merge instead.
     #[tracing::instrument(skip_all, fields(entry = %local_entry.name, peer = %peer_id))]
     pub async fn handle_conflict(
         &self,
@@ -396,7 +602,8 @@ impl<P: PersistenceInterface> EntryManager<P> {
             (false, false) => {}
         }
 
-        if self.state.local_id() < peer_id {
+        let policy = self.conflict_policy(&local_entry.get_sync_dir()).await;
+        if policy.local_wins(local_entry, peer_entry, self.state.local_id(), peer_id) {
             return Ok(VersionCmp::KeepSelf);
         }
 
@@ -422,449 +629,2597 @@ impl<P: PersistenceInterface> EntryManager<P> {
             ext
         ));
 
-        fs::copy(path, new_path).await?;
+        fs::copy(path, &new_path).await?;
+
+        if policy.keeps_both() {
+            info!("kept both versions");
+            return Ok(VersionCmp::KeepOther);
+        }
+
+        let conflict = ConflictRecord {
+            id: Uuid::new_v4(),
+            path: local_entry.name.clone(),
+            conflict_path: RelativePath::new(
+                &CanonicalPath::from_absolute(new_path),
+                self.state.home_path(),
+            )?,
+            peer: peer_id,
+            local_version: local_entry.version.clone(),
+            remote_version: peer_entry.version.clone(),
+            detected_at: now,
+        };
+        self.db.insert_conflict(&conflict).await?;
+
+        if self.merge_base_text(&conflict.path).await?.is_some() {
+            if let Some(stale) = self.take_pending_conflict(&conflict.path).await? {
+                let _ = self
+                    .state
+                    .sse_sender()
+                    .send(ServerEvent::ConflictDetected(stale));
+            }
+            self.db
+                .set_pending_merge(&conflict.path, &conflict.id)
+                .await?;
+        } else {
+            let _ = self
+                .state
+                .sse_sender()
+                .send(ServerEvent::ConflictDetected(conflict));
+        }
 
         Ok(VersionCmp::KeepOther)
     }
 
-    /// Reconciles a single inbound metadata message: drops it if the
-    /// path is excluded, requests/keeps based on
-    /// `compare_and_resolve_conflict` if the entry exists locally, or
-    /// declares the remote version the winner if we've never seen it.
-    pub async fn handle_metadata(
+    async fn conflict_policy(&self, sync_dir: &RelativePath) -> ConflictPolicy {
+        self.state
+            .sync_dirs
+            .read()
+            .await
+            .get(sync_dir)
+            .map(|dir| dir.conflict_policy)
+            .unwrap_or_default()
+    }
+
+    /// Takes in a peer's version of `entry`, once it is on disk and
+    /// stored, and returns the entry to announce. `peer_version` is the
+    /// version vector the peer sent, before it was sanitized.
+    ///
+    /// The version becomes the merge base of the entry. If it settles a
+    /// conflict `handle_conflict` left pending, and both the local
+    /// version in conflict and `peer_version` descend from the previous
+    /// merge base, the conflict copy is three-way merged into it from
+    /// that base. A clean merge replaces the file and the copy as a
+    /// local edit, whose bumped version is returned; only this side
+    /// merges, so the peer simply adopts it. Otherwise the conflict is
+    /// announced and stays open, and `entry` is returned.
+    pub async fn merge_received(
         &self,
-        peer_id: Uuid,
-        peer_entry: &EntryInfo,
-    ) -> io::Result<VersionCmp> {
-        if is_git_path(&peer_entry.name) {
-            return Ok(VersionCmp::KeepSelf);
+        entry: EntryInfo,
+        peer_version: &VersionVector,
+    ) -> io::Result<EntryInfo> {
+        let conflict = self.take_pending_conflict(&entry.name).await?;
+        let mut base = None;
+        if let Some(conflict) = &conflict
+            && let Some(merge_base) = self.db.get_merge_base(&entry.name).await?
+            && merge_base.is_ancestor_of(&conflict.local_version, peer_version)
+        {
+            base = self.merge_bases.get(&merge_base.hash).await?;
         }
+        self.record_merge_base(&entry, peer_version).await?;
 
-        let Some(peer_entry) = Self::sanitize_peer_entry(peer_id, peer_entry) else {
-            return Ok(VersionCmp::KeepSelf);
+        let Some(conflict) = conflict else {
+            return Ok(entry);
         };
 
-        match self.get_entry(&peer_entry.name).await? {
-            Some(mut local_entry) => {
-                self.compare_and_resolve_conflict(&mut local_entry, &peer_entry, peer_id)
-                    .await
-            }
-            None => Ok(VersionCmp::KeepOther),
+        if let Some(base) = base
+            && let Some(merged) = self.merge_conflict(&conflict, &base).await?
+        {
+            return Ok(merged);
         }
+
+        let _ = self
+            .state
+            .sse_sender()
+            .send(ServerEvent::ConflictDetected(conflict));
+        Ok(entry)
     }
 
-    fn sanitize_peer_entry(peer_id: Uuid, entry: &EntryInfo) -> Option<EntryInfo> {
-        let pv = entry.version.get(&peer_id).copied().unwrap_or(0);
-        if pv > MAX_TRUSTED_COUNTER {
-            warn!(
-                entry = %entry.name,
-                peer = %peer_id,
-                counter = pv,
-                "rejecting poisoned peer version counter"
-            );
-            return None;
-        }
+    #[tracing::instrument(skip_all, fields(entry = %conflict.path))]
+    async fn merge_conflict(
+        &self,
+        conflict: &ConflictRecord,
+        base: &str,
+    ) -> io::Result<Option<EntryInfo>> {
+        let path = conflict.path.to_canonical(self.state.home_path());
+        let copy = conflict.conflict_path.to_canonical(self.state.home_path());
+        let (Ok(theirs), Ok(ours)) = (fs::read(&path).await, fs::read(&copy).await) else {
+            return Ok(None);
+        };
+        let (Some(theirs), Some(ours)) = (as_text(&theirs), as_text(&ours)) else {
+            return Ok(None);
+        };
+        let Some(merged) = three_way_merge(base, ours, theirs) else {
+            return Ok(None);
+        };
+        let Some(entry) = self.get_entry(&conflict.path).await? else {
+            return Ok(None);
+        };
 
-        let mut sanitized = entry.clone();
-        sanitized.version = entry
-            .version
-            .get(&peer_id)
-            .map(|pv| HashMap::from([(peer_id, *pv)]))
-            .unwrap_or_default();
-        Some(sanitized)
+        fs::write(&path, &merged).await?;
+        fs::remove_file(&copy).await?;
+        self.db.delete_conflict(&conflict.id).await?;
+        info!("merged concurrent edits");
+
+        let merged = self
+            .entry_modified(entry, Some(hash_bytes(merged.as_bytes())))
+            .await?;
+        Ok(Some(merged))
     }
 
-    /// Merges the peer's version counter for **its own axis** into
-    /// the local copy and persists.
-    ///
-    /// Only `peer_entry.version[peer_id]` is trusted — foreign axes in
-    /// the inbound vector are ignored to prevent a buggy or hostile
-    /// peer from inflating another device's counter. A counter above
-    /// `MAX_TRUSTED_COUNTER` is treated as poisoned and the merge is
-    /// skipped (warn-and-drop) instead of erroring, so one bad entry
-    /// cannot tear down the connection.
-    pub async fn merge_versions_and_insert(
+    /// Records the contents of `entry`, which a peer holds too at
+    /// `peer_version`, as its merge base, at the version both sides
+    /// have seen. Only a live file whose contents on disk still match
+    /// its hash is recorded, and its contents are kept only if they are
+    /// text.
+    pub async fn record_merge_base(
         &self,
-        local_entry: &mut EntryInfo,
-        peer_entry: &EntryInfo,
-        peer_id: Uuid,
+        entry: &EntryInfo,
+        peer_version: &VersionVector,
     ) -> io::Result<()> {
-        local_entry.version.entry(peer_id).or_insert(0);
+        let Some(hash) = entry.hash.as_deref() else {
+            return Ok(());
+        };
+        if !entry.is_file() || entry.is_removed() {
+            return Ok(());
+        }
+        let previous = self.db.get_merge_base(&entry.name).await?;
+        let version: VersionVector = entry
+            .version
+            .iter()
+            .map(|(device, counter)| {
+                (
+                    *device,
+                    (*counter).min(*peer_version.get(device).unwrap_or(&0)),
+                )
+            })
+            .collect();
+        if previous
+            .as_ref()
+            .is_some_and(|base| base.hash == hash && base.version.as_ref() == Some(&version))
+        {
+            return Ok(());
+        }
 
-        if let Some(&pv) = peer_entry.version.get(&peer_id) {
-            if pv > MAX_TRUSTED_COUNTER {
-                warn!(
-                    entry = %local_entry.name,
-                    peer = %peer_id,
-                    counter = pv,
-                    "rejecting poisoned peer version counter"
-                );
+        let path = entry.name.to_canonical(self.state.home_path());
+        let Ok(metadata) = fs::metadata(&path).await else {
+            return Ok(());
+        };
+        if metadata.len() <= MAX_MERGE_BYTES {
+            let Ok(bytes) = fs::read(&path).await else {
+                return Ok(());
+            };
+            if hash_bytes(&bytes) != hash {
                 return Ok(());
             }
-            let local_version = local_entry.version.entry(peer_id).or_insert(0);
-            *local_version = (*local_version).max(pv);
+            if let Some(text) = as_text(&bytes) {
+                self.merge_bases.put(hash, text).await?;
+            }
         }
 
-        trace!(entry = %local_entry.name, peer = %peer_id, "merging versions");
-        self.db.insert_or_replace_entry(local_entry).await?;
+        self.db.set_merge_base(&entry.name, hash, &version).await?;
+        if let Some(previous) = previous
+            && previous.hash != hash
+        {
+            self.release_merge_base(&previous.hash).await?;
+        }
         Ok(())
     }
 
-    pub async fn remove_entry(&self, name: &str) -> io::Result<Option<EntryInfo>> {
-        if let Some(entry) = self.get_entry(name).await? {
-            let updated = self.delete_and_update_entry(entry).await?;
-            Ok(Some(updated))
-        } else {
-            Ok(None)
+    async fn merge_base_text(&self, name: &str) -> io::Result<Option<String>> {
+        match self.db.get_merge_base(name).await? {
+            Some(base) => self.merge_bases.get(&base.hash).await,
+            None => Ok(None),
         }
     }
 
-    pub async fn remove_dir(&self, removed: &str) -> io::Result<Vec<EntryInfo>> {
-        let mut removed_entries = Vec::new();
+    /// Forgets the conflict on `name` left pending by `handle_conflict`
+    /// and returns it, if it is still open.
+    async fn take_pending_conflict(&self, name: &str) -> io::Result<Option<ConflictRecord>> {
+        let Some(id) = self.db.take_pending_merge(name).await? else {
+            return Ok(None);
+        };
+        let conflict = self
+            .db
+            .list_conflicts()
+            .await?
+            .into_iter()
+            .find(|c| c.id == id);
+        Ok(conflict)
+    }
 
-        let entries = self.db.list_all_entries().await?;
-        let removed_path: RelativePath = removed.into();
-        for mut entry in entries {
-            if entry.name.starts_with_dir(&removed_path) {
-                entry = self.delete_and_update_entry(entry).await?;
-                removed_entries.push(entry);
-            }
+    /// Drops the contents of `hash` once no entry has it as its base.
+    async fn release_merge_base(&self, hash: &str) -> io::Result<()> {
+        if !self.db.is_merge_base(hash).await? {
+            self.merge_bases.remove(hash).await?;
         }
+        Ok(())
+    }
 
-        Ok(removed_entries)
+    /// Conflicts awaiting the user, oldest first.
+    pub async fn list_conflicts(&self) -> io::Result<Vec<ConflictRecord>> {
+        let conflicts = self.db.list_conflicts().await?;
+        Ok(conflicts)
     }
 
-    pub async fn delete_and_update_entry(&self, mut entry: EntryInfo) -> io::Result<EntryInfo> {
-        self.db.delete_entry(&entry.name).await?;
+    /// Settles the conflict `id` and returns the entries to announce
+    /// to peers, or `None` if no such conflict is open.
+    ///
+    /// `Local` moves the conflict copy back over the entry as a
+    /// local edit, whose bumped version supersedes the peer's.
+    /// `Both` starts syncing the copy as a new file. `Remote`
+    /// only deletes the copy, as the entry already holds the peer's
+    /// version. The first two fail with `NotFound` if the copy is gone,
+    /// and with `ReadOnlyFilesystem` in a receive-only directory.
+    pub async fn resolve_conflict(
+        &self,
+        id: Uuid,
+        resolution: ConflictResolution,
+    ) -> io::Result<Option<Vec<EntryInfo>>> {
+        let Some(conflict) = self
+            .db
+            .list_conflicts()
+            .await?
+            .into_iter()
+            .find(|c| c.id == id)
+        else {
+            return Ok(None);
+        };
 
-        bump_local_counter(&mut entry.version, self.state.local_id())?;
-        entry.set_removed_hash();
+        if !matches!(resolution, ConflictResolution::Remote) {
+            self.reject_receive_only(&conflict.path).await?;
+        }
 
-        Ok(entry)
-    }
+        let copy = conflict.conflict_path.to_canonical(self.state.home_path());
+        let announce = match resolution {
+            ConflictResolution::Local => {
+                let path = conflict.path.to_canonical(self.state.home_path());
+                fs::rename(&copy, &path).await?;
+
+                let hash = Some(compute_hash(&path).await?);
+                let entry = match self.get_entry(&conflict.path).await? {
+                    Some(entry) if !entry.is_removed() => self.entry_modified(entry, hash).await?,
+                    _ => {
+                        self.entry_created(&conflict.path, EntryKind::File, hash)
+                            .await?
+                    }
+                };
+                vec![entry]
+            }
+            ConflictResolution::Remote => {
+                if let Err(err) = fs::remove_file(&copy).await
+                    && err.kind() != io::ErrorKind::NotFound
+                {
+                    return Err(err);
+                }
+                vec![]
+            }
+            ConflictResolution::Both => {
+                let hash = Some(compute_hash(&copy).await?);
+                let entry = self
+                    .entry_created(&conflict.conflict_path, EntryKind::File, hash)
+                    .await?;
+                vec![entry]
+            }
+        };
 
-    pub async fn get_handshake_data(&self) -> io::Result<HandshakeData> {
-        let sync_dirs = self
+        self.db.delete_conflict(&id).await?;
+        let _ = self
             .state
-            .sync_dirs
-            .read()
-            .await
-            .values()
-            .cloned()
-            .collect::<Vec<_>>();
+            .sse_sender()
+            .send(ServerEvent::ConflictResolved { id, resolution });
 
-        let entries = self
-            .db
-            .list_all_entries()
-            .await?
-            .into_iter()
-            .filter(|f| !is_git_path(&f.name))
-            .map(|f| (f.name.clone(), f))
-            .collect::<HashMap<RelativePath, EntryInfo>>();
+        Ok(Some(announce))
+    }
 
-        Ok(HandshakeData {
-            sync_dirs,
-            entries,
-            instance_id: self.state.instance_id(),
-            hostname: self.state.hostname().clone(),
-        })
+    /// Kept versions of the file `name`, newest first, or `None` if it
+    /// is not in a configured sync directory.
+    pub async fn list_versions(&self, name: &RelativePath) -> io::Result<Option<Vec<FileVersion>>> {
+        if !self.is_versioned_path(name).await {
+            return Ok(None);
+        }
+        self.versions.list(name).await.map(Some)
     }
 
-    pub async fn insert_gitignore(&self, gitignore_path: &CanonicalPath) {
-        self.ignore_handler.insert_gitignore(gitignore_path).await;
+    /// Restores the version of `name` saved at `saved_at` as a local
+    /// edit, whose bumped version supersedes what peers hold, and
+    /// returns the entry to announce; `None` if no such version is
+    /// kept. The contents it replaces are kept as a version in turn.
+    /// Fails with `IsADirectory` if a directory is now at `name`, and
+    /// with `ReadOnlyFilesystem` in a receive-only directory.
+    pub async fn restore_version(
+        &self,
+        name: &RelativePath,
+        saved_at: u64,
+    ) -> io::Result<Option<EntryInfo>> {
+        if !self.is_versioned_path(name).await {
+            return Ok(None);
+        }
+        self.reject_receive_only(name).await?;
+        let path = name.to_canonical(self.state.home_path());
+        if path.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::IsADirectory,
+                format!("{name} is a directory"),
+            ));
+        }
+        if !self.versions.restore(name, saved_at).await? {
+            return Ok(None);
+        }
+
+        let hash = Some(compute_hash(&path).await?);
+        let entry = match self.get_entry(name).await? {
+            Some(entry) if !entry.is_removed() => self.entry_modified(entry, hash).await?,
+            _ => self.entry_created(name, EntryKind::File, hash).await?,
+        };
+        Ok(Some(entry))
     }
 
-    pub async fn remove_gitignore(&self, relative: &RelativePath) {
-        self.ignore_handler.remove_gitignore(relative).await;
+    async fn is_versioned_path(&self, name: &RelativePath) -> bool {
+        name.is_safe_sync_path()
+            && name.contains('/')
+            && !is_synche_path(name)
+            && self
+                .state
+                .sync_dirs
+                .read()
+                .await
+                .contains_key(&name.sync_dir())
     }
-}
 
-/// Increment the local axis of a version vector with overflow checking.
-///
-/// With foreign-axis poisoning prevented in `merge_versions_and_insert`,
-/// this counter only grows via honest local edits, so overflow is
-/// unreachable in practice. `checked_add` is a cheap defense-in-depth.
-fn bump_local_counter(
-    version: &mut crate::domain::VersionVector,
-    local_id: Uuid,
-) -> io::Result<()> {
-    let v = version.entry(local_id).or_insert(0);
-    *v = v
-        .checked_add(1)
-        .ok_or_else(|| io::Error::other("version counter overflow"))?;
-    Ok(())
-}
+    /// Moves what a peer's `tombstone` deleted into the trash of its
+    /// sync directory instead of removing it from disk, records it, and
+    /// announces it as `EntryTrashed`. Does nothing if the path is
+    /// already gone; a sync directory itself is left in place.
+    pub async fn trash_entry(&self, peer_id: Uuid, tombstone: &EntryInfo) -> io::Result<()> {
+        let path = tombstone.name.to_canonical(self.state.home_path());
+        let Ok(metadata) = fs::symlink_metadata(&path).await else {
+            return Ok(());
+        };
+        if !tombstone.name.contains('/') {
+            return Ok(());
+        }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::infra::persistence::sqlite::SqliteDb;
-    use std::fs;
-    use std::net::{IpAddr, Ipv4Addr};
+        let record = TrashRecord {
+            id: Uuid::new_v4(),
+            path: tombstone.name.clone(),
+            kind: if metadata.is_dir() {
+                EntryKind::Directory
+            } else {
+                EntryKind::File
+            },
+            peer: peer_id,
+            deleted_at: unix_now(),
+        };
+        self.trash.put(&record).await?;
+        self.db.insert_trash(&record).await?;
+        let _ = self
+            .state
+            .sse_sender()
+            .send(ServerEvent::EntryTrashed(record));
+
+        self.expire_trash().await
+    }
+
+    /// Entries in the trash, oldest first.
+    pub async fn list_trash(&self) -> io::Result<Vec<TrashRecord>> {
+        let trash = self.db.list_trash().await?;
+        Ok(trash)
+    }
+
+    /// Moves the trashed entry `id` back to its path and returns the
+    /// entries to announce to peers, or `None` if no such entry is in
+    /// the trash.
+    ///
+    /// The entry, and everything in it if it is a directory, is
+    /// recorded as created again, continuing the tombstone's version
+    /// so it supersedes the deletion on every peer. Fails with
+    /// `AlreadyExists` if something is at the path again, and with
+    /// `ReadOnlyFilesystem` in a receive-only directory.
+    pub async fn restore_trash(&self, id: Uuid) -> io::Result<Option<Vec<EntryInfo>>> {
+        let Some(record) = self.find_trash(id).await? else {
+            return Ok(None);
+        };
+        self.reject_receive_only(&record.path).await?;
+        self.trash.restore(&record).await?;
+        self.db.delete_trash(&id).await?;
+
+        let path = record.path.to_canonical(self.state.home_path());
+        let mut restored: Vec<(RelativePath, EntryKind, Option<String>)> = match record.kind {
+            EntryKind::File => vec![(
+                record.path,
+                EntryKind::File,
+                Some(compute_hash(&path).await?),
+            )],
+            EntryKind::Directory => self
+                .build_dir(path)
+                .await?
+                .into_values()
+                .map(|entry| (entry.name, entry.kind, entry.hash))
+                .collect(),
+        };
+        restored.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut announce = Vec::with_capacity(restored.len());
+        for (name, kind, hash) in restored {
+            announce.push(self.entry_created(&name, kind, hash).await?);
+        }
+        Ok(Some(announce))
+    }
+
+    /// Deletes the trashed entry `id` for good; `false` if no such entry
+    /// is in the trash.
+    pub async fn purge_trash(&self, id: Uuid) -> io::Result<bool> {
+        let Some(record) = self.find_trash(id).await? else {
+            return Ok(false);
+        };
+        self.trash.purge(&record).await?;
+        self.db.delete_trash(&id).await?;
+        Ok(true)
+    }
+
+    /// Purges the entries older than the trash expiry of their sync
+    /// directory. Entries of directories no longer synced are kept.
+    async fn expire_trash(&self) -> io::Result<()> {
+        let expiries: HashMap<RelativePath, TrashExpiry> = self
+            .state
+            .sync_dirs
+            .read()
+            .await
+            .iter()
+            .map(|(name, dir)| (name.clone(), dir.trash_expiry))
+            .collect();
+        let now = unix_now();
+
+        for record in self.db.list_trash().await? {
+            let expired = expiries
+                .get(&record.path.sync_dir())
+                .is_some_and(|expiry| expiry.is_expired(record.deleted_at, now));
+            if expired {
+                self.trash.purge(&record).await?;
+                self.db.delete_trash(&record.id).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn find_trash(&self, id: Uuid) -> io::Result<Option<TrashRecord>> {
+        let trash = self.db.list_trash().await?;
+        Ok(trash.into_iter().find(|record| record.id == id))
+    }
+
+    /// Snapshots the sync directory `dir` as `name`, copying the
+    /// contents of its files aside, or returns `None` if `dir` is not a
+    /// configured sync directory. Fails with `AlreadyExists` if `dir`
+    /// already has a snapshot named `name`.
+    pub async fn take_snapshot(
+        &self,
+        dir: &RelativePath,
+        name: &str,
+    ) -> io::Result<Option<Snapshot>> {
+        if !self.state.sync_dirs.read().await.contains_key(dir) {
+            return Ok(None);
+        }
+        if name.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "snapshot name is empty",
+            ));
+        }
+        let snapshots = self.db.list_snapshots().await?;
+        if snapshots.iter().any(|s| s.dir == *dir && s.name == name) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{dir} has a snapshot named {name}"),
+            ));
+        }
+
+        let snapshot = Snapshot {
+            id: Uuid::new_v4(),
+            name: name.to_owned(),
+            dir: dir.clone(),
+            taken_at: unix_now(),
+        };
+        let entries = self.live_entries_within(dir).await?;
+        if let Err(err) = self.save_snapshot(&snapshot, entries).await {
+            self.snapshots.delete(&snapshot).await?;
+            return Err(err);
+        }
+        Ok(Some(snapshot))
+    }
+
+    /// Snapshots taken, oldest first.
+    pub async fn list_snapshots(&self) -> io::Result<Vec<Snapshot>> {
+        let snapshots = self.db.list_snapshots().await?;
+        Ok(snapshots)
+    }
+
+    /// How the sync directory of snapshot `id` has changed since it was
+    /// taken, or `None` if there is no such snapshot.
+    pub async fn diff_snapshot(&self, id: Uuid) -> io::Result<Option<EntryDiff>> {
+        let Some(snapshot) = self.find_snapshot(id).await? else {
+            return Ok(None);
+        };
+        let taken = self.db.list_snapshot_entries(&id).await?;
+        let current = self.live_entries_within(&snapshot.dir).await?;
+        Ok(Some(EntryDiff::between(&taken, &current)))
+    }
+
+    /// Rolls the sync directory of snapshot `id` back to it and returns
+    /// the entries to announce to peers, or `None` if there is no such
+    /// snapshot or its directory is no longer synced.
+    ///
+    /// Only what the diff names is touched. Entries created since are
+    /// deleted, children first, and the rest restored, parents first;
+    /// each is recorded as an ordinary local edit, so peers take the
+    /// rollback like any other change. Files it replaces or deletes are
+    /// kept as versions, as the directory's `Versioning` allows. Fails
+    /// with `ReadOnlyFilesystem` in a receive-only directory.
+    pub async fn rollback_snapshot(&self, id: Uuid) -> io::Result<Option<Vec<EntryInfo>>> {
+        let Some(snapshot) = self.find_snapshot(id).await? else {
+            return Ok(None);
+        };
+        if !self
+            .state
+            .sync_dirs
+            .read()
+            .await
+            .contains_key(&snapshot.dir)
+        {
+            return Ok(None);
+        }
+        self.reject_receive_only(&snapshot.dir).await?;
+        let by_name = |entries: Vec<EntryInfo>| -> HashMap<RelativePath, EntryInfo> {
+            entries.into_iter().map(|e| (e.name.clone(), e)).collect()
+        };
+        let taken = by_name(self.db.list_snapshot_entries(&id).await?);
+        let current = by_name(self.live_entries_within(&snapshot.dir).await?);
+        let diff = EntryDiff::between(
+            &taken.values().cloned().collect::<Vec<_>>(),
+            &current.values().cloned().collect::<Vec<_>>(),
+        );
+        if diff.is_empty() {
+            return Ok(Some(Vec::new()));
+        }
+        let home = self.state.home_path();
+        let mut announce = Vec::new();
+
+        // Entries the snapshot lacks, or holds as the other kind.
+        let mut doomed: Vec<&EntryInfo> = diff
+            .added
+            .iter()
+            .chain(&diff.modified)
+            .filter_map(|name| current.get(name))
+            .filter(|entry| taken.get(&entry.name).is_none_or(|t| t.kind != entry.kind))
+            .collect();
+        doomed.sort_by(|a, b| b.name.cmp(&a.name));
+        for entry in doomed {
+            self.remove_from_disk(entry).await?;
+            announce.push(self.delete_and_update_entry(entry.clone()).await?);
+        }
+
+        let mut restore: Vec<&EntryInfo> = diff
+            .removed
+            .iter()
+            .chain(&diff.modified)
+            .filter_map(|name| taken.get(name))
+            .collect();
+        restore.sort_by(|a, b| a.name.cmp(&b.name));
+        for entry in restore {
+            let path = entry.name.to_canonical(home);
+            let hash = match entry.kind {
+                EntryKind::File => {
+                    self.versions.keep(&entry.name).await?;
+                    self.snapshots.restore(&snapshot, &entry.name).await?;
+                    Some(compute_hash(&path).await?)
+                }
+                EntryKind::Directory => {
+                    fs::create_dir_all(&path).await?;
+                    None
+                }
+            };
+            let restored = match self.get_entry(&entry.name).await? {
+                Some(live) if !live.is_removed() && live.kind == entry.kind => {
+                    self.entry_modified(live, hash).await?
+                }
+                _ => {
+                    self.entry_created(&entry.name, entry.kind.clone(), hash)
+                        .await?
+                }
+            };
+            announce.push(restored);
+        }
+        Ok(Some(announce))
+    }
+
+    /// Deletes snapshot `id` and the contents it kept; `false` if there
+    /// is no such snapshot.
+    pub async fn delete_snapshot(&self, id: Uuid) -> io::Result<bool> {
+        let Some(snapshot) = self.find_snapshot(id).await? else {
+            return Ok(false);
+        };
+        self.snapshots.delete(&snapshot).await?;
+        self.db.delete_snapshot(&id).await?;
+        Ok(true)
+    }
+
+    /// What changed on disk in the receive-only directory `dir` since
+    /// the cluster state the store holds for it, or `None` if `dir` is
+    /// not synced. Fails with `InvalidInput` if it is not receive-only.
+    /// Every file in the directory is hashed.
+    pub async fn local_changes(&self, dir: &RelativePath) -> io::Result<Option<EntryDiff>> {
+        let Some((cluster, disk)) = self.receive_only_entries(dir).await? else {
+            return Ok(None);
+        };
+        Ok(Some(EntryDiff::between(
+            &cluster.into_values().collect::<Vec<_>>(),
+            &disk.into_values().collect::<Vec<_>>(),
+        )))
+    }
+
+    /// Reverts the receive-only directory `dir` to the cluster state the
+    /// store holds for it and returns the files to fetch from peers, or
+    /// `None` if `dir` is not synced. Fails with `InvalidInput` if it is
+    /// not receive-only.
+    ///
+    /// Entries only the disk holds, or holds as the other kind, are
+    /// deleted, children first, and missing directories are recreated,
+    /// parents first. Files missing from disk or holding other contents
+    /// are left for the caller to request; a transfer replaces them
+    /// when it arrives. Files it deletes are kept as versions, as the
+    /// directory's `Versioning` allows.
+    pub async fn revert_local_changes(
+        &self,
+        dir: &RelativePath,
+    ) -> io::Result<Option<Vec<EntryInfo>>> {
+        let Some((cluster, disk)) = self.receive_only_entries(dir).await? else {
+            return Ok(None);
+        };
+        let diff = EntryDiff::between(
+            &cluster.values().cloned().collect::<Vec<_>>(),
+            &disk.values().cloned().collect::<Vec<_>>(),
+        );
+
+        let mut doomed: Vec<&EntryInfo> = diff
+            .added
+            .iter()
+            .chain(&diff.modified)
+            .filter_map(|name| disk.get(name))
+            .filter(|entry| {
+                cluster
+                    .get(&entry.name)
+                    .is_none_or(|c| c.kind != entry.kind)
+            })
+            .collect();
+        doomed.sort_by(|a, b| b.name.cmp(&a.name));
+        for entry in doomed {
+            self.remove_from_disk(entry).await?;
+        }
+
+        let mut missing: Vec<&EntryInfo> = diff
+            .removed
+            .iter()
+            .chain(&diff.modified)
+            .filter_map(|name| cluster.get(name))
+            .collect();
+        missing.sort_by(|a, b| a.name.cmp(&b.name));
+        let mut fetch = Vec::new();
+        for entry in missing {
+            match entry.kind {
+                EntryKind::File => fetch.push(entry.clone()),
+                EntryKind::Directory => {
+                    fs::create_dir_all(entry.name.to_canonical(self.state.home_path())).await?
+                }
+            }
+        }
+        Ok(Some(fetch))
+    }
+
+    /// Fails with `ReadOnlyFilesystem` if `name` is in a receive-only
+    /// directory, where a local edit must be neither recorded nor
+    /// announced.
+    async fn reject_receive_only(&self, name: &RelativePath) -> io::Result<()> {
+        let dir = name.sync_dir();
+        if self.state.directory_mode(&dir).await.is_receive_only() {
+            return Err(io::Error::new(
+                io::ErrorKind::ReadOnlyFilesystem,
+                format!("{dir} is receive-only"),
+            ));
+        }
+        Ok(())
+    }
+
+    /// The live entries the store holds below the receive-only
+    /// directory `dir`, and those on disk, each by name.
+    async fn receive_only_entries(
+        &self,
+        dir: &RelativePath,
+    ) -> io::Result<
+        Option<(
+            HashMap<RelativePath, EntryInfo>,
+            HashMap<RelativePath, EntryInfo>,
+        )>,
+    > {
+        match self.state.sync_dirs.read().await.get(dir) {
+            None => return Ok(None),
+            Some(sync_dir) if !sync_dir.mode.is_receive_only() => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{dir} is not receive-only"),
+                ));
+            }
+            Some(_) => {}
+        }
+
+        let cluster = self
+            .live_entries_within(dir)
+            .await?
+            .into_iter()
+            .map(|e| (e.name.clone(), e))
+            .collect();
+        let mut disk = self
+            .build_dir(dir.to_canonical(self.state.home_path()))
+            .await?;
+        disk.remove(dir);
+        Ok(Some((cluster, disk)))
+    }
+
+    /// Deletes `entry` from disk, keeping a file as a version first as
+    /// its directory's `Versioning` allows. Deleting a missing entry
+    /// does not error.
+    async fn remove_from_disk(&self, entry: &EntryInfo) -> io::Result<()> {
+        let path = entry.name.to_canonical(self.state.home_path());
+        let removed = match entry.kind {
+            EntryKind::File => {
+                self.versions.keep(&entry.name).await?;
+                fs::remove_file(&path).await
+            }
+            EntryKind::Directory => fs::remove_dir_all(&path).await,
+        };
+        match removed {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+
+    /// Copies the files of `entries` into `snapshot` and records it.
+    /// Each file's hash is taken from its copy, so the snapshot holds
+    /// what was copied even if the watcher has yet to catch up with an
+    /// edit; a file deleted meanwhile is left out.
+    async fn save_snapshot(&self, snapshot: &Snapshot, entries: Vec<EntryInfo>) -> io::Result<()> {
+        let mut saved = Vec::with_capacity(entries.len());
+        for mut entry in entries {
+            if entry.is_file() {
+                match self.snapshots.save(snapshot, &entry.name).await {
+                    Ok(copy) => entry.hash = Some(compute_hash(&copy).await?),
+                    Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+                    Err(err) => return Err(err),
+                }
+            }
+            saved.push(entry);
+        }
+        self.db.insert_snapshot(snapshot, &saved).await?;
+        Ok(())
+    }
+
+    async fn find_snapshot(&self, id: Uuid) -> io::Result<Option<Snapshot>> {
+        let snapshots = self.db.list_snapshots().await?;
+        Ok(snapshots.into_iter().find(|snapshot| snapshot.id == id))
+    }
+
+    /// Live entries below the sync directory `dir`, in name order,
+    /// leaving out `dir` itself and git and `.synche` paths.
+    async fn live_entries_within(&self, dir: &RelativePath) -> io::Result<Vec<EntryInfo>> {
+        let mut entries = Vec::new();
+        let mut after: Option<RelativePath> = None;
+
+        loop {
+            let page = self
+                .db
+                .list_entries_within(dir, after.as_deref(), HANDSHAKE_PAGE_ENTRIES)
+                .await?;
+            let last = page.len() < HANDSHAKE_PAGE_ENTRIES;
+            after = page.last().map(|e| e.name.clone());
+
+            entries.extend(page.into_iter().filter(|e| {
+                e.name != *dir
+                    && !e.is_removed()
+                    && !is_git_path(&e.name)
+                    && !is_synche_path(&e.name)
+            }));
+
+            if last {
+                return Ok(entries);
+            }
+        }
+    }
+
+    /// Reconciles a single inbound metadata message: drops it if the
+    /// path is excluded, requests/keeps based on
+    /// `compare_and_resolve_conflict` if the entry exists locally, or
+    /// declares the remote version the winner if we've never seen it —
+    /// unless it is a tombstone, which has nothing left to remove. A
+    /// tombstone matching our own counts as the peer's acknowledgement.
+    /// In a send-only directory, conflicts are never resolved; see
+    /// `settle_send_only`.
+    pub async fn handle_metadata(
+        &self,
+        peer_id: Uuid,
+        peer_entry: &EntryInfo,
+    ) -> io::Result<VersionCmp> {
+        if is_git_path(&peer_entry.name) {
+            return Ok(VersionCmp::KeepSelf);
+        }
+
+        let peer_version = &peer_entry.version;
+        let Some(peer_entry) = Self::sanitize_peer_entry(peer_id, peer_entry) else {
+            return Ok(VersionCmp::KeepSelf);
+        };
+
+        match self.get_entry(&peer_entry.name).await? {
+            Some(mut local_entry) => {
+                if self
+                    .state
+                    .directory_mode(&peer_entry.get_sync_dir())
+                    .await
+                    .is_send_only()
+                    && let Some(cmp) = self
+                        .settle_send_only(&mut local_entry, &peer_entry, peer_id)
+                        .await?
+                {
+                    return Ok(cmp);
+                }
+
+                let cmp = self
+                    .compare_and_resolve_conflict(&mut local_entry, &peer_entry, peer_id)
+                    .await?;
+                if local_entry.hash == peer_entry.hash {
+                    self.record_merge_base(&local_entry, peer_version).await?;
+                }
+
+                if local_entry.is_removed() && peer_entry.is_removed() {
+                    self.acknowledge_tombstone(peer_id, &local_entry.name)
+                        .await?;
+                }
+                Ok(cmp)
+            }
+            None if peer_entry.is_removed() => Ok(VersionCmp::KeepSelf),
+            None => Ok(VersionCmp::KeepOther),
+        }
+    }
+
+    /// Records a peer's dominating version of an entry in a send-only
+    /// directory without applying it: the peer's counter is merged into
+    /// ours and our own is bumped, so the local copy supersedes the
+    /// peer's. Returns the local entry to re-announce, or `None` if we
+    /// do not have the entry, which then stays on the peer's side.
+    pub async fn keep_local(
+        &self,
+        peer_id: Uuid,
+        peer_entry: &EntryInfo,
+    ) -> io::Result<Option<EntryInfo>> {
+        let Some(peer_entry) = Self::sanitize_peer_entry(peer_id, peer_entry) else {
+            return Ok(None);
+        };
+        let Some(mut local_entry) = self.get_entry(&peer_entry.name).await? else {
+            return Ok(None);
+        };
+
+        let pv = peer_entry.version.get(&peer_id).copied().unwrap_or(0);
+        let counter = local_entry.version.entry(peer_id).or_insert(0);
+        *counter = (*counter).max(pv);
+
+        if local_entry.is_removed() {
+            return Ok(Some(self.delete_and_update_entry(local_entry).await?));
+        }
+        bump_local_counter(&mut local_entry.version, self.state.local_id())?;
+        self.store_entry(&local_entry).await?;
+        Ok(Some(local_entry))
+    }
+
+    /// Compares a peer's version against ours in a send-only directory,
+    /// where the local copy never gives way. One that dominates ours
+    /// yields `KeepOther`, for the caller to hand to `keep_local`. A
+    /// concurrent one only has its counter merged and yields
+    /// `KeepSelf`: superseding it as well would have two send-only
+    /// peers with different copies re-announcing to each other forever.
+    /// Returns `None` when ours already wins or matches.
+    async fn settle_send_only(
+        &self,
+        local_entry: &mut EntryInfo,
+        peer_entry: &EntryInfo,
+        peer_id: Uuid,
+    ) -> io::Result<Option<VersionCmp>> {
+        match local_entry.compare(peer_entry) {
+            VersionCmp::KeepOther => Ok(Some(VersionCmp::KeepOther)),
+            VersionCmp::Conflict => {
+                self.merge_versions_and_insert(local_entry, peer_entry, peer_id)
+                    .await?;
+                Ok(Some(VersionCmp::KeepSelf))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn sanitize_peer_entry(peer_id: Uuid, entry: &EntryInfo) -> Option<EntryInfo> {
+        let pv = entry.version.get(&peer_id).copied().unwrap_or(0);
+        if pv > MAX_TRUSTED_COUNTER {
+            warn!(
+                entry = %entry.name,
+                peer = %peer_id,
+                counter = pv,
+                "rejecting poisoned peer version counter"
+            );
+            return None;
+        }
+
+        let mut sanitized = entry.clone();
+        if !sanitized.is_removed() {
+            sanitized.removed_at = None;
+        }
+        sanitized.version = entry
+            .version
+            .get(&peer_id)
+            .map(|pv| HashMap::from([(peer_id, *pv)]))
+            .unwrap_or_default();
+        Some(sanitized)
+    }
+
+    /// Merges the peer's version counter for **its own axis** into
+    /// the local copy and persists.
+    ///
+    /// Only `peer_entry.version[peer_id]` is trusted — foreign axes in
+    /// the inbound vector are ignored to prevent a buggy or hostile
+    /// peer from inflating another device's counter. A counter above
+    /// `MAX_TRUSTED_COUNTER` is treated as poisoned and the merge is
+    /// skipped (warn-and-drop) instead of erroring, so one bad entry
+    /// cannot tear down the connection.
+    pub async fn merge_versions_and_insert(
+        &self,
+        local_entry: &mut EntryInfo,
+        peer_entry: &EntryInfo,
+        peer_id: Uuid,
+    ) -> io::Result<()> {
+        local_entry.version.entry(peer_id).or_insert(0);
+
+        if let Some(&pv) = peer_entry.version.get(&peer_id) {
+            if pv > MAX_TRUSTED_COUNTER {
+                warn!(
+                    entry = %local_entry.name,
+                    peer = %peer_id,
+                    counter = pv,
+                    "rejecting poisoned peer version counter"
+                );
+                return Ok(());
+            }
+            let local_version = local_entry.version.entry(peer_id).or_insert(0);
+            *local_version = (*local_version).max(pv);
+        }
+
+        trace!(entry = %local_entry.name, peer = %peer_id, "merging versions");
+        self.store_entry(local_entry).await?;
+        Ok(())
+    }
+
+    /// Tombstones a live entry. Returns `None` if the entry is unknown
+    /// or already removed, so a deletion we applied on behalf of a peer
+    /// is not re-announced when the watcher reports it.
+    pub async fn remove_entry(&self, name: &str) -> io::Result<Option<EntryInfo>> {
+        match self.get_entry(name).await? {
+            Some(entry) if !entry.is_removed() => {
+                let updated = self.delete_and_update_entry(entry).await?;
+                Ok(Some(updated))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    pub async fn remove_dir(&self, removed: &str) -> io::Result<Vec<EntryInfo>> {
+        let mut removed_entries = Vec::new();
+
+        let entries = self.db.list_all_entries().await?;
+        let removed_path: RelativePath = removed.into();
+        for mut entry in entries {
+            if entry.name.starts_with_dir(&removed_path) && !entry.is_removed() {
+                entry = self.delete_and_update_entry(entry).await?;
+                removed_entries.push(entry);
+            }
+        }
+
+        Ok(removed_entries)
+    }
+
+    /// Turns `entry` into a tombstone: bumps the local counter, stamps
+    /// `REMOVED_HASH` and the removal time as `removed_at`, and
+    /// persists it with no acknowledgements yet.
+    pub async fn delete_and_update_entry(&self, mut entry: EntryInfo) -> io::Result<EntryInfo> {
+        bump_local_counter(&mut entry.version, self.state.local_id())?;
+        entry.set_removed_hash();
+        entry.removed_at = Some(unix_now());
+
+        self.store_entry(&entry).await?;
+        self.db.delete_tombstone_acks(&entry.name).await?;
+        self.collect_tombstone(&entry.name).await?;
+
+        Ok(entry)
+    }
+
+    /// Adopts a tombstone that won against our copy of the entry. The
+    /// sender necessarily holds it, so it is the first acknowledgement.
+    /// Its age counts from the `removed_at` the removing device set, or
+    /// from now for a tombstone from a build that predates it. Returns
+    /// `None` if we never had the entry or the tombstone was dropped by
+    /// sanitization.
+    pub async fn apply_peer_tombstone(
+        &self,
+        peer_id: Uuid,
+        mut entry: EntryInfo,
+    ) -> io::Result<Option<EntryInfo>> {
+        if self.get_entry(&entry.name).await?.is_none() {
+            return Ok(None);
+        }
+        entry.removed_at.get_or_insert_with(unix_now);
+
+        let Some(tombstone) = self.insert_peer_entry(peer_id, entry).await? else {
+            return Ok(None);
+        };
+
+        self.db.delete_tombstone_acks(&tombstone.name).await?;
+        self.db
+            .insert_tombstone_ack(&tombstone.name, &peer_id)
+            .await?;
+        self.collect_tombstone(&tombstone.name).await?;
+
+        Ok(Some(tombstone))
+    }
+
+    /// Records that `peer_id` holds the tombstone for `name`. Ignored if
+    /// our own entry is live or already collected.
+    pub async fn acknowledge_tombstone(&self, peer_id: Uuid, name: &str) -> io::Result<()> {
+        match self.get_entry(name).await? {
+            Some(entry) if entry.is_removed() => {
+                self.db.insert_tombstone_ack(name, &peer_id).await?;
+                self.collect_tombstone(name).await
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Deletes the tombstone for `name` once every trusted device has
+    /// acknowledged it, so no peer is left that could resurrect it, and
+    /// `TOMBSTONE_MIN_AGE_SECS` have passed since its removal, so a
+    /// device paired later still learns of it. A tombstone from a build
+    /// that predates `removed_at` only waits for the acknowledgements.
+    async fn collect_tombstone(&self, name: &str) -> io::Result<()> {
+        let Some(tombstone) = self.get_entry(name).await? else {
+            return Ok(());
+        };
+        let old_enough = tombstone
+            .removed_at
+            .unwrap_or(0)
+            .saturating_add(TOMBSTONE_MIN_AGE_SECS)
+            <= unix_now();
+        if !old_enough {
+            return Ok(());
+        }
+
+        let acks = self.db.list_tombstone_acks(name).await?;
+        let trusted = self.db.list_trusted_devices().await?;
+        if trusted.iter().all(|id| acks.contains(id)) {
+            trace!(entry = %name, "collecting tombstone");
+            self.forget_entry(name).await?;
+        }
+        Ok(())
+    }
+
+    async fn forget_entry(&self, name: &str) -> io::Result<()> {
+        {
+            let mut tree = self.state.digest_tree.write().await;
+            if let Some(old) = self.db.get_entry(name).await? {
+                self.db.delete_entry(name).await?;
+                if !is_git_path(&old.name) {
+                    tree.remove(&old);
+                }
+            }
+        }
+        self.db.delete_tombstone_acks(name).await?;
+
+        if let Some(base) = self.db.get_merge_base(name).await? {
+            self.db.delete_merge_base(name).await?;
+            self.release_merge_base(&base.hash).await?;
+        }
+        self.db.take_pending_merge(name).await?;
+        Ok(())
+    }
+
+    /// Persists `entry` and folds it into the digest tree in place of
+    /// the row it replaces. The tree stays locked from reading that row
+    /// until the write is done, so two writes to one name cannot both
+    /// fold the same old row out.
+    async fn store_entry(&self, entry: &EntryInfo) -> io::Result<()> {
+        let mut tree = self.state.digest_tree.write().await;
+        let old = self.db.get_entry(&entry.name).await?;
+        self.db.insert_or_replace_entry(entry).await?;
+
+        if !is_git_path(&entry.name) {
+            if let Some(old) = old {
+                tree.remove(&old);
+            }
+            tree.insert(entry);
+        }
+        Ok(())
+    }
+
+    /// Rebuilds the digest tree from the store, page by page.
+    async fn load_digest_tree(&self) -> io::Result<()> {
+        let mut tree = self.state.digest_tree.write().await;
+        *tree = DigestTree::default();
+        let mut after: Option<RelativePath> = None;
+
+        loop {
+            let page = self
+                .db
+                .list_entries_after(after.as_deref(), HANDSHAKE_PAGE_ENTRIES)
+                .await?;
+            let last = page.len() < HANDSHAKE_PAGE_ENTRIES;
+            after = page.last().map(|e| e.name.clone());
+
+            for entry in page.iter().filter(|e| !is_git_path(&e.name)) {
+                tree.insert(entry);
+            }
+
+            if last {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Identity, sync directories and protocol for a handshake. The
+    /// entry map is not loaded here; the transport pages through it
+    /// while sending.
+    pub async fn get_handshake_data(self: &Arc<Self>) -> io::Result<HandshakeData> {
+        let sync_dirs = self
+            .state
+            .sync_dirs
+            .read()
+            .await
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+
+        Ok(HandshakeData {
+            sync_dirs,
+            entries: HandshakeEntries::from_pager(self.clone()),
+            instance_id: self.state.instance_id(),
+            transport_port: Some(self.state.ports().transport),
+            cluster_proof: self.state.cluster().proof(self.state.local_id()),
+            hostname: self.state.hostname().clone(),
+            protocol: ProtocolInfo::local(),
+        })
+    }
+
+    /// Persists `id` as an approved device so the pairing survives a
+    /// restart. The in-memory trusted set is `PeerManager`'s concern.
+    pub async fn trust_device(&self, id: Uuid) -> io::Result<()> {
+        self.db.insert_trusted_device(&id).await?;
+        Ok(())
+    }
+
+    pub async fn list_trusted_devices(&self) -> io::Result<Vec<Uuid>> {
+        let ids = self.db.list_trusted_devices().await?;
+        Ok(ids)
+    }
+
+    pub async fn insert_gitignore(&self, gitignore_path: &CanonicalPath) {
+        self.ignore_handler.insert_gitignore(gitignore_path).await;
+    }
+
+    pub async fn remove_gitignore(&self, relative: &RelativePath) {
+        self.ignore_handler.remove_gitignore(relative).await;
+    }
+}
+
+/// Serves the local entry map to outbound handshakes, skipping git
+/// paths. A page may come out short, or even empty, once those and
+/// anything else outside the scope are dropped; `next` still points
+/// past the rows that were read.
+#[async_trait::async_trait]
+impl<P: PersistenceInterface> EntryPager for EntryManager<P> {
+    async fn page(&self, scope: &EntryScope, after: Option<RelativePath>) -> io::Result<EntryPage> {
+        let page = match scope {
+            EntryScope::Entry(name) => {
+                let entry = self.get_entry(name).await?;
+                return Ok(EntryPage {
+                    entries: entry
+                        .into_iter()
+                        .filter(|e| !is_git_path(&e.name))
+                        .collect(),
+                    next: None,
+                });
+            }
+            EntryScope::Files(dir) | EntryScope::Subtree(dir) if !dir.is_empty() => {
+                self.db
+                    .list_entries_within(dir, after.as_deref(), HANDSHAKE_PAGE_ENTRIES)
+                    .await?
+            }
+            _ => {
+                self.db
+                    .list_entries_after(after.as_deref(), HANDSHAKE_PAGE_ENTRIES)
+                    .await?
+            }
+        };
+
+        let next = if page.len() < HANDSHAKE_PAGE_ENTRIES {
+            None
+        } else {
+            page.last().map(|e| e.name.clone())
+        };
+
+        Ok(EntryPage {
+            entries: page
+                .into_iter()
+                .filter(|e| !is_git_path(&e.name) && scope.contains(e))
+                .collect(),
+            next,
+        })
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Increment the local axis of a version vector with overflow checking.
+///
+/// With foreign-axis poisoning prevented in `merge_versions_and_insert`,
+/// this counter only grows via honest local edits, so overflow is
+/// unreachable in practice. `checked_add` is a cheap defense-in-depth.
+fn bump_local_counter(
+    version: &mut crate::domain::VersionVector,
+    local_id: Uuid,
+) -> io::Result<()> {
+    let v = version.entry(local_id).or_insert(0);
+    *v = v
+        .checked_add(1)
+        .ok_or_else(|| io::Error::other("version counter overflow"))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{domain::DirectoryMode, infra::persistence::sqlite::SqliteDb};
+    use std::fs;
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
     use tempfile::TempDir;
     use uuid::Uuid;
 
-    async fn setup() -> (
-        crate::utils::test_support::TestEnv,
-        TempDir,
-        CanonicalPath,
-        Arc<EntryManager<SqliteDb>>,
-    ) {
-        let env = crate::utils::test_support::test_env().await;
-        // Create a sub-dir inside the test's isolated home so the test gets
-        // a sync-dir scaffold that does not collide with other tests.
-        let temp_dir = TempDir::new_in(env.home_path()).unwrap();
-        let sync_dir = CanonicalPath::from_absolute(temp_dir.path());
+    async fn setup() -> (
+        crate::utils::test_support::TestEnv,
+        TempDir,
+        CanonicalPath,
+        Arc<EntryManager<SqliteDb>>,
+    ) {
+        let env = crate::utils::test_support::test_env().await;
+        // Create a sub-dir inside the test's isolated home so the test gets
+        // a sync-dir scaffold that does not collide with other tests.
+        let temp_dir = TempDir::new_in(env.home_path()).unwrap();
+        let sync_dir = CanonicalPath::from_absolute(temp_dir.path());
+
+        let db = SqliteDb::new(":memory:").await.unwrap();
+        let manager = EntryManager::new(db, env.state.clone());
+
+        (env, temp_dir, sync_dir, manager)
+    }
+
+    async fn add_sync_dir(
+        manager: &Arc<EntryManager<SqliteDb>>,
+        sync_dir: &CanonicalPath,
+    ) -> RelativePath {
+        let relative = RelativePath::new(sync_dir, manager.state.home_path()).unwrap();
+        manager
+            .state
+            .sync_dirs
+            .write()
+            .await
+            .insert(relative.clone(), SyncDirectory::new(relative.clone()));
+        relative
+    }
+
+    async fn set_conflict_policy(
+        manager: &Arc<EntryManager<SqliteDb>>,
+        sync_root: &RelativePath,
+        conflict_policy: ConflictPolicy,
+    ) -> bool {
+        let dir = SyncDirectory {
+            conflict_policy,
+            ..SyncDirectory::new(sync_root.clone())
+        };
+        manager.update_sync_dir(dir).await
+    }
+
+    async fn set_mode(
+        manager: &Arc<EntryManager<SqliteDb>>,
+        sync_root: &RelativePath,
+        mode: DirectoryMode,
+    ) -> bool {
+        let dir = SyncDirectory {
+            mode,
+            ..SyncDirectory::new(sync_root.clone())
+        };
+        manager.update_sync_dir(dir).await
+    }
+
+    async fn handshake_entries(
+        manager: &Arc<EntryManager<SqliteDb>>,
+    ) -> HashMap<RelativePath, EntryInfo> {
+        let mut data = manager.get_handshake_data().await.unwrap();
+        let mut entries = HashMap::new();
+        while let Some(page) = data.entries.next_page().await {
+            entries.extend(page.unwrap().into_iter().map(|e| (e.name.clone(), e)));
+        }
+        entries
+    }
+
+    fn entry(name: RelativePath, hash: Option<&str>, peer_id: Uuid) -> EntryInfo {
+        EntryInfo {
+            name,
+            kind: EntryKind::File,
+            hash: hash.map(str::to_string),
+            version: HashMap::from([(peer_id, 1)]),
+            modified: None,
+            removed_at: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn build_dir_excludes_git_directory() {
+        let (_env, _temp_dir, sync_dir, manager) = setup().await;
+
+        fs::write(sync_dir.join("notes.txt"), "hello").unwrap();
+        fs::write(sync_dir.join(".gitignore"), "*.log").unwrap();
+        fs::write(sync_dir.join(".gitattributes"), "* text=auto").unwrap();
+
+        fs::create_dir_all(sync_dir.join(".git/objects/ab")).unwrap();
+        fs::write(sync_dir.join(".git/HEAD"), "ref: refs/heads/main").unwrap();
+        fs::write(sync_dir.join(".git/config"), "[core]").unwrap();
+        fs::write(sync_dir.join(".git/objects/ab/cdef"), "obj").unwrap();
+
+        let entries = manager.build_dir(sync_dir).await.unwrap();
+
+        let names: Vec<&str> = entries.keys().map(|p| p.as_ref()).collect();
+
+        assert!(names.iter().any(|n| n.ends_with("/notes.txt")));
+        assert!(names.iter().any(|n| n.ends_with("/.gitignore")));
+        assert!(names.iter().any(|n| n.ends_with("/.gitattributes")));
+
+        for name in &names {
+            assert!(
+                !name.contains("/.git/") && !name.ends_with("/.git"),
+                "unexpected .git entry: {name}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn get_entries_to_request_ignores_git_peer_entries() {
+        let (_env, _temp_dir, sync_dir, manager) = setup().await;
+        let sync_root = add_sync_dir(&manager, &sync_dir).await;
+        let peer_id = Uuid::new_v4();
+        let peer = Peer::new(
+            peer_id,
+            SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 42882),
+            "peer".to_string(),
+            Uuid::new_v4(),
+            vec![SyncDirectory::new(sync_root.clone())],
+        );
+
+        let git_name: RelativePath = format!("{}/.git/config", &*sync_root).into();
+        let normal_name: RelativePath = format!("{}/notes.txt", &*sync_root).into();
+        let git_entry = entry(git_name.clone(), Some("git-hash"), peer_id);
+        let normal_entry = entry(normal_name.clone(), Some("notes-hash"), peer_id);
+        let peer_entries = vec![git_entry, normal_entry];
+
+        let entries = manager
+            .get_entries_to_request(&peer, peer_entries, &mut HashSet::new())
+            .await
+            .unwrap();
+
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].name, normal_name);
+    }
+
+    #[tokio::test]
+    async fn get_entries_to_request_ignores_foreign_axes_before_compare() {
+        let (_env, _temp_dir, sync_dir, manager) = setup().await;
+        let sync_root = add_sync_dir(&manager, &sync_dir).await;
+        let local_id = manager.state.local_id();
+        let peer_id = Uuid::new_v4();
+        let peer = Peer::new(
+            peer_id,
+            SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 42882),
+            "peer".to_string(),
+            Uuid::new_v4(),
+            vec![SyncDirectory::new(sync_root.clone())],
+        );
+        let name = dir_relative(&sync_root, "notes.txt");
+
+        manager
+            .insert_entry(EntryInfo {
+                name: name.clone(),
+                kind: EntryKind::File,
+                hash: Some("local-hash".into()),
+                version: HashMap::from([(local_id, 2), (peer_id, 1)]),
+                modified: None,
+                removed_at: None,
+            })
+            .await
+            .unwrap();
+
+        let peer_entry = EntryInfo {
+            name: name.clone(),
+            kind: EntryKind::File,
+            hash: Some("peer-hash".into()),
+            version: HashMap::from([(local_id, 99), (peer_id, 1)]),
+            modified: None,
+            removed_at: None,
+        };
+
+        let entries = manager
+            .get_entries_to_request(&peer, vec![peer_entry], &mut HashSet::new())
+            .await
+            .unwrap();
+
+        assert!(
+            entries.is_empty(),
+            "foreign local-axis claim must not make peer entry win"
+        );
+    }
+
+    #[tokio::test]
+    async fn handle_metadata_ignores_git_peer_entries() {
+        let (_env, _temp_dir, sync_dir, manager) = setup().await;
+        let sync_root = add_sync_dir(&manager, &sync_dir).await;
+        let peer_id = Uuid::new_v4();
+        let git_name: RelativePath = format!("{}/.git/config", &*sync_root).into();
+        let peer_entry = entry(git_name.clone(), Some("git-hash"), peer_id);
+
+        let cmp = manager.handle_metadata(peer_id, &peer_entry).await.unwrap();
+
+        assert!(matches!(cmp, VersionCmp::KeepSelf));
+        assert!(manager.get_entry(&git_name).await.unwrap().is_none());
+    }
+
+    /// A directory peer-vs-self conflict can't be materialized as a file,
+    /// so it falls through to the peer-wins branch. Use files so we can
+    /// also assert the on-disk conflict-file side effect.
+    fn dir_relative(sync_root: &RelativePath, leaf: &str) -> RelativePath {
+        format!("{}/{}", &**sync_root, leaf).into()
+    }
+
+    #[tokio::test]
+    async fn entry_modified_bumps_local_version_counter() {
+        let (_env, _temp_dir, sync_dir, manager) = setup().await;
+        let sync_root = add_sync_dir(&manager, &sync_dir).await;
+        let local_id = manager.state.local_id();
+
+        let initial = manager
+            .insert_entry(EntryInfo {
+                name: dir_relative(&sync_root, "file.txt"),
+                kind: EntryKind::File,
+                hash: Some("v1".into()),
+                version: HashMap::from([(local_id, 3)]),
+                modified: None,
+                removed_at: None,
+            })
+            .await
+            .unwrap();
+
+        let bumped = manager
+            .entry_modified(initial, Some("v2".into()))
+            .await
+            .unwrap();
+
+        assert_eq!(bumped.version.get(&local_id), Some(&4));
+        assert_eq!(bumped.hash.as_deref(), Some("v2"));
+    }
+
+    #[tokio::test]
+    async fn compare_and_resolve_conflict_keeps_self_when_local_id_lower_than_peer() {
+        let (_env, _temp_dir, sync_dir, manager) = setup().await;
+        let sync_root = add_sync_dir(&manager, &sync_dir).await;
+        let local_id = manager.state.local_id();
+        // Force peer_id > local_id so the deterministic tiebreak keeps self.
+        let peer_id = Uuid::from_u128(u128::MAX);
+
+        let name = dir_relative(&sync_root, "doc.txt");
+        let mut local = EntryInfo {
+            name: name.clone(),
+            kind: EntryKind::File,
+            hash: Some("local-hash".into()),
+            version: HashMap::from([(local_id, 1)]),
+            modified: None,
+            removed_at: None,
+        };
+        let peer = EntryInfo {
+            name,
+            kind: EntryKind::File,
+            hash: Some("peer-hash".into()),
+            version: HashMap::from([(peer_id, 1)]),
+            modified: None,
+            removed_at: None,
+        };
+
+        let cmp = manager
+            .compare_and_resolve_conflict(&mut local, &peer, peer_id)
+            .await
+            .unwrap();
+
+        assert!(matches!(cmp, VersionCmp::KeepSelf));
+    }
+
+    #[tokio::test]
+    async fn handle_conflict_writes_conflict_file_when_local_id_higher_and_file_exists() {
+        let (_env, _temp_dir, sync_dir, manager) = setup().await;
+        let sync_root = add_sync_dir(&manager, &sync_dir).await;
+        // peer_id < any real Uuid::new_v4 so local must give way.
+        let peer_id = Uuid::nil();
+
+        let rel: RelativePath = dir_relative(&sync_root, "report.md");
+        let absolute = rel.to_canonical(manager.state.home_path());
+        fs::write(&absolute, b"local contents").unwrap();
+
+        let mut local = EntryInfo {
+            name: rel.clone(),
+            kind: EntryKind::File,
+            hash: Some("local-hash".into()),
+            version: HashMap::from([(manager.state.local_id(), 1)]),
+            modified: None,
+            removed_at: None,
+        };
+        let peer = EntryInfo {
+            name: rel,
+            kind: EntryKind::File,
+            hash: Some("peer-hash".into()),
+            version: HashMap::from([(peer_id, 1)]),
+            modified: None,
+            removed_at: None,
+        };
+
+        let cmp = manager
+            .handle_conflict(&mut local, &peer, peer_id)
+            .await
+            .unwrap();
+
+        assert!(matches!(cmp, VersionCmp::KeepOther));
+
+        let siblings: Vec<String> = fs::read_dir(&sync_dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        assert!(
+            siblings.iter().any(|n| n.contains("_CONFLICT_")),
+            "expected a _CONFLICT_ file in {sync_dir:?}, found: {siblings:?}",
+        );
+    }
+
+    #[tokio::test]
+    async fn handle_conflict_follows_the_conflict_policy_of_the_sync_dir() {
+        let (_env, _temp_dir, sync_dir, manager) = setup().await;
+        let sync_root = add_sync_dir(&manager, &sync_dir).await;
+        // The nil id is the lowest, so by default the local side loses.
+        let (local_id, peer_id) = (manager.state.local_id(), Uuid::nil());
+
+        let rel = dir_relative(&sync_root, "notes.md");
+        fs::write(rel.to_canonical(manager.state.home_path()), b"local").unwrap();
+        let mut local = entry(rel.clone(), Some("local-hash"), local_id);
+        local.modified = Some(200);
+        let mut peer = entry(rel, Some("peer-hash"), peer_id);
+        peer.modified = Some(100);
+
+        let mut outcome = async |policy| {
+            assert!(set_conflict_policy(&manager, &sync_root, policy).await);
+            manager
+                .handle_conflict(&mut local, &peer, peer_id)
+                .await
+                .unwrap()
+        };
+        assert!(matches!(
+            outcome(ConflictPolicy::Newest).await,
+            VersionCmp::KeepSelf
+        ));
+        assert!(matches!(
+            outcome(ConflictPolicy::Primary(local_id)).await,
+            VersionCmp::KeepSelf
+        ));
+        assert!(matches!(
+            outcome(ConflictPolicy::Primary(Uuid::new_v4())).await,
+            VersionCmp::KeepOther
+        ));
+    }
+
+    #[tokio::test]
+    async fn handle_conflict_keep_both_leaves_the_copy_to_sync_as_a_new_file() {
+        let (_env, _temp_dir, sync_dir, manager) = setup().await;
+        let sync_root = add_sync_dir(&manager, &sync_dir).await;
+        set_conflict_policy(&manager, &sync_root, ConflictPolicy::KeepBoth).await;
+        let peer_id = Uuid::nil();
+
+        let rel = dir_relative(&sync_root, "photo.jpg");
+        fs::write(rel.to_canonical(manager.state.home_path()), b"local").unwrap();
+        let mut local = entry(rel.clone(), Some("local-hash"), manager.state.local_id());
+        let peer = entry(rel, Some("peer-hash"), peer_id);
+
+        let cmp = manager
+            .handle_conflict(&mut local, &peer, peer_id)
+            .await
+            .unwrap();
+
+        assert!(matches!(cmp, VersionCmp::KeepOther));
+        assert!(manager.list_conflicts().await.unwrap().is_empty());
+        let copy = fs::read_dir(&sync_dir)
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .find(|path| path.to_string_lossy().contains("_CONFLICT_"))
+            .unwrap();
+        let copy = CanonicalPath::from_absolute(copy);
+        let relative = RelativePath::new(&copy, manager.state.home_path()).unwrap();
+        assert!(!manager.is_ignored(&copy, &relative).await);
+        assert_eq!(fs::read(&copy).unwrap(), b"local");
+    }
+
+    /// Gives way on a conflict over `<sync_root>/<leaf>` to a peer
+    /// whose version is then adopted, as its transfer would, holding
+    /// `remote contents`.
+    async fn lose_conflict(
+        manager: &Arc<EntryManager<SqliteDb>>,
+        sync_root: &RelativePath,
+        leaf: &str,
+    ) -> ConflictRecord {
+        let peer_id = Uuid::nil();
+        let local_id = manager.state.local_id();
+        let name = dir_relative(sync_root, leaf);
+        let absolute = name.to_canonical(manager.state.home_path());
+        fs::write(&absolute, b"local contents").unwrap();
+
+        let mut local = entry(name.clone(), Some("local-hash"), local_id);
+        let peer = entry(name.clone(), Some("peer-hash"), peer_id);
+        manager
+            .handle_conflict(&mut local, &peer, peer_id)
+            .await
+            .unwrap();
+
+        fs::write(&absolute, b"remote contents").unwrap();
+        let adopted = EntryInfo {
+            version: HashMap::from([(local_id, 1), (peer_id, 1)]),
+            ..peer
+        };
+        manager.store_entry(&adopted).await.unwrap();
+
+        manager.list_conflicts().await.unwrap().pop().unwrap()
+    }
+
+    #[tokio::test]
+    async fn handle_conflict_records_the_conflict_and_keeps_the_copy_out_of_sync() {
+        let (_env, _temp_dir, sync_dir, manager) = setup().await;
+        let sync_root = add_sync_dir(&manager, &sync_dir).await;
+        let mut rx = manager.state.sse_subscribe();
+
+        let conflict = lose_conflict(&manager, &sync_root, "notes.txt").await;
+
+        assert_eq!(conflict.path, dir_relative(&sync_root, "notes.txt"));
+        assert_eq!(conflict.peer, Uuid::nil());
+        assert_eq!(
+            conflict.local_version,
+            HashMap::from([(manager.state.local_id(), 1)])
+        );
+        assert_eq!(conflict.remote_version, HashMap::from([(Uuid::nil(), 1)]));
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(ServerEvent::ConflictDetected(detected)) if detected == conflict
+        ));
+
+        let copy = conflict
+            .conflict_path
+            .to_canonical(manager.state.home_path());
+        assert_eq!(fs::read(&copy).unwrap(), b"local contents");
+        assert!(manager.is_ignored(&copy, &conflict.conflict_path).await);
+        let walked = manager.build_dir(sync_dir.clone()).await.unwrap();
+        assert!(!walked.contains_key(&conflict.conflict_path));
+    }
+
+    #[tokio::test]
+    async fn resolve_conflict_keep_local_restores_the_copy_as_a_newer_version() {
+        let (_env, _temp_dir, sync_dir, manager) = setup().await;
+        let sync_root = add_sync_dir(&manager, &sync_dir).await;
+        let conflict = lose_conflict(&manager, &sync_root, "notes.txt").await;
+        let home = manager.state.home_path();
+
+        let announced = manager
+            .resolve_conflict(conflict.id, ConflictResolution::Local)
+            .await
+            .unwrap()
+            .unwrap();
+
+        let path = conflict.path.to_canonical(home);
+        assert_eq!(fs::read(&path).unwrap(), b"local contents");
+        assert!(!conflict.conflict_path.to_canonical(home).exists());
+        assert_eq!(announced.len(), 1);
+        assert_eq!(announced[0].hash, Some(compute_hash(&path).await.unwrap()));
+        let peer = entry(conflict.path.clone(), Some("peer-hash"), Uuid::nil());
+        assert!(matches!(announced[0].compare(&peer), VersionCmp::KeepSelf));
+        assert!(manager.list_conflicts().await.unwrap().is_empty());
+    }
+
+    fn is_read_only<T>(result: io::Result<T>) -> bool {
+        result.is_err_and(|err| err.kind() == io::ErrorKind::ReadOnlyFilesystem)
+    }
+
+    #[tokio::test]
+    async fn resolve_conflict_in_a_receive_only_dir_only_keeps_remote() {
+        let (_env, _temp_dir, sync_dir, manager) = setup().await;
+        let sync_root = add_sync_dir(&manager, &sync_dir).await;
+        let conflict = lose_conflict(&manager, &sync_root, "notes.txt").await;
+        assert!(set_mode(&manager, &sync_root, DirectoryMode::ReceiveOnly).await);
+        let home = manager.state.home_path();
+
+        for resolution in [ConflictResolution::Local, ConflictResolution::Both] {
+            assert!(is_read_only(
+                manager.resolve_conflict(conflict.id, resolution).await
+            ));
+        }
+        assert!(conflict.conflict_path.to_canonical(home).exists());
+        assert_eq!(
+            fs::read(conflict.path.to_canonical(home)).unwrap(),
+            b"remote contents"
+        );
+
+        let announced = manager
+            .resolve_conflict(conflict.id, ConflictResolution::Remote)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(announced.is_empty());
+    }
+
+    #[tokio::test]
+    async fn restore_version_in_a_receive_only_dir_is_rejected() {
+        let (_env, _temp_dir, sync_dir, manager) = setup().await;
+        let sync_root = add_sync_dir(&manager, &sync_dir).await;
+        let home = manager.state.home_path();
+        let name = dir_relative(&sync_root, "notes.txt");
+        let before = write_file(&manager, &name, "current").await;
+        let store = home.join(&*sync_root).join(".synche/versions");
+        fs::create_dir_all(&store).unwrap();
+        fs::write(store.join("notes.txt~100"), b"restored").unwrap();
+        assert!(set_mode(&manager, &sync_root, DirectoryMode::ReceiveOnly).await);
+
+        assert!(is_read_only(manager.restore_version(&name, 100).await));
+        assert_eq!(fs::read(name.to_canonical(home)).unwrap(), b"current");
+        assert_eq!(
+            manager.get_entry(&name).await.unwrap().unwrap().version,
+            before.version
+        );
+    }
+
+    #[tokio::test]
+    async fn restore_version_supersedes_the_entry_and_keeps_what_it_replaced() {
+        let (_env, _temp_dir, sync_dir, manager) = setup().await;
+        let sync_root = add_sync_dir(&manager, &sync_dir).await;
+        let home = manager.state.home_path();
+        let name = dir_relative(&sync_root, "notes.txt");
+        let path = name.to_canonical(home);
+        fs::write(&path, b"current").unwrap();
+        let before = manager
+            .entry_created(
+                &name,
+                EntryKind::File,
+                Some(compute_hash(&path).await.unwrap()),
+            )
+            .await
+            .unwrap();
+        let store = home.join(&*sync_root).join(".synche/versions");
+        fs::create_dir_all(&store).unwrap();
+        fs::write(store.join("notes.txt~100"), b"restored").unwrap();
+
+        assert!(manager.restore_version(&name, 99).await.unwrap().is_none());
+        let restored = manager.restore_version(&name, 100).await.unwrap().unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), b"restored");
+        assert_eq!(restored.hash, Some(compute_hash(&path).await.unwrap()));
+        assert!(matches!(restored.compare(&before), VersionCmp::KeepSelf));
+        let versions = manager.list_versions(&name).await.unwrap().unwrap();
+        assert_eq!(versions.len(), 2);
+        assert_eq!(versions[1].saved_at, 100);
+    }
+
+    #[tokio::test]
+    async fn trashed_directory_restores_over_its_tombstones() {
+        let (_env, _temp_dir, sync_dir, manager) = setup().await;
+        let sync_root = add_sync_dir(&manager, &sync_dir).await;
+        let home = manager.state.home_path();
+        let peer = Uuid::new_v4();
+        // Keeps the tombstones from being collected straight away.
+        manager.trust_device(peer).await.unwrap();
+        let dir = dir_relative(&sync_root, "photos");
+        let file = dir_relative(&sync_root, "photos/a.jpg");
+        fs::create_dir_all(dir.to_canonical(home)).unwrap();
+        fs::write(file.to_canonical(home), b"jpg").unwrap();
+        let mut tombstones = Vec::new();
+        for (name, kind, hash) in [
+            (&dir, EntryKind::Directory, None),
+            (&file, EntryKind::File, Some("h".to_string())),
+        ] {
+            let entry = manager.entry_created(name, kind, hash).await.unwrap();
+            tombstones.push(manager.delete_and_update_entry(entry).await.unwrap());
+        }
+
+        manager.trash_entry(peer, &tombstones[0]).await.unwrap();
+
+        assert!(!dir.to_canonical(home).exists());
+        let trash = manager.list_trash().await.unwrap();
+        assert_eq!(trash.len(), 1);
+        assert_eq!(trash[0].kind, EntryKind::Directory);
+        assert!(
+            manager
+                .restore_trash(Uuid::new_v4())
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        let restored = manager.restore_trash(trash[0].id).await.unwrap().unwrap();
+
+        assert_eq!(fs::read(file.to_canonical(home)).unwrap(), b"jpg");
+        assert_eq!(restored.len(), 2);
+        for (entry, tombstone) in restored.iter().zip(&tombstones) {
+            assert_eq!(entry.name, tombstone.name);
+            assert!(matches!(entry.compare(tombstone), VersionCmp::KeepSelf));
+        }
+        assert!(manager.list_trash().await.unwrap().is_empty());
+        assert!(
+            !home
+                .join(&*sync_root)
+                .join(".synche/trash")
+                .join(trash[0].id.to_string())
+                .exists()
+        );
+    }
+
+    #[tokio::test]
+    async fn restore_trash_in_a_receive_only_dir_is_rejected() {
+        let (_env, _temp_dir, sync_dir, manager) = setup().await;
+        let sync_root = add_sync_dir(&manager, &sync_dir).await;
+        let home = manager.state.home_path();
+        let peer = Uuid::new_v4();
+        manager.trust_device(peer).await.unwrap();
+        let name = dir_relative(&sync_root, "a.txt");
+        let live = write_file(&manager, &name, "a").await;
+        let tombstone = manager.delete_and_update_entry(live).await.unwrap();
+        manager.trash_entry(peer, &tombstone).await.unwrap();
+        let trash = manager.list_trash().await.unwrap();
+        assert!(set_mode(&manager, &sync_root, DirectoryMode::ReceiveOnly).await);
+
+        assert!(is_read_only(manager.restore_trash(trash[0].id).await));
+        assert!(!name.to_canonical(home).exists());
+        assert_eq!(manager.list_trash().await.unwrap(), trash);
+        assert_eq!(
+            manager.get_entry(&name).await.unwrap().unwrap().version,
+            tombstone.version
+        );
+    }
 
-        let db = SqliteDb::new(":memory:").await.unwrap();
-        let manager = EntryManager::new(db, env.state.clone());
+    #[tokio::test]
+    async fn trash_is_purged_on_request_or_when_expired() {
+        let (_env, _temp_dir, sync_dir, manager) = setup().await;
+        let sync_root = add_sync_dir(&manager, &sync_dir).await;
+        let home = manager.state.home_path();
+        let mut dir = manager.list_dirs().await[&sync_root].clone();
+        dir.trash_expiry = TrashExpiry(1);
+        manager.update_sync_dir(dir).await;
+        let mut trashed = Vec::new();
+        for name in ["a.txt", "b.txt"] {
+            let name = dir_relative(&sync_root, name);
+            fs::write(name.to_canonical(home), b"x").unwrap();
+            let entry = manager
+                .entry_created(&name, EntryKind::File, Some("h".into()))
+                .await
+                .unwrap();
+            let tombstone = manager.delete_and_update_entry(entry).await.unwrap();
+            manager
+                .trash_entry(Uuid::new_v4(), &tombstone)
+                .await
+                .unwrap();
+            trashed.push(manager.list_trash().await.unwrap().pop().unwrap());
+        }
 
-        (env, temp_dir, sync_dir, manager)
-    }
+        assert!(manager.purge_trash(trashed[0].id).await.unwrap());
+        assert!(!manager.purge_trash(trashed[0].id).await.unwrap());
+        let old = TrashRecord {
+            deleted_at: unix_now() - 2 * 24 * 60 * 60,
+            ..trashed[1].clone()
+        };
+        manager.db.delete_trash(&old.id).await.unwrap();
+        manager.db.insert_trash(&old).await.unwrap();
+        manager.expire_trash().await.unwrap();
 
-    async fn add_sync_dir(
-        manager: &Arc<EntryManager<SqliteDb>>,
-        sync_dir: &CanonicalPath,
-    ) -> RelativePath {
-        let relative = RelativePath::new(sync_dir, manager.state.home_path()).unwrap();
-        manager.state.sync_dirs.write().await.insert(
-            relative.clone(),
-            SyncDirectory {
-                name: relative.clone(),
-            },
+        assert!(manager.list_trash().await.unwrap().is_empty());
+        assert!(
+            !home
+                .join(&*sync_root)
+                .join(".synche/trash")
+                .join(old.id.to_string())
+                .exists()
+        );
+        assert!(
+            !dir_relative(&sync_root, "b.txt")
+                .to_canonical(home)
+                .exists()
         );
-        relative
     }
 
-    fn entry(name: RelativePath, hash: Option<&str>, peer_id: Uuid) -> EntryInfo {
-        EntryInfo {
-            name,
-            kind: EntryKind::File,
-            hash: hash.map(str::to_string),
-            version: HashMap::from([(peer_id, 1)]),
+    /// Writes `contents` to `name` and records it as a local edit.
+    async fn write_file(
+        manager: &Arc<EntryManager<SqliteDb>>,
+        name: &RelativePath,
+        contents: &str,
+    ) -> EntryInfo {
+        let path = name.to_canonical(manager.state.home_path());
+        fs::write(&path, contents).unwrap();
+        let hash = Some(compute_hash(&path).await.unwrap());
+        match manager.get_entry(name).await.unwrap() {
+            Some(entry) if !entry.is_removed() => {
+                manager.entry_modified(entry, hash).await.unwrap()
+            }
+            _ => manager
+                .entry_created(name, EntryKind::File, hash)
+                .await
+                .unwrap(),
         }
     }
 
     #[tokio::test]
-    async fn build_dir_excludes_git_directory() {
+    async fn take_snapshot_copies_contents_and_rejects_duplicate_names() {
         let (_env, _temp_dir, sync_dir, manager) = setup().await;
+        let sync_root = add_sync_dir(&manager, &sync_dir).await;
+        let home = manager.state.home_path();
+        let name = dir_relative(&sync_root, "notes.txt");
+        write_file(&manager, &name, "before").await;
 
-        fs::write(sync_dir.join("notes.txt"), "hello").unwrap();
-        fs::write(sync_dir.join(".gitignore"), "*.log").unwrap();
-        fs::write(sync_dir.join(".gitattributes"), "* text=auto").unwrap();
-
-        fs::create_dir_all(sync_dir.join(".git/objects/ab")).unwrap();
-        fs::write(sync_dir.join(".git/HEAD"), "ref: refs/heads/main").unwrap();
-        fs::write(sync_dir.join(".git/config"), "[core]").unwrap();
-        fs::write(sync_dir.join(".git/objects/ab/cdef"), "obj").unwrap();
-
-        let entries = manager.build_dir(sync_dir).await.unwrap();
+        let snapshot = manager
+            .take_snapshot(&sync_root, "release")
+            .await
+            .unwrap()
+            .unwrap();
 
-        let names: Vec<&str> = entries.keys().map(|p| p.as_ref()).collect();
+        let copy = home
+            .join(&*sync_root)
+            .join(".synche/snapshots")
+            .join(snapshot.id.to_string())
+            .join("notes.txt");
+        assert_eq!(fs::read_to_string(copy).unwrap(), "before");
+        assert_eq!(
+            manager.list_snapshots().await.unwrap(),
+            vec![snapshot.clone()]
+        );
+        let err = manager
+            .take_snapshot(&sync_root, "release")
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert!(
+            manager
+                .take_snapshot(&"unknown".into(), "release")
+                .await
+                .unwrap()
+                .is_none()
+        );
 
-        assert!(names.iter().any(|n| n.ends_with("/notes.txt")));
-        assert!(names.iter().any(|n| n.ends_with("/.gitignore")));
-        assert!(names.iter().any(|n| n.ends_with("/.gitattributes")));
+        write_file(&manager, &name, "after").await;
+        let diff = manager.diff_snapshot(snapshot.id).await.unwrap().unwrap();
+        assert_eq!(diff.modified, vec![name]);
 
-        for name in &names {
-            assert!(
-                !name.contains("/.git/") && !name.ends_with("/.git"),
-                "unexpected .git entry: {name}"
-            );
-        }
+        assert!(manager.delete_snapshot(snapshot.id).await.unwrap());
+        assert!(!manager.delete_snapshot(snapshot.id).await.unwrap());
+        assert!(
+            !home
+                .join(&*sync_root)
+                .join(".synche/snapshots")
+                .join(snapshot.id.to_string())
+                .exists()
+        );
     }
 
     #[tokio::test]
-    async fn get_entries_to_request_ignores_git_peer_entries() {
+    async fn rollback_snapshot_in_a_receive_only_dir_is_rejected() {
         let (_env, _temp_dir, sync_dir, manager) = setup().await;
         let sync_root = add_sync_dir(&manager, &sync_dir).await;
-        let peer_id = Uuid::new_v4();
-        let peer = Peer::new(
-            peer_id,
-            IpAddr::V4(Ipv4Addr::LOCALHOST),
-            "peer".to_string(),
-            Uuid::new_v4(),
-            vec![SyncDirectory {
-                name: sync_root.clone(),
-            }],
+        let home = manager.state.home_path();
+        let name = dir_relative(&sync_root, "a.txt");
+        write_file(&manager, &name, "a").await;
+        let snapshot = manager
+            .take_snapshot(&sync_root, "before")
+            .await
+            .unwrap()
+            .unwrap();
+        let edited = write_file(&manager, &name, "A").await;
+        assert!(set_mode(&manager, &sync_root, DirectoryMode::ReceiveOnly).await);
+
+        assert!(is_read_only(manager.rollback_snapshot(snapshot.id).await));
+        assert_eq!(fs::read(name.to_canonical(home)).unwrap(), b"A");
+        assert_eq!(
+            manager.get_entry(&name).await.unwrap().unwrap().version,
+            edited.version
         );
+    }
 
-        let git_name: RelativePath = format!("{}/.git/config", &*sync_root).into();
-        let normal_name: RelativePath = format!("{}/notes.txt", &*sync_root).into();
-        let git_entry = entry(git_name.clone(), Some("git-hash"), peer_id);
-        let normal_entry = entry(normal_name.clone(), Some("notes-hash"), peer_id);
-        let peer_entries =
-            HashMap::from([(git_name, git_entry), (normal_name.clone(), normal_entry)]);
+    #[tokio::test]
+    async fn rollback_snapshot_reverts_the_directory_as_local_edits() {
+        let (_env, _temp_dir, sync_dir, manager) = setup().await;
+        let sync_root = add_sync_dir(&manager, &sync_dir).await;
+        // Keeps the tombstones from being collected straight away.
+        manager.trust_device(Uuid::new_v4()).await.unwrap();
+        let home = manager.state.home_path();
+        let name = |leaf| dir_relative(&sync_root, leaf);
+        let (edited, deleted, added, sub) =
+            (name("a.txt"), name("b.txt"), name("new.txt"), name("sub"));
+        write_file(&manager, &edited, "a").await;
+        write_file(&manager, &deleted, "b").await;
+        fs::create_dir(sub.to_canonical(home)).unwrap();
+        manager
+            .entry_created(&sub, EntryKind::Directory, None)
+            .await
+            .unwrap();
+        write_file(&manager, &name("sub/c.txt"), "c").await;
+        let snapshot = manager
+            .take_snapshot(&sync_root, "before")
+            .await
+            .unwrap()
+            .unwrap();
 
-        let entries = manager
-            .get_entries_to_request(&peer, peer_entries)
+        write_file(&manager, &edited, "A").await;
+        fs::remove_file(deleted.to_canonical(home)).unwrap();
+        manager.remove_entry(&deleted).await.unwrap();
+        write_file(&manager, &added, "new").await;
+        // `sub` turns from a directory into a file.
+        fs::remove_dir_all(sub.to_canonical(home)).unwrap();
+        manager.remove_dir(&sub).await.unwrap();
+        write_file(&manager, &sub, "x").await;
+        let before = handshake_entries(&manager).await;
+
+        let announced = manager
+            .rollback_snapshot(snapshot.id)
             .await
+            .unwrap()
             .unwrap();
 
-        assert_eq!(entries.len(), 1);
-        assert_eq!(entries[0].name, normal_name);
+        let read = |leaf| fs::read_to_string(name(leaf).to_canonical(home)).unwrap();
+        assert_eq!(read("a.txt"), "a");
+        assert_eq!(read("b.txt"), "b");
+        assert_eq!(read("sub/c.txt"), "c");
+        assert!(!added.to_canonical(home).exists());
+        let mut names: Vec<RelativePath> = announced.iter().map(|e| e.name.clone()).collect();
+        names.sort();
+        // `sub` twice: deleted as a file, then restored as a directory.
+        let mut expected = vec![
+            edited,
+            deleted,
+            added.clone(),
+            sub.clone(),
+            sub,
+            name("sub/c.txt"),
+        ];
+        expected.sort();
+        assert_eq!(names, expected);
+        for entry in &announced {
+            if let Some(old) = before.get(&entry.name) {
+                assert!(matches!(entry.compare(old), VersionCmp::KeepSelf));
+            }
+        }
+        let versions = manager.list_versions(&added).await.unwrap().unwrap();
+        assert_eq!(versions.len(), 1);
+        let diff = manager.diff_snapshot(snapshot.id).await.unwrap().unwrap();
+        assert!(diff.is_empty());
     }
 
     #[tokio::test]
-    async fn get_entries_to_request_ignores_foreign_axes_before_compare() {
+    async fn send_only_keeps_the_local_copy_over_a_winning_peer_version() {
         let (_env, _temp_dir, sync_dir, manager) = setup().await;
         let sync_root = add_sync_dir(&manager, &sync_dir).await;
+        assert!(set_mode(&manager, &sync_root, DirectoryMode::SendOnly).await);
         let local_id = manager.state.local_id();
         let peer_id = Uuid::new_v4();
-        let peer = Peer::new(
-            peer_id,
-            IpAddr::V4(Ipv4Addr::LOCALHOST),
-            "peer".to_string(),
-            Uuid::new_v4(),
-            vec![SyncDirectory {
-                name: sync_root.clone(),
-            }],
+        let home = manager.state.home_path();
+
+        // Concurrent with the local edit: only the counter is merged,
+        // and no conflict copy is made.
+        let name = dir_relative(&sync_root, "a.txt");
+        write_file(&manager, &name, "first").await;
+        let local = write_file(&manager, &name, "local").await;
+        let mut peer_entry = entry(name.clone(), Some("theirs"), peer_id);
+        peer_entry.version.insert(peer_id, 5);
+        let cmp = manager.handle_metadata(peer_id, &peer_entry).await.unwrap();
+        assert!(matches!(cmp, VersionCmp::KeepSelf));
+        assert!(manager.list_conflicts().await.unwrap().is_empty());
+        let merged = manager.get_entry(&name).await.unwrap().unwrap();
+        assert_eq!(merged.hash, local.hash);
+        assert_eq!(merged.version[&peer_id], 5);
+        assert_eq!(merged.version[&local_id], local.version[&local_id]);
+        assert_eq!(
+            fs::read_to_string(name.to_canonical(home)).unwrap(),
+            "local"
         );
-        let name = dir_relative(&sync_root, "notes.txt");
 
-        manager
+        // Dominating the local copy: ours is bumped to supersede it.
+        let name = dir_relative(&sync_root, "b.txt");
+        let ours = write_file(&manager, &name, "ours").await;
+        let ours = manager
             .insert_entry(EntryInfo {
-                name: name.clone(),
-                kind: EntryKind::File,
-                hash: Some("local-hash".into()),
-                version: HashMap::from([(local_id, 2), (peer_id, 1)]),
+                version: HashMap::from([(peer_id, 1)]),
+                ..ours
             })
             .await
             .unwrap();
+        let mut peer_entry = entry(name.clone(), Some("theirs"), peer_id);
+        peer_entry.version.insert(peer_id, 2);
+        let cmp = manager.handle_metadata(peer_id, &peer_entry).await.unwrap();
+        assert!(matches!(cmp, VersionCmp::KeepOther));
 
-        let peer_entry = EntryInfo {
-            name: name.clone(),
-            kind: EntryKind::File,
-            hash: Some("peer-hash".into()),
-            version: HashMap::from([(local_id, 99), (peer_id, 1)]),
-        };
-
-        let entries = manager
-            .get_entries_to_request(&peer, HashMap::from([(name, peer_entry)]))
+        let kept = manager
+            .keep_local(peer_id, &peer_entry)
             .await
+            .unwrap()
             .unwrap();
-
+        assert_eq!(kept.hash, ours.hash);
+        assert_eq!(kept.version[&peer_id], 2);
+        assert_eq!(kept.version[&local_id], 1);
+        assert!(matches!(kept.compare(&peer_entry), VersionCmp::KeepSelf));
+        assert_eq!(fs::read_to_string(name.to_canonical(home)).unwrap(), "ours");
+        let unknown = entry(dir_relative(&sync_root, "c.txt"), Some("c"), peer_id);
         assert!(
-            entries.is_empty(),
-            "foreign local-axis claim must not make peer entry win"
+            manager
+                .keep_local(peer_id, &unknown)
+                .await
+                .unwrap()
+                .is_none()
         );
     }
 
     #[tokio::test]
-    async fn handle_metadata_ignores_git_peer_entries() {
-        let (_env, _temp_dir, sync_dir, manager) = setup().await;
-        let sync_root = add_sync_dir(&manager, &sync_dir).await;
-        let peer_id = Uuid::new_v4();
-        let git_name: RelativePath = format!("{}/.git/config", &*sync_root).into();
-        let peer_entry = entry(git_name.clone(), Some("git-hash"), peer_id);
+    async fn two_send_only_peers_with_different_copies_settle() {
+        let mut sides = Vec::new();
+        for contents in ["from a", "from b"] {
+            let (env, _temp_dir, _, manager) = setup().await;
+            let sync_root: RelativePath = "Shared".into();
+            let dir = SyncDirectory {
+                mode: DirectoryMode::SendOnly,
+                ..SyncDirectory::new(sync_root.clone())
+            };
+            manager.add_sync_dir(dir).await.unwrap();
+            let name = dir_relative(&sync_root, "a.txt");
+            let announced = write_file(&manager, &name, contents).await;
+            sides.push((env, manager, announced));
+        }
 
-        let cmp = manager.handle_metadata(peer_id, &peer_entry).await.unwrap();
+        // Deliver each announcement to the other side, as the receiver
+        // does, until nobody has anything left to re-announce.
+        let mut inbox = vec![(1, sides[0].2.clone()), (0, sides[1].2.clone())];
+        let mut rounds = 0;
+        while let Some((to, announced)) = inbox.pop() {
+            rounds += 1;
+            assert!(rounds <= 8, "send-only peers kept re-announcing");
+            let from = sides[1 - to].1.state.local_id();
+            let manager = &sides[to].1;
+            let cmp = manager.handle_metadata(from, &announced).await.unwrap();
+            if matches!(cmp, VersionCmp::KeepOther)
+                && let Some(local) = manager.keep_local(from, &announced).await.unwrap()
+            {
+                inbox.push((1 - to, local));
+            }
+        }
 
-        assert!(matches!(cmp, VersionCmp::KeepSelf));
-        assert!(manager.get_entry(&git_name).await.unwrap().is_none());
+        for ((_, manager, announced), contents) in sides.iter().zip(["from a", "from b"]) {
+            let path = announced.name.to_canonical(manager.state.home_path());
+            assert_eq!(fs::read_to_string(path).unwrap(), contents);
+            assert!(manager.list_conflicts().await.unwrap().is_empty());
+        }
     }
 
-    /// A directory peer-vs-self conflict can't be materialized as a file,
-    /// so it falls through to the peer-wins branch. Use files so we can
-    /// also assert the on-disk conflict-file side effect.
-    fn dir_relative(sync_root: &RelativePath, leaf: &str) -> RelativePath {
-        format!("{}/{}", &**sync_root, leaf).into()
+    #[tokio::test]
+    async fn receive_only_changes_stay_local_until_reverted() {
+        let (_env, _temp_dir, sync_dir, manager) = setup().await;
+        let sync_root = add_sync_dir(&manager, &sync_dir).await;
+        let home = manager.state.home_path();
+        let name = |leaf| dir_relative(&sync_root, leaf);
+        let (kept, edited, deleted, added) = (
+            name("kept.txt"),
+            name("edited.txt"),
+            name("deleted.txt"),
+            name("added.txt"),
+        );
+        for cluster in [&kept, &edited, &deleted] {
+            write_file(&manager, cluster, "cluster").await;
+        }
+        let before = handshake_entries(&manager).await;
+        assert!(set_mode(&manager, &sync_root, DirectoryMode::ReceiveOnly).await);
+
+        fs::write(edited.to_canonical(home), "local").unwrap();
+        fs::remove_file(deleted.to_canonical(home)).unwrap();
+        fs::write(added.to_canonical(home), "local").unwrap();
+        manager.init().await.unwrap();
+        // The store still holds what the cluster does.
+        let after = handshake_entries(&manager).await;
+        assert_eq!(after[&edited].hash, before[&edited].hash);
+        assert!(!after[&deleted].is_removed());
+        assert!(!after.contains_key(&added));
+
+        let changes = manager.local_changes(&sync_root).await.unwrap().unwrap();
+        assert_eq!(changes.added, vec![added.clone()]);
+        assert_eq!(changes.removed, vec![deleted.clone()]);
+        assert_eq!(changes.modified, vec![edited.clone()]);
+
+        let fetch = manager
+            .revert_local_changes(&sync_root)
+            .await
+            .unwrap()
+            .unwrap();
+        let fetch: Vec<RelativePath> = fetch.into_iter().map(|e| e.name).collect();
+        assert_eq!(fetch, vec![deleted, edited]);
+        assert!(!added.to_canonical(home).exists());
+        assert_eq!(
+            manager.list_versions(&added).await.unwrap().unwrap().len(),
+            1
+        );
+
+        assert!(set_mode(&manager, &sync_root, DirectoryMode::SendReceive).await);
+        let err = manager.local_changes(&sync_root).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(
+            manager
+                .local_changes(&"Unknown".into())
+                .await
+                .unwrap()
+                .is_none()
+        );
     }
 
     #[tokio::test]
-    async fn entry_modified_bumps_local_version_counter() {
+    async fn resolve_conflict_keep_both_syncs_the_copy_and_keep_remote_deletes_it() {
         let (_env, _temp_dir, sync_dir, manager) = setup().await;
         let sync_root = add_sync_dir(&manager, &sync_dir).await;
-        let local_id = manager.state.local_id();
+        let home = manager.state.home_path();
+        let both = lose_conflict(&manager, &sync_root, "a.txt").await;
+        let remote = lose_conflict(&manager, &sync_root, "b.txt").await;
+        let mut rx = manager.state.sse_subscribe();
 
-        let initial = manager
-            .insert_entry(EntryInfo {
-                name: dir_relative(&sync_root, "file.txt"),
-                kind: EntryKind::File,
-                hash: Some("v1".into()),
-                version: HashMap::from([(local_id, 3)]),
-            })
+        let announced = manager
+            .resolve_conflict(both.id, ConflictResolution::Both)
             .await
+            .unwrap()
             .unwrap();
 
-        let bumped = manager
-            .entry_modified(initial, Some("v2".into()))
+        let copy = both.conflict_path.to_canonical(home);
+        assert_eq!(announced.len(), 1);
+        assert_eq!(announced[0].name, both.conflict_path);
+        assert!(!manager.is_ignored(&copy, &both.conflict_path).await);
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(ServerEvent::ConflictResolved { id, resolution: ConflictResolution::Both })
+                if id == both.id
+        ));
+
+        let announced = manager
+            .resolve_conflict(remote.id, ConflictResolution::Remote)
             .await
+            .unwrap()
             .unwrap();
 
-        assert_eq!(bumped.version.get(&local_id), Some(&4));
-        assert_eq!(bumped.hash.as_deref(), Some("v2"));
+        assert!(announced.is_empty());
+        assert!(!remote.conflict_path.to_canonical(home).exists());
+        assert_eq!(
+            fs::read(remote.path.to_canonical(home)).unwrap(),
+            b"remote contents"
+        );
+        assert!(manager.list_conflicts().await.unwrap().is_empty());
+        assert!(
+            manager
+                .resolve_conflict(remote.id, ConflictResolution::Remote)
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    const BASE_TEXT: &str = "one\ntwo\nthree\nfour\nfive\n";
+
+    /// Seeds `<sync_root>/<leaf>` with `BASE_TEXT` as its merge base,
+    /// then edits it locally to `ours` and has the peer with the lowest
+    /// id concurrently edit it to `theirs`. Returns what
+    /// `merge_received` makes of the peer's version once it arrives.
+    async fn concurrent_text_edits(
+        manager: &Arc<EntryManager<SqliteDb>>,
+        sync_root: &RelativePath,
+        leaf: &str,
+        ours: &str,
+        theirs: &str,
+    ) -> (EntryInfo, EntryInfo) {
+        let peer_id = Uuid::nil();
+        let name = dir_relative(sync_root, leaf);
+        let path = name.to_canonical(manager.state.home_path());
+
+        fs::write(&path, BASE_TEXT).unwrap();
+        let base = manager
+            .entry_created(
+                &name,
+                EntryKind::File,
+                Some(hash_bytes(BASE_TEXT.as_bytes())),
+            )
+            .await
+            .unwrap();
+        manager
+            .record_merge_base(&base, &base.version)
+            .await
+            .unwrap();
+
+        fs::write(&path, ours).unwrap();
+        let mut local = manager
+            .entry_modified(base, Some(hash_bytes(ours.as_bytes())))
+            .await
+            .unwrap();
+        let peer = EntryInfo {
+            hash: Some(hash_bytes(theirs.as_bytes())),
+            version: HashMap::from([(peer_id, 1)]),
+            ..local.clone()
+        };
+        let cmp = manager
+            .compare_and_resolve_conflict(&mut local, &peer, peer_id)
+            .await
+            .unwrap();
+        assert!(matches!(cmp, VersionCmp::KeepOther));
+
+        fs::write(&path, theirs).unwrap();
+        let received = manager
+            .insert_peer_entry(peer_id, peer.clone())
+            .await
+            .unwrap()
+            .unwrap();
+        (
+            manager
+                .merge_received(received, &peer.version)
+                .await
+                .unwrap(),
+            peer,
+        )
     }
 
     #[tokio::test]
-    async fn compare_and_resolve_conflict_keeps_self_when_local_id_lower_than_peer() {
+    async fn concurrent_text_edits_to_different_lines_merge_as_a_local_edit() {
         let (_env, _temp_dir, sync_dir, manager) = setup().await;
         let sync_root = add_sync_dir(&manager, &sync_dir).await;
-        let local_id = manager.state.local_id();
-        // Force peer_id > local_id so the deterministic tiebreak keeps self.
-        let peer_id = Uuid::from_u128(u128::MAX);
+        let mut rx = manager.state.sse_subscribe();
+        let ours = BASE_TEXT.replace("two", "TWO");
+        let theirs = BASE_TEXT.replace("four", "FOUR");
+
+        let (merged, peer) =
+            concurrent_text_edits(&manager, &sync_root, "a.txt", &ours, &theirs).await;
+
+        let expected = "one\nTWO\nthree\nFOUR\nfive\n";
+        let path = merged.name.to_canonical(manager.state.home_path());
+        assert_eq!(fs::read_to_string(&path).unwrap(), expected);
+        assert_eq!(merged.hash, Some(hash_bytes(expected.as_bytes())));
+        assert!(matches!(merged.compare(&peer), VersionCmp::KeepSelf));
+        assert!(manager.list_conflicts().await.unwrap().is_empty());
+        assert!(rx.try_recv().is_err());
+        let siblings = fs::read_dir(&sync_dir).unwrap().count();
+        assert_eq!(siblings, 1, "the conflict copy is removed");
+        assert_eq!(
+            manager
+                .db
+                .get_merge_base(&merged.name)
+                .await
+                .unwrap()
+                .map(|base| base.hash),
+            Some(hash_bytes(theirs.as_bytes()))
+        );
+        assert_eq!(
+            manager
+                .merge_base_text(&merged.name)
+                .await
+                .unwrap()
+                .unwrap(),
+            theirs
+        );
+    }
 
-        let name = dir_relative(&sync_root, "doc.txt");
-        let mut local = EntryInfo {
-            name: name.clone(),
-            kind: EntryKind::File,
-            hash: Some("local-hash".into()),
-            version: HashMap::from([(local_id, 1)]),
+    #[tokio::test]
+    async fn concurrent_text_edits_to_the_same_line_keep_the_conflict_copy() {
+        let (_env, _temp_dir, sync_dir, manager) = setup().await;
+        let sync_root = add_sync_dir(&manager, &sync_dir).await;
+        let mut rx = manager.state.sse_subscribe();
+        let ours = BASE_TEXT.replace("three", "ours");
+        let theirs = BASE_TEXT.replace("three", "theirs");
+
+        let (received, peer) =
+            concurrent_text_edits(&manager, &sync_root, "a.txt", &ours, &theirs).await;
+
+        assert_eq!(received.hash, peer.hash);
+        let conflict = manager.list_conflicts().await.unwrap().pop().unwrap();
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(ServerEvent::ConflictDetected(detected)) if detected == conflict
+        ));
+        let copy = conflict
+            .conflict_path
+            .to_canonical(manager.state.home_path());
+        assert_eq!(fs::read_to_string(copy).unwrap(), ours);
+    }
+
+    #[tokio::test]
+    async fn a_merge_base_the_peer_never_held_is_not_merged_from() {
+        let (_env, _temp_dir, sync_dir, manager) = setup().await;
+        let sync_root = add_sync_dir(&manager, &sync_dir).await;
+        let mut rx = manager.state.sse_subscribe();
+        let peer_id = Uuid::nil();
+        let name = dir_relative(&sync_root, "a.txt");
+        let path = name.to_canonical(manager.state.home_path());
+
+        // The base is an edit of ours another device synced, but the
+        // peer in conflict never saw it.
+        fs::write(&path, "zero\n").unwrap();
+        let created = manager
+            .entry_created(&name, EntryKind::File, Some(hash_bytes(b"zero\n")))
+            .await
+            .unwrap();
+        fs::write(&path, BASE_TEXT).unwrap();
+        let base = manager
+            .entry_modified(created, Some(hash_bytes(BASE_TEXT.as_bytes())))
+            .await
+            .unwrap();
+        manager
+            .record_merge_base(&base, &base.version)
+            .await
+            .unwrap();
+
+        let ours = BASE_TEXT.replace("two", "TWO");
+        fs::write(&path, &ours).unwrap();
+        let mut local = manager
+            .entry_modified(base, Some(hash_bytes(ours.as_bytes())))
+            .await
+            .unwrap();
+        let theirs = BASE_TEXT.replace("four", "FOUR");
+        let peer = EntryInfo {
+            hash: Some(hash_bytes(theirs.as_bytes())),
+            version: HashMap::from([(peer_id, 1)]),
+            ..local.clone()
         };
+        manager
+            .compare_and_resolve_conflict(&mut local, &peer, peer_id)
+            .await
+            .unwrap();
+
+        fs::write(&path, &theirs).unwrap();
+        let received = manager
+            .insert_peer_entry(peer_id, peer.clone())
+            .await
+            .unwrap()
+            .unwrap();
+        let announced = manager
+            .merge_received(received, &peer.version)
+            .await
+            .unwrap();
+
+        assert_eq!(announced.hash, peer.hash);
+        assert_eq!(fs::read_to_string(&path).unwrap(), theirs);
+        let conflict = manager.list_conflicts().await.unwrap().pop().unwrap();
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(ServerEvent::ConflictDetected(detected)) if detected == conflict
+        ));
+    }
+
+    #[tokio::test]
+    async fn a_pending_merge_survives_a_restart() {
+        let (env, _temp_dir, sync_dir, _) = setup().await;
+        let db_path = env.home_path().join("entries.db");
+        let manager = EntryManager::new(SqliteDb::new(&db_path).await.unwrap(), env.state.clone());
+        let sync_root = add_sync_dir(&manager, &sync_dir).await;
+        let peer_id = Uuid::nil();
+        let name = dir_relative(&sync_root, "a.txt");
+        let path = name.to_canonical(manager.state.home_path());
+
+        fs::write(&path, BASE_TEXT).unwrap();
+        let base = manager
+            .entry_created(
+                &name,
+                EntryKind::File,
+                Some(hash_bytes(BASE_TEXT.as_bytes())),
+            )
+            .await
+            .unwrap();
+        manager
+            .record_merge_base(&base, &base.version)
+            .await
+            .unwrap();
+        let ours = BASE_TEXT.replace("two", "TWO");
+        fs::write(&path, &ours).unwrap();
+        let mut local = manager
+            .entry_modified(base, Some(hash_bytes(ours.as_bytes())))
+            .await
+            .unwrap();
+        let theirs = BASE_TEXT.replace("four", "FOUR");
         let peer = EntryInfo {
-            name,
-            kind: EntryKind::File,
-            hash: Some("peer-hash".into()),
+            hash: Some(hash_bytes(theirs.as_bytes())),
             version: HashMap::from([(peer_id, 1)]),
+            ..local.clone()
         };
-
-        let cmp = manager
+        manager
             .compare_and_resolve_conflict(&mut local, &peer, peer_id)
             .await
             .unwrap();
+        drop(manager);
 
-        assert!(matches!(cmp, VersionCmp::KeepSelf));
+        let manager = EntryManager::new(SqliteDb::new(&db_path).await.unwrap(), env.state.clone());
+        fs::write(&path, &theirs).unwrap();
+        let received = manager
+            .insert_peer_entry(peer_id, peer.clone())
+            .await
+            .unwrap()
+            .unwrap();
+        let merged = manager
+            .merge_received(received, &peer.version)
+            .await
+            .unwrap();
+
+        let expected = "one\nTWO\nthree\nFOUR\nfive\n";
+        assert_eq!(fs::read_to_string(&path).unwrap(), expected);
+        assert_eq!(merged.hash, Some(hash_bytes(expected.as_bytes())));
+        assert!(manager.list_conflicts().await.unwrap().is_empty());
     }
 
     #[tokio::test]
-    async fn handle_conflict_writes_conflict_file_when_local_id_higher_and_file_exists() {
+    async fn merge_base_contents_are_dropped_once_no_entry_uses_them() {
         let (_env, _temp_dir, sync_dir, manager) = setup().await;
         let sync_root = add_sync_dir(&manager, &sync_dir).await;
-        // peer_id < any real Uuid::new_v4 so local must give way.
-        let peer_id = Uuid::nil();
-
-        let rel: RelativePath = dir_relative(&sync_root, "report.md");
-        let absolute = rel.to_canonical(manager.state.home_path());
-        fs::write(&absolute, b"local contents").unwrap();
+        let name = dir_relative(&sync_root, "a.txt");
+        let path = name.to_canonical(manager.state.home_path());
+        let store = manager.state.dirs().merge_bases_dir();
 
-        let mut local = EntryInfo {
-            name: rel.clone(),
-            kind: EntryKind::File,
-            hash: Some("local-hash".into()),
-            version: HashMap::from([(manager.state.local_id(), 1)]),
-        };
-        let peer = EntryInfo {
-            name: rel,
-            kind: EntryKind::File,
-            hash: Some("peer-hash".into()),
-            version: HashMap::from([(peer_id, 1)]),
-        };
+        fs::write(&path, "v1\n").unwrap();
+        let v1 = entry(name.clone(), Some(&hash_bytes(b"v1\n")), Uuid::nil());
+        manager.record_merge_base(&v1, &v1.version).await.unwrap();
+        assert!(store.join(v1.hash.as_deref().unwrap()).exists());
 
-        let cmp = manager
-            .handle_conflict(&mut local, &peer, peer_id)
+        // Not recorded: the file no longer holds what the entry says.
+        let stale = entry(name.clone(), Some(&hash_bytes(b"v2\n")), Uuid::nil());
+        manager
+            .record_merge_base(&stale, &stale.version)
             .await
             .unwrap();
+        let base = manager.db.get_merge_base(&name).await.unwrap();
+        assert_eq!(base.map(|base| base.hash), v1.hash);
 
-        assert!(matches!(cmp, VersionCmp::KeepOther));
+        fs::write(&path, "v2\n").unwrap();
+        manager
+            .record_merge_base(&stale, &stale.version)
+            .await
+            .unwrap();
+        assert!(!store.join(v1.hash.as_deref().unwrap()).exists());
 
-        let siblings: Vec<String> = fs::read_dir(&sync_dir)
-            .unwrap()
-            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
-            .collect();
-        assert!(
-            siblings.iter().any(|n| n.contains("_CONFLICT_")),
-            "expected a _CONFLICT_ file in {sync_dir:?}, found: {siblings:?}",
-        );
+        manager.forget_entry(&name).await.unwrap();
+        assert!(!store.join(stale.hash.as_deref().unwrap()).exists());
+        assert_eq!(manager.db.get_merge_base(&name).await.unwrap(), None);
     }
 
     #[tokio::test]
@@ -879,6 +3234,8 @@ mod tests {
             kind: EntryKind::File,
             hash: None,
             version: HashMap::from([(manager.state.local_id(), 1)]),
+            modified: None,
+            removed_at: None,
         };
         local.set_removed_hash();
 
@@ -887,6 +3244,8 @@ mod tests {
             kind: EntryKind::File,
             hash: Some("live-peer".into()),
             version: HashMap::from([(peer_id, 1)]),
+            modified: None,
+            removed_at: None,
         };
 
         let cmp = manager
@@ -908,12 +3267,16 @@ mod tests {
             kind: EntryKind::File,
             hash: Some("live-local".into()),
             version: HashMap::from([(manager.state.local_id(), 1)]),
+            modified: None,
+            removed_at: None,
         };
         let mut peer = EntryInfo {
             name,
             kind: EntryKind::File,
             hash: None,
             version: HashMap::from([(peer_id, 1)]),
+            modified: None,
+            removed_at: None,
         };
         peer.set_removed_hash();
 
@@ -955,6 +3318,8 @@ mod tests {
                 kind: EntryKind::File,
                 hash: Some("new-local".into()),
                 version: HashMap::from([(local_id, 5), (peer_id, 1)]),
+                modified: None,
+                removed_at: None,
             })
             .await
             .unwrap();
@@ -964,6 +3329,8 @@ mod tests {
             kind: EntryKind::File,
             hash: Some("old-peer".into()),
             version: HashMap::from([(local_id, 3), (peer_id, 1)]),
+            modified: None,
+            removed_at: None,
         };
 
         let cmp = manager.handle_metadata(peer_id, &peer_entry).await.unwrap();
@@ -984,6 +3351,8 @@ mod tests {
                 kind: EntryKind::File,
                 hash: Some("local-hash".into()),
                 version: HashMap::from([(local_id, 2), (peer_id, 1)]),
+                modified: None,
+                removed_at: None,
             })
             .await
             .unwrap();
@@ -994,6 +3363,8 @@ mod tests {
             hash: Some("peer-hash".into()),
             // This would force KeepOther if compared before sanitizing.
             version: HashMap::from([(local_id, 99), (peer_id, 1)]),
+            modified: None,
+            removed_at: None,
         };
 
         let cmp = manager.handle_metadata(peer_id, &peer_entry).await.unwrap();
@@ -1021,6 +3392,8 @@ mod tests {
                 kind: EntryKind::File,
                 hash: Some("same-hash".into()),
                 version: HashMap::from([(local_id, 2)]),
+                modified: None,
+                removed_at: None,
             })
             .await
             .unwrap();
@@ -1030,6 +3403,8 @@ mod tests {
             kind: EntryKind::File,
             hash: Some("same-hash".into()),
             version: HashMap::from([(local_id, 1), (peer_id, 4), (third_id, 7)]),
+            modified: None,
+            removed_at: None,
         };
 
         let cmp = manager.handle_metadata(peer_id, &peer_entry).await.unwrap();
@@ -1064,6 +3439,8 @@ mod tests {
                 kind: EntryKind::File,
                 hash: Some("same-hash".into()),
                 version: HashMap::from([(local_id, 2)]),
+                modified: None,
+                removed_at: None,
             })
             .await
             .unwrap();
@@ -1073,6 +3450,8 @@ mod tests {
             kind: EntryKind::File,
             hash: Some("same-hash".into()),
             version: HashMap::from([(peer_id, u64::MAX)]),
+            modified: None,
+            removed_at: None,
         };
 
         // Equal kind + hash would normally converge metadata, but the
@@ -1103,6 +3482,8 @@ mod tests {
                 kind: EntryKind::File,
                 hash: Some("local-old".into()),
                 version: HashMap::from([(local_id, 5), (peer_id, 2)]),
+                modified: None,
+                removed_at: None,
             })
             .await
             .unwrap();
@@ -1112,6 +3493,8 @@ mod tests {
             kind: EntryKind::File,
             hash: Some("peer-copy".into()),
             version: HashMap::from([(peer_id, 3), (third_id, 99)]),
+            modified: None,
+            removed_at: None,
         };
 
         let stored = manager
@@ -1136,6 +3519,8 @@ mod tests {
             kind: EntryKind::File,
             hash: Some("peer-copy".into()),
             version: HashMap::from([(peer_id, 3)]),
+            modified: None,
+            removed_at: None,
         };
 
         assert!(
@@ -1191,9 +3576,376 @@ mod tests {
             .await
             .unwrap();
 
-        let data = manager.get_handshake_data().await.unwrap();
+        let entries = handshake_entries(&manager).await;
+
+        assert!(!entries.contains_key(&git_name));
+        assert!(entries.contains_key(&normal_name));
+    }
+
+    #[tokio::test]
+    async fn get_handshake_data_pages_through_the_whole_map_in_name_order() {
+        let (_env, _temp_dir, sync_dir, manager) = setup().await;
+        let sync_root = add_sync_dir(&manager, &sync_dir).await;
+        let peer_id = Uuid::new_v4();
+        for i in 0..=HANDSHAKE_PAGE_ENTRIES {
+            let name = dir_relative(&sync_root, &format!("{i:05}.txt"));
+            manager
+                .insert_entry(entry(name, Some("h"), peer_id))
+                .await
+                .unwrap();
+        }
+
+        let mut data = manager.get_handshake_data().await.unwrap();
+        let mut pages = Vec::new();
+        while let Some(page) = data.entries.next_page().await {
+            pages.push(page.unwrap());
+        }
+
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages[0].len(), HANDSHAKE_PAGE_ENTRIES);
+        let names: Vec<_> = pages.concat().into_iter().map(|e| e.name).collect();
+        assert_eq!(names.len(), HANDSHAKE_PAGE_ENTRIES + 1);
+        assert!(names.is_sorted());
+    }
+
+    #[tokio::test]
+    async fn digest_tree_follows_every_write_to_the_store() {
+        let (_env, _temp_dir, sync_dir, manager) = setup().await;
+        let sync_root = add_sync_dir(&manager, &sync_dir).await;
+        let peer_id = Uuid::new_v4();
+        let kept = dir_relative(&sync_root, "sub/kept.txt");
+        let edited = dir_relative(&sync_root, "sub/edited.txt");
+        let gone = dir_relative(&sync_root, "gone.txt");
+        for name in [&kept, &edited, &gone] {
+            manager
+                .insert_entry(entry(name.clone(), Some("h"), peer_id))
+                .await
+                .unwrap();
+        }
+
+        let stored = manager.get_entry(&edited).await.unwrap().unwrap();
+        manager
+            .entry_modified(stored, Some("h2".into()))
+            .await
+            .unwrap();
+        manager.remove_entry(&gone).await.unwrap();
+
+        let dirs = [
+            RelativePath::from(""),
+            sync_root.clone(),
+            dir_relative(&sync_root, "sub"),
+        ];
+        let maintained: Vec<_> = {
+            let tree = manager.state.digest_tree().await;
+            dirs.iter().map(|d| tree.summary(d)).collect()
+        };
+        manager.load_digest_tree().await.unwrap();
+        let rebuilt: Vec<_> = {
+            let tree = manager.state.digest_tree().await;
+            dirs.iter().map(|d| tree.summary(d)).collect()
+        };
+
+        assert_eq!(maintained, rebuilt);
+        assert!(maintained.iter().all(Option::is_some));
+    }
+
+    #[tokio::test]
+    async fn page_serves_only_the_entries_in_scope() {
+        let (_env, _temp_dir, sync_dir, manager) = setup().await;
+        let sync_root = add_sync_dir(&manager, &sync_dir).await;
+        let peer_id = Uuid::new_v4();
+        let sub = dir_relative(&sync_root, "sub");
+        manager
+            .insert_entry(EntryInfo {
+                name: sub.clone(),
+                kind: EntryKind::Directory,
+                hash: None,
+                version: HashMap::from([(peer_id, 1)]),
+                modified: None,
+                removed_at: None,
+            })
+            .await
+            .unwrap();
+        for name in ["sub/a.txt", "sub/deeper/b.txt", "sibling.txt"] {
+            manager
+                .insert_entry(entry(dir_relative(&sync_root, name), Some("h"), peer_id))
+                .await
+                .unwrap();
+        }
+
+        let names = |page: EntryPage| -> Vec<String> {
+            page.entries
+                .into_iter()
+                .map(|e| e.name.to_string())
+                .collect()
+        };
+        let files = manager
+            .page(&EntryScope::Files(sub.clone()), None)
+            .await
+            .unwrap();
+        let subtree = manager
+            .page(&EntryScope::Subtree(sub.clone()), None)
+            .await
+            .unwrap();
+
+        assert_eq!(names(files), [format!("{sub}/a.txt")]);
+        assert_eq!(
+            names(subtree),
+            [
+                sub.to_string(),
+                format!("{sub}/a.txt"),
+                format!("{sub}/deeper/b.txt")
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn remove_entry_keeps_tombstone_until_old_enough_and_every_trusted_device_acks() {
+        let (_env, _temp_dir, sync_dir, manager) = setup().await;
+        let sync_root = add_sync_dir(&manager, &sync_dir).await;
+        let (peer_a, peer_b) = (Uuid::new_v4(), Uuid::new_v4());
+        manager.trust_device(peer_a).await.unwrap();
+        manager.trust_device(peer_b).await.unwrap();
+        let name = dir_relative(&sync_root, "gone.txt");
+        manager
+            .insert_entry(entry(name.clone(), Some("h"), peer_a))
+            .await
+            .unwrap();
+
+        let removed = manager.remove_entry(&name).await.unwrap().unwrap();
+        assert!(removed.is_removed());
+        assert!(manager.remove_entry(&name).await.unwrap().is_none());
+
+        let entries = handshake_entries(&manager).await;
+        let advertised = entries.get(&name).expect("tombstone in handshake");
+        assert!(advertised.is_removed());
+        assert_eq!(advertised.version, removed.version);
+
+        manager
+            .insert_entry(EntryInfo {
+                removed_at: Some(unix_now() - TOMBSTONE_MIN_AGE_SECS),
+                ..removed
+            })
+            .await
+            .unwrap();
+        manager.acknowledge_tombstone(peer_a, &name).await.unwrap();
+        assert!(manager.get_entry(&name).await.unwrap().is_some());
+
+        manager.acknowledge_tombstone(peer_b, &name).await.unwrap();
+        assert!(manager.get_entry(&name).await.unwrap().is_none());
+
+        let young = dir_relative(&sync_root, "young.txt");
+        manager
+            .insert_entry(entry(young.clone(), Some("h"), peer_a))
+            .await
+            .unwrap();
+        manager.remove_entry(&young).await.unwrap();
+        manager.acknowledge_tombstone(peer_a, &young).await.unwrap();
+        manager.acknowledge_tombstone(peer_b, &young).await.unwrap();
+        assert!(
+            manager.get_entry(&young).await.unwrap().is_some(),
+            "a device paired later must still learn about the removal"
+        );
+    }
+
+    #[tokio::test]
+    async fn apply_peer_tombstone_keeps_the_removal_time_of_the_removing_device() {
+        let (_env, _temp_dir, sync_dir, manager) = setup().await;
+        let sync_root = add_sync_dir(&manager, &sync_dir).await;
+        let peer_id = Uuid::new_v4();
+        manager.trust_device(peer_id).await.unwrap();
+        let name = dir_relative(&sync_root, "gone.txt");
+        let live = entry(name.clone(), Some("h"), peer_id);
+        manager.insert_entry(live.clone()).await.unwrap();
+
+        let mut tombstone = EntryInfo {
+            version: HashMap::from([(peer_id, 2)]),
+            removed_at: Some(1_700_000_000),
+            ..live
+        };
+        tombstone.set_removed_hash();
+        let applied = manager
+            .apply_peer_tombstone(peer_id, tombstone)
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(applied.removed_at, Some(1_700_000_000));
+        assert!(
+            manager.get_entry(&name).await.unwrap().is_none(),
+            "old enough and acknowledged by the only trusted device"
+        );
+    }
+
+    #[tokio::test]
+    async fn tombstone_without_trusted_devices_is_kept_until_old_enough() {
+        let (_env, _temp_dir, sync_dir, manager) = setup().await;
+        let sync_root = add_sync_dir(&manager, &sync_dir).await;
+        let name = dir_relative(&sync_root, "gone.txt");
+        manager
+            .insert_entry(entry(name.clone(), Some("h"), Uuid::new_v4()))
+            .await
+            .unwrap();
+
+        let removed = manager.remove_entry(&name).await.unwrap().unwrap();
+        manager.init().await.unwrap();
+        assert!(
+            manager.get_entry(&name).await.unwrap().is_some(),
+            "a device paired later must still learn about the removal"
+        );
+
+        manager
+            .insert_entry(EntryInfo {
+                removed_at: Some(unix_now() - TOMBSTONE_MIN_AGE_SECS),
+                ..removed
+            })
+            .await
+            .unwrap();
+        manager.init().await.unwrap();
+        assert!(manager.get_entry(&name).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn get_entries_to_request_skips_unknown_tombstones_and_acks_local_ones() {
+        let (_env, _temp_dir, sync_dir, manager) = setup().await;
+        let sync_root = add_sync_dir(&manager, &sync_dir).await;
+        let peer_id = Uuid::new_v4();
+        manager.trust_device(peer_id).await.unwrap();
+        let peer = Peer::new(
+            peer_id,
+            SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 42882),
+            "peer".to_string(),
+            Uuid::new_v4(),
+            vec![SyncDirectory::new(sync_root.clone())],
+        );
+
+        let local_name = dir_relative(&sync_root, "local-gone.txt");
+        manager
+            .insert_entry(entry(local_name.clone(), Some("h"), peer_id))
+            .await
+            .unwrap();
+        let removed = manager.remove_entry(&local_name).await.unwrap().unwrap();
+        manager
+            .insert_entry(EntryInfo {
+                removed_at: Some(unix_now() - TOMBSTONE_MIN_AGE_SECS),
+                ..removed
+            })
+            .await
+            .unwrap();
+
+        let peer_name = dir_relative(&sync_root, "peer-gone.txt");
+        let mut peer_tombstone = entry(peer_name.clone(), None, peer_id);
+        peer_tombstone.set_removed_hash();
+
+        let mut tombstones = manager.local_tombstones().await.unwrap();
+        let entries = manager
+            .get_entries_to_request(&peer, vec![peer_tombstone], &mut tombstones)
+            .await
+            .unwrap();
+        manager
+            .acknowledge_tombstones(peer_id, tombstones)
+            .await
+            .unwrap();
+
+        assert!(entries.is_empty(), "nothing to remove locally");
+        assert!(manager.get_entry(&peer_name).await.unwrap().is_none());
+        assert!(
+            manager.get_entry(&local_name).await.unwrap().is_none(),
+            "the only trusted peer no longer has it, so the tombstone is collected"
+        );
+    }
+
+    #[tokio::test]
+    async fn get_entries_to_request_keeps_tombstones_the_peer_still_holds_live() {
+        let (_env, _temp_dir, sync_dir, manager) = setup().await;
+        let sync_root = add_sync_dir(&manager, &sync_dir).await;
+        let peer_id = Uuid::new_v4();
+        manager.trust_device(peer_id).await.unwrap();
+        let peer = Peer::new(
+            peer_id,
+            SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 42882),
+            "peer".to_string(),
+            Uuid::new_v4(),
+            vec![SyncDirectory::new(sync_root.clone())],
+        );
+
+        let name = dir_relative(&sync_root, "gone.txt");
+        let stale = entry(name.clone(), Some("h"), peer_id);
+        manager.insert_entry(stale.clone()).await.unwrap();
+        manager.remove_entry(&name).await.unwrap();
+
+        let mut tombstones = manager.local_tombstones().await.unwrap();
+        assert!(tombstones.contains(&name));
+        let entries = manager
+            .get_entries_to_request(&peer, vec![stale], &mut tombstones)
+            .await
+            .unwrap();
+        manager
+            .acknowledge_tombstones(peer_id, tombstones)
+            .await
+            .unwrap();
+
+        assert!(entries.is_empty());
+        assert!(
+            manager.get_entry(&name).await.unwrap().is_some(),
+            "the peer still has a live copy and must learn about the removal"
+        );
+    }
+
+    #[tokio::test]
+    async fn handle_metadata_unknown_tombstone_returns_keep_self() {
+        let (_env, _temp_dir, sync_dir, manager) = setup().await;
+        let sync_root = add_sync_dir(&manager, &sync_dir).await;
+        let peer_id = Uuid::new_v4();
+        let mut tombstone = entry(dir_relative(&sync_root, "gone.txt"), None, peer_id);
+        tombstone.set_removed_hash();
+
+        let cmp = manager.handle_metadata(peer_id, &tombstone).await.unwrap();
+
+        assert!(matches!(cmp, VersionCmp::KeepSelf));
+    }
+
+    #[tokio::test]
+    async fn entry_created_over_tombstone_continues_its_version() {
+        let (_env, _temp_dir, sync_dir, manager) = setup().await;
+        let sync_root = add_sync_dir(&manager, &sync_dir).await;
+        let local_id = manager.state.local_id();
+        let peer_id = Uuid::new_v4();
+        manager.trust_device(peer_id).await.unwrap();
+        let name = dir_relative(&sync_root, "back.txt");
+        manager
+            .insert_entry(entry(name.clone(), Some("h"), peer_id))
+            .await
+            .unwrap();
+        let tombstone = manager.remove_entry(&name).await.unwrap().unwrap();
+
+        let revived = manager
+            .entry_created(&name, EntryKind::File, Some("new".into()))
+            .await
+            .unwrap();
+
+        assert!(!revived.is_removed());
+        assert_eq!(revived.version.get(&peer_id), Some(&1));
+        assert_eq!(revived.version.get(&local_id), Some(&2));
+        assert!(matches!(revived.compare(&tombstone), VersionCmp::KeepSelf));
+    }
+
+    #[tokio::test]
+    async fn init_tombstones_entries_deleted_while_offline() {
+        let (_env, _temp_dir, sync_dir, manager) = setup().await;
+        let sync_root = add_sync_dir(&manager, &sync_dir).await;
+        let local_id = manager.state.local_id();
+        manager.trust_device(Uuid::new_v4()).await.unwrap();
+        let name = dir_relative(&sync_root, "offline.txt");
+        manager
+            .insert_entry(entry(name.clone(), Some("h"), local_id))
+            .await
+            .unwrap();
+
+        manager.init().await.unwrap();
 
-        assert!(!data.entries.contains_key(&git_name));
-        assert!(data.entries.contains_key(&normal_name));
+        let stored = manager.get_entry(&name).await.unwrap().unwrap();
+        assert!(stored.is_removed());
+        assert_eq!(stored.version.get(&local_id), Some(&2));
     }
 }
@@ -0,0 +1,62 @@
+use crate::{application::AppState, domain::CanonicalPath};
+use std::sync::Arc;
+use tokio::{fs, io};
+
+/// Text contents kept by hash under `merge_bases_dir`, as the
+/// ancestors `EntryManager` three-way merges concurrent edits from.
+///
+/// Which content is the ancestor of each entry is recorded in the
+/// store; this only holds the bytes. Names are the lowercase hex
+/// SHA-256 `EntryManager` computed itself, so a peer-supplied hash
+/// never becomes a path.
+pub struct MergeBases {
+    state: Arc<AppState>,
+}
+
+impl MergeBases {
+    pub fn new(state: Arc<AppState>) -> Self {
+        Self { state }
+    }
+
+    pub async fn put(&self, hash: &str, text: &str) -> io::Result<()> {
+        let Some(path) = self.path(hash) else {
+            return Ok(());
+        };
+        if path.exists() {
+            return Ok(());
+        }
+        fs::create_dir_all(self.state.dirs().merge_bases_dir()).await?;
+
+        // Written aside first, so a crash never leaves a truncated base.
+        let partial = path.with_extension("partial");
+        fs::write(&partial, text).await?;
+        fs::rename(partial, path).await
+    }
+
+    pub async fn get(&self, hash: &str) -> io::Result<Option<String>> {
+        let Some(path) = self.path(hash) else {
+            return Ok(None);
+        };
+        match fs::read_to_string(path).await {
+            Ok(text) => Ok(Some(text)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Removing a missing base does not error.
+    pub async fn remove(&self, hash: &str) -> io::Result<()> {
+        let Some(path) = self.path(hash) else {
+            return Ok(());
+        };
+        match fs::remove_file(path).await {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+
+    fn path(&self, hash: &str) -> Option<CanonicalPath> {
+        let valid = !hash.is_empty() && hash.bytes().all(|b| b.is_ascii_hexdigit());
+        valid.then(|| self.state.dirs().merge_bases_dir().join(hash))
+    }
+}
@@ -1,8 +1,13 @@
 mod app_state;
 mod entry_manager;
 mod ignore;
+mod merge_bases;
 mod peer_manager;
+mod snapshots;
+mod trash;
+mod versions;
 
 pub use app_state::{AppState, default_ports};
 pub use entry_manager::EntryManager;
 pub use peer_manager::PeerManager;
+pub use versions::VersionStore;
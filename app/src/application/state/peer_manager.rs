@@ -1,12 +1,21 @@
 use super::app_state::AppState;
-use crate::domain::{EntryInfo, Peer, ServerEvent};
-use std::{net::IpAddr, sync::Arc};
+use crate::domain::{EntryInfo, Peer, PeerAddrs, PendingDevice, ServerEvent};
+use std::{
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
 use tokio::sync::broadcast;
-use tracing::info;
+use tracing::{info, trace};
 use uuid::Uuid;
 
-/// Coordinates the live peer map on `AppState` and emits
-/// `ServerEvent`s to the GUI when peers come and go.
+/// Coordinates the live peer map and the pairing state on `AppState`
+/// and emits `ServerEvent`s to the GUI when peers come and go or a
+/// device waits for approval.
+///
+/// Trust is tracked in memory only; callers persist approvals through
+/// `EntryManager::trust_device` and seed the set with `load_trusted`
+/// at startup.
 pub struct PeerManager {
     state: Arc<AppState>,
     sse_tx: broadcast::Sender<ServerEvent>,
@@ -23,9 +32,27 @@ impl PeerManager {
     /// Inserts or refreshes a peer. Emits `PeerConnected` only on the
     /// first appearance for a given `(id, instance_id)` pair, so a
     /// peer restart fires a fresh event while plain re-pings do not.
+    ///
+    /// The peer's address joins its `PeerAddrs`, in front on a first
+    /// appearance, and the peer is stored with the first of them, so a
+    /// refresh does not undo the address the sender last reached it at.
     #[tracing::instrument(skip_all, fields(peer = %peer.id, addr = %peer.addr))]
-    pub async fn insert(&self, peer: Peer) {
-        if !self.seen(&peer.id, &peer.instance_id).await {
+    pub async fn insert(&self, mut peer: Peer) {
+        let seen = self.seen(&peer.id, &peer.instance_id).await;
+        {
+            let mut book = self.state.peer_addrs.write().await;
+            let addrs = book.entry(peer.id).or_default();
+            match seen {
+                true => addrs.add(peer.transport_addr()),
+                false => addrs.prefer(peer.transport_addr()),
+            }
+            if let Some(first) = addrs.first() {
+                peer.addr = first.ip();
+                peer.transport_port = first.port();
+            }
+        }
+
+        if !seen {
             info!("Peer connected: {}", peer.id);
             self.send_sse_event(ServerEvent::PeerConnected {
                 id: peer.id,
@@ -38,6 +65,8 @@ impl PeerManager {
                     .unwrap_or_default()
                     .as_secs(),
                 sync_dirs: peer.sync_dirs.keys().cloned().collect(),
+                protocol_version: peer.protocol_version,
+                capabilities: peer.capabilities.iter().copied().collect(),
             })
             .await;
         }
@@ -51,23 +80,136 @@ impl PeerManager {
         matches!(self.state.peers.read().await.get(id), Some(peer) if peer.instance_id == *instance_id)
     }
 
-    pub async fn exists(&self, addr: IpAddr) -> bool {
-        self.state
+    /// Marks `id` as heard from just now. No-op for unknown peers.
+    pub async fn touch(&self, id: &Uuid) {
+        if let Some(peer) = self.state.peers.write().await.get_mut(id) {
+            peer.last_seen = SystemTime::now();
+        }
+    }
+
+    /// Removes every peer not heard from for longer than `timeout`,
+    /// announcing each as disconnected. Returns the evicted ids.
+    pub async fn evict_silent(&self, timeout: Duration) -> Vec<Uuid> {
+        let silent: Vec<Uuid> = self
+            .state
             .peers
             .read()
             .await
             .values()
-            .any(|peer| peer.addr == addr)
+            .filter(|peer| {
+                peer.last_seen
+                    .elapsed()
+                    .is_ok_and(|silence| silence > timeout)
+            })
+            .map(|peer| peer.id)
+            .collect();
+
+        for id in &silent {
+            info!(peer = %id, "Evicting silent peer");
+            self.remove_peer(*id).await;
+        }
+        silent
+    }
+
+    pub async fn exists(&self, addr: SocketAddr) -> bool {
+        self.state.peer_at(addr).await.is_some()
+    }
+
+    /// Adds addresses `id` announced to its `PeerAddrs`, behind the
+    /// ones already known.
+    ///
+    /// A connected peer announcing an address not known yet has moved,
+    /// so its announced addresses go in front of the old ones instead.
+    /// Returns whether it moved.
+    pub async fn add_addrs(&self, id: Uuid, addrs: &[SocketAddr]) -> bool {
+        let connected = self.state.peers.read().await.contains_key(&id);
+
+        let mut book = self.state.peer_addrs.write().await;
+        let known = book.entry(id).or_default();
+        let moved = connected && addrs.iter().any(|addr| !known.contains(*addr));
+        for addr in addrs {
+            known.add(*addr);
+        }
+        if moved {
+            for addr in addrs.iter().rev() {
+                known.prefer(*addr);
+            }
+        }
+        moved
+    }
+
+    /// Where to try reaching the device at `target`, in order: all of
+    /// its `PeerAddrs` if it has any, else `target` alone.
+    pub async fn candidates(&self, target: SocketAddr) -> Vec<SocketAddr> {
+        let book = self.state.peer_addrs.read().await;
+        book.values()
+            .find(|addrs| addrs.contains(target))
+            .map(PeerAddrs::to_vec)
+            .unwrap_or_else(|| vec![target])
+    }
+
+    /// Records that a message went through to `addr`, moving it to the
+    /// front of its device's `PeerAddrs` so it is tried first next
+    /// time. Emits `PeerAddressChanged` when that changes the connected
+    /// peer's address.
+    pub async fn remember(&self, addr: SocketAddr) {
+        let id = {
+            let mut book = self.state.peer_addrs.write().await;
+            let Some((id, addrs)) = book.iter_mut().find(|(_, addrs)| addrs.contains(addr)) else {
+                return;
+            };
+            addrs.prefer(addr);
+            *id
+        };
+
+        {
+            let mut peers = self.state.peers.write().await;
+            let Some(peer) = peers.get_mut(&id) else {
+                return;
+            };
+            if peer.addr == addr.ip() && peer.transport_port == addr.port() {
+                return;
+            }
+            trace!(peer = %id, %addr, "peer reached at another address");
+            peer.addr = addr.ip();
+            peer.transport_port = addr.port();
+        }
+        self.send_sse_event(ServerEvent::PeerAddressChanged {
+            id,
+            addr: addr.ip(),
+        })
+        .await;
+    }
+
+    /// Connected peers whose preferred `PeerAddrs` entry is not the
+    /// address they are connected at, i.e. that announced a move no
+    /// handshake has followed yet, each with the address to reach it at.
+    pub async fn moved(&self) -> Vec<(Uuid, SocketAddr)> {
+        let book = self.state.peer_addrs.read().await;
+        let peers = self.state.peers.read().await;
+        peers
+            .values()
+            .filter_map(|peer| {
+                let addr = book.get(&peer.id)?.first()?;
+                (addr != peer.transport_addr()).then_some((peer.id, addr))
+            })
+            .collect()
+    }
+
+    /// The port `id`'s transport listens on, if it is a known peer.
+    pub async fn transport_port(&self, id: &Uuid) -> Option<u16> {
+        let peers = self.state.peers.read().await;
+        peers.get(id).map(|peer| peer.transport_port)
     }
 
     pub async fn list(&self) -> Vec<Peer> {
         self.state.peers.read().await.values().cloned().collect()
     }
 
-    /// Returns the addresses of peers that share the sync directory
-    /// containing `entry`, i.e. the recipients of an outbound
+    /// Returns the transport addresses of peers that share the sync
+    /// directory containing `entry`, i.e. the recipients of an outbound
     /// metadata broadcast for that entry.
-    pub async fn get_peers_to_send_metadata(&self, entry: &EntryInfo) -> Vec<IpAddr> {
+    pub async fn get_peers_to_send_metadata(&self, entry: &EntryInfo) -> Vec<SocketAddr> {
         let root_dir = entry.get_sync_dir();
 
         self.state
@@ -76,33 +218,148 @@ impl PeerManager {
             .await
             .values()
             .filter(|peer| peer.sync_dirs.contains_key(&root_dir))
-            .map(|peer| peer.addr)
+            .map(|peer| peer.transport_addr())
             .collect()
     }
 
+    /// Returns the transport address of a peer sharing the sync
+    /// directory containing `entry` to request it from: the one with
+    /// the highest counter in the entry's version, which wrote it last.
+    pub async fn get_peer_to_request(&self, entry: &EntryInfo) -> Option<SocketAddr> {
+        let root_dir = entry.get_sync_dir();
+
+        self.state
+            .peers
+            .read()
+            .await
+            .values()
+            .filter(|peer| peer.sync_dirs.contains_key(&root_dir))
+            .max_by_key(|peer| entry.version.get(&peer.id).copied().unwrap_or(0))
+            .map(|peer| peer.transport_addr())
+    }
+
+    /// Seeds the trusted set with ids approved in earlier runs.
+    pub async fn load_trusted(&self, ids: Vec<Uuid>) {
+        self.state.trusted_devices.write().await.extend(ids);
+    }
+
+    pub async fn is_trusted(&self, id: &Uuid) -> bool {
+        self.state.is_trusted_device(id).await
+    }
+
+    /// Records an untrusted device as awaiting approval. Trusted and
+    /// rejected ids are ignored. Emits `DevicePending` when the device
+    /// is new, or when its addresses or hostname changed — a handshake
+    /// following a bare presence ping fills in the hostname.
+    #[tracing::instrument(skip_all, fields(device = %device.id, addr = %device.addr))]
+    pub async fn add_pending(&self, mut device: PendingDevice) {
+        if self.is_trusted(&device.id).await
+            || self
+                .state
+                .rejected_devices
+                .read()
+                .await
+                .contains(&device.id)
+        {
+            return;
+        }
+
+        let mut pending = self.state.pending_devices.write().await;
+
+        if let Some(known) = pending.get(&device.id) {
+            if device.hostname.is_none() {
+                device.hostname = known.hostname.clone();
+            }
+            if known.transport_addr() == device.transport_addr()
+                && known.hostname == device.hostname
+            {
+                return;
+            }
+        }
+
+        info!("Device pending approval: {}", device.id);
+        pending.insert(device.id, device.clone());
+        drop(pending);
+
+        self.send_sse_event(ServerEvent::DevicePending {
+            id: device.id,
+            addr: device.addr,
+            hostname: device.hostname,
+        })
+        .await;
+    }
+
+    pub async fn get_pending(&self, id: &Uuid) -> Option<PendingDevice> {
+        self.state.pending_devices.read().await.get(id).cloned()
+    }
+
+    pub async fn list_pending(&self) -> Vec<PendingDevice> {
+        self.state
+            .pending_devices
+            .read()
+            .await
+            .values()
+            .cloned()
+            .collect()
+    }
+
+    /// Moves a pending device into the trusted set. Returns `None` if
+    /// `id` was not pending.
+    #[tracing::instrument(skip_all, fields(device = %id))]
+    pub async fn approve(&self, id: Uuid) -> Option<PendingDevice> {
+        let device = self.state.pending_devices.write().await.remove(&id)?;
+        self.state.trusted_devices.write().await.insert(id);
+
+        info!("Device approved: {id}");
+        self.send_sse_event(ServerEvent::DeviceApproved(id)).await;
+        Some(device)
+    }
+
+    /// Drops a pending device and ignores it until the next restart.
+    /// Returns `false` if `id` was not pending.
+    #[tracing::instrument(skip_all, fields(device = %id))]
+    pub async fn reject(&self, id: Uuid) -> bool {
+        if self
+            .state
+            .pending_devices
+            .write()
+            .await
+            .remove(&id)
+            .is_none()
+        {
+            return false;
+        }
+        self.state.rejected_devices.write().await.insert(id);
+
+        info!("Device rejected: {id}");
+        self.send_sse_event(ServerEvent::DeviceRejected(id)).await;
+        true
+    }
+
+    /// Forgets the peer and every address it was known at.
     #[tracing::instrument(skip_all, fields(peer = %id))]
     pub async fn remove_peer(&self, id: Uuid) {
+        self.state.peer_addrs.write().await.remove(&id);
         if self.state.peers.write().await.remove(&id).is_some() {
             info!("Peer disconnected: {id}");
+            self.announce_removal(id);
             self.send_sse_event(ServerEvent::PeerDisconnected(id)).await;
         }
     }
 
     #[tracing::instrument(skip_all, fields(addr = %addr))]
-    pub async fn remove_peer_by_addr(&self, addr: IpAddr) {
-        let mut peers = self.state.peers.write().await;
-
-        if let Some(peer_id) = peers
-            .iter()
-            .find_map(|(id, peer)| (peer.addr == addr).then_some(*id))
-        {
-            peers.remove(&peer_id);
-            info!("Peer disconnected: {peer_id}");
-            self.send_sse_event(ServerEvent::PeerDisconnected(peer_id))
-                .await;
+    pub async fn remove_peer_by_addr(&self, addr: SocketAddr) {
+        if let Some(peer_id) = self.state.peer_at(addr).await {
+            self.remove_peer(peer_id).await;
         }
     }
 
+    /// Tells adapters the peer is gone so they close its connections;
+    /// nobody listening is fine.
+    fn announce_removal(&self, id: Uuid) {
+        let _ = self.state.peer_removals.sender().send(id);
+    }
+
     async fn send_sse_event(&self, event: ServerEvent) {
         if let Err(err) = self.sse_tx.send(event) {
             tracing::error!("Send Peer SSE error: {err}");
@@ -115,10 +372,12 @@ mod tests {
     use super::*;
     use crate::domain::{RelativePath, SyncDirectory};
     use std::collections::HashMap;
-    use std::net::{IpAddr, Ipv4Addr};
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
     use std::time::SystemTime;
     use tokio::sync::broadcast::error::TryRecvError;
 
+    const PORT: u16 = 42882;
+
     async fn setup() -> (
         crate::utils::test_support::TestEnv,
         Arc<PeerManager>,
@@ -135,15 +394,18 @@ mod tests {
             id,
             instance_id: instance,
             addr,
+            transport_port: PORT,
             hostname: "host".into(),
             last_seen: SystemTime::now(),
             sync_dirs: dirs
                 .into_iter()
                 .map(|d| {
                     let rel: RelativePath = d.into();
-                    (rel.clone(), SyncDirectory { name: rel })
+                    (rel.clone(), SyncDirectory::new(rel))
                 })
                 .collect::<HashMap<_, _>>(),
+            protocol_version: 0,
+            capabilities: Default::default(),
         }
     }
 
@@ -176,7 +438,7 @@ mod tests {
         pm.insert(peer(id, Uuid::new_v4(), addr, vec![])).await;
 
         assert_eq!(drain_connect(&mut rx), Some(id));
-        assert!(pm.exists(addr).await);
+        assert!(pm.exists(SocketAddr::new(addr, PORT)).await);
     }
 
     #[tokio::test]
@@ -233,21 +495,109 @@ mod tests {
 
         pm.remove_peer(id).await;
         assert_eq!(drain_disconnect(&mut rx), Some(id));
-        assert!(!pm.exists(addr).await);
+        assert!(!pm.exists(SocketAddr::new(addr, PORT)).await);
     }
 
     #[tokio::test]
     async fn remove_peer_by_addr_emits_disconnected_and_removes() {
-        let (_env, pm, mut rx) = setup().await;
+        let (env, pm, mut rx) = setup().await;
+        let mut removals = env.state.subscribe_peer_removals();
         let id = Uuid::new_v4();
         let addr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 6));
 
         pm.insert(peer(id, Uuid::new_v4(), addr, vec![])).await;
         let _ = drain_connect(&mut rx);
 
-        pm.remove_peer_by_addr(addr).await;
+        pm.remove_peer_by_addr(SocketAddr::new(addr, PORT)).await;
         assert_eq!(drain_disconnect(&mut rx), Some(id));
-        assert!(!pm.exists(addr).await);
+        assert_eq!(removals.try_recv().unwrap(), id);
+        assert!(!pm.exists(SocketAddr::new(addr, PORT)).await);
+    }
+
+    #[tokio::test]
+    async fn evict_silent_removes_only_peers_quiet_past_the_timeout() {
+        let (_env, pm, mut rx) = setup().await;
+        let (quiet, touched, fresh) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let long_ago = SystemTime::now() - Duration::from_secs(600);
+
+        for (id, last) in [(quiet, 1), (touched, 2), (fresh, 3)] {
+            let mut peer = peer(
+                id,
+                Uuid::new_v4(),
+                IpAddr::V4(Ipv4Addr::new(10, 0, 2, last)),
+                vec![],
+            );
+            if id != fresh {
+                peer.last_seen = long_ago;
+            }
+            pm.insert(peer).await;
+        }
+        while drain_connect(&mut rx).is_some() {}
+        pm.touch(&touched).await;
+
+        let evicted = pm.evict_silent(Duration::from_secs(60)).await;
+
+        assert_eq!(evicted, vec![quiet]);
+        assert_eq!(drain_disconnect(&mut rx), Some(quiet));
+        let mut left: Vec<Uuid> = pm.list().await.iter().map(|peer| peer.id).collect();
+        left.sort();
+        let mut expected = vec![touched, fresh];
+        expected.sort();
+        assert_eq!(left, expected);
+    }
+
+    #[tokio::test]
+    async fn refreshes_keep_the_remembered_address_and_restarts_replace_it() {
+        let (_env, pm, _rx) = setup().await;
+        let id = Uuid::new_v4();
+        let instance = Uuid::new_v4();
+        let ipv4 = IpAddr::V4(Ipv4Addr::new(10, 0, 3, 1));
+        let ipv6: IpAddr = "2001:db8::1".parse().unwrap();
+        let ipv6_addr = SocketAddr::new(ipv6, PORT);
+
+        pm.insert(peer(id, instance, ipv4, vec![])).await;
+        pm.add_addrs(id, &[ipv6_addr]).await;
+        pm.remember(ipv6_addr).await;
+        assert_eq!(pm.list().await[0].addr, ipv6);
+
+        // A handshake from the same instance only adds its address.
+        pm.insert(peer(id, instance, ipv4, vec![])).await;
+        assert_eq!(pm.list().await[0].addr, ipv6);
+        assert!(pm.exists(SocketAddr::new(ipv4, PORT)).await);
+
+        // After a restart, the handshake's address goes first.
+        pm.insert(peer(id, Uuid::new_v4(), ipv4, vec![])).await;
+        assert_eq!(pm.list().await[0].addr, ipv4);
+        assert_eq!(
+            pm.candidates(ipv6_addr).await,
+            vec![SocketAddr::new(ipv4, PORT), ipv6_addr]
+        );
+
+        pm.remove_peer(id).await;
+        assert_eq!(pm.candidates(ipv6_addr).await, vec![ipv6_addr]);
+    }
+
+    #[tokio::test]
+    async fn moved_peer_is_announced_once_it_is_reached_at_the_new_address() {
+        let (_env, pm, mut rx) = setup().await;
+        let id = Uuid::new_v4();
+        let old = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 5));
+        let new = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 5)), PORT);
+
+        pm.insert(peer(id, Uuid::new_v4(), old, vec![])).await;
+        assert!(!pm.add_addrs(id, &[SocketAddr::new(old, PORT)]).await);
+        assert!(pm.add_addrs(id, &[new]).await);
+        assert_eq!(pm.list().await[0].addr, old);
+        drain_connect(&mut rx);
+
+        pm.remember(new).await;
+        pm.remember(new).await;
+        assert_eq!(pm.list().await[0].addr, new.ip());
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(ServerEvent::PeerAddressChanged { id: changed, addr }) if changed == id && addr == new.ip()
+        ));
+        assert!(matches!(rx.try_recv(), Err(TryRecvError::Empty)));
     }
 
     #[tokio::test]
@@ -255,7 +605,7 @@ mod tests {
         let (_env, pm, mut rx) = setup().await;
         let addr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 99));
 
-        pm.remove_peer_by_addr(addr).await;
+        pm.remove_peer_by_addr(SocketAddr::new(addr, PORT)).await;
         assert_eq!(drain_disconnect(&mut rx), None);
     }
 
@@ -280,10 +630,91 @@ mod tests {
             kind: crate::domain::EntryKind::File,
             hash: Some("h".into()),
             version: HashMap::new(),
+            modified: None,
+            removed_at: None,
         };
 
         let recipients = pm.get_peers_to_send_metadata(&entry).await;
-        assert_eq!(recipients, vec![sharing]);
+        assert_eq!(recipients, vec![SocketAddr::new(sharing, PORT)]);
+    }
+
+    fn pending(id: Uuid, hostname: Option<&str>) -> PendingDevice {
+        PendingDevice {
+            id,
+            addr: IpAddr::V4(Ipv4Addr::new(10, 0, 1, 1)),
+            transport_port: 42882,
+            hostname: hostname.map(str::to_string),
+        }
+    }
+
+    #[tokio::test]
+    async fn add_pending_emits_event_again_only_when_hostname_is_learned() {
+        let (_env, pm, mut rx) = setup().await;
+        let id = Uuid::new_v4();
+
+        pm.add_pending(pending(id, None)).await;
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(ServerEvent::DevicePending { hostname: None, .. })
+        ));
+
+        pm.add_pending(pending(id, None)).await;
+        assert!(matches!(rx.try_recv(), Err(TryRecvError::Empty)));
+
+        pm.add_pending(pending(id, Some("laptop"))).await;
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(ServerEvent::DevicePending { hostname: Some(h), .. }) if h == "laptop"
+        ));
+
+        // A later bare ping must not erase the hostname.
+        pm.add_pending(pending(id, None)).await;
+        assert!(matches!(rx.try_recv(), Err(TryRecvError::Empty)));
+        assert_eq!(
+            pm.list_pending().await[0].hostname.as_deref(),
+            Some("laptop")
+        );
+    }
+
+    #[tokio::test]
+    async fn approve_moves_pending_device_into_trusted_set() {
+        let (_env, pm, mut rx) = setup().await;
+        let id = Uuid::new_v4();
+
+        pm.add_pending(pending(id, None)).await;
+        let _ = rx.try_recv();
+
+        assert!(pm.approve(id).await.is_some());
+        assert!(pm.is_trusted(&id).await);
+        assert!(pm.list_pending().await.is_empty());
+        assert!(matches!(rx.try_recv(), Ok(ServerEvent::DeviceApproved(ev)) if ev == id));
+
+        // Trusted devices never re-enter the pending list.
+        pm.add_pending(pending(id, None)).await;
+        assert!(pm.list_pending().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn rejected_device_is_not_listed_as_pending_again() {
+        let (_env, pm, _rx) = setup().await;
+        let id = Uuid::new_v4();
+
+        pm.add_pending(pending(id, None)).await;
+        assert!(pm.reject(id).await);
+        assert!(!pm.reject(id).await);
+
+        pm.add_pending(pending(id, Some("laptop"))).await;
+        assert!(pm.list_pending().await.is_empty());
+        assert!(!pm.is_trusted(&id).await);
+    }
+
+    #[tokio::test]
+    async fn approve_unknown_device_returns_none() {
+        let (_env, pm, _rx) = setup().await;
+        let id = Uuid::new_v4();
+
+        assert!(pm.approve(id).await.is_none());
+        assert!(!pm.is_trusted(&id).await);
     }
 
     #[tokio::test]
@@ -0,0 +1,90 @@
+use crate::{
+    application::AppState,
+    domain::{CanonicalPath, RelativePath, Snapshot},
+    utils::fs::{SYNCHE_DIR, clone_file},
+};
+use std::sync::Arc;
+use tokio::{fs, io};
+
+const SNAPSHOTS_DIR: &str = "snapshots";
+
+/// File contents of the snapshots of each sync directory, kept under
+/// `<sync dir>/.synche/snapshots/<id>`.
+///
+/// Contents are copied rather than hard linked: a hard link would take
+/// in every later edit made in place. Within one filesystem that can
+/// clone files (Btrfs, XFS or APFS) each copy is a reflink, so a
+/// snapshot costs no space until its files change. Which entries a
+/// snapshot holds is recorded in the store; this only keeps contents.
+pub struct SnapshotStore {
+    state: Arc<AppState>,
+}
+
+impl SnapshotStore {
+    pub fn new(state: Arc<AppState>) -> Self {
+        Self { state }
+    }
+
+    /// Copies the file at `name` into `snapshot` and returns where the
+    /// copy is.
+    pub async fn save(
+        &self,
+        snapshot: &Snapshot,
+        name: &RelativePath,
+    ) -> io::Result<CanonicalPath> {
+        let location = self.location(snapshot, name)?;
+        if let Some(parent) = location.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        clone_file(name.to_canonical(self.state.home_path()), &location).await?;
+        Ok(location)
+    }
+
+    /// Puts the contents `snapshot` holds for `name` back in place. The
+    /// copy is made aside within the store and renamed over `name`, so
+    /// the file is never half-written.
+    pub async fn restore(&self, snapshot: &Snapshot, name: &RelativePath) -> io::Result<()> {
+        let location = self.location(snapshot, name)?;
+        let mut restoring = location.as_os_str().to_owned();
+        restoring.push(".restoring");
+        clone_file(&location, &restoring).await?;
+
+        let path = name.to_canonical(self.state.home_path());
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        fs::rename(&restoring, &path).await
+    }
+
+    /// Deletes the contents of `snapshot`. Deleting a missing snapshot
+    /// does not error.
+    pub async fn delete(&self, snapshot: &Snapshot) -> io::Result<()> {
+        match fs::remove_dir_all(self.dir(snapshot)).await {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+
+    fn location(&self, snapshot: &Snapshot, name: &RelativePath) -> io::Result<CanonicalPath> {
+        let rest = name
+            .strip_prefix(&*snapshot.dir)
+            .and_then(|rest| rest.strip_prefix('/'))
+            .filter(|_| name.is_safe_sync_path())
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{name} is not in {}", snapshot.dir),
+                )
+            })?;
+        Ok(self.dir(snapshot).join(rest))
+    }
+
+    fn dir(&self, snapshot: &Snapshot) -> CanonicalPath {
+        self.state
+            .home_path()
+            .join(&*snapshot.dir)
+            .join(SYNCHE_DIR)
+            .join(SNAPSHOTS_DIR)
+            .join(snapshot.id.to_string())
+    }
+}
@@ -0,0 +1,90 @@
+use crate::{
+    application::AppState,
+    domain::{CanonicalPath, TrashRecord},
+    utils::fs::SYNCHE_DIR,
+};
+use std::sync::Arc;
+use tokio::{fs, io};
+
+const TRASH_DIR: &str = "trash";
+
+/// Entries deleted by peers, kept under `<sync dir>/.synche/trash`
+/// instead of being removed from disk.
+///
+/// Each trashed entry gets a folder named by its `TrashRecord::id` and
+/// keeps its path within the sync directory below it, so entries
+/// trashed from the same path never collide. Which entries are in the
+/// trash is recorded in the store; this only moves them.
+pub struct Trash {
+    state: Arc<AppState>,
+}
+
+impl Trash {
+    pub fn new(state: Arc<AppState>) -> Self {
+        Self { state }
+    }
+
+    /// Moves the entry of `record` into the trash.
+    pub async fn put(&self, record: &TrashRecord) -> io::Result<()> {
+        let location = self.location(record)?;
+        if let Some(parent) = location.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        fs::rename(record.path.to_canonical(self.state.home_path()), location).await
+    }
+
+    /// Moves the entry of `record` back to its path. Fails with
+    /// `AlreadyExists` if something is at the path again.
+    pub async fn restore(&self, record: &TrashRecord) -> io::Result<()> {
+        let path = record.path.to_canonical(self.state.home_path());
+        if fs::symlink_metadata(&path).await.is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} exists", record.path),
+            ));
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        fs::rename(self.location(record)?, path).await?;
+        self.purge(record).await
+    }
+
+    /// Deletes the entry of `record` for good. Purging a missing entry
+    /// does not error.
+    pub async fn purge(&self, record: &TrashRecord) -> io::Result<()> {
+        match fs::remove_dir_all(self.dir(record)?).await {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+
+    fn location(&self, record: &TrashRecord) -> io::Result<CanonicalPath> {
+        let (_, rest) = record
+            .path
+            .split_once('/')
+            .ok_or_else(|| not_trashable(record))?;
+        Ok(self.dir(record)?.join(rest))
+    }
+
+    fn dir(&self, record: &TrashRecord) -> io::Result<CanonicalPath> {
+        if !record.path.is_safe_sync_path() || !record.path.contains('/') {
+            return Err(not_trashable(record));
+        }
+        Ok(self
+            .state
+            .home_path()
+            .join(&*record.path.sync_dir())
+            .join(SYNCHE_DIR)
+            .join(TRASH_DIR)
+            .join(record.id.to_string()))
+    }
+}
+
+/// Sync directories themselves are never trashed.
+fn not_trashable(record: &TrashRecord) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("{} cannot be trashed", record.path),
+    )
+}
@@ -0,0 +1,335 @@
+use crate::{
+    application::AppState,
+    domain::{CanonicalPath, FileVersion, RelativePath, Versioning},
+    utils::fs::{SYNCHE_DIR, clone_file},
+};
+use std::{
+    collections::HashSet,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::{fs, io};
+use walkdir::WalkDir;
+
+const VERSIONS_DIR: &str = "versions";
+
+/// Replaced contents of synced files, kept under
+/// `<sync dir>/.synche/versions` as the directory's `Versioning`
+/// allows.
+///
+/// The version of `<sync dir>/<path>` saved at `<unix>` seconds is the
+/// file `<sync dir>/.synche/versions/<path>~<unix>`. A version saved
+/// within the same second as the newest one is dated a second after it
+/// instead, so none replaces another. `SYNCHE_DIR` is never synced, so
+/// versions stay on the device that saved them.
+pub struct VersionStore {
+    state: Arc<AppState>,
+}
+
+impl VersionStore {
+    pub fn new(state: Arc<AppState>) -> Self {
+        Self { state }
+    }
+
+    /// Keeps the contents of the file at `name`, about to be replaced,
+    /// and prunes its versions.
+    pub async fn keep(&self, name: &RelativePath) -> io::Result<()> {
+        self.save(name).await?;
+        self.prune(name).await
+    }
+
+    /// Saves the file at `name` as a version and leaves it in place. The
+    /// version is a clone where the filesystem allows, so saving costs
+    /// no space until the file changes, and a copy otherwise; it never
+    /// shares its bytes with the file, so a replace that fails or
+    /// writes in place cannot change it.
+    pub async fn save(&self, name: &RelativePath) -> io::Result<()> {
+        let path = name.to_canonical(self.state.home_path());
+        if !path.is_file() || self.versioning(name).await.is_off() {
+            return Ok(());
+        }
+        let Some(target) = self.prepare(name).await? else {
+            return Ok(());
+        };
+
+        clone_file(&path, &target).await
+    }
+
+    /// The versions kept of the file at `name`, newest first.
+    pub async fn list(&self, name: &RelativePath) -> io::Result<Vec<FileVersion>> {
+        let Some(latest) = self.target(name, 0) else {
+            return Ok(Vec::new());
+        };
+        let (Some(dir), Some(file_name)) = (latest.parent(), name.rsplit('/').next()) else {
+            return Ok(Vec::new());
+        };
+
+        let mut read_dir = match fs::read_dir(dir).await {
+            Ok(read_dir) => read_dir,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+        let mut versions = Vec::new();
+        while let Some(entry) = read_dir.next_entry().await? {
+            let stored = entry.file_name().to_string_lossy().into_owned();
+            let Some((original, saved_at)) = parse(&stored) else {
+                continue;
+            };
+            if original == file_name && entry.file_type().await?.is_file() {
+                versions.push(FileVersion {
+                    path: name.clone(),
+                    saved_at,
+                    size: entry.metadata().await?.len(),
+                });
+            }
+        }
+        versions.sort_by_key(|v| std::cmp::Reverse(v.saved_at));
+        Ok(versions)
+    }
+
+    /// Puts the version of `name` saved at `saved_at` back in place,
+    /// keeping the contents it replaces as a version of their own.
+    /// Returns `false` if no such version is kept.
+    ///
+    /// The version is copied aside within the store and renamed over
+    /// `name`, so the file is never half-written and never shares its
+    /// bytes with a kept version.
+    pub async fn restore(&self, name: &RelativePath, saved_at: u64) -> io::Result<bool> {
+        let Some(version) = self.target(name, saved_at).filter(|path| path.is_file()) else {
+            return Ok(false);
+        };
+        let mut restoring = version.as_os_str().to_owned();
+        restoring.push(".restoring");
+        fs::copy(&version, &restoring).await?;
+
+        self.save(name).await?;
+        let path = name.to_canonical(self.state.home_path());
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        fs::rename(&restoring, &path).await?;
+
+        self.prune(name).await?;
+        Ok(true)
+    }
+
+    /// Deletes the versions of `name` past its directory's retention.
+    pub async fn prune(&self, name: &RelativePath) -> io::Result<()> {
+        let versioning = self.versioning(name).await;
+        let saved_at: Vec<u64> = self.list(name).await?.iter().map(|v| v.saved_at).collect();
+
+        for saved_at in versioning.expired(&saved_at, now()) {
+            if let Some(path) = self.target(name, saved_at) {
+                remove_if_exists(&path).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Prunes the versions of every file in every sync directory, so
+    /// versions age out even when their file is not replaced again.
+    pub async fn prune_all(&self) -> io::Result<()> {
+        let sync_dirs: Vec<RelativePath> =
+            { self.state.sync_dirs.read().await.keys().cloned().collect() };
+        let home = self.state.home_path();
+
+        for sync_dir in sync_dirs {
+            let root = home.join(&*sync_dir).join(SYNCHE_DIR).join(VERSIONS_DIR);
+            let originals: HashSet<RelativePath> = WalkDir::new(&root)
+                .into_iter()
+                .filter_map(Result::ok)
+                .filter(|entry| entry.file_type().is_file())
+                .filter_map(|entry| {
+                    let rest = entry.path().strip_prefix(&root).ok()?.to_str()?;
+                    let (original, _) = parse(rest)?;
+                    Some(format!("{sync_dir}/{}", original.replace('\\', "/")).into())
+                })
+                .collect();
+
+            for original in originals {
+                self.prune(&original).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn versioning(&self, name: &RelativePath) -> Versioning {
+        self.state
+            .sync_dirs
+            .read()
+            .await
+            .get(&name.sync_dir())
+            .map_or(Versioning::Off, |dir| dir.versioning)
+    }
+
+    /// `target` for a version of `name` saved now, with its parent
+    /// created. It is dated after the newest version kept, so a file
+    /// replaced twice within a second keeps both versions, in order.
+    async fn prepare(&self, name: &RelativePath) -> io::Result<Option<CanonicalPath>> {
+        let newest = self.list(name).await?.first().map(|v| v.saved_at);
+        let saved_at = newest.map_or(now(), |newest| now().max(newest + 1));
+
+        let Some(target) = self.target(name, saved_at) else {
+            return Ok(None);
+        };
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        Ok(Some(target))
+    }
+
+    fn target(&self, name: &RelativePath, saved_at: u64) -> Option<CanonicalPath> {
+        let (sync_dir, rest) = name.split_once('/')?;
+        Some(
+            self.state
+                .home_path()
+                .join(sync_dir)
+                .join(SYNCHE_DIR)
+                .join(VERSIONS_DIR)
+                .join(format!("{rest}~{saved_at}")),
+        )
+    }
+}
+
+/// Splits a stored version's name into the name of its file and when it
+/// was saved.
+fn parse(stored: &str) -> Option<(&str, u64)> {
+    let (original, saved_at) = stored.rsplit_once('~')?;
+    if original.is_empty() || !saved_at.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some((original, saved_at.parse().ok()?))
+}
+
+async fn remove_if_exists(path: &CanonicalPath) -> io::Result<()> {
+    match fs::remove_file(path).await {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        domain::SyncDirectory,
+        utils::test_support::{TestEnv, test_env_with_dirs},
+    };
+    use std::fs as std_fs;
+
+    async fn setup(versioning: Versioning) -> (TestEnv, VersionStore) {
+        let env = test_env_with_dirs(&["Docs"]).await;
+        let dir = SyncDirectory {
+            versioning,
+            ..SyncDirectory::new("Docs".into())
+        };
+        env.state
+            .sync_dirs
+            .write()
+            .await
+            .insert(dir.name.clone(), dir);
+        std_fs::create_dir_all(env.home_path().join("Docs/notes")).unwrap();
+        let store = VersionStore::new(env.state.clone());
+        (env, store)
+    }
+
+    fn write(env: &TestEnv, name: &str, contents: &str) {
+        std_fs::write(env.home_path().join(name), contents).unwrap();
+    }
+
+    #[tokio::test]
+    async fn kept_versions_survive_the_file_being_replaced() {
+        let (env, store) = setup(Versioning::Simple { keep: 5 }).await;
+        let name: RelativePath = "Docs/notes/a.txt".into();
+        write(&env, &name, "first");
+
+        store.keep(&name).await.unwrap();
+        // Replaced the way a finished transfer replaces it.
+        let staged = env.home_path().join("staged");
+        std_fs::write(&staged, "second").unwrap();
+        std_fs::rename(&staged, env.home_path().join(&*name)).unwrap();
+
+        let versions = store.list(&name).await.unwrap();
+        assert_eq!(versions.len(), 1);
+        assert_eq!(versions[0].size, 5);
+        let kept = format!("Docs/.synche/versions/notes/a.txt~{}", versions[0].saved_at);
+        assert_eq!(
+            std_fs::read_to_string(env.home_path().join(kept)).unwrap(),
+            "first"
+        );
+        assert!(store.list(&"Docs/notes/a".into()).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn versions_saved_within_a_second_are_all_kept_in_order() {
+        let (env, store) = setup(Versioning::Simple { keep: 5 }).await;
+        let name: RelativePath = "Docs/a.txt".into();
+
+        for contents in ["one", "two", "three"] {
+            write(&env, &name, contents);
+            store.keep(&name).await.unwrap();
+        }
+
+        let versions = store.list(&name).await.unwrap();
+        let kept: Vec<String> = versions
+            .iter()
+            .map(|v| {
+                let stored = format!("Docs/.synche/versions/a.txt~{}", v.saved_at);
+                std_fs::read_to_string(env.home_path().join(stored)).unwrap()
+            })
+            .collect();
+        assert_eq!(kept, vec!["three", "two", "one"]);
+    }
+
+    #[tokio::test]
+    async fn a_version_does_not_change_with_its_file() {
+        let (env, store) = setup(Versioning::Simple { keep: 5 }).await;
+        let name: RelativePath = "Docs/a.txt".into();
+        write(&env, &name, "kept");
+
+        store.save(&name).await.unwrap();
+        // Written in place, as a copy falling back from a failed rename
+        // would.
+        write(&env, &name, "overwritten");
+
+        let saved_at = store.list(&name).await.unwrap()[0].saved_at;
+        let stored = env
+            .home_path()
+            .join(format!("Docs/.synche/versions/a.txt~{saved_at}"));
+        assert_eq!(std_fs::read_to_string(stored).unwrap(), "kept");
+    }
+
+    #[tokio::test]
+    async fn pruning_applies_the_directory_retention() {
+        let (env, store) = setup(Versioning::Simple { keep: 2 }).await;
+        let name: RelativePath = "Docs/a.txt".into();
+        let versions = env.home_path().join("Docs/.synche/versions");
+        std_fs::create_dir_all(&versions).unwrap();
+        for saved_at in [100, 200, 300] {
+            std_fs::write(versions.join(format!("a.txt~{saved_at}")), "old").unwrap();
+        }
+
+        store.prune_all().await.unwrap();
+
+        let saved_at: Vec<u64> = store
+            .list(&name)
+            .await
+            .unwrap()
+            .iter()
+            .map(|v| v.saved_at)
+            .collect();
+        assert_eq!(saved_at, vec![300, 200]);
+
+        let (env, store) = setup(Versioning::Off).await;
+        write(&env, &name, "current");
+        store.keep(&name).await.unwrap();
+        assert!(store.list(&name).await.unwrap().is_empty());
+    }
+}
@@ -3,24 +3,29 @@ use crate::{
     application::{
         EntryManager, PeerManager,
         network::{
-            presence::{interface::PresenceInterface, service::PresenceService},
+            presence::{
+                interface::PresenceInterface, liveness::LivenessService, service::PresenceService,
+            },
             transport::{TransportService, interface::TransportInterface},
         },
         persistence::interface::PersistenceInterface,
         state::default_ports,
         watcher::{FileWatcher, interface::FileWatcherInterface},
     },
-    domain::ServerEvent,
+    domain::{ServerEvent, TransportChannelData},
     infra::{
         self,
-        network::{mdns::MdnsAdapter, tcp::TcpAdapter},
+        network::{
+            presence::{PresenceAdapter, presence_adapter},
+            transport::TransportAdapter,
+        },
         persistence::sqlite::SqliteDb,
         watcher::notify::NotifyFileWatcher,
     },
     utils::dirs::SyncheDirs,
 };
 use std::sync::Arc;
-use tokio::io;
+use tokio::{io, sync::mpsc::Sender};
 
 /// Parses the `HOME_PATH_CHANGED:<old>:<new>` sentinel emitted by the
 /// HTTP layer when the user changes `home_path` through the GUI.
@@ -35,13 +40,16 @@ fn parse_home_path_changed_sentinel(err: &io::Error) -> Option<(String, String)>
     Some((old.to_string(), new.to_string()))
 }
 
-/// Top-level orchestrator that wires the application's four concurrent
-/// subsystems — transport, presence, file watcher, and HTTP server —
-/// around a shared `AppState`.
+/// Top-level orchestrator that wires the application's five concurrent
+/// subsystems — transport, presence, peer liveness, file watcher, and
+/// HTTP server — around a shared `AppState`.
 ///
 /// Generic over each port so tests can inject in-memory adapters; the
-/// production wiring is `Synchronizer<NotifyFileWatcher, TcpAdapter,
-/// SqliteDb, MdnsAdapter>` (see `new_default_with_dirs`).
+/// production wiring is `Synchronizer<NotifyFileWatcher,
+/// TransportAdapter, SqliteDb, PresenceAdapter>` (see
+/// `new_default_with_dirs`), where `TransportAdapter` is the TCP or
+/// QUIC adapter chosen in `config.toml` and `PresenceAdapter` merges
+/// mDNS, the configured `[[peer]]` addresses and subnet broadcast.
 pub struct Synchronizer<
     W: FileWatcherInterface,
     T: TransportInterface,
@@ -52,11 +60,13 @@ pub struct Synchronizer<
     file_watcher: FileWatcher<W, P>,
     peer_manager: Arc<PeerManager>,
     entry_manager: Arc<EntryManager<P>>,
+    sender_tx: Sender<TransportChannelData>,
     presence_service: PresenceService<R>,
+    liveness_service: LivenessService,
     transport_service: TransportService<T, P>,
 }
 
-impl Synchronizer<NotifyFileWatcher, TcpAdapter, SqliteDb, MdnsAdapter> {
+impl Synchronizer<NotifyFileWatcher, TransportAdapter, SqliteDb, PresenceAdapter> {
     /// Builds a `Synchronizer` wired with the production adapters and
     /// the supplied `SyncheDirs` (so the binary uses OS dirs and tests
     /// can inject isolated temporary ones).
@@ -64,11 +74,18 @@ impl Synchronizer<NotifyFileWatcher, TcpAdapter, SqliteDb, MdnsAdapter> {
         let state = AppState::new(dirs, default_ports()).await;
 
         let notify = NotifyFileWatcher::new(state.clone());
-        let mdns_adapter = MdnsAdapter::new(state.clone());
-        let tcp_adapter = TcpAdapter::new(state.clone()).await;
+        let presence_adapter = presence_adapter(state.clone()).await;
+        let transport_adapter = TransportAdapter::new(state.clone()).await;
         let sqlite_adapter = SqliteDb::new(state.dirs().data_db_file()).await.unwrap();
 
-        Self::new(state, notify, mdns_adapter, tcp_adapter, sqlite_adapter).await
+        Self::new(
+            state,
+            notify,
+            presence_adapter,
+            transport_adapter,
+            sqlite_adapter,
+        )
+        .await
     }
 
     /// Runs the synchronizer in a loop, rebuilding the entire
@@ -119,6 +136,9 @@ impl<W: FileWatcherInterface, T: TransportInterface, P: PersistenceInterface, D:
         entry_manager.init().await.unwrap();
 
         let peer_manager = PeerManager::new(state.clone());
+        peer_manager
+            .load_trusted(entry_manager.list_trusted_devices().await.unwrap())
+            .await;
 
         let (transport_service, sender_tx) = TransportService::new(
             transport_adapter,
@@ -139,20 +159,25 @@ impl<W: FileWatcherInterface, T: TransportInterface, P: PersistenceInterface, D:
             presence_adapter,
             state.clone(),
             peer_manager.clone(),
-            sender_tx,
+            sender_tx.clone(),
         );
 
+        let liveness_service =
+            LivenessService::new(state.clone(), peer_manager.clone(), sender_tx.clone());
+
         Self {
             state,
             file_watcher,
             peer_manager,
             entry_manager,
+            sender_tx,
             presence_service,
+            liveness_service,
             transport_service,
         }
     }
 
-    /// Runs the four subsystems concurrently until any one exits or a
+    /// Runs the five subsystems concurrently until any one exits or a
     /// shutdown signal arrives (`SIGINT`/`SIGTERM`/`SIGHUP` on Unix,
     /// `Ctrl+C` elsewhere). Returns the `HOME_PATH_CHANGED:` sentinel
     /// untouched so `run_default_with_restart` can rebuild.
@@ -239,11 +264,13 @@ impl<W: FileWatcherInterface, T: TransportInterface, P: PersistenceInterface, D:
         tokio::select!(
             res = self.transport_service.run() => res,
             res = self.presence_service.run() => res,
+            res = self.liveness_service.run() => res,
             res = self.file_watcher.run() => res,
             res = infra::http::run(
                 self.state.clone(),
                 self.peer_manager.clone(),
                 self.entry_manager.clone(),
+                self.sender_tx.clone(),
             ) => res,
         )
     }
@@ -6,11 +6,14 @@ use crate::{
     },
     domain::{
         Config, ConfigWatcherEvent, EntryInfo, EntryKind, HomeWatcherEvent, RelativePath,
-        ServerEvent, TransportChannelData, WatcherEventPath,
+        ServerEvent, SyncDirectory, TransportChannelData, WatcherEventPath,
     },
     utils::fs::compute_hash,
 };
-use std::{collections::HashSet, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 use tokio::{io, sync::mpsc::Sender};
 use tracing::{error, info, trace, warn};
 
@@ -19,6 +22,8 @@ use tracing::{error, info, trace, warn};
 /// `WatcherBuffer`, and reacts: home-tree changes become outbound
 /// `Metadata` transfers and persistence writes; `config.toml` changes
 /// are applied live (including the `home_path` restart sentinel).
+/// Changes in receive-only directories are neither recorded nor sent,
+/// so the store keeps what the cluster holds for them.
 pub struct FileWatcher<T: FileWatcherInterface, P: PersistenceInterface> {
     adapter: T,
     buffer: WatcherBuffer,
@@ -124,9 +129,15 @@ impl<T: FileWatcherInterface, P: PersistenceInterface> FileWatcher<T, P> {
 
     #[tracing::instrument(skip_all, fields(path = %path.relative))]
     async fn handle_entry_create_or_modify(&self, path: WatcherEventPath) -> io::Result<()> {
+        if self.is_receive_only(&path).await {
+            return Ok(());
+        }
+
         match self.entry_manager.get_entry(&path.relative).await? {
             None => self.handle_entry_create(path).await,
 
+            Some(entry) if entry.is_removed() => self.handle_entry_create(path).await,
+
             Some(entry) if path.is_file() && entry.is_file() => {
                 self.handle_modify_file(path, entry).await
             }
@@ -163,10 +174,11 @@ impl<T: FileWatcherInterface, P: PersistenceInterface> FileWatcher<T, P> {
         let dir_entries = self.entry_manager.build_dir(path.canonical).await?;
 
         for (relative, info) in dir_entries {
-            self.entry_manager
-                .entry_created(&relative, info.kind.clone(), info.hash.clone())
+            let entry = self
+                .entry_manager
+                .entry_created(&relative, info.kind, info.hash)
                 .await?;
-            self.send_metadata(info).await;
+            self.send_metadata(entry).await;
         }
         Ok(())
     }
@@ -187,6 +199,10 @@ impl<T: FileWatcherInterface, P: PersistenceInterface> FileWatcher<T, P> {
 
     #[tracing::instrument(skip_all, fields(path = %path.relative))]
     async fn handle_entry_remove(&self, path: WatcherEventPath) -> io::Result<()> {
+        if self.is_receive_only(&path).await {
+            return Ok(());
+        }
+
         if let Some(removed) = self.entry_manager.remove_entry(&path.relative).await? {
             if !removed.is_file() {
                 let removed_entries = self.entry_manager.remove_dir(&path.relative).await?;
@@ -209,6 +225,13 @@ impl<T: FileWatcherInterface, P: PersistenceInterface> FileWatcher<T, P> {
         Ok(())
     }
 
+    async fn is_receive_only(&self, path: &WatcherEventPath) -> bool {
+        self.state
+            .directory_mode(&path.relative.sync_dir())
+            .await
+            .is_receive_only()
+    }
+
     async fn send_metadata(&self, file: EntryInfo) {
         if let Err(err) = self
             .sender_tx
@@ -251,12 +274,20 @@ impl<T: FileWatcherInterface, P: PersistenceInterface> FileWatcher<T, P> {
             .cloned()
             .collect();
 
-        let new_dirs: HashSet<RelativePath> = new_config
+        let new_config_dirs: HashMap<RelativePath, SyncDirectory> = new_config
             .directory
             .iter()
-            .map(|d| d.name.clone())
+            .map(|d| (d.name.clone(), d.to_sync()))
             .collect();
 
+        for dir in new_config_dirs.values() {
+            if self.entry_manager.update_sync_dir(dir.clone()).await {
+                info!("Sync dir settings changed: {dir:?}");
+            }
+        }
+
+        let new_dirs: HashSet<RelativePath> = new_config_dirs.keys().cloned().collect();
+
         if new_dirs == current_dirs {
             info!("Config modified but sync directories unchanged");
             return Ok(());
@@ -274,7 +305,7 @@ impl<T: FileWatcherInterface, P: PersistenceInterface> FileWatcher<T, P> {
 
         for dir in added {
             trace!("Config change: adding sync dir {dir:?}");
-            if let Err(e) = self.add_sync_dir(dir.clone()).await {
+            if let Err(e) = self.add_sync_dir(new_config_dirs[&dir].clone()).await {
                 error!("Failed to add sync dir {dir:?}: {e}");
             }
         }
@@ -282,8 +313,9 @@ impl<T: FileWatcherInterface, P: PersistenceInterface> FileWatcher<T, P> {
         self.resync_all_peers().await
     }
 
-    async fn add_sync_dir(&self, name: RelativePath) -> io::Result<()> {
-        self.entry_manager.add_sync_dir(name.clone()).await?;
+    async fn add_sync_dir(&self, dir: SyncDirectory) -> io::Result<()> {
+        let name = dir.name.clone();
+        self.entry_manager.add_sync_dir(dir).await?;
         info!("Sync dir added: {name:?}");
         let _ = self
             .state
@@ -311,7 +343,7 @@ impl<T: FileWatcherInterface, P: PersistenceInterface> FileWatcher<T, P> {
 
         for peer in peers {
             self.sender_tx
-                .send(TransportChannelData::HandshakeSyn(peer.addr))
+                .send(TransportChannelData::HandshakeSyn(peer.transport_addr()))
                 .await
                 .map_err(|e| io::Error::other(e.to_string()))?;
         }
@@ -1,5 +1,5 @@
 use crate::{
-    domain::{CanonicalPath, ConfigDirectory},
+    domain::{CanonicalPath, ConfigDirectory, ConfigPeer, DiscoveryConfig, LivenessConfig},
     utils::{dirs::SyncheDirs, fs::default_home_dir},
 };
 use serde::{Deserialize, Serialize};
@@ -7,14 +7,41 @@ use tokio::{fs, io};
 
 /// On-disk representation of `config.toml`.
 ///
-/// Holds the user's chosen `home_path` and the list of sync
-/// directories. Edits to this file are observed by the config watcher
-/// and applied live; changing `home_path` triggers the synchronizer's
+/// Holds the user's chosen `home_path`, the `cluster` this device
+/// belongs to, the peer-to-peer `transport`, the peer `liveness`
+/// timings, which `discovery` sources run, the list of sync directories
+/// and the statically configured peers. Edits to this file are observed by the config watcher and
+/// applied live; changing `home_path` triggers the synchronizer's
 /// restart loop (see `Synchronizer::run_default_with_restart`).
+/// `cluster`, `transport`, `liveness`, `discovery` and `peer` are only
+/// read when the synchronizer starts.
 #[derive(Serialize, Deserialize)]
 pub struct Config {
     pub home_path: CanonicalPath,
+    /// Name shared by the devices that sync together; see `Cluster`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cluster: Option<String>,
+    #[serde(default)]
+    pub transport: TransportProtocol,
+    #[serde(default)]
+    pub liveness: LivenessConfig,
+    #[serde(default)]
+    pub discovery: DiscoveryConfig,
     pub directory: Vec<ConfigDirectory>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub peer: Vec<ConfigPeer>,
+}
+
+/// Which transport carries messages between peers. Devices only reach
+/// each other when they pick the same one.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TransportProtocol {
+    /// Noise-encrypted TCP, multiplexed per peer.
+    #[default]
+    Tcp,
+    /// QUIC, with TLS 1.3 and a stream per message.
+    Quic,
 }
 
 impl Config {
@@ -48,7 +75,12 @@ impl Default for Config {
     fn default() -> Self {
         Self {
             home_path: default_home_dir().unwrap(),
+            cluster: None,
+            transport: TransportProtocol::default(),
+            liveness: LivenessConfig::default(),
+            discovery: DiscoveryConfig::default(),
             directory: vec![ConfigDirectory::new("Default Folder")],
+            peer: Vec::new(),
         }
     }
 }
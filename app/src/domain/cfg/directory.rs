@@ -1,4 +1,6 @@
-use crate::domain::{RelativePath, SyncDirectory};
+use crate::domain::{
+    ConflictPolicy, DirectoryMode, RelativePath, SyncDirectory, TrashExpiry, Versioning,
+};
 use serde::{Deserialize, Serialize};
 
 /// On-disk representation of a single entry in the `directory = [...]`
@@ -8,17 +10,35 @@ use serde::{Deserialize, Serialize};
 #[derive(Serialize, Deserialize)]
 pub struct ConfigDirectory {
     pub name: RelativePath,
+    #[serde(default, skip_serializing_if = "ConflictPolicy::is_default")]
+    pub conflict_policy: ConflictPolicy,
+    #[serde(default, skip_serializing_if = "Versioning::is_default")]
+    pub versioning: Versioning,
+    #[serde(default, skip_serializing_if = "TrashExpiry::is_default")]
+    pub trash_expiry: TrashExpiry,
+    #[serde(default, skip_serializing_if = "DirectoryMode::is_default")]
+    pub mode: DirectoryMode,
 }
 
 impl ConfigDirectory {
     pub fn new(name: &str) -> Self {
-        Self { name: name.into() }
+        Self {
+            name: name.into(),
+            conflict_policy: ConflictPolicy::default(),
+            versioning: Versioning::default(),
+            trash_expiry: TrashExpiry::default(),
+            mode: DirectoryMode::default(),
+        }
     }
 
     /// Returns the in-memory `SyncDirectory` representation.
     pub fn to_sync(&self) -> SyncDirectory {
         SyncDirectory {
             name: self.name.clone(),
+            conflict_policy: self.conflict_policy,
+            versioning: self.versioning,
+            trash_expiry: self.trash_expiry,
+            mode: self.mode,
         }
     }
 }
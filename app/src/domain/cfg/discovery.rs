@@ -0,0 +1,23 @@
+use serde::{Deserialize, Serialize};
+
+/// On-disk representation of the `[discovery]` table of `config.toml`:
+/// which sources look for peers. All are on by default; turning off
+/// `mdns` and `static_peers` leaves broadcast discovery alone, for
+/// networks where only subnet broadcast gets through.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(default)]
+pub struct DiscoveryConfig {
+    pub mdns: bool,
+    pub static_peers: bool,
+    pub broadcast: bool,
+}
+
+impl Default for DiscoveryConfig {
+    fn default() -> Self {
+        Self {
+            mdns: true,
+            static_peers: true,
+            broadcast: true,
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// On-disk representation of the `[liveness]` table of `config.toml`,
+/// in seconds.
+///
+/// `peer_timeout` is how long a peer may stay silent before it is
+/// evicted; `anti_entropy_interval` is how often live peers are
+/// handshaked again to repair missed updates. `0` turns either off,
+/// though anti-entropy keeps running while eviction is on.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(default)]
+pub struct LivenessConfig {
+    pub peer_timeout: u64,
+    pub anti_entropy_interval: u64,
+}
+
+impl LivenessConfig {
+    pub fn peer_timeout(&self) -> Option<Duration> {
+        (self.peer_timeout > 0).then(|| Duration::from_secs(self.peer_timeout))
+    }
+
+    /// The configured interval, capped at a third of `peer_timeout`
+    /// while eviction is on, or that third when anti-entropy is off.
+    /// Its handshakes are what keep alive the peers presence no longer
+    /// pings, so two of them must fit well within the timeout.
+    pub fn anti_entropy_interval(&self) -> Option<Duration> {
+        let configured = self.configured_anti_entropy_interval();
+        match self.peer_timeout() {
+            Some(timeout) => {
+                let longest = timeout / 3;
+                Some(configured.map_or(longest, |interval| interval.min(longest)))
+            }
+            None => configured,
+        }
+    }
+
+    /// Whether `anti_entropy_interval` differs from the configured one.
+    pub fn is_anti_entropy_adjusted(&self) -> bool {
+        self.anti_entropy_interval() != self.configured_anti_entropy_interval()
+    }
+
+    fn configured_anti_entropy_interval(&self) -> Option<Duration> {
+        (self.anti_entropy_interval > 0).then(|| Duration::from_secs(self.anti_entropy_interval))
+    }
+}
+
+impl Default for LivenessConfig {
+    fn default() -> Self {
+        Self {
+            peer_timeout: 180,
+            anti_entropy_interval: 60,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn liveness(peer_timeout: u64, anti_entropy_interval: u64) -> LivenessConfig {
+        LivenessConfig {
+            peer_timeout,
+            anti_entropy_interval,
+        }
+    }
+
+    #[test]
+    fn anti_entropy_runs_well_within_the_peer_timeout() {
+        let secs = |secs| Some(Duration::from_secs(secs));
+
+        assert_eq!(liveness(180, 60).anti_entropy_interval(), secs(60));
+        assert!(!liveness(180, 60).is_anti_entropy_adjusted());
+        assert_eq!(liveness(180, 30).anti_entropy_interval(), secs(30));
+
+        for interval in [0, 180, 600] {
+            let config = liveness(180, interval);
+            assert_eq!(config.anti_entropy_interval(), secs(60));
+            assert!(config.is_anti_entropy_adjusted());
+        }
+
+        assert_eq!(liveness(0, 600).anti_entropy_interval(), secs(600));
+        assert_eq!(liveness(0, 0).anti_entropy_interval(), None);
+        assert!(!liveness(0, 0).is_anti_entropy_adjusted());
+    }
+}
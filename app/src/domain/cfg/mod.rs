@@ -1,5 +1,11 @@
 mod config;
 mod directory;
+mod discovery;
+mod liveness;
+mod peer;
 
-pub use config::Config;
+pub use config::{Config, TransportProtocol};
 pub use directory::ConfigDirectory;
+pub use discovery::DiscoveryConfig;
+pub use liveness::LivenessConfig;
+pub use peer::ConfigPeer;
@@ -0,0 +1,79 @@
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, SocketAddr};
+use uuid::Uuid;
+
+/// On-disk representation of a single entry in the `[[peer]]` list of
+/// `config.toml`: a device to probe directly, for networks where
+/// multicast discovery does not get through.
+///
+/// `address` is a host name or IP, optionally followed by the port the
+/// peer answers probes on (its presence port). `id`, when set, is the
+/// device id the address must answer with; replies from any other
+/// device are ignored.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ConfigPeer {
+    pub address: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<Uuid>,
+}
+
+impl ConfigPeer {
+    /// Splits `address` into host and port, using `default_port` when
+    /// it names none. IPv6 literals take a port only in brackets
+    /// (`[::1]:42881`).
+    pub fn host_port(&self, default_port: u16) -> (String, u16) {
+        let address = self.address.trim();
+
+        if let Ok(addr) = address.parse::<SocketAddr>() {
+            return (addr.ip().to_string(), addr.port());
+        }
+        if let Ok(ip) = address
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse::<IpAddr>()
+        {
+            return (ip.to_string(), default_port);
+        }
+
+        match address.rsplit_once(':') {
+            Some((host, port)) if !host.contains(':') => match port.parse() {
+                Ok(port) => (host.to_string(), port),
+                Err(_) => (address.to_string(), default_port),
+            },
+            _ => (address.to_string(), default_port),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peer(address: &str) -> ConfigPeer {
+        ConfigPeer {
+            address: address.into(),
+            id: None,
+        }
+    }
+
+    #[test]
+    fn host_port_uses_the_named_port_or_the_default() {
+        let cases = [
+            ("10.0.0.5", ("10.0.0.5", 42881)),
+            ("10.0.0.5:5000", ("10.0.0.5", 5000)),
+            ("nas.lan", ("nas.lan", 42881)),
+            (" nas.lan:5000 ", ("nas.lan", 5000)),
+            ("::1", ("::1", 42881)),
+            ("[::1]", ("::1", 42881)),
+            ("[::1]:5000", ("::1", 5000)),
+        ];
+
+        for (address, (host, port)) in cases {
+            assert_eq!(
+                peer(address).host_port(42881),
+                (host.to_string(), port),
+                "{address}"
+            );
+        }
+    }
+}
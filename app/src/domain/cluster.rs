@@ -0,0 +1,150 @@
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use std::fmt::Write;
+use uuid::Uuid;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Hex digits of an announced `tag`.
+const TAG_LEN: usize = 16;
+
+/// The group of devices this one discovers and syncs with, named by
+/// `cluster` in `config.toml`.
+///
+/// The name works as a shared secret and never leaves the device.
+/// Presence announcements carry its `tag`, a short hash that lets
+/// devices skip announcements from other clusters at a glance.
+/// Handshakes carry a `proof`, an HMAC of the sender's id keyed by the
+/// name, which only a device that knows the name can produce. Devices
+/// without a name form the default cluster, which announces no tag and
+/// sends no proof, as builds that predate clusters do.
+#[derive(Clone, Default)]
+pub struct Cluster {
+    name: Option<String>,
+    key: Option<[u8; 32]>,
+}
+
+impl Cluster {
+    /// An empty name is the default cluster.
+    pub fn new(name: Option<String>) -> Self {
+        let name = name.filter(|name| !name.is_empty());
+        let key = name.as_ref().map(|name| {
+            let mut hasher = Sha256::new();
+            hasher.update(b"synche cluster\0");
+            hasher.update(name.as_bytes());
+            hasher.finalize().into()
+        });
+        Self { name, key }
+    }
+
+    /// The configured name, to write back to `config.toml`.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// What presence announcements carry; `None` in the default
+    /// cluster.
+    pub fn tag(&self) -> Option<String> {
+        let mut tag = hex(&self.mac(b"tag")?);
+        tag.truncate(TAG_LEN);
+        Some(tag)
+    }
+
+    /// Whether an announcement carrying `tag` comes from this cluster.
+    pub fn accepts_tag(&self, tag: Option<&str>) -> bool {
+        self.tag().as_deref() == tag
+    }
+
+    /// What the handshakes of `device` carry; `None` in the default
+    /// cluster.
+    pub fn proof(&self, device: Uuid) -> Option<String> {
+        Some(hex(&self.mac(&proof_message(device))?))
+    }
+
+    /// Whether a handshake from `device` carrying `proof` comes from
+    /// this cluster. Compared in constant time.
+    pub fn verifies(&self, device: Uuid, proof: Option<&str>) -> bool {
+        match (&self.key, proof) {
+            (None, None) => true,
+            (Some(key), Some(proof)) => {
+                let Some(proof) = unhex(proof) else {
+                    return false;
+                };
+                let mut mac = HmacSha256::new_from_slice(key).expect("HMAC takes any key size");
+                mac.update(&proof_message(device));
+                mac.verify_slice(&proof).is_ok()
+            }
+            _ => false,
+        }
+    }
+
+    fn mac(&self, message: &[u8]) -> Option<Vec<u8>> {
+        let mut mac =
+            HmacSha256::new_from_slice(self.key.as_ref()?).expect("HMAC takes any key size");
+        mac.update(message);
+        Some(mac.finalize().into_bytes().to_vec())
+    }
+}
+
+impl std::fmt::Debug for Cluster {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Cluster")
+            .field("tag", &self.tag())
+            .finish_non_exhaustive()
+    }
+}
+
+fn proof_message(device: Uuid) -> Vec<u8> {
+    [b"proof".as_slice(), device.as_bytes()].concat()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let mut hex = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        let _ = write!(hex, "{byte:02x}");
+    }
+    hex
+}
+
+fn unhex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cluster(name: &str) -> Cluster {
+        Cluster::new(Some(name.to_string()))
+    }
+
+    #[test]
+    fn only_the_same_cluster_accepts_tags_and_proofs() {
+        let (team_a, team_b, default) = (cluster("team-a"), cluster("team-b"), cluster(""));
+        let device = Uuid::new_v4();
+
+        assert_eq!(default.tag(), None);
+        assert_eq!(team_a.tag().unwrap().len(), TAG_LEN);
+        assert!(team_a.accepts_tag(cluster("team-a").tag().as_deref()));
+        assert!(!team_a.accepts_tag(team_b.tag().as_deref()));
+        assert!(!team_a.accepts_tag(None));
+        assert!(default.accepts_tag(None));
+        assert!(!default.accepts_tag(team_a.tag().as_deref()));
+
+        let proof = team_a.proof(device);
+        assert!(team_a.verifies(device, proof.as_deref()));
+        assert!(!team_a.verifies(Uuid::new_v4(), proof.as_deref()));
+        assert!(!team_b.verifies(device, proof.as_deref()));
+        assert!(!team_a.verifies(device, team_a.tag().as_deref()));
+        assert!(!team_a.verifies(device, Some("not hex")));
+        assert!(!team_a.verifies(device, None));
+        assert!(!default.verifies(device, proof.as_deref()));
+        assert!(default.verifies(device, None));
+    }
+}
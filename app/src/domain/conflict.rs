@@ -0,0 +1,195 @@
+use crate::domain::{EntryInfo, RelativePath, VersionVector};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// A concurrent edit this device gave way on, kept until the user
+/// resolves it.
+///
+/// When a conflict goes to the peer, the local copy of `path` is saved
+/// as `conflict_path` before the peer's version replaces it. The
+/// conflict copy stays on this device and is not synced while the
+/// record is open. `local_version` and `remote_version` are the two
+/// version vectors that were found concurrent.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ConflictRecord {
+    pub id: Uuid,
+    pub path: RelativePath,
+    pub conflict_path: RelativePath,
+    /// Device whose version replaced ours.
+    pub peer: Uuid,
+    pub local_version: VersionVector,
+    pub remote_version: VersionVector,
+    /// Seconds since UNIX epoch.
+    pub detected_at: u64,
+}
+
+/// How the user settles a `ConflictRecord`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum ConflictResolution {
+    /// Restores the conflict copy over `path`, as a local edit that
+    /// supersedes the peer's version.
+    #[serde(rename = "keep_local")]
+    Local,
+    /// Keeps the peer's version and deletes the conflict copy.
+    #[serde(rename = "keep_remote")]
+    Remote,
+    /// Keeps both, syncing the conflict copy as a file of its own.
+    #[serde(rename = "keep_both")]
+    Both,
+}
+
+/// Which side wins a conflict between two live versions of an entry,
+/// set per sync directory by `conflict_policy` in `config.toml`.
+///
+/// Whatever the policy, a live version beats a removed one, and the
+/// side that gives way copies its version aside before adopting the
+/// winner. Every policy decides from what both devices know — the two
+/// entries and the two device ids — so each side reaches the same
+/// decision, provided every device syncing the directory sets the same
+/// policy.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictPolicy {
+    /// The device with the lower id wins, and the conflict is recorded
+    /// for the user to resolve.
+    #[default]
+    LowestId,
+    /// The version modified last wins; ties fall back to `LowestId`.
+    Newest,
+    /// The version from this device wins; conflicts between two other
+    /// devices fall back to `LowestId`.
+    Primary(Uuid),
+    /// The winner is picked as by `LowestId`, and the conflict copy is
+    /// synced as a file of its own instead of being recorded.
+    KeepBoth,
+}
+
+impl ConflictPolicy {
+    /// Whether `local`, from device `local_id`, wins over `peer`, from
+    /// device `peer_id`. Swapping the sides gives the opposite answer.
+    pub fn local_wins(
+        &self,
+        local: &EntryInfo,
+        peer: &EntryInfo,
+        local_id: Uuid,
+        peer_id: Uuid,
+    ) -> bool {
+        let newer = match self {
+            Self::Primary(primary) if *primary == local_id => return true,
+            Self::Primary(primary) if *primary == peer_id => return false,
+            Self::Newest => local.modified.cmp(&peer.modified),
+            _ => std::cmp::Ordering::Equal,
+        };
+        newer.then(peer_id.cmp(&local_id)).is_gt()
+    }
+
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Whether the side that gives way syncs its conflict copy.
+    pub fn keeps_both(&self) -> bool {
+        matches!(self, Self::KeepBoth)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::EntryKind;
+    use std::collections::HashMap;
+
+    fn entry(device: Uuid, modified: Option<u64>) -> EntryInfo {
+        EntryInfo {
+            name: "Notes/todo.md".into(),
+            kind: EntryKind::File,
+            hash: Some(device.to_string()),
+            version: HashMap::from([(device, 1)]),
+            modified,
+            removed_at: None,
+        }
+    }
+
+    /// The winner between `(a, a_modified)` and `(b, b_modified)` as
+    /// each side sees it, asserting both sides agree.
+    fn winner(policy: ConflictPolicy, a: (Uuid, Option<u64>), b: (Uuid, Option<u64>)) -> Uuid {
+        let (ea, eb) = (entry(a.0, a.1), entry(b.0, b.1));
+        let a_wins = policy.local_wins(&ea, &eb, a.0, b.0);
+        assert_ne!(a_wins, policy.local_wins(&eb, &ea, b.0, a.0));
+        if a_wins { a.0 } else { b.0 }
+    }
+
+    fn ids() -> (Uuid, Uuid, Uuid) {
+        let mut ids = [Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4()];
+        ids.sort();
+        (ids[0], ids[1], ids[2])
+    }
+
+    #[test]
+    fn lowest_id_ignores_modification_times() {
+        let (low, high, _) = ids();
+
+        let policy = ConflictPolicy::LowestId;
+        assert_eq!(winner(policy, (low, Some(1)), (high, Some(2))), low);
+        assert_eq!(winner(policy, (high, None), (low, None)), low);
+        assert!(!policy.keeps_both());
+    }
+
+    #[test]
+    fn newest_prefers_the_later_modification_then_the_lowest_id() {
+        let (low, high, _) = ids();
+
+        let policy = ConflictPolicy::Newest;
+        assert_eq!(winner(policy, (low, Some(1)), (high, Some(2))), high);
+        assert_eq!(winner(policy, (low, Some(3)), (high, Some(2))), low);
+        assert_eq!(winner(policy, (low, None), (high, Some(0))), high);
+        assert_eq!(winner(policy, (high, Some(5)), (low, Some(5))), low);
+        assert_eq!(winner(policy, (high, None), (low, None)), low);
+    }
+
+    #[test]
+    fn primary_always_wins_and_others_fall_back_to_the_lowest_id() {
+        let (low, mid, high) = ids();
+
+        let policy = ConflictPolicy::Primary(high);
+        assert_eq!(winner(policy, (low, Some(2)), (high, Some(1))), high);
+        assert_eq!(winner(policy, (high, None), (mid, None)), high);
+        assert_eq!(winner(policy, (mid, Some(2)), (low, Some(1))), low);
+    }
+
+    #[test]
+    fn keep_both_picks_the_lowest_id_and_keeps_the_copy() {
+        let (low, high, _) = ids();
+
+        let policy = ConflictPolicy::KeepBoth;
+        assert_eq!(winner(policy, (low, Some(1)), (high, Some(2))), low);
+        assert!(policy.keeps_both());
+    }
+
+    #[test]
+    fn policies_read_from_config_toml() {
+        #[derive(Deserialize)]
+        struct Dir {
+            conflict_policy: ConflictPolicy,
+        }
+        let policy = |toml: &str| toml::from_str::<Dir>(toml).unwrap().conflict_policy;
+        let primary = Uuid::new_v4();
+
+        assert_eq!(
+            policy(r#"conflict_policy = "lowest_id""#),
+            ConflictPolicy::LowestId
+        );
+        assert_eq!(
+            policy(r#"conflict_policy = "newest""#),
+            ConflictPolicy::Newest
+        );
+        assert_eq!(
+            policy(r#"conflict_policy = "keep_both""#),
+            ConflictPolicy::KeepBoth
+        );
+        assert_eq!(
+            policy(&format!(r#"conflict_policy = {{ primary = "{primary}" }}"#)),
+            ConflictPolicy::Primary(primary)
+        );
+    }
+}
@@ -1,21 +1,105 @@
-use crate::domain::{ConfigDirectory, RelativePath};
+use crate::domain::{ConfigDirectory, ConflictPolicy, RelativePath, TrashExpiry, Versioning};
 use serde::{Deserialize, Serialize};
 
+/// Which way changes flow through a sync directory, set per directory
+/// by `mode` in `config.toml`.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DirectoryMode {
+    /// Local and remote changes are both applied and announced.
+    #[default]
+    SendReceive,
+    /// Local changes are announced; remote ones are recorded in the
+    /// entry's version but never applied, so the local copy wins.
+    SendOnly,
+    /// Remote changes are applied; local ones are neither recorded nor
+    /// announced, and can be reverted to what the cluster holds.
+    ReceiveOnly,
+}
+
+impl DirectoryMode {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn is_send_only(&self) -> bool {
+        *self == Self::SendOnly
+    }
+
+    pub fn is_receive_only(&self) -> bool {
+        *self == Self::ReceiveOnly
+    }
+}
+
 /// A top-level synchronized directory under the Synche home path.
 ///
 /// Sync directories are the root scopes that peers can replicate
 /// independently — entries inside them are addressed by paths relative
-/// to home.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// to home. `conflict_policy` decides which side wins when two peers
+/// edit an entry inside it concurrently, `versioning` which replaced
+/// contents are kept, `trash_expiry` how long entries deleted by peers
+/// stay in its trash, and `mode` which way changes flow; only the first
+/// is sent to peers.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct SyncDirectory {
     pub name: RelativePath,
+    #[serde(default)]
+    pub conflict_policy: ConflictPolicy,
+    #[serde(skip)]
+    pub versioning: Versioning,
+    #[serde(skip)]
+    pub trash_expiry: TrashExpiry,
+    #[serde(skip)]
+    pub mode: DirectoryMode,
 }
 
 impl SyncDirectory {
+    /// A directory with the default conflict policy, versioning, trash
+    /// expiry and mode.
+    #[cfg(test)]
+    pub fn new(name: RelativePath) -> Self {
+        Self {
+            name,
+            conflict_policy: ConflictPolicy::default(),
+            versioning: Versioning::default(),
+            trash_expiry: TrashExpiry::default(),
+            mode: DirectoryMode::default(),
+        }
+    }
+
     /// Returns the on-disk `ConfigDirectory` representation for `config.toml`.
     pub fn to_config(&self) -> ConfigDirectory {
         ConfigDirectory {
             name: self.name.clone(),
+            conflict_policy: self.conflict_policy,
+            versioning: self.versioning,
+            trash_expiry: self.trash_expiry,
+            mode: self.mode,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mode_reads_from_config_toml() {
+        let mode = |toml: &str| toml::from_str::<ConfigDirectory>(toml).unwrap().mode;
+
+        assert_eq!(
+            mode("name = \"Docs\"\nmode = \"send_only\""),
+            DirectoryMode::SendOnly
+        );
+        assert_eq!(
+            mode("name = \"Docs\"\nmode = \"receive_only\""),
+            DirectoryMode::ReceiveOnly
+        );
+        assert_eq!(mode("name = \"Docs\""), DirectoryMode::SendReceive);
+        assert!(
+            !toml::to_string(&ConfigDirectory::new("Docs"))
+                .unwrap()
+                .contains("mode")
+        );
+    }
+}
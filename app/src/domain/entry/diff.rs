@@ -0,0 +1,112 @@
+use crate::domain::{EntryInfo, RelativePath};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// How one set of entries differs from another, each list in name
+/// order: the live entries of a sync directory against one of its
+/// snapshots, or the disk of a receive-only directory against what the
+/// cluster holds.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct EntryDiff {
+    /// Entries only `after` holds.
+    pub added: Vec<RelativePath>,
+    /// Entries only `before` holds.
+    pub removed: Vec<RelativePath>,
+    /// Entries whose contents, or whether they are a file or a
+    /// directory, differ between the two.
+    pub modified: Vec<RelativePath>,
+}
+
+impl EntryDiff {
+    /// Compares the `after` entries against the `before` entries.
+    /// Tombstones count as absent.
+    pub fn between(before: &[EntryInfo], after: &[EntryInfo]) -> Self {
+        let live = |entries: &[EntryInfo]| -> HashMap<RelativePath, EntryInfo> {
+            entries
+                .iter()
+                .filter(|e| !e.is_removed())
+                .map(|e| (e.name.clone(), e.clone()))
+                .collect()
+        };
+        let (before, after) = (live(before), live(after));
+
+        let mut diff = Self::default();
+        for (name, entry) in &after {
+            match before.get(name) {
+                None => diff.added.push(name.clone()),
+                Some(old) if old.kind != entry.kind || old.hash != entry.hash => {
+                    diff.modified.push(name.clone())
+                }
+                Some(_) => {}
+            }
+        }
+        diff.removed = before
+            .into_keys()
+            .filter(|name| !after.contains_key(name))
+            .collect();
+
+        diff.added.sort();
+        diff.removed.sort();
+        diff.modified.sort();
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::EntryKind;
+    use uuid::Uuid;
+
+    fn entry(name: &str, hash: Option<&str>) -> EntryInfo {
+        EntryInfo {
+            name: name.into(),
+            kind: if hash.is_some() {
+                EntryKind::File
+            } else {
+                EntryKind::Directory
+            },
+            hash: hash.map(str::to_string),
+            version: HashMap::from([(Uuid::new_v4(), 1)]),
+            modified: None,
+            removed_at: None,
+        }
+    }
+
+    #[test]
+    fn diff_sorts_entries_into_added_removed_and_modified() {
+        let mut deleted = entry("Docs/deleted.txt", Some("d"));
+        deleted.set_removed_hash();
+        let snapshot = [
+            entry("Docs/same.txt", Some("s")),
+            entry("Docs/edited.txt", Some("old")),
+            entry("Docs/gone.txt", Some("g")),
+            entry("Docs/was_a_file", Some("f")),
+            entry("Docs/deleted.txt", Some("d")),
+        ];
+        let current = [
+            entry("Docs/same.txt", Some("s")),
+            entry("Docs/edited.txt", Some("new")),
+            entry("Docs/was_a_file", None),
+            entry("Docs/new.txt", Some("n")),
+            deleted,
+        ];
+
+        let diff = EntryDiff::between(&snapshot, &current);
+
+        let names = |names: &[&str]| -> Vec<RelativePath> {
+            names.iter().map(|name| (*name).into()).collect()
+        };
+        assert_eq!(diff.added, names(&["Docs/new.txt"]));
+        assert_eq!(diff.removed, names(&["Docs/deleted.txt", "Docs/gone.txt"]));
+        assert_eq!(
+            diff.modified,
+            names(&["Docs/edited.txt", "Docs/was_a_file"])
+        );
+        assert!(EntryDiff::between(&snapshot, &snapshot).is_empty());
+    }
+}
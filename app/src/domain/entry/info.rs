@@ -15,6 +15,19 @@ pub struct EntryInfo {
     pub kind: EntryKind,
     pub hash: Option<String>,
     pub version: VersionVector,
+    /// Seconds since UNIX epoch the file was last modified on the
+    /// device that wrote this version, as `ConflictPolicy::Newest`
+    /// compares. `None` for directories and for versions from builds
+    /// that predate it. Not part of the entry's digest.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified: Option<u64>,
+    /// Seconds since UNIX epoch the entry was removed, set by the device
+    /// that removed it and passed on unchanged, so every device ages
+    /// the tombstone from the same moment. `None` for live entries and
+    /// for tombstones from builds that predate it. Not part of the
+    /// entry's digest.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub removed_at: Option<u64>,
 }
 
 /// Whether an `EntryInfo` describes a file or a directory.
@@ -1,6 +1,8 @@
+pub mod diff;
 pub mod info;
 pub mod version;
 
+pub use diff::EntryDiff;
 pub use info::EntryInfo;
 pub use info::EntryKind;
 pub use version::MAX_TRUSTED_COUNTER;
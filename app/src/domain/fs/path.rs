@@ -47,7 +47,7 @@ impl AsRef<Path> for CanonicalPath {
 /// Path relative to home directory with forward-slash separators.
 ///
 /// Always uses `/` on all platforms (Windows backslashes converted).
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct RelativePath(String);
 
 impl RelativePath {
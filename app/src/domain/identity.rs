@@ -0,0 +1,167 @@
+use ed25519_dalek::{
+    Signature, Signer, SigningKey, Verifier, VerifyingKey, pkcs8::EncodePrivateKey,
+};
+use rand_core::OsRng;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use uuid::Uuid;
+
+/// The device's long-lived key pair.
+///
+/// The Ed25519 key is what gets persisted; its X25519 (Montgomery)
+/// form is the static key used in the transport handshake. The
+/// device id is derived from that X25519 public key, so any peer that
+/// completes a handshake has proved it owns the id it presents.
+#[derive(Clone)]
+pub struct DeviceKey {
+    signing: SigningKey,
+}
+
+impl DeviceKey {
+    pub fn generate() -> Self {
+        Self {
+            signing: SigningKey::generate(&mut OsRng),
+        }
+    }
+
+    /// Rebuilds a key from its 32-byte secret seed. Returns `None` if
+    /// `bytes` has the wrong length.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let seed: [u8; 32] = bytes.try_into().ok()?;
+        Some(Self {
+            signing: SigningKey::from_bytes(&seed),
+        })
+    }
+
+    /// The 32-byte secret seed, as written to the `device_key` file.
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.signing.to_bytes()
+    }
+
+    pub fn device_id(&self) -> Uuid {
+        device_id_from_static_key(&self.public_static_key())
+    }
+
+    /// X25519 private key for the transport handshake.
+    pub fn private_static_key(&self) -> [u8; 32] {
+        self.signing.to_scalar_bytes()
+    }
+
+    /// X25519 public key matching `private_static_key`.
+    pub fn public_static_key(&self) -> [u8; 32] {
+        self.signing.verifying_key().to_montgomery().to_bytes()
+    }
+
+    /// The Ed25519 public key, which `verify_device_signature` maps
+    /// back to this device's id.
+    pub fn verifying_key(&self) -> [u8; 32] {
+        self.signing.verifying_key().to_bytes()
+    }
+
+    /// Ed25519 signature of `message`, for announcements sent outside
+    /// an authenticated session.
+    pub fn sign(&self, message: &[u8]) -> [u8; 64] {
+        self.signing.sign(message).to_bytes()
+    }
+
+    /// The Ed25519 key as PKCS#8 DER, for transports that authenticate
+    /// with certificates instead of a Noise handshake.
+    pub fn to_pkcs8_der(&self) -> Vec<u8> {
+        self.signing
+            .to_pkcs8_der()
+            .expect("Ed25519 keys always encode")
+            .as_bytes()
+            .to_vec()
+    }
+}
+
+impl fmt::Debug for DeviceKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DeviceKey")
+            .field("device_id", &self.device_id())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Derives a device id from an X25519 static public key: the first
+/// 16 bytes of its SHA-256.
+pub fn device_id_from_static_key(key: &[u8]) -> Uuid {
+    let digest = Sha256::digest(key);
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&digest[..16]);
+    Uuid::from_bytes(bytes)
+}
+
+/// Derives a device id from an Ed25519 public key, through the same
+/// X25519 form `DeviceKey::device_id` uses. Returns `None` if `key` is
+/// not a valid Ed25519 point.
+pub fn device_id_from_verifying_key(key: &[u8; 32]) -> Option<Uuid> {
+    let key = VerifyingKey::from_bytes(key).ok()?;
+    Some(device_id_from_static_key(&key.to_montgomery().to_bytes()))
+}
+
+/// Checks `signature` over `message` against the Ed25519 public key
+/// `key`. Returns the id of the device owning `key` if it holds.
+pub fn verify_device_signature(
+    key: &[u8; 32],
+    message: &[u8],
+    signature: &[u8; 64],
+) -> Option<Uuid> {
+    let verifying = VerifyingKey::from_bytes(key).ok()?;
+    verifying
+        .verify(message, &Signature::from_bytes(signature))
+        .ok()?;
+    device_id_from_verifying_key(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn device_id_round_trips_through_persisted_seed() {
+        let key = DeviceKey::generate();
+        let restored = DeviceKey::from_bytes(&key.to_bytes()).unwrap();
+
+        assert_eq!(key.device_id(), restored.device_id());
+        assert_eq!(key.public_static_key(), restored.public_static_key());
+    }
+
+    #[test]
+    fn distinct_keys_yield_distinct_device_ids() {
+        assert_ne!(
+            DeviceKey::generate().device_id(),
+            DeviceKey::generate().device_id()
+        );
+    }
+
+    #[test]
+    fn verifying_key_yields_the_same_device_id() {
+        let key = DeviceKey::generate();
+        let public = key.signing.verifying_key().to_bytes();
+
+        assert_eq!(device_id_from_verifying_key(&public), Some(key.device_id()));
+    }
+
+    #[test]
+    fn signatures_verify_to_the_signer_id_only_for_the_signed_message() {
+        let key = DeviceKey::generate();
+        let signature = key.sign(b"announcement");
+
+        assert_eq!(
+            verify_device_signature(&key.verifying_key(), b"announcement", &signature),
+            Some(key.device_id())
+        );
+        assert!(verify_device_signature(&key.verifying_key(), b"tampered", &signature).is_none());
+
+        let other = DeviceKey::generate();
+        assert!(
+            verify_device_signature(&other.verifying_key(), b"announcement", &signature).is_none()
+        );
+    }
+
+    #[test]
+    fn from_bytes_rejects_wrong_length() {
+        assert!(DeviceKey::from_bytes(&[0u8; 31]).is_none());
+    }
+}
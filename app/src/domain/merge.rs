@@ -0,0 +1,258 @@
+use crate::domain::VersionVector;
+
+/// The version of an entry a device last held in common with a peer,
+/// which concurrent edits of the entry are merged from.
+///
+/// `version` is `None` for bases recorded before their versions were
+/// kept; those are never merged from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MergeBase {
+    pub hash: String,
+    pub version: Option<VersionVector>,
+}
+
+impl MergeBase {
+    /// Whether both `ours` and `theirs` descend from this base, i.e.
+    /// each is at least as new on every device's counter. Only then are
+    /// the edits either side made since the base the whole difference.
+    pub fn is_ancestor_of(&self, ours: &VersionVector, theirs: &VersionVector) -> bool {
+        let Some(base) = &self.version else {
+            return false;
+        };
+        base.iter().all(|(device, counter)| {
+            ours.get(device).unwrap_or(&0) >= counter && theirs.get(device).unwrap_or(&0) >= counter
+        })
+    }
+}
+
+/// Largest file, in bytes, that is merged line by line.
+pub const MAX_MERGE_BYTES: u64 = 1024 * 1024;
+
+/// Most lines inserted plus deleted between the base and either side
+/// before `three_way_merge` gives up.
+const MAX_EDIT_DISTANCE: isize = 1000;
+
+/// The contents of a file that can be merged line by line: UTF-8,
+/// without NUL bytes, and at most `MAX_MERGE_BYTES` long.
+pub fn as_text(bytes: &[u8]) -> Option<&str> {
+    if bytes.len() as u64 > MAX_MERGE_BYTES || bytes.contains(&0) {
+        return None;
+    }
+    std::str::from_utf8(bytes).ok()
+}
+
+/// Merges two texts edited concurrently from `base`, line by line.
+///
+/// Each side is diffed against `base`. Runs of lines neither side
+/// touched are kept; every other hunk is taken from the side that
+/// changed it, or once when both changed it the same way. Returns
+/// `None` when both sides changed the same or adjacent lines
+/// differently, or when either side is too far from `base`.
+///
+/// Depends on nothing but the three texts, and swapping `ours` and
+/// `theirs` gives the same result, so every device merging the same
+/// versions writes the same bytes.
+pub fn three_way_merge(base: &str, ours: &str, theirs: &str) -> Option<String> {
+    let base: Vec<&str> = base.split_inclusive('\n').collect();
+    let ours: Vec<&str> = ours.split_inclusive('\n').collect();
+    let theirs: Vec<&str> = theirs.split_inclusive('\n').collect();
+
+    let in_ours = matches(&base, &ours)?;
+    let in_theirs = matches(&base, &theirs)?;
+
+    let mut merged = String::new();
+    let (mut b, mut o, mut t) = (0, 0, 0);
+    loop {
+        while b < base.len() && in_ours[b] == Some(o) && in_theirs[b] == Some(t) {
+            merged.push_str(base[b]);
+            (b, o, t) = (b + 1, o + 1, t + 1);
+        }
+
+        // The hunk runs up to the next base line both sides kept.
+        let (next_b, next_o, next_t) = (b..base.len())
+            .find_map(|i| Some((i, in_ours[i]?, in_theirs[i]?)))
+            .unwrap_or((base.len(), ours.len(), theirs.len()));
+        let hunk = (&base[b..next_b], &ours[o..next_o], &theirs[t..next_t]);
+
+        let kept = match hunk {
+            ([], [], []) => return Some(merged),
+            (base, ours, theirs) if ours == base => theirs,
+            (base, ours, theirs) if theirs == base || ours == theirs => ours,
+            _ => return None,
+        };
+        merged.extend(kept.iter().copied());
+        (b, o, t) = (next_b, next_o, next_t);
+    }
+}
+
+/// For each line of `base`, the line of `other` it matches in a
+/// shortest diff, if any.
+fn matches(base: &[&str], other: &[&str]) -> Option<Vec<Option<usize>>> {
+    let prefix = base.iter().zip(other).take_while(|(a, b)| a == b).count();
+    let suffix = base[prefix..]
+        .iter()
+        .rev()
+        .zip(other[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut matched = vec![None; base.len()];
+    for (i, line) in matched.iter_mut().take(prefix).enumerate() {
+        *line = Some(i);
+    }
+    for i in 1..=suffix {
+        matched[base.len() - i] = Some(other.len() - i);
+    }
+
+    let middle = myers(
+        &base[prefix..base.len() - suffix],
+        &other[prefix..other.len() - suffix],
+    )?;
+    for (i, j) in middle {
+        matched[prefix + i] = Some(prefix + j);
+    }
+    Some(matched)
+}
+
+/// Myers' O(ND) diff: the pairs of equal lines along a shortest edit
+/// script from `a` to `b`, or `None` past `MAX_EDIT_DISTANCE` edits.
+fn myers(a: &[&str], b: &[&str]) -> Option<Vec<(usize, usize)>> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = (n + m).min(MAX_EDIT_DISTANCE);
+    let offset = max + 1;
+    let at = |k: isize| (k + offset) as usize;
+
+    // Furthest `x` reached on each diagonal `k = x - y`, per edit count.
+    let mut v = vec![0isize; at(max + 1) + 1];
+    let mut trace = Vec::new();
+    let mut reached = false;
+    for d in 0..=max {
+        trace.push(v.clone());
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && v[at(k - 1)] < v[at(k + 1)]) {
+                v[at(k + 1)]
+            } else {
+                v[at(k - 1)] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                (x, y) = (x + 1, y + 1);
+            }
+            v[at(k)] = x;
+            if x >= n && y >= m {
+                reached = true;
+                break;
+            }
+        }
+        if reached {
+            break;
+        }
+    }
+    if !reached {
+        return None;
+    }
+
+    let mut pairs = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && v[at(k - 1)] < v[at(k + 1)]) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = v[at(prev_k)];
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            (x, y) = (x - 1, y - 1);
+            pairs.push((x as usize, y as usize));
+        }
+        (x, y) = (prev_x, prev_y);
+    }
+    pairs.reverse();
+    Some(pairs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_base_is_an_ancestor_only_of_versions_that_dominate_it() {
+        let (a, b) = (uuid::Uuid::new_v4(), uuid::Uuid::new_v4());
+        let base = MergeBase {
+            hash: "h".into(),
+            version: Some(VersionVector::from([(a, 2), (b, 1)])),
+        };
+        let ours = VersionVector::from([(a, 3), (b, 1)]);
+        let theirs = VersionVector::from([(a, 2), (b, 2)]);
+
+        assert!(base.is_ancestor_of(&ours, &theirs));
+        assert!(!base.is_ancestor_of(&ours, &VersionVector::from([(a, 1), (b, 5)])));
+        assert!(!base.is_ancestor_of(&ours, &VersionVector::from([(b, 2)])));
+        assert!(
+            !MergeBase {
+                version: None,
+                ..base
+            }
+            .is_ancestor_of(&ours, &theirs)
+        );
+    }
+
+    const BASE: &str = "fn a() {\n    1\n}\n\nfn b() {\n    2\n}\n\nfn c() {\n    3\n}\n";
+
+    #[test]
+    fn edits_to_different_hunks_merge_the_same_way_from_either_side() {
+        let ours = BASE.replace("    1", "    10");
+        let theirs = BASE
+            .replace("    3", "    30")
+            .replace("fn c", "fn d() {}\n\nfn c");
+
+        let merged = three_way_merge(BASE, &ours, &theirs).unwrap();
+
+        assert_eq!(
+            merged,
+            "fn a() {\n    10\n}\n\nfn b() {\n    2\n}\n\nfn d() {}\n\nfn c() {\n    30\n}\n"
+        );
+        assert_eq!(three_way_merge(BASE, &theirs, &ours).unwrap(), merged);
+    }
+
+    #[test]
+    fn the_same_edit_on_both_sides_is_kept_once() {
+        let both = BASE.replace("    2", "    20");
+
+        assert_eq!(three_way_merge(BASE, &both, &both).unwrap(), both);
+        assert_eq!(three_way_merge(BASE, BASE, &both).unwrap(), both);
+    }
+
+    #[test]
+    fn overlapping_or_adjacent_edits_do_not_merge() {
+        let ours = BASE.replace("    2", "    20");
+        let theirs = BASE.replace("    2", "    21");
+        assert_eq!(three_way_merge(BASE, &ours, &theirs), None);
+
+        let theirs = BASE.replace("fn b() {", "fn b2() {");
+        assert_eq!(three_way_merge(BASE, &ours, &theirs), None);
+
+        let (ours, theirs) = (format!("{BASE}ours\n"), format!("{BASE}theirs\n"));
+        assert_eq!(three_way_merge(BASE, &ours, &theirs), None);
+    }
+
+    #[test]
+    fn sides_too_far_from_the_base_do_not_merge() {
+        let rewritten: String = (0..2 * MAX_EDIT_DISTANCE)
+            .map(|i| format!("{i}\n"))
+            .collect();
+
+        assert_eq!(three_way_merge(BASE, &rewritten, BASE), None);
+    }
+
+    #[test]
+    fn only_small_utf8_without_nul_is_text() {
+        assert_eq!(as_text(b"fn main() {}\n"), Some("fn main() {}\n"));
+        assert_eq!(as_text(b"\x89PNG\r\n\x1a\n\0"), None);
+        assert_eq!(as_text(&[0xff, 0xfe]), None);
+        assert_eq!(as_text(&vec![b'a'; MAX_MERGE_BYTES as usize + 1]), None);
+    }
+}
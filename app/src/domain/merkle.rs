@@ -0,0 +1,375 @@
+use crate::domain::{EntryInfo, EntryKind, RelativePath};
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt::Write,
+};
+
+/// Digest of a set of entries: the XOR of one SHA-256 per entry, so
+/// an entry can be folded in or out without rehashing its siblings.
+/// The empty set is all zeroes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct TreeDigest([u8; 32]);
+
+impl TreeDigest {
+    /// Digest of a single entry over its name, hash and version
+    /// vector. Zero counters are skipped, since a missing axis and a
+    /// zero one compare the same.
+    pub fn of_entry(entry: &EntryInfo) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(entry.name.as_bytes());
+        match &entry.hash {
+            Some(hash) => {
+                hasher.update([1]);
+                hasher.update(hash.as_bytes());
+            }
+            None => hasher.update([0]),
+        }
+
+        let version: BTreeMap<_, _> = entry.version.iter().filter(|(_, c)| **c > 0).collect();
+        for (id, counter) in version {
+            hasher.update(id.as_bytes());
+            hasher.update(counter.to_be_bytes());
+        }
+
+        Self(hasher.finalize().into())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == [0; 32]
+    }
+
+    fn toggle(&mut self, other: &TreeDigest) {
+        for (a, b) in self.0.iter_mut().zip(other.0) {
+            *a ^= b;
+        }
+    }
+}
+
+impl Serialize for TreeDigest {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut hex = String::with_capacity(64);
+        for byte in self.0 {
+            let _ = write!(hex, "{byte:02x}");
+        }
+        serializer.serialize_str(&hex)
+    }
+}
+
+impl<'de> Deserialize<'de> for TreeDigest {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        if hex.len() != 64 || !hex.is_ascii() {
+            return Err(de::Error::custom("digest must be 64 hex digits"));
+        }
+
+        let mut bytes = [0u8; 32];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).map_err(de::Error::custom)?;
+        }
+        Ok(Self(bytes))
+    }
+}
+
+/// What a peer sees of one directory while descending its digest tree.
+///
+/// `own` is the directory's own entry, if it has one; `files` covers
+/// the non-directory entries directly inside it; `dirs` maps each
+/// child directory to the digest of everything at or below it.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct DirSummary {
+    pub own: Option<TreeDigest>,
+    pub files: TreeDigest,
+    pub dirs: BTreeMap<String, TreeDigest>,
+}
+
+/// Part of the entry map a handshake asks its sender for.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryScope {
+    /// Every entry.
+    All,
+    /// A single entry.
+    Entry(RelativePath),
+    /// The non-directory entries directly inside a directory.
+    Files(RelativePath),
+    /// A directory's own entry and everything below it.
+    Subtree(RelativePath),
+}
+
+impl EntryScope {
+    pub fn contains(&self, entry: &EntryInfo) -> bool {
+        match self {
+            Self::All => true,
+            Self::Entry(name) => entry.name == *name,
+            Self::Files(dir) => entry.kind != EntryKind::Directory && parent(&entry.name) == &**dir,
+            Self::Subtree(dir) => entry.name.starts_with_dir(dir),
+        }
+    }
+}
+
+/// Where two digest trees part ways below one directory: child
+/// directories that exist on both sides but differ, to be descended
+/// into next, and the scopes whose entries must be fetched.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SubtreeDiff {
+    pub descend: Vec<RelativePath>,
+    pub fetch: Vec<EntryScope>,
+}
+
+/// Per-directory digests over the entry map.
+///
+/// Every directory that holds at least one entry, at any depth, has a
+/// node whose digest covers all of them; the root node `""` covers the
+/// whole map. Two devices whose root digests match hold the same
+/// entries, and where they differ, comparing child digests narrows the
+/// difference down one level at a time.
+#[derive(Debug, Default)]
+pub struct DigestTree {
+    nodes: HashMap<RelativePath, Node>,
+}
+
+#[derive(Debug, Default)]
+struct Node {
+    subtree: TreeDigest,
+    own: Option<TreeDigest>,
+    files: TreeDigest,
+    dirs: BTreeSet<String>,
+    count: usize,
+}
+
+impl DigestTree {
+    pub fn insert(&mut self, entry: &EntryInfo) {
+        self.fold(entry, true);
+    }
+
+    /// Folds `entry` back out. It must be exactly the entry that was
+    /// inserted, or the digests above it no longer match the map.
+    pub fn remove(&mut self, entry: &EntryInfo) {
+        self.fold(entry, false);
+    }
+
+    /// The summary of `dir`, or `None` if nothing is stored at or
+    /// below it.
+    pub fn summary(&self, dir: &RelativePath) -> Option<DirSummary> {
+        let node = self.nodes.get(dir)?;
+        Some(DirSummary {
+            own: node.own,
+            files: node.files,
+            dirs: node
+                .dirs
+                .iter()
+                .filter_map(|child| {
+                    let digest = self.nodes.get(&join(dir, child))?.subtree;
+                    Some((child.clone(), digest))
+                })
+                .collect(),
+        })
+    }
+
+    /// Compares a peer's summary of `dir` with ours. Only what the
+    /// peer holds is fetched: an entry or directory it lacks is not its
+    /// to send.
+    pub fn diff(&self, dir: &RelativePath, remote: &DirSummary) -> SubtreeDiff {
+        let local = self.nodes.get(dir);
+        let mut diff = SubtreeDiff::default();
+
+        if remote.own.is_some() && remote.own != local.and_then(|n| n.own) {
+            diff.fetch.push(EntryScope::Entry(dir.clone()));
+        }
+
+        let local_files = local.map(|n| n.files).unwrap_or_default();
+        if !remote.files.is_empty() && remote.files != local_files {
+            diff.fetch.push(EntryScope::Files(dir.clone()));
+        }
+
+        for (child, digest) in &remote.dirs {
+            let path = join(dir, child);
+            match self.nodes.get(&path) {
+                None => diff.fetch.push(EntryScope::Subtree(path)),
+                Some(node) if node.subtree != *digest => diff.descend.push(path),
+                Some(_) => {}
+            }
+        }
+
+        diff
+    }
+
+    fn fold(&mut self, entry: &EntryInfo, insert: bool) {
+        let leaf = TreeDigest::of_entry(entry);
+        let name: &str = &entry.name;
+
+        let mut dir = if entry.kind == EntryKind::Directory {
+            self.node(name).own = insert.then_some(leaf);
+            name
+        } else {
+            self.node(parent(name)).files.toggle(&leaf);
+            parent(name)
+        };
+
+        loop {
+            let node = self.node(dir);
+            node.subtree.toggle(&leaf);
+            node.count = match insert {
+                true => node.count + 1,
+                false => node.count.saturating_sub(1),
+            };
+            let empty = node.count == 0;
+
+            if dir.is_empty() {
+                return;
+            }
+
+            let up = parent(dir);
+            let child = dir[up.len()..].trim_start_matches('/').to_string();
+            if empty {
+                self.nodes.remove(&RelativePath::from(dir));
+                self.node(up).dirs.remove(&child);
+            } else {
+                self.node(up).dirs.insert(child);
+            }
+            dir = up;
+        }
+    }
+
+    fn node(&mut self, dir: &str) -> &mut Node {
+        self.nodes.entry(dir.into()).or_default()
+    }
+}
+
+/// The directory holding `name`, `""` at the top level.
+fn parent(name: &str) -> &str {
+    name.rsplit_once('/').map(|(dir, _)| dir).unwrap_or("")
+}
+
+fn join(dir: &RelativePath, child: &str) -> RelativePath {
+    if dir.is_empty() {
+        child.into()
+    } else {
+        format!("{dir}/{child}").into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use uuid::Uuid;
+
+    fn file(name: &str, hash: &str) -> EntryInfo {
+        EntryInfo {
+            name: name.into(),
+            kind: EntryKind::File,
+            hash: Some(hash.into()),
+            version: HashMap::from([(Uuid::nil(), 1)]),
+            modified: None,
+            removed_at: None,
+        }
+    }
+
+    fn dir(name: &str) -> EntryInfo {
+        EntryInfo {
+            name: name.into(),
+            kind: EntryKind::Directory,
+            hash: None,
+            version: HashMap::new(),
+            modified: None,
+            removed_at: None,
+        }
+    }
+
+    fn tree(entries: &[EntryInfo]) -> DigestTree {
+        let mut tree = DigestTree::default();
+        for entry in entries {
+            tree.insert(entry);
+        }
+        tree
+    }
+
+    #[test]
+    fn digests_do_not_depend_on_insertion_order() {
+        let entries = [
+            dir("a"),
+            dir("a/b"),
+            file("a/b/1.txt", "x"),
+            file("a/2.txt", "y"),
+        ];
+        let mut reversed = entries.clone();
+        reversed.reverse();
+
+        let root = RelativePath::from("");
+        let summary = tree(&entries).summary(&root).unwrap();
+        assert_eq!(summary, tree(&reversed).summary(&root).unwrap());
+        assert!(!summary.dirs["a"].is_empty());
+    }
+
+    #[test]
+    fn removing_every_entry_leaves_an_empty_tree() {
+        let entries = [dir("a"), dir("a/b"), file("a/b/1.txt", "x")];
+        let mut tree = tree(&entries);
+
+        for entry in &entries {
+            tree.remove(entry);
+        }
+
+        assert_eq!(tree.summary(&"".into()).unwrap(), DirSummary::default());
+        assert!(tree.summary(&"a".into()).is_none());
+    }
+
+    #[test]
+    fn diff_descends_only_into_subtrees_that_changed() {
+        let shared = [dir("a"), dir("a/same"), file("a/same/1.txt", "x")];
+        let local = tree(
+            &[
+                &shared[..],
+                &[dir("a/changed"), file("a/changed/2.txt", "old")],
+            ]
+            .concat(),
+        );
+        let remote = tree(
+            &[
+                &shared[..],
+                &[
+                    dir("a/changed"),
+                    file("a/changed/2.txt", "new"),
+                    dir("a/new"),
+                    file("a/new/3.txt", "z"),
+                ],
+            ]
+            .concat(),
+        );
+
+        let diff = local.diff(&"a".into(), &remote.summary(&"a".into()).unwrap());
+        assert_eq!(diff.descend, vec![RelativePath::from("a/changed")]);
+        assert_eq!(diff.fetch, vec![EntryScope::Subtree("a/new".into())]);
+
+        let diff = local.diff(
+            &"a/changed".into(),
+            &remote.summary(&"a/changed".into()).unwrap(),
+        );
+        assert!(diff.descend.is_empty());
+        assert_eq!(diff.fetch, vec![EntryScope::Files("a/changed".into())]);
+    }
+
+    #[test]
+    fn digests_ignore_zero_version_counters() {
+        let mut padded = file("a/1.txt", "x");
+        padded.version.insert(Uuid::new_v4(), 0);
+
+        assert_eq!(
+            TreeDigest::of_entry(&padded),
+            TreeDigest::of_entry(&file("a/1.txt", "x"))
+        );
+    }
+
+    #[test]
+    fn summaries_round_trip_through_json() {
+        let summary = tree(&[dir("a"), file("a/1.txt", "x"), dir("a/b")])
+            .summary(&"a".into())
+            .unwrap();
+
+        let json = serde_json::to_string(&summary).unwrap();
+        assert_eq!(serde_json::from_str::<DirSummary>(&json).unwrap(), summary);
+    }
+}
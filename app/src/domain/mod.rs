@@ -1,18 +1,37 @@
 mod cfg;
 mod chan;
+mod cluster;
+mod conflict;
 mod directory;
 mod entry;
 mod fs;
+mod identity;
+mod merge;
+mod merkle;
 mod peer;
 mod ports;
+mod protocol;
+mod snapshot;
 mod sse;
 mod transport;
+mod trash;
+mod versioning;
 
 pub use cfg::Config;
 pub use cfg::ConfigDirectory;
+pub use cfg::ConfigPeer;
+pub use cfg::DiscoveryConfig;
+pub use cfg::LivenessConfig;
+pub use cfg::TransportProtocol;
 pub use chan::BroadcastChannel;
 pub use chan::MutexChannel;
+pub use cluster::Cluster;
+pub use conflict::ConflictPolicy;
+pub use conflict::ConflictRecord;
+pub use conflict::ConflictResolution;
+pub use directory::DirectoryMode;
 pub use directory::SyncDirectory;
+pub use entry::EntryDiff;
 pub use entry::EntryInfo;
 pub use entry::EntryKind;
 pub use entry::MAX_TRUSTED_COUNTER;
@@ -23,11 +42,35 @@ pub use fs::ConfigWatcherEvent;
 pub use fs::HomeWatcherEvent;
 pub use fs::RelativePath;
 pub use fs::WatcherEventPath;
+pub use identity::DeviceKey;
+pub use identity::device_id_from_static_key;
+pub use identity::device_id_from_verifying_key;
+pub use identity::verify_device_signature;
+pub use merge::MAX_MERGE_BYTES;
+pub use merge::MergeBase;
+pub use merge::as_text;
+pub use merge::three_way_merge;
+pub use merkle::DigestTree;
+pub use merkle::DirSummary;
+pub use merkle::EntryScope;
+pub use merkle::TreeDigest;
 pub use peer::Peer;
+pub use peer::PeerAddrs;
+pub use peer::PendingDevice;
 pub use ports::AppPorts;
+pub use protocol::Capability;
+pub use protocol::ProtocolInfo;
+pub use snapshot::Snapshot;
 pub use sse::ServerEvent;
+pub use transport::EntryPage;
+pub use transport::EntryPager;
 pub use transport::HandshakeData;
+pub use transport::HandshakeEntries;
 pub use transport::TransportChannelData;
 pub use transport::TransportData;
 pub use transport::TransportEvent;
 pub use transport::TransportMetadata;
+pub use trash::TrashExpiry;
+pub use trash::TrashRecord;
+pub use versioning::FileVersion;
+pub use versioning::Versioning;
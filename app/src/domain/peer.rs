@@ -1,6 +1,10 @@
-use crate::domain::{RelativePath, SyncDirectory};
+use crate::domain::{Capability, ProtocolInfo, RelativePath, SyncDirectory};
 use serde::Serialize;
-use std::{collections::HashMap, net::IpAddr, time::SystemTime};
+use std::{
+    collections::{BTreeSet, HashMap},
+    net::{IpAddr, SocketAddr},
+    time::SystemTime,
+};
 use uuid::Uuid;
 
 /// A remote Synche instance currently visible on the network.
@@ -8,25 +12,33 @@ use uuid::Uuid;
 /// `id` is the peer's persistent device identifier; `instance_id` is
 /// regenerated on every process start, so a change to it signals that
 /// the peer restarted even when `id` and `addr` stay the same.
-/// `last_seen` is refreshed on every presence announcement and is used
-/// to evict peers that have gone silent.
+/// `addr` and `transport_port` are the first of the peer's `PeerAddrs`,
+/// the address its transport was last reached at; the port need not be
+/// ours. `last_seen` is refreshed on every presence
+/// announcement and every message the peer sends, and is used to evict
+/// peers that have gone silent. `protocol_version` and
+/// `capabilities` come from the peer's handshake; `capabilities` holds
+/// only the features both sides support.
 #[derive(Debug, Clone, Serialize)]
 pub struct Peer {
     pub id: Uuid,
     pub addr: IpAddr,
+    pub transport_port: u16,
     pub hostname: String,
     pub instance_id: Uuid,
     pub last_seen: SystemTime,
     pub sync_dirs: HashMap<RelativePath, SyncDirectory>,
+    pub protocol_version: u32,
+    pub capabilities: BTreeSet<Capability>,
 }
 
 impl Peer {
-    /// Constructs a `Peer`, stamping `last_seen` with the current time
-    /// and stripping the trailing `.local` suffix that mDNS appends to
-    /// hostnames.
+    /// Constructs a `Peer` reachable at `addr`, stamping `last_seen`
+    /// with the current time and stripping the trailing `.local` suffix
+    /// that mDNS appends to hostnames.
     pub fn new(
         id: Uuid,
-        addr: IpAddr,
+        addr: SocketAddr,
         hostname: String,
         instance_id: Uuid,
         sync_dirs: Vec<SyncDirectory>,
@@ -43,10 +55,146 @@ impl Peer {
         Self {
             id,
             instance_id,
-            addr,
+            addr: addr.ip(),
+            transport_port: addr.port(),
             hostname,
             sync_dirs,
             last_seen: SystemTime::now(),
+            protocol_version: 0,
+            capabilities: BTreeSet::new(),
         }
     }
+
+    /// Records the protocol the peer advertised, keeping only the
+    /// capabilities this build shares with it.
+    pub fn with_protocol(mut self, protocol: &ProtocolInfo) -> Self {
+        self.protocol_version = protocol.version;
+        self.capabilities = protocol.negotiate(&ProtocolInfo::local());
+        self
+    }
+
+    pub fn supports(&self, capability: Capability) -> bool {
+        self.capabilities.contains(&capability)
+    }
+
+    /// Where the peer's transport accepts connections.
+    pub fn transport_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.addr, self.transport_port)
+    }
+}
+
+/// A device that announced itself or opened a handshake but has not
+/// been approved yet.
+///
+/// `hostname` is only known once the device has sent a handshake — a
+/// bare presence ping carries just the id and addresses. Pending devices
+/// live in memory only; approving one persists its id, rejecting one
+/// hides it until the next restart.
+#[derive(Debug, Clone, Serialize)]
+pub struct PendingDevice {
+    pub id: Uuid,
+    pub addr: IpAddr,
+    pub transport_port: u16,
+    pub hostname: Option<String>,
+}
+
+impl PendingDevice {
+    /// Where the device's transport accepts connections.
+    pub fn transport_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.addr, self.transport_port)
+    }
+}
+
+/// Every address a device's transport may be reached at, in the order
+/// they are tried.
+///
+/// The first is the one last reached; the rest follow in the order
+/// they were learned. IPv6 link-local addresses carry the scope of the
+/// interface they were seen on, without which they cannot be dialed.
+/// Addresses are matched without their scope, since the source of an
+/// inbound connection has none, and the scoped form is kept once
+/// known.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PeerAddrs(Vec<SocketAddr>);
+
+impl PeerAddrs {
+    pub fn first(&self) -> Option<SocketAddr> {
+        self.0.first().copied()
+    }
+
+    pub fn contains(&self, addr: SocketAddr) -> bool {
+        self.position(addr).is_some()
+    }
+
+    pub fn to_vec(&self) -> Vec<SocketAddr> {
+        self.0.clone()
+    }
+
+    /// Appends `addr` unless it is known, in which case a scope it
+    /// carries replaces a missing one.
+    pub fn add(&mut self, addr: SocketAddr) {
+        match self.position(addr) {
+            Some(i) => {
+                if scope_of(self.0[i]) == 0 {
+                    self.0[i] = addr;
+                }
+            }
+            None => self.0.push(addr),
+        }
+    }
+
+    /// Moves `addr` to the front, adding it if unknown.
+    pub fn prefer(&mut self, addr: SocketAddr) {
+        self.add(addr);
+        if let Some(i) = self.position(addr) {
+            let addr = self.0.remove(i);
+            self.0.insert(0, addr);
+        }
+    }
+
+    fn position(&self, addr: SocketAddr) -> Option<usize> {
+        self.0
+            .iter()
+            .position(|known| known.ip() == addr.ip() && known.port() == addr.port())
+    }
+}
+
+fn scope_of(addr: SocketAddr) -> u32 {
+    match addr {
+        SocketAddr::V4(_) => 0,
+        SocketAddr::V6(addr) => addr.scope_id(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddrV6;
+
+    fn addr(s: &str) -> SocketAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn peer_addrs_keep_the_preferred_address_first_and_learn_scopes() {
+        let mut addrs = PeerAddrs::default();
+        addrs.add(addr("10.0.0.1:42882"));
+        addrs.add(addr("[fe80::1]:42882"));
+        addrs.add(addr("10.0.0.1:42882"));
+        assert_eq!(
+            addrs.to_vec(),
+            vec![addr("10.0.0.1:42882"), addr("[fe80::1]:42882")]
+        );
+
+        let scoped = SocketAddr::V6(SocketAddrV6::new("fe80::1".parse().unwrap(), 42882, 0, 3));
+        addrs.add(scoped);
+        addrs.prefer(addr("[fe80::1]:42882"));
+        assert_eq!(addrs.first(), Some(scoped));
+        assert_eq!(scope_of(addrs.first().unwrap()), 3);
+
+        addrs.prefer(addr("[2001:db8::1]:42882"));
+        assert_eq!(addrs.first(), Some(addr("[2001:db8::1]:42882")));
+        assert!(addrs.contains(addr("10.0.0.1:42882")));
+        assert!(!addrs.contains(addr("10.0.0.1:42001")));
+    }
 }
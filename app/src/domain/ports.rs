@@ -1,10 +1,12 @@
 use serde::{Deserialize, Serialize};
 
-/// The three network ports the application binds.
+/// The four network ports the application binds.
 ///
-/// `http` serves the GUI and JSON API; `presence` is the mDNS discovery
-/// port; `transport` is the TCP port that carries handshakes, metadata,
-/// requests, and entry transfers.
+/// `http` serves the GUI and JSON API; `presence` is the port advertised
+/// over mDNS, on which static-peer probes are answered (UDP);
+/// `transport` is the TCP port that carries handshakes, metadata,
+/// requests, and entry transfers; `broadcast` is the UDP port subnet
+/// broadcast announcements are sent to and heard on.
 ///
 /// A value of `0` requests an OS-assigned ephemeral port — tests rely on
 /// this so they don't collide with the production defaults defined in
@@ -14,4 +16,5 @@ pub struct AppPorts {
     pub http: u16,
     pub presence: u16,
     pub transport: u16,
+    pub broadcast: u16,
 }
@@ -0,0 +1,152 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+/// Wire protocol revision spoken by this build. Bump it whenever a
+/// frame changes in a way an older peer could not decode.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Oldest peer revision this build still talks to. Peers that predate
+/// versioning advertise nothing and read as version 0; they use the
+/// same framing, just without any optional capability.
+pub const MIN_PROTOCOL_VERSION: u32 = 0;
+
+/// Optional protocol feature a peer can advertise. A feature is only
+/// used on a link when both ends advertise it.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    /// Block-level `DeltaTransfer` frames.
+    Delta,
+    /// Resume offsets in `Request` / `Transfer` frames.
+    Resume,
+    /// Handshake entry maps streamed as bounded pages.
+    PagedHandshake,
+    /// Handshakes that compare per-directory digests first and only
+    /// page through subtrees that differ.
+    SubtreeDigests,
+    /// One long-lived connection per peer carrying every message as a
+    /// stream of its own.
+    Multiplex,
+    /// `Transfer` payloads compressed with zstd, chunk by chunk.
+    Zstd,
+    /// A capability from a newer build that this one does not know.
+    #[serde(other)]
+    Unknown,
+}
+
+/// Protocol revision range and capabilities advertised in a handshake.
+///
+/// The `Default` value describes a peer that predates versioning:
+/// revision 0 with no capabilities.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct ProtocolInfo {
+    pub version: u32,
+    pub min_version: u32,
+    pub capabilities: BTreeSet<Capability>,
+}
+
+impl ProtocolInfo {
+    /// What this build advertises.
+    pub fn local() -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            min_version: MIN_PROTOCOL_VERSION,
+            capabilities: BTreeSet::from([
+                Capability::Delta,
+                Capability::Resume,
+                Capability::PagedHandshake,
+                Capability::SubtreeDigests,
+                Capability::Multiplex,
+                Capability::Zstd,
+            ]),
+        }
+    }
+
+    /// Explains why a peer advertising `self` cannot talk to `local`,
+    /// or `None` when each side's revision is one the other accepts.
+    pub fn incompatibility(&self, local: &ProtocolInfo) -> Option<String> {
+        if self.version < local.min_version {
+            Some(format!(
+                "peer speaks protocol v{}, this device needs at least v{}",
+                self.version, local.min_version
+            ))
+        } else if local.version < self.min_version {
+            Some(format!(
+                "peer needs protocol v{} or newer, this device speaks v{}",
+                self.min_version, local.version
+            ))
+        } else {
+            None
+        }
+    }
+
+    /// Capabilities both sides advertise.
+    pub fn negotiate(&self, local: &ProtocolInfo) -> BTreeSet<Capability> {
+        self.capabilities
+            .intersection(&local.capabilities)
+            .filter(|c| **c != Capability::Unknown)
+            .copied()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn legacy_handshake_json_reads_as_version_zero_without_capabilities() {
+        let info: ProtocolInfo = serde_json::from_str("{}").unwrap();
+
+        assert_eq!(info, ProtocolInfo::default());
+        assert!(info.incompatibility(&ProtocolInfo::local()).is_none());
+        assert!(info.negotiate(&ProtocolInfo::local()).is_empty());
+    }
+
+    #[test]
+    fn unknown_capabilities_are_tolerated_and_never_negotiated() {
+        let info: ProtocolInfo = serde_json::from_str(
+            r#"{"version":3,"min_version":1,"capabilities":["delta","teleport"]}"#,
+        )
+        .unwrap();
+
+        assert!(info.capabilities.contains(&Capability::Unknown));
+        assert_eq!(
+            info.negotiate(&ProtocolInfo::local()),
+            BTreeSet::from([Capability::Delta])
+        );
+    }
+
+    #[test]
+    fn incompatibility_is_reported_in_both_directions() {
+        let local = ProtocolInfo {
+            version: 4,
+            min_version: 2,
+            capabilities: BTreeSet::new(),
+        };
+        let too_old = ProtocolInfo {
+            version: 1,
+            ..ProtocolInfo::default()
+        };
+        let too_new = ProtocolInfo {
+            version: 9,
+            min_version: 5,
+            capabilities: BTreeSet::new(),
+        };
+
+        assert!(
+            too_old
+                .incompatibility(&local)
+                .unwrap()
+                .contains("at least v2")
+        );
+        assert!(
+            too_new
+                .incompatibility(&local)
+                .unwrap()
+                .contains("v5 or newer")
+        );
+        assert!(local.incompatibility(&local).is_none());
+    }
+}
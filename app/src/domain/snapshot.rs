@@ -0,0 +1,19 @@
+use crate::domain::RelativePath;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// A named point-in-time copy of a sync directory, taken by the user.
+///
+/// The store keeps the `EntryInfo` of every live entry the directory
+/// held when the snapshot was taken; the contents of its files are
+/// copied to `<sync dir>/.synche/snapshots/<id>/<rest of path>`. Like
+/// versions and the trash, snapshots stay on the device that took them.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Snapshot {
+    pub id: Uuid,
+    /// Unique among the snapshots of `dir`.
+    pub name: String,
+    pub dir: RelativePath,
+    /// Seconds since UNIX epoch.
+    pub taken_at: u64,
+}
@@ -1,4 +1,4 @@
-use crate::domain::RelativePath;
+use crate::domain::{Capability, ConflictRecord, ConflictResolution, RelativePath, TrashRecord};
 use serde::{Deserialize, Serialize};
 use std::net::IpAddr;
 use uuid::Uuid;
@@ -19,9 +19,40 @@ pub enum ServerEvent {
         last_seen: u64,
         /// Names of the sync directories this peer is sharing.
         sync_dirs: Vec<RelativePath>,
+        /// Protocol revision the peer advertised (0 if it predates versioning).
+        protocol_version: u32,
+        /// Optional features both sides support.
+        capabilities: Vec<Capability>,
     },
     /// A peer was evicted (timed out, or explicitly disconnected).
     PeerDisconnected(Uuid),
+    /// An unknown device is waiting to be approved. Re-sent when a
+    /// handshake fills in a hostname the presence ping did not carry.
+    DevicePending {
+        id: Uuid,
+        addr: IpAddr,
+        hostname: Option<String>,
+    },
+    /// A trusted device handshaked with a protocol revision this build
+    /// cannot talk to, so it was not connected.
+    PeerIncompatible {
+        id: Uuid,
+        addr: IpAddr,
+        hostname: String,
+        protocol_version: u32,
+        /// Human-readable explanation of the version mismatch.
+        reason: String,
+    },
+    /// A connected peer is now reached at another address, after it
+    /// moved networks or an address stopped answering.
+    PeerAddressChanged { id: Uuid, addr: IpAddr },
+    /// This device's own network changed; every peer is handshaked
+    /// again so it learns the new address.
+    NetworkChanged { local_ip: IpAddr },
+    /// A pending device was approved and added to the trusted set.
+    DeviceApproved(Uuid),
+    /// A pending device was rejected by the user.
+    DeviceRejected(Uuid),
     /// A sync directory was added to the local config.
     SyncDirectoryAdded(RelativePath),
     /// A sync directory was removed from the local config.
@@ -49,6 +80,16 @@ pub enum ServerEvent {
         /// Human-readable failure reason (hash mismatch, oversized, I/O error).
         reason: String,
     },
+    /// A concurrent edit went to a peer; our copy was saved aside
+    /// and awaits the user.
+    ConflictDetected(ConflictRecord),
+    /// The user resolved a conflict.
+    ConflictResolved {
+        id: Uuid,
+        resolution: ConflictResolution,
+    },
+    /// A peer deleted an entry and it was moved into the trash.
+    EntryTrashed(TrashRecord),
     /// The server is restarting (e.g. after a `home_path` change) — the
     /// GUI should reconnect.
     ServerRestart,
@@ -1,6 +1,10 @@
-use crate::domain::{EntryInfo, RelativePath, SyncDirectory};
-use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, net::IpAddr};
+use crate::domain::{EntryInfo, EntryScope, ProtocolInfo, RelativePath, SyncDirectory};
+use std::{
+    collections::{HashMap, VecDeque},
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
+use tokio::{io, sync::mpsc};
 use uuid::Uuid;
 
 /// An inbound transport message, paired with the metadata that
@@ -37,23 +41,142 @@ pub enum TransportData {
 
 /// Payload for the handshake exchange — everything a peer needs to
 /// reconcile its world view against the sender's on first contact.
-#[derive(Serialize, Deserialize, Clone)]
+///
+/// `protocol` is absent from peers that predate versioning and then
+/// defaults to revision 0 with no capabilities. `transport_port` is
+/// absent from peers that predate per-peer ports; they listen on the
+/// same port as this device. `cluster_proof` is the sender's
+/// `Cluster::proof`, absent in the default cluster.
 pub struct HandshakeData {
     pub hostname: String,
     pub instance_id: Uuid,
+    pub transport_port: Option<u16>,
+    pub cluster_proof: Option<String>,
     pub sync_dirs: Vec<SyncDirectory>,
-    pub entries: HashMap<RelativePath, EntryInfo>,
+    pub entries: HandshakeEntries,
+    pub protocol: ProtocolInfo,
+}
+
+/// A handshake's entry map, handed out one page at a time so neither
+/// side has to hold all of it.
+///
+/// Outbound maps are read from the local store as they are sent;
+/// inbound ones arrive from the transport while the receiver works
+/// through earlier pages. Pages come in ascending name order, except
+/// for maps from peers that send everything at once, which arrive as a
+/// single page. A peer that compared digest trees first may ask for
+/// only some subtrees; see `restrict`.
+pub struct HandshakeEntries(Pages);
+
+enum Pages {
+    Map(Option<HashMap<RelativePath, EntryInfo>>),
+    Local {
+        pager: Arc<dyn EntryPager>,
+        scopes: VecDeque<EntryScope>,
+        after: Option<RelativePath>,
+    },
+    Incoming(mpsc::Receiver<io::Result<Vec<EntryInfo>>>),
+}
+
+impl HandshakeEntries {
+    /// A map that is already in memory.
+    pub fn from_map(entries: HashMap<RelativePath, EntryInfo>) -> Self {
+        Self(Pages::Map(Some(entries)))
+    }
+
+    /// The local entry map, read through `pager` on demand.
+    pub fn from_pager(pager: Arc<dyn EntryPager>) -> Self {
+        Self(Pages::Local {
+            pager,
+            scopes: VecDeque::from([EntryScope::All]),
+            after: None,
+        })
+    }
+
+    /// Narrows the map to the entries within `scopes`. Has no effect
+    /// on inbound maps, which hold whatever the peer chose to send.
+    pub fn restrict(&mut self, scopes: Vec<EntryScope>) {
+        match &mut self.0 {
+            Pages::Map(entries) => {
+                if let Some(entries) = entries {
+                    entries.retain(|_, e| scopes.iter().any(|s| s.contains(e)));
+                }
+            }
+            Pages::Local {
+                scopes: current,
+                after,
+                ..
+            } => {
+                *current = scopes.into();
+                *after = None;
+            }
+            Pages::Incoming(_) => {}
+        }
+    }
+
+    /// Pages pushed by the transport as they come off the wire. The
+    /// sender closes the channel after the last page, or sends an
+    /// error if the stream broke off before it.
+    pub fn from_channel(pages: mpsc::Receiver<io::Result<Vec<EntryInfo>>>) -> Self {
+        Self(Pages::Incoming(pages))
+    }
+
+    /// The next page, or `None` once the whole map has been seen.
+    pub async fn next_page(&mut self) -> Option<io::Result<Vec<EntryInfo>>> {
+        match &mut self.0 {
+            Pages::Map(entries) => entries.take().map(|e| Ok(e.into_values().collect())),
+            Pages::Local {
+                pager,
+                scopes,
+                after,
+            } => {
+                let scope = scopes.front()?;
+                match pager.page(scope, after.take()).await {
+                    Ok(page) => {
+                        if page.next.is_none() {
+                            scopes.pop_front();
+                        }
+                        *after = page.next;
+                        Some(Ok(page.entries))
+                    }
+                    Err(err) => {
+                        scopes.clear();
+                        Some(Err(err))
+                    }
+                }
+            }
+            Pages::Incoming(pages) => pages.recv().await,
+        }
+    }
+}
+
+/// One page of the local entry map.
+///
+/// `next` is where the following page starts, or `None` when this was
+/// the last one of its scope.
+pub struct EntryPage {
+    pub entries: Vec<EntryInfo>,
+    pub next: Option<RelativePath>,
+}
+
+/// Read side of the local entry map, walked in ascending name order.
+#[async_trait::async_trait]
+pub trait EntryPager: Send + Sync {
+    /// The page of `scope` starting right after `after`, or its first
+    /// page when `after` is `None`.
+    async fn page(&self, scope: &EntryScope, after: Option<RelativePath>) -> io::Result<EntryPage>;
 }
 
 /// Outbound transport intent enqueued by application services for the
 /// transport sender to dispatch.
 ///
-/// Mirrors `TransportData` but carries the target peer address since the
-/// sender, unlike the receiver, must know where to send.
+/// Mirrors `TransportData` but carries the target peer's transport
+/// address since the sender, unlike the receiver, must know where to
+/// send.
 pub enum TransportChannelData {
-    HandshakeSyn(IpAddr),
-    _HandshakeAck(IpAddr),
+    HandshakeSyn(SocketAddr),
+    HandshakeAck(SocketAddr),
     Metadata(EntryInfo),
-    Request((IpAddr, EntryInfo)),
-    Transfer((IpAddr, EntryInfo)),
+    Request((SocketAddr, EntryInfo)),
+    Transfer((SocketAddr, EntryInfo)),
 }
@@ -0,0 +1,76 @@
+use crate::domain::{EntryKind, RelativePath};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+const DAY: u64 = 24 * 60 * 60;
+
+/// An entry a peer deleted, moved into the trash of its sync directory
+/// instead of being removed from disk.
+///
+/// The entry is kept at `<sync dir>/.synche/trash/<id>/<rest of path>`
+/// until the user restores or purges it, or it outlives the directory's
+/// `TrashExpiry`. The trash is not synced; it stays on this device.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TrashRecord {
+    pub id: Uuid,
+    pub path: RelativePath,
+    pub kind: EntryKind,
+    /// Device whose tombstone deleted the entry.
+    pub peer: Uuid,
+    /// Seconds since UNIX epoch.
+    pub deleted_at: u64,
+}
+
+/// How many days a sync directory keeps trashed entries, set per
+/// directory by `trash_expiry` in `config.toml`. `0` keeps them until
+/// the user purges them.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct TrashExpiry(pub u64);
+
+impl Default for TrashExpiry {
+    fn default() -> Self {
+        Self(30)
+    }
+}
+
+impl TrashExpiry {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Whether an entry trashed at `deleted_at` has expired at `now`.
+    pub fn is_expired(&self, deleted_at: u64, now: u64) -> bool {
+        self.0 > 0 && now.saturating_sub(deleted_at) > self.0 * DAY
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000;
+
+    #[test]
+    fn entries_expire_after_the_configured_days() {
+        let expiry = TrashExpiry(7);
+
+        assert!(!expiry.is_expired(NOW - 7 * DAY, NOW));
+        assert!(expiry.is_expired(NOW - 7 * DAY - 1, NOW));
+        assert!(!TrashExpiry(0).is_expired(0, NOW));
+        assert!(!expiry.is_expired(NOW + 60, NOW));
+    }
+
+    #[test]
+    fn expiry_reads_from_config_toml() {
+        #[derive(Deserialize)]
+        struct Dir {
+            #[serde(default)]
+            trash_expiry: TrashExpiry,
+        }
+        let expiry = |toml: &str| toml::from_str::<Dir>(toml).unwrap().trash_expiry;
+
+        assert_eq!(expiry("trash_expiry = 90"), TrashExpiry(90));
+        assert_eq!(expiry(""), TrashExpiry(30));
+    }
+}
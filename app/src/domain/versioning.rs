@@ -0,0 +1,164 @@
+use crate::domain::RelativePath;
+use serde::{Deserialize, Serialize};
+
+const HOUR: u64 = 60 * 60;
+const DAY: u64 = 24 * HOUR;
+const WEEK: u64 = 7 * DAY;
+
+/// Which replaced versions of its files a sync directory keeps, set
+/// per directory by `versioning` in `config.toml`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Versioning {
+    /// Replaced contents are discarded.
+    Off,
+    /// The `keep` newest versions of each file are kept.
+    Simple { keep: usize },
+    /// Versions are thinned as they age: one per 30 seconds for the
+    /// first hour, one per hour for the first day, one per day for the
+    /// first 30 days, then one per week. Versions older than `max_age`
+    /// days are deleted; `0` keeps them forever.
+    Staggered { max_age: u64 },
+}
+
+impl Default for Versioning {
+    fn default() -> Self {
+        Self::Simple { keep: 5 }
+    }
+}
+
+impl Versioning {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn is_off(&self) -> bool {
+        matches!(self, Self::Off)
+    }
+
+    /// Which of the versions of one file, saved at the `saved_at`
+    /// seconds since UNIX epoch, are past retention at `now`.
+    pub fn expired(&self, saved_at: &[u64], now: u64) -> Vec<u64> {
+        let mut newest_first = saved_at.to_vec();
+        newest_first.sort_unstable_by(|a, b| b.cmp(a));
+
+        match *self {
+            Self::Off => newest_first,
+            Self::Simple { keep } => newest_first.into_iter().skip(keep).collect(),
+            Self::Staggered { max_age } => {
+                let mut expired = Vec::new();
+                let mut last_kept: Option<u64> = None;
+                for saved in newest_first {
+                    let age = now.saturating_sub(saved);
+                    let too_old = max_age > 0 && age > max_age * DAY;
+                    let too_close = last_kept.is_some_and(|kept| kept - saved < spacing(age));
+                    if too_old || too_close {
+                        expired.push(saved);
+                    } else {
+                        last_kept = Some(saved);
+                    }
+                }
+                expired
+            }
+        }
+    }
+}
+
+/// Least time between two versions kept by `Staggered`, for versions
+/// `age` seconds old.
+fn spacing(age: u64) -> u64 {
+    match age {
+        age if age < HOUR => 30,
+        age if age < DAY => HOUR,
+        age if age < 30 * DAY => DAY,
+        _ => WEEK,
+    }
+}
+
+/// A replaced version of the file at `path`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FileVersion {
+    pub path: RelativePath,
+    /// Seconds since UNIX epoch the version was replaced; identifies it
+    /// among the versions of `path`.
+    pub saved_at: u64,
+    pub size: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000;
+    const MINUTE: u64 = 60;
+
+    #[test]
+    fn simple_keeps_the_newest_versions() {
+        let saved = [NOW - 30, NOW - 10, NOW - 20, NOW - 40];
+
+        assert_eq!(
+            Versioning::Simple { keep: 2 }.expired(&saved, NOW),
+            vec![NOW - 30, NOW - 40]
+        );
+        assert!(
+            Versioning::Simple { keep: 5 }
+                .expired(&saved, NOW)
+                .is_empty()
+        );
+        assert_eq!(Versioning::Off.expired(&saved, NOW).len(), 4);
+    }
+
+    #[test]
+    fn staggered_thins_versions_as_they_age() {
+        let saved = [
+            NOW - 10,
+            NOW - 20, // within 30 s of the one kept before it
+            NOW - 50,
+            NOW - 2 * HOUR,
+            NOW - 2 * HOUR - 10 * MINUTE, // within the same hour
+            NOW - 3 * HOUR,
+            NOW - 10 * DAY,
+            NOW - 10 * DAY - HOUR, // within the same day
+            NOW - 60 * DAY,
+            NOW - 62 * DAY, // within the same week
+            NOW - 400 * DAY,
+        ];
+
+        let expired = Versioning::Staggered { max_age: 365 }.expired(&saved, NOW);
+
+        assert_eq!(
+            expired,
+            vec![
+                NOW - 20,
+                NOW - 2 * HOUR - 10 * MINUTE,
+                NOW - 10 * DAY - HOUR,
+                NOW - 62 * DAY,
+                NOW - 400 * DAY,
+            ]
+        );
+        let forever = Versioning::Staggered { max_age: 0 }.expired(&saved, NOW);
+        assert!(!forever.contains(&(NOW - 400 * DAY)));
+    }
+
+    #[test]
+    fn versioning_reads_from_config_toml() {
+        #[derive(Deserialize)]
+        struct Dir {
+            versioning: Versioning,
+        }
+        let versioning = |toml: &str| toml::from_str::<Dir>(toml).unwrap().versioning;
+
+        assert_eq!(
+            versioning(r#"versioning = { type = "off" }"#),
+            Versioning::Off
+        );
+        assert_eq!(
+            versioning(r#"versioning = { type = "simple", keep = 10 }"#),
+            Versioning::Simple { keep: 10 }
+        );
+        assert_eq!(
+            versioning(r#"versioning = { type = "staggered", max_age = 365 }"#),
+            Versioning::Staggered { max_age: 365 }
+        );
+    }
+}
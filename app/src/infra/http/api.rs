@@ -2,7 +2,10 @@ use crate::{
     application::{
         AppState, EntryManager, PeerManager, persistence::interface::PersistenceInterface,
     },
-    domain::RelativePath,
+    domain::{
+        ConflictRecord, ConflictResolution, EntryDiff, FileVersion, PendingDevice, RelativePath,
+        Snapshot, TransportChannelData, TrashRecord,
+    },
 };
 use async_stream::try_stream;
 use axum::{
@@ -15,17 +18,16 @@ use axum::{
 use futures_util::stream::Stream;
 use serde::{Deserialize, Serialize};
 use std::{convert::Infallible, sync::Arc};
-use tokio::sync::broadcast;
+use tokio::sync::{broadcast, mpsc::Sender};
 use tracing::{error, info, warn};
 use uuid::Uuid;
 
 #[derive(Clone)]
 struct ApiState<P: PersistenceInterface> {
     pub state: Arc<AppState>,
-    #[allow(dead_code)]
     pub peer_manager: Arc<PeerManager>,
-    #[allow(dead_code)]
     pub entry_manager: Arc<EntryManager<P>>,
+    pub sender_tx: Sender<TransportChannelData>,
 }
 
 #[derive(Deserialize)]
@@ -38,6 +40,49 @@ struct SetHomePathParams {
     pub path: String,
 }
 
+#[derive(Deserialize)]
+struct DeviceParams {
+    pub id: Uuid,
+}
+
+#[derive(Deserialize)]
+struct ResolveConflictParams {
+    pub id: Uuid,
+    pub resolution: ConflictResolution,
+}
+
+#[derive(Deserialize)]
+struct VersionsParams {
+    pub path: RelativePath,
+}
+
+#[derive(Deserialize)]
+struct RestoreVersionParams {
+    pub path: RelativePath,
+    pub saved_at: u64,
+}
+
+#[derive(Deserialize)]
+struct TrashParams {
+    pub id: Uuid,
+}
+
+#[derive(Deserialize)]
+struct TakeSnapshotParams {
+    pub dir: RelativePath,
+    pub name: String,
+}
+
+#[derive(Deserialize)]
+struct SnapshotParams {
+    pub id: Uuid,
+}
+
+#[derive(Deserialize)]
+struct LocalChangesParams {
+    pub dir: RelativePath,
+}
+
 #[derive(Serialize)]
 struct InfoResponse {
     pub version: &'static str,
@@ -46,17 +91,21 @@ struct InfoResponse {
     pub hostname: String,
 }
 
-/// JSON API routes — peer listing, sync-directory management,
-/// `home_path` updates, and the SSE stream of `ServerEvent`s.
+/// JSON API routes — peer listing, device pairing, sync-directory
+/// management, `home_path` updates, conflict resolution, file version
+/// history, the trash, snapshots, the local changes of receive-only
+/// directories, and the SSE stream of `ServerEvent`s.
 pub fn routes<P: PersistenceInterface>(
     state: Arc<AppState>,
     peer_manager: Arc<PeerManager>,
     entry_manager: Arc<EntryManager<P>>,
+    sender_tx: Sender<TransportChannelData>,
 ) -> Router {
     let api_state = Arc::new(ApiState {
         state,
         peer_manager,
         entry_manager,
+        sender_tx,
     });
 
     Router::new().nest(
@@ -67,6 +116,23 @@ pub fn routes<P: PersistenceInterface>(
             .route("/add-sync-dir", post(add_sync_dir::<P>))
             .route("/remove-sync-dir", post(remove_sync_dir::<P>))
             .route("/set-home-path", post(set_home_path::<P>))
+            .route("/pending-devices", get(pending_devices::<P>))
+            .route("/approve-device", post(approve_device::<P>))
+            .route("/reject-device", post(reject_device::<P>))
+            .route("/conflicts", get(conflicts::<P>))
+            .route("/resolve-conflict", post(resolve_conflict::<P>))
+            .route("/versions", get(versions::<P>))
+            .route("/restore-version", post(restore_version::<P>))
+            .route("/trash", get(trash::<P>))
+            .route("/restore-trash", post(restore_trash::<P>))
+            .route("/purge-trash", post(purge_trash::<P>))
+            .route("/snapshots", get(snapshots::<P>))
+            .route("/take-snapshot", post(take_snapshot::<P>))
+            .route("/snapshot-diff", get(snapshot_diff::<P>))
+            .route("/rollback-snapshot", post(rollback_snapshot::<P>))
+            .route("/delete-snapshot", post(delete_snapshot::<P>))
+            .route("/local-changes", get(local_changes::<P>))
+            .route("/revert-local-changes", post(revert_local_changes::<P>))
             .with_state(api_state),
     )
 }
@@ -132,6 +198,350 @@ async fn set_home_path<P: PersistenceInterface>(
     }
 }
 
+async fn pending_devices<P: PersistenceInterface>(
+    State(state): State<Arc<ApiState<P>>>,
+) -> Json<Vec<PendingDevice>> {
+    Json(state.peer_manager.list_pending().await)
+}
+
+/// Persists the device id first so an approval is never trusted in
+/// memory without surviving a restart, then opens a handshake so the
+/// two devices start syncing without waiting for the next ping.
+async fn approve_device<P: PersistenceInterface>(
+    State(state): State<Arc<ApiState<P>>>,
+    Query(params): Query<DeviceParams>,
+) -> StatusCode {
+    if state.peer_manager.get_pending(&params.id).await.is_none() {
+        return StatusCode::NOT_FOUND;
+    }
+
+    if let Err(err) = state.entry_manager.trust_device(params.id).await {
+        error!("Approve device error: {err}");
+        return StatusCode::INTERNAL_SERVER_ERROR;
+    }
+
+    let Some(device) = state.peer_manager.approve(params.id).await else {
+        return StatusCode::NOT_FOUND;
+    };
+
+    if let Err(err) = state
+        .sender_tx
+        .send(TransportChannelData::HandshakeSyn(device.transport_addr()))
+        .await
+    {
+        error!("Approve device handshake error: {err}");
+    }
+    StatusCode::OK
+}
+
+async fn reject_device<P: PersistenceInterface>(
+    State(state): State<Arc<ApiState<P>>>,
+    Query(params): Query<DeviceParams>,
+) -> StatusCode {
+    if state.peer_manager.reject(params.id).await {
+        StatusCode::OK
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
+
+async fn conflicts<P: PersistenceInterface>(
+    State(state): State<Arc<ApiState<P>>>,
+) -> Result<Json<Vec<ConflictRecord>>, StatusCode> {
+    match state.entry_manager.list_conflicts().await {
+        Ok(conflicts) => Ok(Json(conflicts)),
+        Err(err) => {
+            error!("List conflicts error: {err}");
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Announces the entries the resolution changed to every peer, as any
+/// local edit is.
+async fn resolve_conflict<P: PersistenceInterface>(
+    State(state): State<Arc<ApiState<P>>>,
+    Query(params): Query<ResolveConflictParams>,
+) -> StatusCode {
+    let entries = match state
+        .entry_manager
+        .resolve_conflict(params.id, params.resolution)
+        .await
+    {
+        Ok(Some(entries)) => entries,
+        Ok(None) => return StatusCode::NOT_FOUND,
+        Err(err) => {
+            error!("Resolve conflict error: {err}");
+            return match err.kind() {
+                std::io::ErrorKind::NotFound | std::io::ErrorKind::ReadOnlyFilesystem => {
+                    StatusCode::CONFLICT
+                }
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+        }
+    };
+
+    for entry in entries {
+        if let Err(err) = state
+            .sender_tx
+            .send(TransportChannelData::Metadata(entry))
+            .await
+        {
+            error!("Resolve conflict metadata error: {err}");
+        }
+    }
+    StatusCode::OK
+}
+
+async fn versions<P: PersistenceInterface>(
+    State(state): State<Arc<ApiState<P>>>,
+    Query(params): Query<VersionsParams>,
+) -> Result<Json<Vec<FileVersion>>, StatusCode> {
+    match state.entry_manager.list_versions(&params.path).await {
+        Ok(Some(versions)) => Ok(Json(versions)),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(err) => {
+            error!("List versions error: {err}");
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Announces the restored entry to every peer, as any local edit is.
+async fn restore_version<P: PersistenceInterface>(
+    State(state): State<Arc<ApiState<P>>>,
+    Query(params): Query<RestoreVersionParams>,
+) -> StatusCode {
+    let entry = match state
+        .entry_manager
+        .restore_version(&params.path, params.saved_at)
+        .await
+    {
+        Ok(Some(entry)) => entry,
+        Ok(None) => return StatusCode::NOT_FOUND,
+        Err(err) => {
+            error!("Restore version error: {err}");
+            return match err.kind() {
+                std::io::ErrorKind::IsADirectory | std::io::ErrorKind::ReadOnlyFilesystem => {
+                    StatusCode::CONFLICT
+                }
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+        }
+    };
+
+    if let Err(err) = state
+        .sender_tx
+        .send(TransportChannelData::Metadata(entry))
+        .await
+    {
+        error!("Restore version metadata error: {err}");
+    }
+    StatusCode::OK
+}
+
+async fn trash<P: PersistenceInterface>(
+    State(state): State<Arc<ApiState<P>>>,
+) -> Result<Json<Vec<TrashRecord>>, StatusCode> {
+    match state.entry_manager.list_trash().await {
+        Ok(trash) => Ok(Json(trash)),
+        Err(err) => {
+            error!("List trash error: {err}");
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Announces the restored entries to every peer, as any local edit is.
+async fn restore_trash<P: PersistenceInterface>(
+    State(state): State<Arc<ApiState<P>>>,
+    Query(params): Query<TrashParams>,
+) -> StatusCode {
+    let entries = match state.entry_manager.restore_trash(params.id).await {
+        Ok(Some(entries)) => entries,
+        Ok(None) => return StatusCode::NOT_FOUND,
+        Err(err) => {
+            error!("Restore trash error: {err}");
+            return match err.kind() {
+                std::io::ErrorKind::AlreadyExists | std::io::ErrorKind::ReadOnlyFilesystem => {
+                    StatusCode::CONFLICT
+                }
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+        }
+    };
+
+    for entry in entries {
+        if let Err(err) = state
+            .sender_tx
+            .send(TransportChannelData::Metadata(entry))
+            .await
+        {
+            error!("Restore trash metadata error: {err}");
+        }
+    }
+    StatusCode::OK
+}
+
+async fn purge_trash<P: PersistenceInterface>(
+    State(state): State<Arc<ApiState<P>>>,
+    Query(params): Query<TrashParams>,
+) -> StatusCode {
+    match state.entry_manager.purge_trash(params.id).await {
+        Ok(true) => StatusCode::OK,
+        Ok(false) => StatusCode::NOT_FOUND,
+        Err(err) => {
+            error!("Purge trash error: {err}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+async fn snapshots<P: PersistenceInterface>(
+    State(state): State<Arc<ApiState<P>>>,
+) -> Result<Json<Vec<Snapshot>>, StatusCode> {
+    match state.entry_manager.list_snapshots().await {
+        Ok(snapshots) => Ok(Json(snapshots)),
+        Err(err) => {
+            error!("List snapshots error: {err}");
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn take_snapshot<P: PersistenceInterface>(
+    State(state): State<Arc<ApiState<P>>>,
+    Query(params): Query<TakeSnapshotParams>,
+) -> Result<(StatusCode, Json<Snapshot>), StatusCode> {
+    match state
+        .entry_manager
+        .take_snapshot(&params.dir, params.name.trim())
+        .await
+    {
+        Ok(Some(snapshot)) => {
+            info!("Snapshot taken: {} of {}", snapshot.name, snapshot.dir);
+            Ok((StatusCode::CREATED, Json(snapshot)))
+        }
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(err) => {
+            error!("Take snapshot error: {err}");
+            Err(match err.kind() {
+                std::io::ErrorKind::InvalidInput => StatusCode::BAD_REQUEST,
+                std::io::ErrorKind::AlreadyExists => StatusCode::CONFLICT,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            })
+        }
+    }
+}
+
+async fn snapshot_diff<P: PersistenceInterface>(
+    State(state): State<Arc<ApiState<P>>>,
+    Query(params): Query<SnapshotParams>,
+) -> Result<Json<EntryDiff>, StatusCode> {
+    match state.entry_manager.diff_snapshot(params.id).await {
+        Ok(Some(diff)) => Ok(Json(diff)),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(err) => {
+            error!("Snapshot diff error: {err}");
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Announces every entry the rollback changed to every peer, as any
+/// local edit is.
+async fn rollback_snapshot<P: PersistenceInterface>(
+    State(state): State<Arc<ApiState<P>>>,
+    Query(params): Query<SnapshotParams>,
+) -> StatusCode {
+    let entries = match state.entry_manager.rollback_snapshot(params.id).await {
+        Ok(Some(entries)) => entries,
+        Ok(None) => return StatusCode::NOT_FOUND,
+        Err(err) => {
+            error!("Rollback snapshot error: {err}");
+            return match err.kind() {
+                std::io::ErrorKind::ReadOnlyFilesystem => StatusCode::CONFLICT,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+        }
+    };
+
+    for entry in entries {
+        if let Err(err) = state
+            .sender_tx
+            .send(TransportChannelData::Metadata(entry))
+            .await
+        {
+            error!("Rollback snapshot metadata error: {err}");
+        }
+    }
+    StatusCode::OK
+}
+
+async fn delete_snapshot<P: PersistenceInterface>(
+    State(state): State<Arc<ApiState<P>>>,
+    Query(params): Query<SnapshotParams>,
+) -> StatusCode {
+    match state.entry_manager.delete_snapshot(params.id).await {
+        Ok(true) => StatusCode::OK,
+        Ok(false) => StatusCode::NOT_FOUND,
+        Err(err) => {
+            error!("Delete snapshot error: {err}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+async fn local_changes<P: PersistenceInterface>(
+    State(state): State<Arc<ApiState<P>>>,
+    Query(params): Query<LocalChangesParams>,
+) -> Result<Json<EntryDiff>, StatusCode> {
+    match state.entry_manager.local_changes(&params.dir).await {
+        Ok(Some(diff)) => Ok(Json(diff)),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(err) if err.kind() == std::io::ErrorKind::InvalidInput => Err(StatusCode::BAD_REQUEST),
+        Err(err) => {
+            error!("Local changes error: {err}");
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Requests every file the revert left to fetch from the peer that
+/// wrote it last; files no connected peer shares are fetched at the
+/// next handshake that offers them.
+async fn revert_local_changes<P: PersistenceInterface>(
+    State(state): State<Arc<ApiState<P>>>,
+    Query(params): Query<LocalChangesParams>,
+) -> StatusCode {
+    let fetch = match state.entry_manager.revert_local_changes(&params.dir).await {
+        Ok(Some(fetch)) => fetch,
+        Ok(None) => return StatusCode::NOT_FOUND,
+        Err(err) if err.kind() == std::io::ErrorKind::InvalidInput => {
+            return StatusCode::BAD_REQUEST;
+        }
+        Err(err) => {
+            error!("Revert local changes error: {err}");
+            return StatusCode::INTERNAL_SERVER_ERROR;
+        }
+    };
+
+    for entry in fetch {
+        let Some(target) = state.peer_manager.get_peer_to_request(&entry).await else {
+            warn!("No peer to fetch {} from", entry.name);
+            continue;
+        };
+        if let Err(err) = state
+            .sender_tx
+            .send(TransportChannelData::Request((target, entry)))
+            .await
+        {
+            error!("Revert local changes request error: {err}");
+        }
+    }
+    StatusCode::OK
+}
+
 async fn sse_events<P: PersistenceInterface>(
     State(state): State<Arc<ApiState<P>>>,
 ) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
@@ -166,20 +576,33 @@ async fn sse_events<P: PersistenceInterface>(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{application::persistence::interface::PersistenceResult, domain::EntryInfo};
+    use crate::{
+        application::persistence::interface::PersistenceResult,
+        domain::{DirectoryMode, EntryInfo, EntryKind, MergeBase, SyncDirectory, VersionVector},
+    };
     use axum::http::StatusCode;
     use std::time::Duration;
     use tokio::sync::Mutex;
     use uuid::Uuid;
 
+    type SnapshotRow = (Snapshot, Vec<EntryInfo>);
+
     struct MockPersistence {
         entries: Arc<Mutex<Vec<EntryInfo>>>,
+        trusted: Arc<Mutex<Vec<Uuid>>>,
+        conflicts: Arc<Mutex<Vec<ConflictRecord>>>,
+        trash: Arc<Mutex<Vec<TrashRecord>>>,
+        snapshots: Arc<Mutex<Vec<SnapshotRow>>>,
     }
 
     impl MockPersistence {
         fn new() -> Self {
             Self {
                 entries: Arc::new(Mutex::new(vec![])),
+                trusted: Arc::new(Mutex::new(vec![])),
+                conflicts: Arc::new(Mutex::new(vec![])),
+                trash: Arc::new(Mutex::new(vec![])),
+                snapshots: Arc::new(Mutex::new(vec![])),
             }
         }
     }
@@ -205,10 +628,166 @@ mod tests {
             Ok(self.entries.lock().await.clone())
         }
 
+        async fn list_entries_after(
+            &self,
+            after: Option<&str>,
+            limit: usize,
+        ) -> PersistenceResult<Vec<EntryInfo>> {
+            let mut entries: Vec<_> = self
+                .entries
+                .lock()
+                .await
+                .iter()
+                .filter(|e| after.is_none_or(|after| &*e.name > after))
+                .cloned()
+                .collect();
+            entries.sort_by(|a, b| a.name.cmp(&b.name));
+            entries.truncate(limit);
+            Ok(entries)
+        }
+
+        async fn list_entries_within(
+            &self,
+            dir: &str,
+            after: Option<&str>,
+            limit: usize,
+        ) -> PersistenceResult<Vec<EntryInfo>> {
+            let dir = RelativePath::from(dir);
+            let mut entries = self.list_entries_after(after, usize::MAX).await?;
+            entries.retain(|e| e.name.starts_with_dir(&dir));
+            entries.truncate(limit);
+            Ok(entries)
+        }
+
         async fn delete_entry(&self, name: &str) -> PersistenceResult<()> {
             self.entries.lock().await.retain(|e| &*e.name != name);
             Ok(())
         }
+
+        async fn insert_trusted_device(&self, id: &Uuid) -> PersistenceResult<()> {
+            self.trusted.lock().await.push(*id);
+            Ok(())
+        }
+
+        async fn list_trusted_devices(&self) -> PersistenceResult<Vec<Uuid>> {
+            Ok(self.trusted.lock().await.clone())
+        }
+
+        async fn insert_tombstone_ack(&self, _name: &str, _id: &Uuid) -> PersistenceResult<()> {
+            Ok(())
+        }
+
+        async fn list_tombstone_acks(&self, _name: &str) -> PersistenceResult<Vec<Uuid>> {
+            Ok(vec![])
+        }
+
+        async fn delete_tombstone_acks(&self, _name: &str) -> PersistenceResult<()> {
+            Ok(())
+        }
+
+        async fn insert_conflict(&self, conflict: &ConflictRecord) -> PersistenceResult<()> {
+            self.conflicts.lock().await.push(conflict.clone());
+            Ok(())
+        }
+
+        async fn list_conflicts(&self) -> PersistenceResult<Vec<ConflictRecord>> {
+            Ok(self.conflicts.lock().await.clone())
+        }
+
+        async fn delete_conflict(&self, id: &Uuid) -> PersistenceResult<()> {
+            self.conflicts.lock().await.retain(|c| c.id != *id);
+            Ok(())
+        }
+
+        async fn insert_trash(&self, record: &TrashRecord) -> PersistenceResult<()> {
+            self.trash.lock().await.push(record.clone());
+            Ok(())
+        }
+
+        async fn list_trash(&self) -> PersistenceResult<Vec<TrashRecord>> {
+            Ok(self.trash.lock().await.clone())
+        }
+
+        async fn delete_trash(&self, id: &Uuid) -> PersistenceResult<()> {
+            self.trash.lock().await.retain(|r| r.id != *id);
+            Ok(())
+        }
+
+        async fn insert_snapshot(
+            &self,
+            snapshot: &Snapshot,
+            entries: &[EntryInfo],
+        ) -> PersistenceResult<()> {
+            self.snapshots
+                .lock()
+                .await
+                .push((snapshot.clone(), entries.to_vec()));
+            Ok(())
+        }
+
+        async fn list_snapshots(&self) -> PersistenceResult<Vec<Snapshot>> {
+            Ok(self
+                .snapshots
+                .lock()
+                .await
+                .iter()
+                .map(|(snapshot, _)| snapshot.clone())
+                .collect())
+        }
+
+        async fn list_snapshot_entries(&self, id: &Uuid) -> PersistenceResult<Vec<EntryInfo>> {
+            Ok(self
+                .snapshots
+                .lock()
+                .await
+                .iter()
+                .find(|(snapshot, _)| snapshot.id == *id)
+                .map(|(_, entries)| entries.clone())
+                .unwrap_or_default())
+        }
+
+        async fn delete_snapshot(&self, id: &Uuid) -> PersistenceResult<()> {
+            self.snapshots
+                .lock()
+                .await
+                .retain(|(snapshot, _)| snapshot.id != *id);
+            Ok(())
+        }
+
+        async fn set_merge_base(
+            &self,
+            _name: &str,
+            _hash: &str,
+            _version: &VersionVector,
+        ) -> PersistenceResult<()> {
+            Ok(())
+        }
+
+        async fn get_merge_base(&self, _name: &str) -> PersistenceResult<Option<MergeBase>> {
+            Ok(None)
+        }
+
+        async fn delete_merge_base(&self, _name: &str) -> PersistenceResult<()> {
+            Ok(())
+        }
+
+        async fn is_merge_base(&self, _hash: &str) -> PersistenceResult<bool> {
+            Ok(false)
+        }
+
+        async fn set_pending_merge(&self, _name: &str, _id: &Uuid) -> PersistenceResult<()> {
+            Ok(())
+        }
+
+        async fn take_pending_merge(&self, _name: &str) -> PersistenceResult<Option<Uuid>> {
+            Ok(None)
+        }
+    }
+
+    /// A sender whose receiver is already gone, for tests that never
+    /// trigger a handshake.
+    fn sender_tx() -> Sender<TransportChannelData> {
+        tokio::sync::mpsc::channel(1).0
     }
 
     async fn create_test_components() -> (
@@ -238,6 +817,7 @@ mod tests {
             state: state.clone(),
             peer_manager: pm,
             entry_manager: em,
+            sender_tx: sender_tx(),
         });
 
         let params = ModifySyncDirParams {
@@ -268,6 +848,7 @@ mod tests {
             state: state.clone(),
             peer_manager: pm,
             entry_manager: em,
+            sender_tx: sender_tx(),
         });
 
         let params = ModifySyncDirParams {
@@ -291,6 +872,7 @@ mod tests {
             state: state.clone(),
             peer_manager: pm,
             entry_manager: em,
+            sender_tx: sender_tx(),
         });
 
         let params = ModifySyncDirParams {
@@ -317,6 +899,7 @@ mod tests {
             state: state.clone(),
             peer_manager: pm,
             entry_manager: em,
+            sender_tx: sender_tx(),
         });
 
         let params = ModifySyncDirParams {
@@ -335,6 +918,7 @@ mod tests {
             state,
             peer_manager: pm,
             entry_manager: em,
+            sender_tx: sender_tx(),
         });
 
         let params = ModifySyncDirParams {
@@ -361,6 +945,7 @@ mod tests {
             state,
             peer_manager: pm,
             entry_manager: em,
+            sender_tx: sender_tx(),
         });
 
         let params = ModifySyncDirParams {
@@ -379,6 +964,7 @@ mod tests {
             state,
             peer_manager: pm,
             entry_manager: em,
+            sender_tx: sender_tx(),
         });
 
         let temp_dir = tempfile::tempdir().unwrap();
@@ -398,6 +984,7 @@ mod tests {
             state,
             peer_manager: pm,
             entry_manager: em,
+            sender_tx: sender_tx(),
         });
 
         let temp_dir = tempfile::tempdir().unwrap();
@@ -424,6 +1011,7 @@ mod tests {
             state: state.clone(),
             peer_manager: pm,
             entry_manager: em,
+            sender_tx: sender_tx(),
         });
 
         let Json(body) = info(State(api_state)).await;
@@ -451,4 +1039,416 @@ mod tests {
             "Event should be received successfully"
         );
     }
+
+    fn pending_device(id: Uuid) -> PendingDevice {
+        PendingDevice {
+            id,
+            addr: "10.0.0.9".parse().unwrap(),
+            transport_port: 42882,
+            hostname: Some("laptop".into()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_approve_device_persists_trust_and_starts_handshake() {
+        let (_env, state, pm, em) = create_test_components().await;
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        let id = Uuid::new_v4();
+        pm.add_pending(pending_device(id)).await;
+
+        let api_state = Arc::new(ApiState {
+            state,
+            peer_manager: pm.clone(),
+            entry_manager: em.clone(),
+            sender_tx: tx,
+        });
+
+        let Json(pending) = pending_devices(State(api_state.clone())).await;
+        assert_eq!(pending.len(), 1);
+
+        let status = approve_device(State(api_state), Query(DeviceParams { id })).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert!(pm.is_trusted(&id).await);
+        assert_eq!(em.list_trusted_devices().await.unwrap(), vec![id]);
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(TransportChannelData::HandshakeSyn(addr)) if addr == pending_device(id).transport_addr()
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_approve_unknown_device_is_not_found() {
+        let (_env, state, pm, em) = create_test_components().await;
+        let api_state = Arc::new(ApiState {
+            state,
+            peer_manager: pm,
+            entry_manager: em.clone(),
+            sender_tx: sender_tx(),
+        });
+
+        let status =
+            approve_device(State(api_state), Query(DeviceParams { id: Uuid::new_v4() })).await;
+
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert!(em.list_trusted_devices().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_conflict_announces_the_kept_copy() {
+        let env = crate::utils::test_support::test_env().await;
+        let state = env.state.clone();
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        let conflict = ConflictRecord {
+            id: Uuid::new_v4(),
+            path: "sync/a.txt".into(),
+            conflict_path: "sync/a_CONFLICT_100_x.txt".into(),
+            peer: Uuid::new_v4(),
+            local_version: Default::default(),
+            remote_version: Default::default(),
+            detected_at: 100,
+        };
+        let copy = conflict.conflict_path.to_canonical(state.home_path());
+        tokio::fs::create_dir_all(copy.parent().unwrap())
+            .await
+            .unwrap();
+        tokio::fs::write(&copy, b"ours").await.unwrap();
+        let db = MockPersistence::new();
+        db.conflicts.lock().await.push(conflict.clone());
+
+        let api_state = Arc::new(ApiState {
+            state: state.clone(),
+            peer_manager: PeerManager::new(state.clone()),
+            entry_manager: EntryManager::new(db, state),
+            sender_tx: tx,
+        });
+        let params = |resolution| ResolveConflictParams {
+            id: conflict.id,
+            resolution,
+        };
+
+        let Json(listed) = conflicts(State(api_state.clone())).await.unwrap();
+        assert_eq!(listed, vec![conflict.clone()]);
+
+        let status = resolve_conflict(
+            State(api_state.clone()),
+            Query(params(ConflictResolution::Both)),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(TransportChannelData::Metadata(entry)) if entry.name == conflict.conflict_path
+        ));
+
+        let status =
+            resolve_conflict(State(api_state), Query(params(ConflictResolution::Remote))).await;
+
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_restore_version_announces_the_restored_file() {
+        let (_env, state, pm, em) = create_test_components().await;
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        let home = state.home_path().clone();
+        let path: RelativePath = "Default Folder/a.txt".into();
+        let store = home.join("Default Folder/.synche/versions");
+        tokio::fs::create_dir_all(&store).await.unwrap();
+        tokio::fs::write(store.join("a.txt~100"), b"old")
+            .await
+            .unwrap();
+        tokio::fs::write(home.join(&*path), b"new").await.unwrap();
+
+        let api_state = Arc::new(ApiState {
+            state,
+            peer_manager: pm,
+            entry_manager: em,
+            sender_tx: tx,
+        });
+        let params = |path: &str, saved_at| RestoreVersionParams {
+            path: path.into(),
+            saved_at,
+        };
+
+        let Json(listed) = versions(
+            State(api_state.clone()),
+            Query(VersionsParams { path: path.clone() }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].saved_at, 100);
+
+        let status = restore_version(State(api_state.clone()), Query(params(&path, 100))).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(TransportChannelData::Metadata(entry)) if entry.name == path
+        ));
+        assert_eq!(tokio::fs::read(home.join(&*path)).await.unwrap(), b"old");
+
+        let status = restore_version(State(api_state.clone()), Query(params(&path, 99))).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let status = restore_version(State(api_state), Query(params("../a.txt", 100))).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_local_edits_in_a_receive_only_dir_are_rejected_unannounced() {
+        let (_env, state, pm, em) = create_test_components().await;
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        let home = state.home_path().clone();
+        let path: RelativePath = "Default Folder/a.txt".into();
+        let store = home.join("Default Folder/.synche/versions");
+        tokio::fs::create_dir_all(&store).await.unwrap();
+        tokio::fs::write(store.join("a.txt~100"), b"old")
+            .await
+            .unwrap();
+        tokio::fs::write(home.join(&*path), b"new").await.unwrap();
+        let snapshot = em
+            .take_snapshot(&"Default Folder".into(), "before")
+            .await
+            .unwrap()
+            .unwrap();
+        let dir = SyncDirectory {
+            mode: DirectoryMode::ReceiveOnly,
+            ..SyncDirectory::new("Default Folder".into())
+        };
+        assert!(em.update_sync_dir(dir).await);
+
+        let api_state = Arc::new(ApiState {
+            state,
+            peer_manager: pm,
+            entry_manager: em,
+            sender_tx: tx,
+        });
+
+        let params = RestoreVersionParams {
+            path: path.clone(),
+            saved_at: 100,
+        };
+        let status = restore_version(State(api_state.clone()), Query(params)).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        let params = SnapshotParams { id: snapshot.id };
+        let status = rollback_snapshot(State(api_state), Query(params)).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert!(rx.try_recv().is_err());
+        assert_eq!(tokio::fs::read(home.join(&*path)).await.unwrap(), b"new");
+    }
+
+    #[tokio::test]
+    async fn test_restore_trash_announces_the_entry_unless_its_path_is_taken() {
+        let (_env, state, pm, em) = create_test_components().await;
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        let home = state.home_path().clone();
+        let path: RelativePath = "Default Folder/a.txt".into();
+        tokio::fs::create_dir_all(home.join("Default Folder"))
+            .await
+            .unwrap();
+        let tombstone = EntryInfo {
+            name: path.clone(),
+            kind: EntryKind::File,
+            hash: None,
+            version: std::collections::HashMap::from([(Uuid::new_v4(), 1)]),
+            modified: None,
+            removed_at: None,
+        };
+        let put_in_trash = |contents: &'static [u8]| {
+            let (em, home, tombstone) = (em.clone(), home.clone(), tombstone.clone());
+            async move {
+                tokio::fs::write(home.join(&*tombstone.name), contents)
+                    .await
+                    .unwrap();
+                em.trash_entry(Uuid::new_v4(), &tombstone).await.unwrap();
+                em.list_trash().await.unwrap().pop().unwrap().id
+            }
+        };
+        let first = put_in_trash(b"first").await;
+
+        let api_state = Arc::new(ApiState {
+            state,
+            peer_manager: pm,
+            entry_manager: em.clone(),
+            sender_tx: tx,
+        });
+
+        let status =
+            restore_trash(State(api_state.clone()), Query(TrashParams { id: first })).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(TransportChannelData::Metadata(entry)) if entry.name == path
+        ));
+        assert_eq!(tokio::fs::read(home.join(&*path)).await.unwrap(), b"first");
+
+        let status =
+            restore_trash(State(api_state.clone()), Query(TrashParams { id: first })).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let second = put_in_trash(b"second").await;
+        tokio::fs::write(home.join(&*path), b"taken").await.unwrap();
+
+        let status =
+            restore_trash(State(api_state.clone()), Query(TrashParams { id: second })).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+
+        let status = purge_trash(State(api_state.clone()), Query(TrashParams { id: second })).await;
+        assert_eq!(status, StatusCode::OK);
+        let Json(listed) = trash(State(api_state)).await.unwrap();
+        assert!(listed.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_local_changes_of_a_receive_only_dir_are_listed_and_reverted() {
+        let (_env, state, pm, em) = create_test_components().await;
+        let home = state.home_path().clone();
+        tokio::fs::create_dir_all(home.join("Default Folder"))
+            .await
+            .unwrap();
+        tokio::fs::write(home.join("Default Folder/local.txt"), b"local")
+            .await
+            .unwrap();
+
+        let api_state = Arc::new(ApiState {
+            state,
+            peer_manager: pm,
+            entry_manager: em.clone(),
+            sender_tx: sender_tx(),
+        });
+        let changes = |dir: &str| {
+            local_changes(
+                State(api_state.clone()),
+                Query(LocalChangesParams { dir: dir.into() }),
+            )
+        };
+        let revert = |dir: &str| {
+            revert_local_changes(
+                State(api_state.clone()),
+                Query(LocalChangesParams { dir: dir.into() }),
+            )
+        };
+
+        assert_eq!(
+            changes("Default Folder").await.unwrap_err(),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(revert("Default Folder").await, StatusCode::BAD_REQUEST);
+        assert_eq!(changes("Unknown").await.unwrap_err(), StatusCode::NOT_FOUND);
+
+        let dir = SyncDirectory {
+            mode: DirectoryMode::ReceiveOnly,
+            ..SyncDirectory::new("Default Folder".into())
+        };
+        assert!(em.update_sync_dir(dir).await);
+        let Json(diff) = changes("Default Folder").await.unwrap();
+        assert_eq!(
+            diff.added,
+            vec![RelativePath::from("Default Folder/local.txt")]
+        );
+
+        assert_eq!(revert("Default Folder").await, StatusCode::OK);
+        assert!(!home.join("Default Folder/local.txt").exists());
+        let Json(diff) = changes("Default Folder").await.unwrap();
+        assert!(diff.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_take_snapshot_then_list_and_delete_it() {
+        let (_env, state, pm, em) = create_test_components().await;
+        let home = state.home_path().clone();
+        tokio::fs::create_dir_all(home.join("Default Folder"))
+            .await
+            .unwrap();
+        tokio::fs::write(home.join("Default Folder/a.txt"), b"a")
+            .await
+            .unwrap();
+        em.entry_created(
+            &"Default Folder/a.txt".into(),
+            EntryKind::File,
+            Some(
+                crate::utils::fs::compute_hash(&home.join("Default Folder/a.txt"))
+                    .await
+                    .unwrap(),
+            ),
+        )
+        .await
+        .unwrap();
+
+        let api_state = Arc::new(ApiState {
+            state,
+            peer_manager: pm,
+            entry_manager: em,
+            sender_tx: sender_tx(),
+        });
+        let take = |dir: &str, name: &str| {
+            take_snapshot(
+                State(api_state.clone()),
+                Query(TakeSnapshotParams {
+                    dir: dir.into(),
+                    name: name.into(),
+                }),
+            )
+        };
+
+        let (status, Json(snapshot)) = take("Default Folder", " before ").await.unwrap();
+
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(snapshot.name, "before");
+        let Json(listed) = snapshots(State(api_state.clone())).await.unwrap();
+        assert_eq!(listed, vec![snapshot.clone()]);
+        assert_eq!(
+            take("Default Folder", "before").await.unwrap_err(),
+            StatusCode::CONFLICT
+        );
+        assert_eq!(
+            take("Default Folder", " ").await.unwrap_err(),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            take("Unknown", "before").await.unwrap_err(),
+            StatusCode::NOT_FOUND
+        );
+
+        let Json(diff) = snapshot_diff(
+            State(api_state.clone()),
+            Query(SnapshotParams { id: snapshot.id }),
+        )
+        .await
+        .unwrap();
+        assert!(diff.is_empty());
+
+        let params = |id| Query(SnapshotParams { id });
+        let status = delete_snapshot(State(api_state.clone()), params(snapshot.id)).await;
+        assert_eq!(status, StatusCode::OK);
+        let status = rollback_snapshot(State(api_state.clone()), params(snapshot.id)).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let status = delete_snapshot(State(api_state), params(snapshot.id)).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_reject_device_removes_it_without_trusting() {
+        let (_env, state, pm, em) = create_test_components().await;
+        let id = Uuid::new_v4();
+        pm.add_pending(pending_device(id)).await;
+
+        let api_state = Arc::new(ApiState {
+            state,
+            peer_manager: pm.clone(),
+            entry_manager: em.clone(),
+            sender_tx: sender_tx(),
+        });
+
+        let status = reject_device(State(api_state), Query(DeviceParams { id })).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert!(pm.list_pending().await.is_empty());
+        assert!(!pm.is_trusted(&id).await);
+        assert!(em.list_trusted_devices().await.unwrap().is_empty());
+    }
 }
@@ -3,7 +3,7 @@ use crate::application::{
 };
 use axum::{Router, extract::State, http::StatusCode, response::Html, routing::get};
 use minijinja::{Environment, context};
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 use tower_http::services::ServeDir;
 
 struct GuiState<P: PersistenceInterface> {
@@ -39,6 +39,23 @@ async fn index<P: PersistenceInterface>(
 ) -> Result<Html<String>, StatusCode> {
     let dirs = state.entry_manager.list_dirs().await;
     let dirs: Vec<_> = dirs.values().cloned().collect();
+    let trash = state
+        .entry_manager
+        .list_trash()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut local_changes = HashMap::new();
+    for dir in dirs.iter().filter(|dir| dir.mode.is_receive_only()) {
+        if let Some(changes) = state
+            .entry_manager
+            .local_changes(&dir.name)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        {
+            local_changes.insert(dir.name.clone(), changes);
+        }
+    }
 
     let tmpl = state
         .engine
@@ -51,6 +68,9 @@ async fn index<P: PersistenceInterface>(
             hostname => state.state.hostname(),
             local_id => state.state.local_id(),
             peers => state.peer_manager.list().await,
+            pending => state.peer_manager.list_pending().await,
+            trash => trash,
+            local_changes => local_changes,
             local_ip => state.state.local_ip().await,
             home_path => state.state.home_path().display().to_string(),
             version => env!("CARGO_PKG_VERSION"),
@@ -64,19 +84,26 @@ async fn index<P: PersistenceInterface>(
 mod tests {
     use super::*;
     use crate::{
-        application::persistence::interface::PersistenceResult, domain::EntryInfo,
+        application::persistence::interface::PersistenceResult,
+        domain::{
+            ConflictRecord, DirectoryMode, EntryInfo, MergeBase, RelativePath, Snapshot,
+            SyncDirectory, TrashRecord, VersionVector,
+        },
         infra::http::server::init_template_engine,
     };
     use tokio::sync::Mutex;
+    use uuid::Uuid;
 
     struct MockPersistence {
         entries: Arc<Mutex<Vec<EntryInfo>>>,
+        trash: Arc<Mutex<Vec<TrashRecord>>>,
     }
 
     impl MockPersistence {
         fn new() -> Self {
             Self {
                 entries: Arc::new(Mutex::new(vec![])),
+                trash: Arc::new(Mutex::new(vec![])),
             }
         }
     }
@@ -102,10 +129,135 @@ mod tests {
             Ok(self.entries.lock().await.clone())
         }
 
+        async fn list_entries_after(
+            &self,
+            after: Option<&str>,
+            limit: usize,
+        ) -> PersistenceResult<Vec<EntryInfo>> {
+            let mut entries: Vec<_> = self
+                .entries
+                .lock()
+                .await
+                .iter()
+                .filter(|e| after.is_none_or(|after| &*e.name > after))
+                .cloned()
+                .collect();
+            entries.sort_by(|a, b| a.name.cmp(&b.name));
+            entries.truncate(limit);
+            Ok(entries)
+        }
+
+        async fn list_entries_within(
+            &self,
+            dir: &str,
+            after: Option<&str>,
+            limit: usize,
+        ) -> PersistenceResult<Vec<EntryInfo>> {
+            let dir = RelativePath::from(dir);
+            let mut entries = self.list_entries_after(after, usize::MAX).await?;
+            entries.retain(|e| e.name.starts_with_dir(&dir));
+            entries.truncate(limit);
+            Ok(entries)
+        }
+
         async fn delete_entry(&self, name: &str) -> PersistenceResult<()> {
             self.entries.lock().await.retain(|e| &*e.name != name);
             Ok(())
         }
+
+        async fn insert_trusted_device(&self, _id: &Uuid) -> PersistenceResult<()> {
+            Ok(())
+        }
+
+        async fn list_trusted_devices(&self) -> PersistenceResult<Vec<Uuid>> {
+            Ok(vec![])
+        }
+
+        async fn insert_tombstone_ack(&self, _name: &str, _id: &Uuid) -> PersistenceResult<()> {
+            Ok(())
+        }
+
+        async fn list_tombstone_acks(&self, _name: &str) -> PersistenceResult<Vec<Uuid>> {
+            Ok(vec![])
+        }
+
+        async fn delete_tombstone_acks(&self, _name: &str) -> PersistenceResult<()> {
+            Ok(())
+        }
+
+        async fn insert_conflict(&self, _conflict: &ConflictRecord) -> PersistenceResult<()> {
+            Ok(())
+        }
+
+        async fn list_conflicts(&self) -> PersistenceResult<Vec<ConflictRecord>> {
+            Ok(vec![])
+        }
+
+        async fn delete_conflict(&self, _id: &Uuid) -> PersistenceResult<()> {
+            Ok(())
+        }
+
+        async fn insert_trash(&self, record: &TrashRecord) -> PersistenceResult<()> {
+            self.trash.lock().await.push(record.clone());
+            Ok(())
+        }
+
+        async fn list_trash(&self) -> PersistenceResult<Vec<TrashRecord>> {
+            Ok(self.trash.lock().await.clone())
+        }
+
+        async fn delete_trash(&self, _id: &Uuid) -> PersistenceResult<()> {
+            Ok(())
+        }
+
+        async fn insert_snapshot(
+            &self,
+            _snapshot: &Snapshot,
+            _entries: &[EntryInfo],
+        ) -> PersistenceResult<()> {
+            Ok(())
+        }
+
+        async fn list_snapshots(&self) -> PersistenceResult<Vec<Snapshot>> {
+            Ok(vec![])
+        }
+
+        async fn list_snapshot_entries(&self, _id: &Uuid) -> PersistenceResult<Vec<EntryInfo>> {
+            Ok(vec![])
+        }
+
+        async fn delete_snapshot(&self, _id: &Uuid) -> PersistenceResult<()> {
+            Ok(())
+        }
+
+        async fn set_merge_base(
+            &self,
+            _name: &str,
+            _hash: &str,
+            _version: &VersionVector,
+        ) -> PersistenceResult<()> {
+            Ok(())
+        }
+
+        async fn get_merge_base(&self, _name: &str) -> PersistenceResult<Option<MergeBase>> {
+            Ok(None)
+        }
+
+        async fn delete_merge_base(&self, _name: &str) -> PersistenceResult<()> {
+            Ok(())
+        }
+
+        async fn is_merge_base(&self, _hash: &str) -> PersistenceResult<bool> {
+            Ok(false)
+        }
+
+        async fn set_pending_merge(&self, _name: &str, _id: &Uuid) -> PersistenceResult<()> {
+            Ok(())
+        }
+
+        async fn take_pending_merge(&self, _name: &str) -> PersistenceResult<Option<Uuid>> {
+            Ok(None)
+        }
     }
 
     async fn create_test_components() -> (
@@ -157,4 +309,84 @@ mod tests {
             "Should contain crate version in footer"
         );
     }
+
+    #[tokio::test]
+    async fn test_index_renders_pending_devices_with_actions() {
+        let (_env, state, pm, em, engine) = create_test_components().await;
+        let id = Uuid::new_v4();
+        pm.add_pending(crate::domain::PendingDevice {
+            id,
+            addr: "10.0.0.9".parse().unwrap(),
+            transport_port: 42882,
+            hostname: None,
+        })
+        .await;
+
+        let gui_state = Arc::new(GuiState {
+            state,
+            engine,
+            peer_manager: pm,
+            entry_manager: em,
+        });
+
+        let Html(html) = index(State(gui_state)).await.unwrap();
+
+        assert!(html.contains(&format!("pending-{id}")));
+        assert!(html.contains("Unknown device"));
+        assert!(html.contains("approve-device-btn"));
+    }
+
+    #[tokio::test]
+    async fn test_index_renders_trash_with_actions() {
+        let (_env, state, pm, _em, engine) = create_test_components().await;
+        let db = MockPersistence::new();
+        let record = TrashRecord {
+            id: Uuid::new_v4(),
+            path: "Docs/report.md".into(),
+            kind: crate::domain::EntryKind::File,
+            peer: Uuid::new_v4(),
+            deleted_at: 1_700_000_000,
+        };
+        db.trash.lock().await.push(record.clone());
+
+        let gui_state = Arc::new(GuiState {
+            state: state.clone(),
+            engine,
+            peer_manager: pm,
+            entry_manager: EntryManager::new(db, state),
+        });
+
+        let Html(html) = index(State(gui_state)).await.unwrap();
+
+        assert!(html.contains(&format!("trash-{}", record.id)));
+        assert!(html.contains("Docs/report.md"));
+        assert!(html.contains("restore-trash-btn"));
+    }
+
+    #[tokio::test]
+    async fn test_index_renders_local_changes_of_receive_only_dirs() {
+        let (_env, state, pm, em, engine) = create_test_components().await;
+        let dir = state.home_path().join("Default Folder");
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        tokio::fs::write(dir.join("local.txt"), b"local")
+            .await
+            .unwrap();
+        let receive_only = SyncDirectory {
+            mode: DirectoryMode::ReceiveOnly,
+            ..SyncDirectory::new("Default Folder".into())
+        };
+        assert!(em.update_sync_dir(receive_only).await);
+
+        let gui_state = Arc::new(GuiState {
+            state,
+            engine,
+            peer_manager: pm,
+            entry_manager: em,
+        });
+
+        let Html(html) = index(State(gui_state)).await.unwrap();
+
+        assert!(html.contains("Added: Default Folder/local.txt"));
+        assert!(html.contains("revert-local-btn"));
+    }
 }
@@ -2,11 +2,13 @@ use crate::{
     application::{
         AppState, EntryManager, PeerManager, persistence::interface::PersistenceInterface,
     },
+    domain::TransportChannelData,
     infra::http::{api, gui},
 };
 use axum::Router;
 use minijinja::Environment;
 use std::sync::Arc;
+use tokio::sync::mpsc::Sender;
 
 /// Composes the GUI and JSON API routers into the application's
 /// single top-level `axum::Router`.
@@ -14,6 +16,7 @@ pub fn build_router<P: PersistenceInterface>(
     state: Arc<AppState>,
     peer_manager: Arc<PeerManager>,
     entry_manager: Arc<EntryManager<P>>,
+    sender_tx: Sender<TransportChannelData>,
     template_engine: Environment<'static>,
 ) -> Router {
     Router::new()
@@ -23,5 +26,5 @@ pub fn build_router<P: PersistenceInterface>(
             peer_manager.clone(),
             entry_manager.clone(),
         ))
-        .merge(api::routes(state, peer_manager, entry_manager))
+        .merge(api::routes(state, peer_manager, entry_manager, sender_tx))
 }
@@ -2,6 +2,7 @@ use crate::{
     application::{
         AppState, EntryManager, PeerManager, persistence::interface::PersistenceInterface,
     },
+    domain::TransportChannelData,
     infra::http::routes,
 };
 use axum::{
@@ -12,7 +13,7 @@ use axum::{
 };
 use minijinja::Environment;
 use std::sync::Arc;
-use tokio::net::TcpListener;
+use tokio::{net::TcpListener, sync::mpsc::Sender};
 use tower_http::trace::TraceLayer;
 
 const VERSION_HEADER: HeaderName = HeaderName::from_static("x-synche-version");
@@ -25,13 +26,20 @@ pub async fn run<P: PersistenceInterface>(
     state: Arc<AppState>,
     peer_manager: Arc<PeerManager>,
     entry_manager: Arc<EntryManager<P>>,
+    sender_tx: Sender<TransportChannelData>,
 ) -> tokio::io::Result<()> {
     let port = state.ports().http;
     let template_engine = init_template_engine();
 
-    let router = routes::build_router(state, peer_manager, entry_manager, template_engine)
-        .layer(middleware::from_fn(insert_version_header))
-        .layer(TraceLayer::new_for_http());
+    let router = routes::build_router(
+        state,
+        peer_manager,
+        entry_manager,
+        sender_tx,
+        template_engine,
+    )
+    .layer(middleware::from_fn(insert_version_header))
+    .layer(TraceLayer::new_for_http());
 
     let addr = format!("0.0.0.0:{port}");
     let listener = TcpListener::bind(&addr).await?;
@@ -0,0 +1,355 @@
+use crate::{
+    application::AppState,
+    application::network::presence::interface::{PresenceEvent, PresenceInterface},
+    domain::{RelativePath, verify_device_signature},
+    infra::network::socket,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    net::{Ipv4Addr, SocketAddr},
+    sync::{Arc, Mutex, PoisonError},
+    time::Duration,
+};
+use tokio::{io, net::UdpSocket, task::JoinHandle};
+use tracing::{trace, warn};
+use uuid::Uuid;
+
+/// How often this device announces itself.
+const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(10);
+/// Largest announcement sent or read. Keeps a datagram inside one
+/// Ethernet frame; sync-dir names that do not fit are left out.
+const MAX_ANNOUNCEMENT_LEN: usize = 1200;
+/// Leading bytes of every announcement.
+const MAGIC: &[u8; 6] = b"synche";
+const KEY_LEN: usize = 32;
+const SIGNATURE_LEN: usize = 64;
+const HEADER_LEN: usize = MAGIC.len() + KEY_LEN + SIGNATURE_LEN;
+
+/// Signed body of an announcement, as JSON.
+#[derive(Serialize, Deserialize, Debug)]
+struct Announcement {
+    id: Uuid,
+    instance_id: Uuid,
+    transport_port: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cluster: Option<String>,
+    sync_dirs: Vec<RelativePath>,
+}
+
+/// `PresenceInterface` implementation over plain UDP broadcast, for
+/// networks that drop mDNS but pass subnet broadcast.
+///
+/// Once advertising, sends an announcement every `ANNOUNCE_INTERVAL` to
+/// the broadcast address of every IPv4 interface, at the broadcast
+/// port, and turns the announcements it hears into `Ping`s. An
+/// announcement is `MAGIC`, the sender's Ed25519 public key, the
+/// signature of the JSON body, then the body. It is only taken when the
+/// signature holds and the key derives the id the body names, so no
+/// device can announce itself under another's id. Replaying one only
+/// points at the replayer's address, where the transport handshake then
+/// fails. The sync-dir names are informational: they are logged and
+/// nothing else.
+pub struct BroadcastAdapter {
+    state: Arc<AppState>,
+    socket: Arc<UdpSocket>,
+    /// Where announcements go; `None` means the broadcast address of
+    /// every interface, looked up again for each announcement.
+    targets: Option<Vec<SocketAddr>>,
+    announcer: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl BroadcastAdapter {
+    pub async fn new(state: Arc<AppState>) -> io::Result<Self> {
+        Self::bind(state, None).await
+    }
+
+    /// Binds the broadcast port, to announce to `targets` instead of
+    /// the interfaces' broadcast addresses when given.
+    pub(super) async fn bind(
+        state: Arc<AppState>,
+        targets: Option<Vec<SocketAddr>>,
+    ) -> io::Result<Self> {
+        let socket = UdpSocket::from_std(socket::broadcast_socket(state.ports().broadcast)?)?;
+
+        Ok(Self {
+            state,
+            socket: Arc::new(socket),
+            targets,
+            announcer: Mutex::new(None),
+        })
+    }
+
+    /// Builds this device's signed announcement.
+    async fn announcement(state: &AppState) -> io::Result<Vec<u8>> {
+        let mut announcement = Announcement {
+            id: state.local_id(),
+            instance_id: state.instance_id(),
+            transport_port: state.ports().transport,
+            cluster: state.cluster().tag(),
+            sync_dirs: state.sync_dir_names().await,
+        };
+        announcement.sync_dirs.sort();
+
+        let mut body = serde_json::to_vec(&announcement).map_err(io::Error::other)?;
+        while HEADER_LEN + body.len() > MAX_ANNOUNCEMENT_LEN {
+            if announcement.sync_dirs.pop().is_none() {
+                return Err(io::Error::other("Announcement does not fit a datagram"));
+            }
+            body = serde_json::to_vec(&announcement).map_err(io::Error::other)?;
+        }
+
+        let key = state.device_key();
+        let mut datagram = Vec::with_capacity(HEADER_LEN + body.len());
+        datagram.extend_from_slice(MAGIC);
+        datagram.extend_from_slice(&key.verifying_key());
+        datagram.extend_from_slice(&key.sign(&body));
+        datagram.extend_from_slice(&body);
+        Ok(datagram)
+    }
+
+    /// Checks an announcement's signature and returns its body.
+    fn verify(datagram: &[u8]) -> Option<Announcement> {
+        let rest = datagram.strip_prefix(MAGIC)?;
+        if rest.len() < KEY_LEN + SIGNATURE_LEN {
+            return None;
+        }
+        let (key, rest) = rest.split_at(KEY_LEN);
+        let (signature, body) = rest.split_at(SIGNATURE_LEN);
+
+        let signer =
+            verify_device_signature(key.try_into().ok()?, body, signature.try_into().ok()?)?;
+        let announcement: Announcement = serde_json::from_slice(body).ok()?;
+        (announcement.id == signer).then_some(announcement)
+    }
+
+    /// Broadcast addresses of the IPv4 interfaces that are up, or the
+    /// limited broadcast address when there are none.
+    fn broadcast_targets(port: u16) -> Vec<SocketAddr> {
+        let interfaces = if_addrs::get_if_addrs().unwrap_or_else(|err| {
+            warn!("failed to list network interfaces: {err}");
+            Vec::new()
+        });
+
+        let mut targets: Vec<SocketAddr> = interfaces
+            .into_iter()
+            .filter(|iface| !iface.is_loopback() && iface.is_oper_up())
+            .filter_map(|iface| match iface.addr {
+                if_addrs::IfAddr::V4(addr) => addr.broadcast,
+                if_addrs::IfAddr::V6(_) => None,
+            })
+            .map(|ip| SocketAddr::new(ip.into(), port))
+            .collect();
+        targets.sort();
+        targets.dedup();
+
+        if targets.is_empty() {
+            targets.push(SocketAddr::new(Ipv4Addr::BROADCAST.into(), port));
+        }
+        targets
+    }
+
+    /// Announces this device every `ANNOUNCE_INTERVAL` until aborted.
+    async fn announce_loop(
+        state: Arc<AppState>,
+        socket: Arc<UdpSocket>,
+        targets: Option<Vec<SocketAddr>>,
+    ) {
+        let mut interval = tokio::time::interval(ANNOUNCE_INTERVAL);
+        loop {
+            interval.tick().await;
+
+            let datagram = match Self::announcement(&state).await {
+                Ok(datagram) => datagram,
+                Err(err) => {
+                    warn!("failed to build broadcast announcement: {err}");
+                    continue;
+                }
+            };
+            let targets = targets
+                .clone()
+                .unwrap_or_else(|| Self::broadcast_targets(state.ports().broadcast));
+            for target in targets {
+                if let Err(err) = socket.send_to(&datagram, target).await {
+                    trace!(%target, "failed to send broadcast announcement: {err}");
+                }
+            }
+        }
+    }
+
+    fn handle_datagram(&self, datagram: &[u8], source: SocketAddr) -> Option<PresenceEvent> {
+        let Some(announcement) = Self::verify(datagram) else {
+            trace!(%source, "ignoring invalid broadcast announcement");
+            return None;
+        };
+        if announcement.id == self.state.local_id() || announcement.transport_port == 0 {
+            return None;
+        }
+
+        trace!(
+            peer = %announcement.id,
+            %source,
+            sync_dirs = ?announcement.sync_dirs,
+            "broadcast announcement"
+        );
+        let mut addr = source;
+        addr.set_port(announcement.transport_port);
+        Some(PresenceEvent::Ping {
+            id: announcement.id,
+            addrs: vec![addr],
+            instance_id: announcement.instance_id,
+            cluster: announcement.cluster,
+        })
+    }
+
+    fn stop_announcing(&self) {
+        let announcer = self
+            .announcer
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if let Some(announcer) = announcer.as_ref() {
+            announcer.abort();
+        }
+    }
+}
+
+impl PresenceInterface for BroadcastAdapter {
+    async fn advertise(&self) -> io::Result<()> {
+        let announcer = tokio::spawn(Self::announce_loop(
+            self.state.clone(),
+            self.socket.clone(),
+            self.targets.clone(),
+        ));
+        let previous = self
+            .announcer
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .replace(announcer);
+        if let Some(previous) = previous {
+            previous.abort();
+        }
+        Ok(())
+    }
+
+    async fn next(&self) -> io::Result<Option<PresenceEvent>> {
+        let mut buf = [0u8; MAX_ANNOUNCEMENT_LEN];
+        loop {
+            let (len, source) = self.socket.recv_from(&mut buf).await?;
+            if let Some(event) = self.handle_datagram(&buf[..len], source) {
+                return Ok(Some(event));
+            }
+        }
+    }
+
+    async fn shutdown(&self) {
+        self.stop_announcing();
+    }
+}
+
+impl Drop for BroadcastAdapter {
+    fn drop(&mut self) {
+        self.stop_announcing();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        domain::{AppPorts, DeviceKey},
+        utils::test_support::{TestEnv, test_env_with_ports},
+    };
+    use tokio::time::timeout;
+
+    async fn adapter(targets: Vec<SocketAddr>) -> (TestEnv, BroadcastAdapter) {
+        let ports = AppPorts {
+            http: 0,
+            presence: 0,
+            transport: 42882,
+            broadcast: 0,
+        };
+        let env = test_env_with_ports(&["Docs", "Photos"], ports).await;
+        let adapter = BroadcastAdapter::bind(env.state.clone(), Some(targets))
+            .await
+            .unwrap();
+        (env, adapter)
+    }
+
+    fn local_addr(adapter: &BroadcastAdapter) -> SocketAddr {
+        let port = adapter.socket.local_addr().unwrap().port();
+        SocketAddr::new(Ipv4Addr::LOCALHOST.into(), port)
+    }
+
+    #[tokio::test]
+    async fn announcements_are_heard_on_loopback() {
+        let (_listener_env, listener) = adapter(vec![]).await;
+        let (announcer_env, announcer) = adapter(vec![local_addr(&listener)]).await;
+
+        announcer.advertise().await.unwrap();
+        let event = timeout(Duration::from_secs(5), listener.next())
+            .await
+            .expect("listener should hear the announcement")
+            .unwrap();
+
+        let state = &announcer_env.state;
+        match event {
+            Some(PresenceEvent::Ping {
+                id,
+                addrs,
+                instance_id,
+                cluster,
+            }) => {
+                assert_eq!(id, state.local_id());
+                assert_eq!(cluster, None);
+                assert_eq!(instance_id, state.instance_id());
+                assert_eq!(
+                    addrs,
+                    vec![SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 42882)]
+                );
+            }
+            _ => panic!("expected a ping"),
+        }
+    }
+
+    #[tokio::test]
+    async fn announcements_carry_the_sync_dirs_and_verify() {
+        let (env, _adapter) = adapter(vec![]).await;
+
+        let datagram = BroadcastAdapter::announcement(&env.state).await.unwrap();
+        let announcement = BroadcastAdapter::verify(&datagram).unwrap();
+
+        assert_eq!(announcement.id, env.state.local_id());
+        assert_eq!(
+            announcement.sync_dirs,
+            vec![RelativePath::from("Docs"), RelativePath::from("Photos")]
+        );
+    }
+
+    #[tokio::test]
+    async fn tampered_or_impersonating_announcements_are_rejected() {
+        let (env, adapter) = adapter(vec![]).await;
+        let source = "10.0.0.9:42883".parse().unwrap();
+
+        let mut tampered = BroadcastAdapter::announcement(&env.state).await.unwrap();
+        let last = tampered.len() - 2;
+        tampered[last] ^= 1;
+
+        // Validly signed, but by a key that does not own the id it names.
+        let impostor = DeviceKey::generate();
+        let body = serde_json::to_vec(&Announcement {
+            id: Uuid::new_v4(),
+            instance_id: Uuid::new_v4(),
+            transport_port: 42882,
+            cluster: None,
+            sync_dirs: vec![],
+        })
+        .unwrap();
+        let mut forged = MAGIC.to_vec();
+        forged.extend_from_slice(&impostor.verifying_key());
+        forged.extend_from_slice(&impostor.sign(&body));
+        forged.extend_from_slice(&body);
+
+        assert!(adapter.handle_datagram(&tampered, source).is_none());
+        assert!(adapter.handle_datagram(&forged, source).is_none());
+        assert!(adapter.handle_datagram(b"synche", source).is_none());
+    }
+}
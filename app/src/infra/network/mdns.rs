@@ -3,9 +3,12 @@ use crate::{
     application::network::presence::interface::{PresenceEvent, PresenceInterface},
 };
 use mdns_sd::{
-    IfKind, Receiver, ResolvedService, ServiceDaemon, ServiceEvent, ServiceInfo, TxtProperties,
+    Receiver, ResolvedService, ScopedIp, ServiceDaemon, ServiceEvent, ServiceInfo, TxtProperties,
+};
+use std::{
+    net::{SocketAddr, SocketAddrV6},
+    sync::Arc,
 };
-use std::sync::Arc;
 use tokio::io;
 use tracing::{error, info, warn};
 use uuid::Uuid;
@@ -13,13 +16,24 @@ use uuid::Uuid;
 const SERVICE_TYPE: &str = "_synche._udp.local.";
 const RETRY_COUNT: usize = 3;
 
+const INSTANCE_ID_KEY: &str = "instance_id";
+const TRANSPORT_PORT_KEY: &str = "transport_port";
+const CLUSTER_KEY: &str = "cluster";
+
 /// `mdns-sd` adapter implementing `PresenceInterface`.
 ///
 /// Browses the `_synche._udp.local.` service to learn about peers and
-/// publishes this instance's own record (with its `instance_id` in the
-/// TXT properties) so other peers can detect restarts. IPv6 is
-/// disabled at the daemon level because the application's transport
-/// addresses are IPv4-only.
+/// publishes this instance's own record, with its `instance_id` in the
+/// TXT properties so other peers can detect restarts, and its
+/// `transport_port` so they connect to the port its transport listens
+/// on. Records without a `transport_port` come from builds that
+/// predate it and listen on the same port as this one. A `cluster`
+/// property carries the `Cluster::tag` of devices outside the default
+/// cluster. The record
+/// carries every address of every interface, IPv4 and IPv6; a peer's
+/// `Ping` lists all of them that can be dialed, IPv4 first, then
+/// global IPv6, then link-local IPv6 scoped to the interface the
+/// record arrived on.
 pub struct MdnsAdapter {
     state: Arc<AppState>,
     daemon: ServiceDaemon,
@@ -31,8 +45,6 @@ impl MdnsAdapter {
     pub fn new(state: Arc<AppState>) -> Self {
         let daemon = ServiceDaemon::new().expect("Failed to create mdns daemon");
 
-        daemon.disable_interface(IfKind::IPv6).unwrap();
-
         let service_type = SERVICE_TYPE.to_string();
         let receiver = daemon.browse(&service_type).expect("Failed to browse");
 
@@ -48,7 +60,14 @@ impl MdnsAdapter {
 impl PresenceInterface for MdnsAdapter {
     async fn advertise(&self) -> io::Result<()> {
         let hostname = self.state.hostname().clone() + ".local.";
-        let properties = [("instance_id", self.state.instance_id())];
+        let transport_port = self.state.ports().transport.to_string();
+        let mut properties = vec![
+            (INSTANCE_ID_KEY, self.state.instance_id().to_string()),
+            (TRANSPORT_PORT_KEY, transport_port),
+        ];
+        if let Some(tag) = self.state.cluster().tag() {
+            properties.push((CLUSTER_KEY, tag));
+        }
 
         let service_info = ServiceInfo::new(
             &self.service_type,
@@ -98,24 +117,62 @@ impl MdnsAdapter {
         }
 
         let instance_id = self.get_peer_instance_id(info.get_properties())?;
+        let transport_port = self.get_peer_transport_port(info.get_properties())?;
+        let cluster = info
+            .get_properties()
+            .get_property_val_str(CLUSTER_KEY)
+            .map(str::to_owned);
+
+        let mut addrs: Vec<SocketAddr> = info
+            .addresses
+            .iter()
+            .filter_map(|ip| Self::transport_addr(ip, transport_port))
+            .collect();
+        if addrs.is_empty() {
+            return None;
+        }
+        addrs.sort_by_key(|addr| (Self::preference(addr), *addr));
+
+        Some(PresenceEvent::Ping {
+            id,
+            addrs,
+            instance_id,
+            cluster,
+        })
+    }
 
-        for addr in info.addresses {
-            if addr.is_ipv6() {
-                continue;
+    /// Where the peer's transport listens on `ip`. `None` for loopback
+    /// addresses, and for link-local IPv6 ones without a scope, which
+    /// cannot be dialed.
+    fn transport_addr(ip: &ScopedIp, port: u16) -> Option<SocketAddr> {
+        match ip {
+            ScopedIp::V4(v4) if !v4.addr().is_loopback() => {
+                Some(SocketAddr::new((*v4.addr()).into(), port))
             }
-
-            let addr = addr.to_ip_addr();
-            if addr.is_loopback() {
-                continue;
+            ScopedIp::V6(v6) if !v6.addr().is_loopback() => {
+                let link_local = v6.addr().is_unicast_link_local();
+                let scope_id = if link_local { v6.scope_id().index } else { 0 };
+                if link_local && scope_id == 0 {
+                    return None;
+                }
+                Some(SocketAddr::V6(SocketAddrV6::new(
+                    *v6.addr(),
+                    port,
+                    0,
+                    scope_id,
+                )))
             }
+            _ => None,
+        }
+    }
 
-            return Some(PresenceEvent::Ping {
-                id,
-                addr,
-                instance_id,
-            });
+    /// Rank of `addr` in a `Ping`; lower is tried first.
+    fn preference(addr: &SocketAddr) -> u8 {
+        match addr {
+            SocketAddr::V4(_) => 0,
+            SocketAddr::V6(v6) if !v6.ip().is_unicast_link_local() => 1,
+            SocketAddr::V6(_) => 2,
         }
-        None
     }
 
     fn handle_service_removed(&self, fullname: &str) -> Option<PresenceEvent> {
@@ -136,12 +193,23 @@ impl MdnsAdapter {
     }
 
     fn get_peer_instance_id(&self, props: &TxtProperties) -> Option<Uuid> {
-        let instance_bytes = props.get_property_val("instance_id")??;
+        let instance_bytes = props.get_property_val(INSTANCE_ID_KEY)??;
 
         let instance_str = std::str::from_utf8(instance_bytes).ok()?;
         Uuid::parse_str(instance_str).ok()
     }
 
+    /// The advertised transport port, or ours when the record has none.
+    /// `None` when the property is present but not a valid port.
+    fn get_peer_transport_port(&self, props: &TxtProperties) -> Option<u16> {
+        let Some(port_bytes) = props.get_property_val(TRANSPORT_PORT_KEY) else {
+            return Some(self.state.ports().transport);
+        };
+
+        let port_str = std::str::from_utf8(port_bytes?).ok()?;
+        port_str.parse().ok().filter(|port| *port != 0)
+    }
+
     fn unregister(&self) {
         let fullname = format!("{}.{}", self.state.local_id(), self.service_type);
         Self::retry_mdns_operation("mDNS UNREGISTER", || self.daemon.unregister(&fullname));
@@ -179,6 +247,8 @@ impl MdnsAdapter {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use mdns_sd::IntoTxtProperties;
+    use std::net::IpAddr;
     use uuid::Uuid;
 
     async fn create_test_adapter() -> (crate::utils::test_support::TestEnv, MdnsAdapter) {
@@ -252,4 +322,51 @@ mod tests {
 
         assert!(result.is_none());
     }
+
+    #[test]
+    fn test_transport_addr_skips_addresses_that_cannot_be_dialed() {
+        let addr =
+            |ip: &str| MdnsAdapter::transport_addr(&ip.parse::<IpAddr>().unwrap().into(), 42882);
+
+        assert_eq!(
+            addr("192.168.1.5"),
+            Some("192.168.1.5:42882".parse().unwrap())
+        );
+        assert_eq!(
+            addr("2001:db8::5"),
+            Some("[2001:db8::5]:42882".parse().unwrap())
+        );
+        assert_eq!(addr("127.0.0.1"), None);
+        assert_eq!(addr("::1"), None);
+        // Link-local, but without the interface it was seen on.
+        assert_eq!(addr("fe80::5"), None);
+
+        let mut addrs: Vec<SocketAddr> = ["[fe80::5%2]:1", "[2001:db8::5]:1", "10.0.0.5:1"]
+            .iter()
+            .map(|addr| addr.parse().unwrap())
+            .collect();
+        addrs.sort_by_key(|addr| (MdnsAdapter::preference(addr), *addr));
+        assert!(addrs[0].is_ipv4());
+        assert_eq!(addrs[1].ip(), "2001:db8::5".parse::<IpAddr>().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_get_peer_transport_port() {
+        let (env, adapter) = create_test_adapter().await;
+        let props = |port: &str| {
+            [(TRANSPORT_PORT_KEY, port)]
+                .as_slice()
+                .into_txt_properties()
+        };
+
+        let advertised = adapter.get_peer_transport_port(&props("42001"));
+        let zero = adapter.get_peer_transport_port(&props("0"));
+        let invalid = adapter.get_peer_transport_port(&props("not-a-port"));
+        let missing = adapter.get_peer_transport_port(&TxtProperties::new());
+
+        assert_eq!(advertised, Some(42001));
+        assert_eq!(zero, None);
+        assert_eq!(invalid, None);
+        assert_eq!(missing, Some(env.state.ports().transport));
+    }
 }
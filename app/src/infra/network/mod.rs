@@ -1,2 +1,9 @@
+pub mod broadcast;
 pub mod mdns;
+pub mod network_change;
+pub mod presence;
+pub mod quic;
+pub mod socket;
+pub mod static_peers;
 pub mod tcp;
+pub mod transport;
@@ -0,0 +1,117 @@
+use crate::application::network::presence::interface::{PresenceEvent, PresenceInterface};
+use std::{
+    net::IpAddr,
+    sync::{Mutex, PoisonError},
+    time::Duration,
+};
+use tokio::{
+    io,
+    time::{Interval, MissedTickBehavior, interval},
+};
+use tracing::{info, warn};
+
+/// How often this host's interfaces are listed.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// `PresenceInterface` implementation that watches this host's own
+/// network interfaces rather than discovering peers, so it runs next
+/// to the discovery adapters in a `MergedPresence`.
+///
+/// Lists the addresses of every interface that is up each
+/// `POLL_INTERVAL`, and yields a `NetworkChanged` when they differ from
+/// the previous listing, as when a laptop joins another network. The
+/// first listing is only the baseline. A listing that fails is skipped.
+pub struct NetworkChangeAdapter {
+    ticks: tokio::sync::Mutex<Interval>,
+    addrs: Mutex<Option<Vec<IpAddr>>>,
+}
+
+impl NetworkChangeAdapter {
+    pub fn new() -> Self {
+        let mut ticks = interval(POLL_INTERVAL);
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        Self {
+            ticks: tokio::sync::Mutex::new(ticks),
+            addrs: Mutex::new(None),
+        }
+    }
+
+    /// Addresses of the interfaces that are up, loopback aside, sorted.
+    fn interface_addrs() -> Option<Vec<IpAddr>> {
+        let interfaces = if_addrs::get_if_addrs()
+            .inspect_err(|err| warn!("failed to list network interfaces: {err}"))
+            .ok()?;
+
+        let mut addrs: Vec<IpAddr> = interfaces
+            .iter()
+            .filter(|iface| !iface.is_loopback() && iface.is_oper_up())
+            .map(|iface| iface.ip())
+            .collect();
+        addrs.sort();
+        addrs.dedup();
+        Some(addrs)
+    }
+
+    /// Records the current listing and tells whether it differs from
+    /// the previous one.
+    fn update(&self, current: Vec<IpAddr>) -> bool {
+        let mut addrs = self.addrs.lock().unwrap_or_else(PoisonError::into_inner);
+        let changed = addrs.as_ref().is_some_and(|previous| *previous != current);
+        if changed {
+            info!(addrs = ?current, "network interfaces changed");
+        }
+        *addrs = Some(current);
+        changed
+    }
+}
+
+impl Default for NetworkChangeAdapter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PresenceInterface for NetworkChangeAdapter {
+    async fn advertise(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    async fn next(&self) -> io::Result<Option<PresenceEvent>> {
+        let mut ticks = self.ticks.lock().await;
+        loop {
+            ticks.tick().await;
+
+            let Some(current) = Self::interface_addrs() else {
+                continue;
+            };
+            if self.update(current) {
+                let local_ip = local_ip_address::local_ip().ok();
+                return Ok(Some(PresenceEvent::NetworkChanged { local_ip }));
+            }
+        }
+    }
+
+    async fn shutdown(&self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ips(ips: &[&str]) -> Vec<IpAddr> {
+        ips.iter().map(|ip| ip.parse().unwrap()).collect()
+    }
+
+    #[tokio::test]
+    async fn only_a_listing_that_differs_from_the_previous_one_is_a_change() {
+        let adapter = NetworkChangeAdapter::new();
+
+        assert!(!adapter.update(ips(&["192.168.1.5"])));
+        assert!(!adapter.update(ips(&["192.168.1.5"])));
+        assert!(adapter.update(ips(&["10.0.0.7"])));
+        assert!(adapter.update(ips(&["10.0.0.7", "2001:db8::7"])));
+        assert!(adapter.update(ips(&[])));
+        assert!(!adapter.update(ips(&[])));
+    }
+}
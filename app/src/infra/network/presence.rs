@@ -0,0 +1,113 @@
+use crate::{
+    application::{AppState, network::presence::merged::MergedPresence},
+    infra::network::{
+        broadcast::BroadcastAdapter, mdns::MdnsAdapter, network_change::NetworkChangeAdapter,
+        static_peers::StaticPeerAdapter,
+    },
+};
+use std::sync::Arc;
+use tracing::warn;
+
+/// Every discovery source the production `Synchronizer` runs at once:
+/// mDNS, the `[[peer]]` addresses of `config.toml` and subnet
+/// broadcast, merged into one `PresenceInterface` together with the
+/// watch on this host's own interfaces. Each source is `None` when
+/// `[discovery]` turns it off.
+pub type PresenceAdapter = MergedPresence<
+    MergedPresence<
+        MergedPresence<Option<MdnsAdapter>, Option<StaticPeerAdapter>>,
+        Option<BroadcastAdapter>,
+    >,
+    NetworkChangeAdapter,
+>;
+
+/// Builds the production `PresenceAdapter` from the sources
+/// `[discovery]` turns on. A source that cannot bind its port is logged
+/// and left out, and discovery goes on without it.
+pub async fn presence_adapter(state: Arc<AppState>) -> PresenceAdapter {
+    let discovery = state.discovery();
+    let mdns = discovery.mdns.then(|| MdnsAdapter::new(state.clone()));
+    let static_peers = match discovery.static_peers {
+        true => StaticPeerAdapter::new(state.clone())
+            .await
+            .inspect_err(|err| warn!("static peer discovery disabled: {err}"))
+            .ok(),
+        false => None,
+    };
+    let broadcast = match discovery.broadcast {
+        true => BroadcastAdapter::new(state)
+            .await
+            .inspect_err(|err| warn!("broadcast discovery disabled: {err}"))
+            .ok(),
+        false => None,
+    };
+
+    MergedPresence::new(
+        MergedPresence::new(MergedPresence::new(mdns, static_peers), broadcast),
+        NetworkChangeAdapter::new(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        application::{
+            AppState,
+            network::presence::interface::{PresenceEvent, PresenceInterface},
+        },
+        domain::AppPorts,
+        utils::test_support::test_env_with_ports,
+    };
+    use std::{
+        net::{Ipv4Addr, SocketAddr},
+        time::Duration,
+    };
+    use tokio::time::timeout;
+
+    #[tokio::test]
+    async fn broadcast_discovery_runs_alone() {
+        let port = std::net::UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let ports = AppPorts {
+            http: 0,
+            presence: 0,
+            transport: 42882,
+            broadcast: port,
+        };
+        let env = test_env_with_ports(&["sync"], ports.clone()).await;
+        let config = std::fs::read_to_string(env.dirs.config_file())
+            .unwrap()
+            .replace("mdns = true", "mdns = false")
+            .replace("static_peers = true", "static_peers = false");
+        std::fs::write(env.dirs.config_file(), config).unwrap();
+        let state = AppState::new(env.dirs.clone(), ports.clone()).await;
+        let presence = presence_adapter(state).await;
+
+        let announcer_env = test_env_with_ports(
+            &["sync"],
+            AppPorts {
+                broadcast: 0,
+                ..ports
+            },
+        )
+        .await;
+        let target = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+        let announcer = BroadcastAdapter::bind(announcer_env.state.clone(), Some(vec![target]))
+            .await
+            .unwrap();
+        announcer.advertise().await.unwrap();
+
+        let event = timeout(Duration::from_secs(5), presence.next())
+            .await
+            .expect("the broadcast source should hear the announcement")
+            .unwrap();
+        assert!(matches!(
+            event,
+            Some(PresenceEvent::Ping { id, .. }) if id == announcer_env.state.local_id()
+        ));
+    }
+}
@@ -0,0 +1,520 @@
+use crate::{
+    application::AppState,
+    application::network::transport::interface::{
+        TransportError, TransportInterface, TransportResult,
+    },
+    domain::{TransportData, TransportEvent},
+    infra::network::{
+        quic::{
+            connections::{QuicConnections, QuicStream},
+            tls,
+        },
+        socket,
+        tcp::{
+            Inbox, Lanes, PeerStream, SECURE_HANDSHAKE_TIMEOUT, TcpSender, TcpStreamKind,
+            read_stream_header, wire_format,
+        },
+    },
+};
+use quinn::{Endpoint, EndpointConfig, Incoming, TransportConfig};
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+use tokio::{sync::broadcast::error::RecvError, task::JoinHandle, time::timeout};
+use tracing::{trace, warn};
+
+/// How often an idle connection is pinged so neither side's idle
+/// timeout closes it.
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long a connection may go without hearing from the peer before
+/// it is considered dead and the next message dials a new one.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// QUIC adapter implementing `TransportInterface`.
+///
+/// Speaks the same `TcpStreamKind` wire format as `TcpAdapter` through
+/// the shared `TcpSender` / `TcpReceiver`, but over QUIC on the
+/// transport port (UDP, IPv4 and IPv6): every message is a bidirectional stream of
+/// its own, QUIC provides encryption and multiplexing, and a stream
+/// that stalls holds up no other. Connections are authenticated with
+/// mutual TLS 1.3 over self-signed certificates for the device key, so
+/// the source id reported upward is the one the certificate proves,
+/// exactly as with a Noise handshake. Devices the user has not approved
+/// may only send handshakes; any other stream is rejected before its
+/// payload is read.
+///
+/// Inbound streams are read by an `Inbox`, as with `TcpAdapter`.
+/// Endpoint bind failures are fatal; handshake and framing errors on a
+/// connection or stream are logged and dropped.
+pub struct QuicAdapter {
+    sender: TcpSender,
+    endpoint: Endpoint,
+    connections: Arc<QuicConnections>,
+    inbox: Inbox,
+    background: Vec<JoinHandle<()>>,
+}
+
+impl QuicAdapter {
+    pub async fn new(state: Arc<AppState>) -> Self {
+        let mut server = tls::server_config(state.device_key()).unwrap_or_else(|err| {
+            let TransportError::Failure(message) = err;
+            panic!("{message}")
+        });
+        server.transport_config(Arc::new(Self::transport_config()));
+        let mut client = tls::client_config(state.device_key()).unwrap_or_else(|err| {
+            let TransportError::Failure(message) = err;
+            panic!("{message}")
+        });
+        client.transport_config(Arc::new(Self::transport_config()));
+
+        let socket = socket::udp_socket(state.ports().transport).unwrap();
+        let runtime = quinn::default_runtime().expect("no async runtime for QUIC");
+        let mut endpoint =
+            Endpoint::new(EndpointConfig::default(), Some(server), socket, runtime).unwrap();
+        endpoint.set_default_client_config(client);
+
+        let connections = Arc::new(QuicConnections::new(state.clone(), endpoint.clone()));
+        let (sender, inbox) = wire_format(state.clone(), connections.clone()).await;
+
+        let background = vec![tokio::spawn(Self::evict_removed_peers(
+            state,
+            connections.clone(),
+        ))];
+
+        Self {
+            sender,
+            endpoint,
+            connections,
+            inbox,
+            background,
+        }
+    }
+
+    fn transport_config() -> TransportConfig {
+        let mut config = TransportConfig::default();
+        config.keep_alive_interval(Some(KEEP_ALIVE_INTERVAL));
+        config.max_idle_timeout(Some(IDLE_TIMEOUT.try_into().unwrap()));
+        config
+    }
+
+    /// Completes the handshake of an incoming connection, then hands
+    /// the streams the peer opens on it to the lanes until the
+    /// connection closes. Each stream's header is read on a task of its
+    /// own, so a stream that stays silent holds up no other; like QUIC
+    /// itself, this keeps no order between streams.
+    async fn serve(
+        incoming: Incoming,
+        connections: Arc<QuicConnections>,
+        lanes: Lanes,
+    ) -> TransportResult<()> {
+        let source_ip = incoming.remote_address().ip().to_canonical();
+        let connecting = incoming
+            .accept()
+            .map_err(|err| TransportError::new(&err.to_string()))?;
+        let conn = timeout(SECURE_HANDSHAKE_TIMEOUT, connecting)
+            .await
+            .map_err(|_| TransportError::new("QUIC handshake timed out"))?
+            .map_err(|err| TransportError::new(&err.to_string()))?;
+        let source_id = tls::remote_id(&conn)?;
+        trace!(peer = %source_ip, peer_id = %source_id, "quic connection accepted");
+
+        connections.track(&conn, source_id);
+        while let Ok((send, recv)) = conn.accept_bi().await {
+            let lanes = lanes.clone();
+
+            tokio::spawn(async move {
+                let mut stream = PeerStream::new(QuicStream::new(send, recv), source_id);
+
+                let result = async {
+                    let kind = read_stream_header(&mut stream, source_id).await?;
+                    if let TcpStreamKind::Multiplex = kind {
+                        return Err(TransportError::new("Multiplex requested on a QUIC stream"));
+                    }
+                    lanes.route(stream, kind, source_ip).await
+                }
+                .await;
+
+                if let Err(TransportError::Failure(message)) = result {
+                    warn!(peer = ?source_ip, "Ignoring invalid QUIC transport message: {message}");
+                }
+            });
+        }
+        connections.forget(&conn);
+        Ok(())
+    }
+
+    async fn evict_removed_peers(state: Arc<AppState>, connections: Arc<QuicConnections>) {
+        let mut removals = state.subscribe_peer_removals();
+        loop {
+            match removals.recv().await {
+                Ok(id) => {
+                    trace!(peer_id = %id, "closing connections to removed peer");
+                    connections.evict(id);
+                }
+                Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => return,
+            }
+        }
+    }
+}
+
+impl Drop for QuicAdapter {
+    fn drop(&mut self) {
+        for task in &self.background {
+            task.abort();
+        }
+        self.connections.close_all();
+        self.endpoint.close(0u32.into(), b"shutting down");
+    }
+}
+
+impl TransportInterface for QuicAdapter {
+    async fn send(&self, target: SocketAddr, data: TransportData) -> TransportResult<()> {
+        self.sender.send_data(target, data).await
+    }
+
+    /// Accepts connections while waiting for the next message, so the
+    /// endpoint is only served while the application is listening.
+    async fn recv(&self) -> TransportResult<TransportEvent> {
+        let mut events = self.inbox.events().rx.lock().await;
+
+        loop {
+            tokio::select! {
+                Some(event) = events.recv() => return Ok(event),
+                incoming = self.endpoint.accept() => {
+                    let incoming = incoming
+                        .ok_or_else(|| TransportError::new("QUIC endpoint closed"))?;
+                    let source_ip = incoming.remote_address().ip().to_canonical();
+
+                    let serve = Self::serve(incoming, self.connections.clone(), self.inbox.lanes());
+                    tokio::spawn(async move {
+                        if let Err(TransportError::Failure(message)) = serve.await {
+                            warn!(peer = ?source_ip, "Ignoring invalid QUIC transport message: {message}");
+                        }
+                    });
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        application::PeerManager,
+        domain::{AppPorts, DeviceKey, EntryInfo, EntryKind},
+        utils::test_support::{test_env_with_dirs, test_env_with_ports},
+    };
+    use quinn::Connection;
+    use std::{
+        collections::HashMap,
+        net::{IpAddr, Ipv4Addr, SocketAddr},
+    };
+    use tokio::io::{AsyncWrite, AsyncWriteExt};
+    use uuid::Uuid;
+
+    const LOCALHOST: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+    fn local_addr(adapter: &QuicAdapter) -> SocketAddr {
+        SocketAddr::new(LOCALHOST, adapter.endpoint.local_addr().unwrap().port())
+    }
+
+    /// Dials `addr` as the device owning `key`.
+    async fn connect(addr: SocketAddr, key: &DeviceKey) -> Connection {
+        let mut endpoint = Endpoint::client((LOCALHOST, 0).into()).unwrap();
+        endpoint.set_default_client_config(tls::client_config(key).ok().unwrap());
+        endpoint
+            .connect(addr, tls::SERVER_NAME)
+            .unwrap()
+            .await
+            .unwrap()
+    }
+
+    async fn write_header<S: AsyncWrite + Unpin>(
+        stream: &mut S,
+        source_id: Uuid,
+        kind: TcpStreamKind,
+    ) {
+        stream.write_all(source_id.as_bytes()).await.unwrap();
+        stream.write_all(&[kind as u8]).await.unwrap();
+    }
+
+    async fn write_metadata(conn: &Connection, source_id: Uuid, entry: &EntryInfo) {
+        let (mut stream, _) = conn.open_bi().await.unwrap();
+        let contents = serde_json::to_vec(entry).unwrap();
+
+        write_header(&mut stream, source_id, TcpStreamKind::Metadata).await;
+        stream
+            .write_all(&(contents.len() as u32).to_be_bytes())
+            .await
+            .unwrap();
+        stream.write_all(&contents).await.unwrap();
+        stream.finish().unwrap();
+    }
+
+    /// Starts a transfer of `entry` and writes only part of it.
+    async fn write_partial_transfer(
+        conn: &Connection,
+        source_id: Uuid,
+        entry: &EntryInfo,
+        finish: bool,
+    ) -> quinn::SendStream {
+        let (mut stream, _) = conn.open_bi().await.unwrap();
+        let contents = b"not the advertised hash";
+        let entry_json = serde_json::to_vec(entry).unwrap();
+
+        write_header(&mut stream, source_id, TcpStreamKind::Transfer).await;
+        stream
+            .write_all(&(entry_json.len() as u32).to_be_bytes())
+            .await
+            .unwrap();
+        stream.write_all(&entry_json).await.unwrap();
+        stream
+            .write_all(&(contents.len() as u64 * 1024).to_be_bytes())
+            .await
+            .unwrap();
+        stream.write_all(contents).await.unwrap();
+        if finish {
+            stream.finish().unwrap();
+        }
+        stream
+    }
+
+    fn file_entry(name: &str, hash: &str) -> EntryInfo {
+        EntryInfo {
+            name: name.into(),
+            kind: EntryKind::File,
+            hash: Some(hash.to_string()),
+            version: HashMap::from([(Uuid::new_v4(), 1)]),
+            modified: None,
+            removed_at: None,
+        }
+    }
+
+    async fn trust(state: &Arc<AppState>, id: Uuid) {
+        PeerManager::new(state.clone()).load_trusted(vec![id]).await;
+    }
+
+    async fn recv_event(adapter: &QuicAdapter) -> TransportEvent {
+        let result = timeout(Duration::from_secs(5), adapter.recv())
+            .await
+            .expect("adapter should keep listening");
+
+        match result {
+            Ok(event) => event,
+            Err(TransportError::Failure(message)) => {
+                panic!("unexpected transport error: {message}")
+            }
+        }
+    }
+
+    fn metadata_name(event: TransportEvent) -> String {
+        match event.payload {
+            TransportData::Metadata(entry) => entry.name.to_string(),
+            _ => panic!("expected metadata"),
+        }
+    }
+
+    #[tokio::test]
+    async fn recv_ignores_corrupt_transfer_and_keeps_listening() {
+        let env = test_env_with_dirs(&["bad"]).await;
+        let adapter = QuicAdapter::new(env.state.clone()).await;
+        let addr = local_addr(&adapter);
+        let key = DeviceKey::generate();
+        trust(&env.state, key.device_id()).await;
+        let source_id = key.device_id();
+        let corrupt_entry = file_entry("bad/payload.bin", "deadbeef");
+        let metadata_entry = file_entry("ok/payload.bin", "hash");
+
+        let sent = metadata_entry.clone();
+        let writer = tokio::spawn(async move {
+            let conn = connect(addr, &key).await;
+            write_partial_transfer(&conn, source_id, &corrupt_entry, true).await;
+            write_metadata(&conn, source_id, &sent).await;
+            conn
+        });
+
+        let event = recv_event(&adapter).await;
+        let _conn = writer.await.unwrap();
+
+        assert_eq!(event.metadata.source_id, source_id);
+        assert_eq!(metadata_name(event), metadata_entry.name.to_string());
+    }
+
+    /// A peer that authenticates with its own certificate but writes
+    /// somebody else's id into the stream must be dropped; its next
+    /// honest stream is still accepted under the authenticated id.
+    #[tokio::test]
+    async fn recv_rejects_forged_source_id() {
+        let env = test_env_with_dirs(&["sync"]).await;
+        let adapter = QuicAdapter::new(env.state.clone()).await;
+        let addr = local_addr(&adapter);
+        let key = DeviceKey::generate();
+        trust(&env.state, key.device_id()).await;
+        let forged_id = Uuid::new_v4();
+        let forged_entry = file_entry("sync/forged.bin", "hash");
+        let honest_entry = file_entry("sync/honest.bin", "hash");
+
+        let sent = honest_entry.clone();
+        let writer = tokio::spawn(async move {
+            let conn = connect(addr, &key).await;
+            write_metadata(&conn, forged_id, &forged_entry).await;
+            write_metadata(&conn, key.device_id(), &sent).await;
+            (conn, key.device_id())
+        });
+
+        let event = recv_event(&adapter).await;
+        let (_conn, authenticated_id) = writer.await.unwrap();
+
+        assert_eq!(event.metadata.source_id, authenticated_id);
+        assert_ne!(event.metadata.source_id, forged_id);
+        assert_eq!(metadata_name(event), honest_entry.name.to_string());
+    }
+
+    /// An authenticated but unapproved device gets no data stream
+    /// through; its handshake still arrives so it can be paired.
+    #[tokio::test]
+    async fn recv_drops_data_frames_from_unapproved_device() {
+        let env = test_env_with_dirs(&["sync"]).await;
+        let adapter = QuicAdapter::new(env.state.clone()).await;
+        let addr = local_addr(&adapter);
+        let key = DeviceKey::generate();
+        let entry = file_entry("sync/stranger.bin", "hash");
+        let hs_data = serde_json::json!({
+            "hostname": "stranger",
+            "instance_id": Uuid::new_v4(),
+            "sync_dirs": [],
+            "entries": {},
+        });
+
+        let writer = tokio::spawn(async move {
+            let conn = connect(addr, &key).await;
+            write_metadata(&conn, key.device_id(), &entry).await;
+
+            let (mut hello, _) = conn.open_bi().await.unwrap();
+            let contents = serde_json::to_vec(&hs_data).unwrap();
+            write_header(&mut hello, key.device_id(), TcpStreamKind::HandshakeSyn).await;
+            hello
+                .write_all(&(contents.len() as u32).to_be_bytes())
+                .await
+                .unwrap();
+            hello.write_all(&contents).await.unwrap();
+            hello.finish().unwrap();
+            conn
+        });
+
+        let event = recv_event(&adapter).await;
+        let _conn = writer.await.unwrap();
+
+        assert!(matches!(event.payload, TransportData::HandshakeSyn(_)));
+    }
+
+    /// A transfer that stalls halfway holds up neither the connection
+    /// nor the control messages behind it.
+    #[tokio::test]
+    async fn stalled_transfer_does_not_block_control_messages() {
+        let env = test_env_with_dirs(&["sync"]).await;
+        let adapter = QuicAdapter::new(env.state.clone()).await;
+        let addr = local_addr(&adapter);
+        let key = DeviceKey::generate();
+        trust(&env.state, key.device_id()).await;
+        let source_id = key.device_id();
+        let stalled = file_entry("sync/large.bin", "hash");
+        let entries: Vec<_> = (0..3)
+            .map(|i| file_entry(&format!("sync/{i}.bin"), "hash"))
+            .collect();
+
+        let sent = entries.clone();
+        let writer = tokio::spawn(async move {
+            let conn = connect(addr, &key).await;
+            let transfer = write_partial_transfer(&conn, source_id, &stalled, false).await;
+            for entry in &sent {
+                write_metadata(&conn, source_id, entry).await;
+            }
+            (conn, transfer)
+        });
+
+        let mut received = Vec::new();
+        for _ in 0..entries.len() {
+            let event = recv_event(&adapter).await;
+            assert_eq!(event.metadata.source_id, source_id);
+            received.push(metadata_name(event));
+        }
+        let _held_open = writer.await.unwrap();
+
+        received.sort();
+        let expected: Vec<_> = entries.iter().map(|e| e.name.to_string()).collect();
+        assert_eq!(received, expected);
+    }
+
+    #[tokio::test]
+    async fn stalled_stream_header_does_not_block_later_streams() {
+        let env = test_env_with_dirs(&["sync"]).await;
+        let adapter = QuicAdapter::new(env.state.clone()).await;
+        let addr = local_addr(&adapter);
+        let key = DeviceKey::generate();
+        trust(&env.state, key.device_id()).await;
+        let source_id = key.device_id();
+        let entry = file_entry("sync/after.bin", "hash");
+
+        let sent = entry.clone();
+        let writer = tokio::spawn(async move {
+            let conn = connect(addr, &key).await;
+            // Part of the source id, then nothing until the header
+            // timeout.
+            let (mut stalled, _) = conn.open_bi().await.unwrap();
+            stalled.write_all(&source_id.as_bytes()[..4]).await.unwrap();
+            write_metadata(&conn, source_id, &sent).await;
+            (conn, stalled)
+        });
+
+        let event = timeout(SECURE_HANDSHAKE_TIMEOUT / 2, adapter.recv())
+            .await
+            .expect("the later stream should not wait for the stalled header");
+        let _held_open = writer.await.unwrap();
+
+        match event {
+            Ok(event) => assert_eq!(metadata_name(event), entry.name.to_string()),
+            Err(TransportError::Failure(message)) => panic!("{message}"),
+        }
+    }
+
+    /// Messages sent through `TcpSender` reach a peer's `QuicAdapter`
+    /// under the sender's device id.
+    #[tokio::test]
+    async fn sender_delivers_to_a_quic_peer() {
+        let env = test_env_with_dirs(&["sync"]).await;
+        let adapter = QuicAdapter::new(env.state.clone()).await;
+        let peer = test_env_with_ports(
+            &["sync"],
+            AppPorts {
+                http: 0,
+                presence: 0,
+                transport: local_addr(&adapter).port(),
+                broadcast: 0,
+            },
+        )
+        .await;
+        trust(&env.state, peer.state.local_id()).await;
+        trust(&peer.state, env.state.local_id()).await;
+
+        let mut endpoint = Endpoint::client((LOCALHOST, 0).into()).unwrap();
+        endpoint
+            .set_default_client_config(tls::client_config(peer.state.device_key()).ok().unwrap());
+        let connections = Arc::new(QuicConnections::new(peer.state.clone(), endpoint));
+        let (sender, _inbox) = wire_format(peer.state.clone(), connections).await;
+
+        let entries: Vec<_> = (0..2)
+            .map(|i| file_entry(&format!("sync/{i}.bin"), "hash"))
+            .collect();
+        for entry in &entries {
+            let send =
+                sender.send_data(local_addr(&adapter), TransportData::Metadata(entry.clone()));
+            let (sent, event) = tokio::join!(send, recv_event(&adapter));
+            assert!(sent.is_ok());
+
+            assert_eq!(event.metadata.source_id, peer.state.local_id());
+            assert_eq!(metadata_name(event), entry.name.to_string());
+        }
+    }
+}
@@ -0,0 +1,196 @@
+use crate::{
+    application::AppState,
+    application::network::transport::interface::{TransportError, TransportResult},
+    infra::network::{
+        quic::tls::{self, SERVER_NAME},
+        tcp::{PeerStream, SECURE_HANDSHAKE_TIMEOUT, StreamOpener},
+    },
+};
+use quinn::{Connection, Endpoint, RecvStream, SendStream};
+use std::{
+    collections::HashMap,
+    io,
+    net::SocketAddr,
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    task::{Context, Poll},
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    time::timeout,
+};
+use tracing::trace;
+use uuid::Uuid;
+
+/// Both halves of a QUIC bidirectional stream.
+pub(super) struct QuicStream {
+    send: SendStream,
+    recv: RecvStream,
+}
+
+impl QuicStream {
+    pub fn new(send: SendStream, recv: RecvStream) -> Self {
+        Self { send, recv }
+    }
+}
+
+/// A connection and the device its certificate proved.
+#[derive(Clone)]
+struct Peer {
+    conn: Connection,
+    remote_id: Uuid,
+}
+
+/// The QUIC connections of a `QuicAdapter`.
+///
+/// Messages go out on one connection per peer transport address, dialed on first
+/// use and dialed again once it fails. Connections peers dial to us
+/// only carry their messages in, and are tracked so that every
+/// connection to a peer `PeerManager` removes can be closed through
+/// `evict`.
+pub(super) struct QuicConnections {
+    state: Arc<AppState>,
+    endpoint: Endpoint,
+    dialed: Mutex<HashMap<SocketAddr, Peer>>,
+    accepted: Mutex<HashMap<usize, Peer>>,
+}
+
+impl QuicConnections {
+    pub fn new(state: Arc<AppState>, endpoint: Endpoint) -> Self {
+        Self {
+            state,
+            endpoint,
+            dialed: Mutex::new(HashMap::new()),
+            accepted: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Opens a stream to the device at `target`, dialing it first if
+    /// there is no live connection. Refuses to continue if the device
+    /// answering at `target` is not an approved one, so a stranger that
+    /// takes over a peer's address receives nothing.
+    pub async fn open(&self, target: SocketAddr) -> TransportResult<(QuicStream, Uuid)> {
+        let cached = lock(&self.dialed)
+            .get(&target)
+            .filter(|peer| peer.conn.close_reason().is_none())
+            .cloned();
+        if let Some(peer) = cached {
+            self.ensure_trusted(peer.remote_id, target).await?;
+            if let Ok((send, recv)) = peer.conn.open_bi().await {
+                return Ok((QuicStream::new(send, recv), peer.remote_id));
+            }
+        }
+
+        let peer = self.dial(target).await?;
+        lock(&self.dialed).insert(target, peer.clone());
+
+        let (send, recv) = peer
+            .conn
+            .open_bi()
+            .await
+            .map_err(|err| TransportError::new(&err.to_string()))?;
+        Ok((QuicStream::new(send, recv), peer.remote_id))
+    }
+
+    /// Keeps track of a connection a peer dialed, until `forget`.
+    pub fn track(&self, conn: &Connection, remote_id: Uuid) {
+        let peer = Peer {
+            conn: conn.clone(),
+            remote_id,
+        };
+        lock(&self.accepted).insert(conn.stable_id(), peer);
+    }
+
+    pub fn forget(&self, conn: &Connection) {
+        lock(&self.accepted).remove(&conn.stable_id());
+    }
+
+    /// Closes every connection to `id`.
+    pub fn evict(&self, id: Uuid) {
+        let close = |peer: &Peer| {
+            let keep = peer.remote_id != id;
+            if !keep {
+                peer.conn.close(0u32.into(), b"peer removed");
+            }
+            keep
+        };
+        lock(&self.dialed).retain(|_, peer| close(peer));
+        lock(&self.accepted).retain(|_, peer| close(peer));
+    }
+
+    /// Closes every connection.
+    pub fn close_all(&self) {
+        for (_, peer) in lock(&self.dialed).drain() {
+            peer.conn.close(0u32.into(), b"shutting down");
+        }
+        for (_, peer) in lock(&self.accepted).drain() {
+            peer.conn.close(0u32.into(), b"shutting down");
+        }
+    }
+
+    async fn dial(&self, target: SocketAddr) -> TransportResult<Peer> {
+        let connecting = self
+            .endpoint
+            .connect(target, SERVER_NAME)
+            .map_err(|err| TransportError::new(&err.to_string()))?;
+        let conn = timeout(SECURE_HANDSHAKE_TIMEOUT, connecting)
+            .await
+            .map_err(|_| TransportError::new("QUIC handshake timed out"))?
+            .map_err(|err| TransportError::new(&err.to_string()))?;
+
+        let remote_id = tls::remote_id(&conn)?;
+        self.ensure_trusted(remote_id, target).await?;
+
+        trace!(target = ?target, peer_id = %remote_id, "quic connection established");
+        Ok(Peer { conn, remote_id })
+    }
+
+    async fn ensure_trusted(&self, remote_id: Uuid, target: SocketAddr) -> TransportResult<()> {
+        if self.state.is_trusted_device(&remote_id).await {
+            return Ok(());
+        }
+        Err(TransportError::new(&format!(
+            "Refusing to send to unapproved device {remote_id} at {target}"
+        )))
+    }
+}
+
+#[async_trait::async_trait]
+impl StreamOpener for QuicConnections {
+    async fn open_stream(&self, target: SocketAddr) -> TransportResult<PeerStream> {
+        let (stream, remote_id) = self.open(target).await?;
+        Ok(PeerStream::new(stream, remote_id))
+    }
+}
+
+fn lock<K, V>(map: &Mutex<HashMap<K, V>>) -> MutexGuard<'_, HashMap<K, V>> {
+    map.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl AsyncRead for QuicStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        AsyncRead::poll_read(Pin::new(&mut self.recv), cx, buf)
+    }
+}
+
+impl AsyncWrite for QuicStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        AsyncWrite::poll_write(Pin::new(&mut self.send), cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        AsyncWrite::poll_flush(Pin::new(&mut self.send), cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        AsyncWrite::poll_shutdown(Pin::new(&mut self.send), cx)
+    }
+}
@@ -0,0 +1,5 @@
+mod adapter;
+mod connections;
+mod tls;
+
+pub use adapter::QuicAdapter;
@@ -0,0 +1,227 @@
+use crate::{
+    application::network::transport::interface::{TransportError, TransportResult},
+    domain::{DeviceKey, device_id_from_verifying_key},
+};
+use quinn::crypto::rustls::{QuicClientConfig, QuicServerConfig};
+use rustls::{
+    CertificateError, DigitallySignedStruct, DistinguishedName, SignatureScheme,
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    crypto::{CryptoProvider, WebPkiSupportedAlgorithms, ring, verify_tls13_signature},
+    pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer, ServerName, UnixTime},
+    server::danger::{ClientCertVerified, ClientCertVerifier},
+    version::TLS13,
+};
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Application protocol both ends must agree on.
+const ALPN: &[u8] = b"synche/1";
+
+/// Name clients connect under. Certificates are self-signed and checked
+/// by key, so it is never matched against anything.
+pub(super) const SERVER_NAME: &str = "synche";
+
+/// DER prefix of an Ed25519 `SubjectPublicKeyInfo`; the 32-byte key
+/// follows it.
+const ED25519_SPKI_PREFIX: [u8; 12] = [
+    0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00,
+];
+
+/// Server side of the mutual TLS 1.3 handshake: presents a certificate
+/// for the device key and demands one from every client.
+pub(super) fn server_config(key: &DeviceKey) -> TransportResult<quinn::ServerConfig> {
+    let (cert, private) = certificate(key)?;
+    let mut config = rustls::ServerConfig::builder_with_provider(provider())
+        .with_protocol_versions(&[&TLS13])
+        .map_err(tls_error)?
+        .with_client_cert_verifier(Arc::new(DeviceVerifier::new()))
+        .with_single_cert(vec![cert], private)
+        .map_err(tls_error)?;
+    config.alpn_protocols = vec![ALPN.to_vec()];
+
+    let crypto =
+        QuicServerConfig::try_from(config).map_err(|err| TransportError::new(&err.to_string()))?;
+    Ok(quinn::ServerConfig::with_crypto(Arc::new(crypto)))
+}
+
+/// Client side of the mutual TLS 1.3 handshake.
+pub(super) fn client_config(key: &DeviceKey) -> TransportResult<quinn::ClientConfig> {
+    let (cert, private) = certificate(key)?;
+    let mut config = rustls::ClientConfig::builder_with_provider(provider())
+        .with_protocol_versions(&[&TLS13])
+        .map_err(tls_error)?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(DeviceVerifier::new()))
+        .with_client_auth_cert(vec![cert], private)
+        .map_err(tls_error)?;
+    config.alpn_protocols = vec![ALPN.to_vec()];
+
+    let crypto =
+        QuicClientConfig::try_from(config).map_err(|err| TransportError::new(&err.to_string()))?;
+    Ok(quinn::ClientConfig::new(Arc::new(crypto)))
+}
+
+/// The device id proved by the certificate the other end of `conn`
+/// presented.
+pub(super) fn remote_id(conn: &quinn::Connection) -> TransportResult<Uuid> {
+    conn.peer_identity()
+        .and_then(|identity| identity.downcast::<Vec<CertificateDer<'static>>>().ok())
+        .and_then(|certs| certs.first().and_then(device_id))
+        .ok_or_else(|| TransportError::new("Peer presented no device certificate"))
+}
+
+/// A self-signed certificate for the device's Ed25519 key, so the
+/// certificate proves the same device id a Noise handshake would.
+fn certificate(
+    key: &DeviceKey,
+) -> TransportResult<(CertificateDer<'static>, PrivateKeyDer<'static>)> {
+    let der = key.to_pkcs8_der();
+    let key_pair = rcgen::KeyPair::try_from(der.as_slice()).map_err(rcgen_error)?;
+    let cert = rcgen::CertificateParams::new(vec![SERVER_NAME.to_string()])
+        .map_err(rcgen_error)?
+        .self_signed(&key_pair)
+        .map_err(rcgen_error)?;
+
+    let private = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(der));
+    Ok((cert.der().clone(), private))
+}
+
+/// The device id of an Ed25519 certificate, or `None` for any other.
+fn device_id(cert: &CertificateDer<'_>) -> Option<Uuid> {
+    let cert = webpki::EndEntityCert::try_from(cert).ok()?;
+    let spki = cert.subject_public_key_info();
+    let key = spki.strip_prefix(&ED25519_SPKI_PREFIX[..])?;
+    device_id_from_verifying_key(key.try_into().ok()?)
+}
+
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(ring::default_provider())
+}
+
+fn tls_error(err: rustls::Error) -> TransportError {
+    TransportError::new(&format!("TLS setup failed: {err}"))
+}
+
+fn rcgen_error(err: rcgen::Error) -> TransportError {
+    TransportError::new(&format!("Device certificate failed: {err}"))
+}
+
+/// Accepts any self-signed Ed25519 certificate on both sides of the
+/// handshake. Certificates carry no trust of their own: like the Noise
+/// handshake, TLS only proves which device id the peer owns, and
+/// whether that device is approved is decided above the transport.
+#[derive(Debug)]
+struct DeviceVerifier {
+    algorithms: WebPkiSupportedAlgorithms,
+}
+
+impl DeviceVerifier {
+    fn new() -> Self {
+        Self {
+            algorithms: ring::default_provider().signature_verification_algorithms,
+        }
+    }
+
+    fn check(cert: &CertificateDer<'_>) -> Result<(), rustls::Error> {
+        match device_id(cert) {
+            Some(_) => Ok(()),
+            None => Err(rustls::Error::InvalidCertificate(
+                CertificateError::BadEncoding,
+            )),
+        }
+    }
+
+    fn verify_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(message, cert, dss, &self.algorithms)
+    }
+}
+
+impl ServerCertVerifier for DeviceVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Self::check(end_entity).map(|_| ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        _message: &[u8],
+        _cert: &CertificateDer<'_>,
+        _dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        Err(rustls::Error::General("TLS 1.2 is not supported".into()))
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.verify_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        vec![SignatureScheme::ED25519]
+    }
+}
+
+impl ClientCertVerifier for DeviceVerifier {
+    fn root_hint_subjects(&self) -> &[DistinguishedName] {
+        &[]
+    }
+
+    fn verify_client_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _now: UnixTime,
+    ) -> Result<ClientCertVerified, rustls::Error> {
+        Self::check(end_entity).map(|_| ClientCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        _message: &[u8],
+        _cert: &CertificateDer<'_>,
+        _dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        Err(rustls::Error::General("TLS 1.2 is not supported".into()))
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.verify_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        vec![SignatureScheme::ED25519]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn device_certificates_prove_the_device_id() {
+        let key = DeviceKey::generate();
+        let (cert, _) = certificate(&key).ok().unwrap();
+
+        assert_eq!(device_id(&cert), Some(key.device_id()));
+    }
+}
@@ -0,0 +1,116 @@
+use socket2::{Domain, Protocol, Socket, Type};
+use std::{
+    io,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+};
+use tracing::warn;
+
+/// Connections the TCP listener queues before they are accepted.
+const LISTEN_BACKLOG: i32 = 1024;
+
+/// Binds a TCP listener on `port` of every IPv4 and IPv6 address.
+/// See `bind` for hosts without IPv6.
+pub fn tcp_listener(port: u16) -> io::Result<tokio::net::TcpListener> {
+    let socket = bind(Type::STREAM, Protocol::TCP, port)?;
+    socket.listen(LISTEN_BACKLOG)?;
+    tokio::net::TcpListener::from_std(socket.into())
+}
+
+/// Binds a UDP socket on `port` of every IPv4 and IPv6 address.
+/// See `bind` for hosts without IPv6.
+pub fn udp_socket(port: u16) -> io::Result<std::net::UdpSocket> {
+    Ok(bind(Type::DGRAM, Protocol::UDP, port)?.into())
+}
+
+/// Binds a UDP socket on `port` of every IPv4 address that may send
+/// and receive broadcasts. `SO_REUSEADDR` lets other processes on the
+/// host bind the same port, so each of them hears the broadcasts.
+pub fn broadcast_socket(port: u16) -> io::Result<std::net::UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    socket.set_broadcast(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, port)).into())?;
+    Ok(socket.into())
+}
+
+/// Where a datagram to `addr` goes from a socket bound by `udp_socket`
+/// at `local`. On a dual-stack socket an IPv4 address is given as
+/// IPv4-mapped IPv6, since not every platform takes plain IPv4 there.
+pub fn udp_target(local: SocketAddr, addr: SocketAddr) -> SocketAddr {
+    match (local, addr) {
+        (SocketAddr::V6(_), SocketAddr::V4(v4)) => {
+            SocketAddr::from((v4.ip().to_ipv6_mapped(), v4.port()))
+        }
+        _ => addr,
+    }
+}
+
+/// Binds an IPv6 socket that also takes IPv4, which is not the default
+/// on every platform. IPv4 peers then show up as IPv4-mapped IPv6
+/// addresses; callers turn them back with `IpAddr::to_canonical`.
+///
+/// Where the host has no IPv6, binds every IPv4 address instead. A
+/// port already in use is reported as is.
+fn bind(ty: Type, protocol: Protocol, port: u16) -> io::Result<Socket> {
+    let dual_stack = SocketAddr::from((Ipv6Addr::UNSPECIFIED, port));
+    match open(Domain::IPV6, ty, protocol, dual_stack) {
+        Ok(socket) => Ok(socket),
+        Err(err) if err.kind() == io::ErrorKind::AddrInUse => Err(err),
+        Err(err) => {
+            warn!("IPv6 unavailable, listening on IPv4 only: {err}");
+            let ipv4 = SocketAddr::from((Ipv4Addr::UNSPECIFIED, port));
+            open(Domain::IPV4, ty, protocol, ipv4)
+        }
+    }
+}
+
+fn open(domain: Domain, ty: Type, protocol: Protocol, addr: SocketAddr) -> io::Result<Socket> {
+    let socket = Socket::new(domain, ty, Some(protocol))?;
+    if domain == Domain::IPV6 {
+        socket.set_only_v6(false)?;
+    }
+    // Matches `std`, so a restart can listen again at once.
+    #[cfg(unix)]
+    if ty == Type::STREAM {
+        socket.set_reuse_address(true)?;
+    }
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    Ok(socket)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::IpAddr;
+    use tokio::net::{TcpStream, UdpSocket};
+
+    #[tokio::test]
+    async fn sockets_take_ipv4_and_ipv6() {
+        let listener = tcp_listener(0).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        for ip in [
+            IpAddr::from(Ipv4Addr::LOCALHOST),
+            Ipv6Addr::LOCALHOST.into(),
+        ] {
+            let client = TcpStream::connect((ip, port)).await.unwrap();
+            let (_stream, source) = listener.accept().await.unwrap();
+            assert_eq!(source.ip().to_canonical(), ip);
+            drop(client);
+        }
+
+        let socket = UdpSocket::from_std(udp_socket(0).unwrap()).unwrap();
+        let port = socket.local_addr().unwrap().port();
+        for ip in [
+            IpAddr::from(Ipv4Addr::LOCALHOST),
+            Ipv6Addr::LOCALHOST.into(),
+        ] {
+            let client = UdpSocket::bind((ip, 0)).await.unwrap();
+            client.send_to(b"ping", (ip, port)).await.unwrap();
+            let mut buf = [0u8; 4];
+            let (_, source) = socket.recv_from(&mut buf).await.unwrap();
+            assert_eq!(source.ip().to_canonical(), ip);
+        }
+    }
+}
@@ -0,0 +1,388 @@
+use crate::{
+    application::AppState,
+    application::network::presence::interface::{PresenceEvent, PresenceInterface},
+    domain::ConfigPeer,
+    infra::network::socket,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex, PoisonError},
+    time::Duration,
+};
+use tokio::{io, net::UdpSocket, task::JoinHandle};
+use tracing::{trace, warn};
+use uuid::Uuid;
+
+/// How often every configured peer is probed.
+const PROBE_INTERVAL: Duration = Duration::from_secs(15);
+/// Largest datagram read; probes are far smaller.
+const MAX_PROBE_LEN: usize = 512;
+
+/// Addresses probed in the current round, with the device id each one
+/// must answer with, if configured.
+type Probed = Arc<Mutex<HashMap<SocketAddr, Option<Uuid>>>>;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum ProbeKind {
+    Probe,
+    Reply,
+}
+
+/// One UDP datagram of the probe exchange, as JSON. A probe and its
+/// reply carry the same fields: who sent it, and where its transport
+/// listens.
+#[derive(Serialize, Deserialize, Debug)]
+struct ProbeMessage {
+    kind: ProbeKind,
+    id: Uuid,
+    instance_id: Uuid,
+    transport_port: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cluster: Option<String>,
+}
+
+/// `PresenceInterface` implementation for the `[[peer]]` entries of
+/// `config.toml`, for networks that drop multicast and so never let
+/// `MdnsAdapter` resolve anyone.
+///
+/// Listens on the presence port (UDP, IPv4 and IPv6) and, once
+/// advertising, sends a probe to every address each configured peer
+/// resolves to, each `PROBE_INTERVAL`. A device
+/// answers every probe with a reply, whether or not it lists the
+/// prober itself. Both directions yield a `Ping`: the reply tells the
+/// prober about the device it probed, the probe tells the device about
+/// the prober, so a handshake follows whichever side has the lower id.
+/// Replies are only taken from addresses probed in the current round,
+/// and from a peer configured with an `id`, only when they carry it.
+///
+/// Like mDNS records, probes are not authenticated; the transport
+/// handshake proves who a device is before anything is exchanged.
+pub struct StaticPeerAdapter {
+    state: Arc<AppState>,
+    socket: Arc<UdpSocket>,
+    peers: Vec<ConfigPeer>,
+    probed: Probed,
+    prober: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl StaticPeerAdapter {
+    pub async fn new(state: Arc<AppState>) -> io::Result<Self> {
+        let peers = state.static_peers().to_vec();
+        Self::bind(state, peers).await
+    }
+
+    async fn bind(state: Arc<AppState>, peers: Vec<ConfigPeer>) -> io::Result<Self> {
+        let socket = UdpSocket::from_std(socket::udp_socket(state.ports().presence)?)?;
+
+        Ok(Self {
+            state,
+            socket: Arc::new(socket),
+            peers,
+            probed: Default::default(),
+            prober: Mutex::new(None),
+        })
+    }
+
+    fn message(state: &AppState, kind: ProbeKind) -> io::Result<Vec<u8>> {
+        let message = ProbeMessage {
+            kind,
+            id: state.local_id(),
+            instance_id: state.instance_id(),
+            transport_port: state.ports().transport,
+            cluster: state.cluster().tag(),
+        };
+        serde_json::to_vec(&message).map_err(io::Error::other)
+    }
+
+    /// Resolves every configured peer and probes it, every
+    /// `PROBE_INTERVAL`, until aborted.
+    async fn probe_loop(
+        state: Arc<AppState>,
+        socket: Arc<UdpSocket>,
+        peers: Vec<ConfigPeer>,
+        probed: Probed,
+    ) {
+        let mut interval = tokio::time::interval(PROBE_INTERVAL);
+        loop {
+            interval.tick().await;
+
+            let mut round = HashMap::new();
+            for peer in &peers {
+                let (host, port) = peer.host_port(state.ports().presence);
+                match tokio::net::lookup_host((host.as_str(), port)).await {
+                    Ok(addrs) => {
+                        let before = round.len();
+                        round.extend(addrs.map(|addr| (canonical(addr), peer.id)));
+                        if round.len() == before {
+                            warn!(address = %peer.address, "static peer resolved to no address")
+                        }
+                    }
+                    Err(err) => {
+                        warn!(address = %peer.address, "cannot resolve static peer: {err}")
+                    }
+                }
+            }
+            *probed.lock().unwrap_or_else(PoisonError::into_inner) = round.clone();
+
+            let Ok(probe) = Self::message(&state, ProbeKind::Probe) else {
+                continue;
+            };
+            let Ok(local) = socket.local_addr() else {
+                continue;
+            };
+            for addr in round.into_keys() {
+                trace!(%addr, "probing static peer");
+                if let Err(err) = socket
+                    .send_to(&probe, socket::udp_target(local, addr))
+                    .await
+                {
+                    warn!(%addr, "failed to probe static peer: {err}");
+                }
+            }
+        }
+    }
+
+    /// Answers a probe and turns a probe or a reply into a `Ping`.
+    /// Never awaits, so `next` stays cancel-safe.
+    fn handle_datagram(&self, datagram: &[u8], reply_to: SocketAddr) -> Option<PresenceEvent> {
+        let source = canonical(reply_to);
+        let message: ProbeMessage = match serde_json::from_slice(datagram) {
+            Ok(message) => message,
+            Err(err) => {
+                trace!(%source, "ignoring invalid probe datagram: {err}");
+                return None;
+            }
+        };
+        if message.id == self.state.local_id() || message.transport_port == 0 {
+            return None;
+        }
+
+        match message.kind {
+            ProbeKind::Probe => {
+                let reply = Self::message(&self.state, ProbeKind::Reply).ok()?;
+                if let Err(err) = self.socket.try_send_to(&reply, reply_to) {
+                    trace!(%source, "failed to answer probe: {err}");
+                }
+            }
+            ProbeKind::Reply => {
+                let probed = self.probed.lock().unwrap_or_else(PoisonError::into_inner);
+                match probed.get(&source) {
+                    None => {
+                        trace!(%source, "ignoring unsolicited probe reply");
+                        return None;
+                    }
+                    Some(Some(expected)) if *expected != message.id => {
+                        warn!(
+                            %source,
+                            peer = %message.id,
+                            %expected,
+                            "static peer answered with another device id"
+                        );
+                        return None;
+                    }
+                    Some(_) => {}
+                }
+            }
+        }
+
+        let mut addr = source;
+        addr.set_port(message.transport_port);
+        Some(PresenceEvent::Ping {
+            id: message.id,
+            addrs: vec![addr],
+            instance_id: message.instance_id,
+            cluster: message.cluster,
+        })
+    }
+
+    fn stop_probing(&self) {
+        let prober = self.prober.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(prober) = prober.as_ref() {
+            prober.abort();
+        }
+    }
+}
+
+/// `addr` with an IPv4-mapped IPv6 address, as a dual-stack socket
+/// reports IPv4 sources, turned back into IPv4.
+fn canonical(addr: SocketAddr) -> SocketAddr {
+    SocketAddr::new(addr.ip().to_canonical(), addr.port())
+}
+
+impl PresenceInterface for StaticPeerAdapter {
+    async fn advertise(&self) -> io::Result<()> {
+        let prober = tokio::spawn(Self::probe_loop(
+            self.state.clone(),
+            self.socket.clone(),
+            self.peers.clone(),
+            self.probed.clone(),
+        ));
+        let previous = self
+            .prober
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .replace(prober);
+        if let Some(previous) = previous {
+            previous.abort();
+        }
+        Ok(())
+    }
+
+    async fn next(&self) -> io::Result<Option<PresenceEvent>> {
+        let mut buf = [0u8; MAX_PROBE_LEN];
+        loop {
+            let (len, source) = self.socket.recv_from(&mut buf).await?;
+            if let Some(event) = self.handle_datagram(&buf[..len], source) {
+                return Ok(Some(event));
+            }
+        }
+    }
+
+    async fn shutdown(&self) {
+        self.stop_probing();
+    }
+}
+
+impl Drop for StaticPeerAdapter {
+    fn drop(&mut self) {
+        self.stop_probing();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        domain::AppPorts,
+        utils::test_support::{TestEnv, test_env_with_ports},
+    };
+    use tokio::time::timeout;
+
+    async fn adapter(peers: Vec<ConfigPeer>) -> (TestEnv, StaticPeerAdapter) {
+        let ports = AppPorts {
+            http: 0,
+            presence: 0,
+            transport: 42882,
+            broadcast: 0,
+        };
+        let env = test_env_with_ports(&["sync"], ports).await;
+        let adapter = StaticPeerAdapter::bind(env.state.clone(), peers)
+            .await
+            .unwrap();
+        (env, adapter)
+    }
+
+    fn config_peer(target: &StaticPeerAdapter, id: Option<Uuid>) -> ConfigPeer {
+        let port = target.socket.local_addr().unwrap().port();
+        ConfigPeer {
+            address: format!("127.0.0.1:{port}"),
+            id,
+        }
+    }
+
+    async fn next_ping_addr(adapter: &StaticPeerAdapter) -> SocketAddr {
+        let event = timeout(Duration::from_secs(5), adapter.next())
+            .await
+            .expect("adapter should yield an event")
+            .unwrap();
+        match event {
+            Some(PresenceEvent::Ping { addrs, .. }) => addrs[0],
+            _ => panic!("expected a ping"),
+        }
+    }
+
+    async fn next_ping(adapter: &StaticPeerAdapter) -> (Uuid, Uuid) {
+        let event = timeout(Duration::from_secs(5), adapter.next())
+            .await
+            .expect("adapter should yield an event")
+            .unwrap();
+        match event {
+            Some(PresenceEvent::Ping {
+                id, instance_id, ..
+            }) => (id, instance_id),
+            _ => panic!("expected a ping"),
+        }
+    }
+
+    #[tokio::test]
+    async fn probe_and_reply_introduce_both_devices() {
+        let (target_env, target) = adapter(vec![]).await;
+        let (prober_env, prober) = adapter(vec![config_peer(&target, None)]).await;
+
+        prober.advertise().await.unwrap();
+        target.advertise().await.unwrap();
+
+        let (probe, reply) = tokio::join!(next_ping(&target), next_ping(&prober));
+
+        let prober_state = &prober_env.state;
+        let target_state = &target_env.state;
+        assert_eq!(probe, (prober_state.local_id(), prober_state.instance_id()));
+        assert_eq!(reply, (target_state.local_id(), target_state.instance_id()));
+    }
+
+    #[tokio::test]
+    async fn peers_are_probed_over_ipv6_too() {
+        let (_target_env, target) = adapter(vec![]).await;
+        let port = target.socket.local_addr().unwrap().port();
+        let peer = ConfigPeer {
+            address: format!("[::1]:{port}"),
+            id: None,
+        };
+        let (_prober_env, prober) = adapter(vec![peer]).await;
+
+        prober.advertise().await.unwrap();
+
+        let (probe, reply) = tokio::join!(next_ping_addr(&target), next_ping_addr(&prober));
+        assert_eq!(probe.ip(), std::net::Ipv6Addr::LOCALHOST);
+        assert_eq!(reply, "[::1]:42882".parse().unwrap());
+    }
+
+    #[tokio::test]
+    async fn ipv4_probes_arrive_as_ipv4() {
+        let (_target_env, target) = adapter(vec![]).await;
+        let (_prober_env, prober) = adapter(vec![config_peer(&target, None)]).await;
+
+        prober.advertise().await.unwrap();
+
+        let (probe, reply) = tokio::join!(next_ping_addr(&target), next_ping_addr(&prober));
+        assert_eq!(probe.ip(), std::net::Ipv4Addr::LOCALHOST);
+        assert_eq!(reply, "127.0.0.1:42882".parse().unwrap());
+    }
+
+    #[tokio::test]
+    async fn replies_from_an_unexpected_device_are_ignored() {
+        let (_target_env, target) = adapter(vec![]).await;
+        let (prober_env, prober) = adapter(vec![config_peer(&target, Some(Uuid::new_v4()))]).await;
+
+        prober.advertise().await.unwrap();
+
+        // The target still learns about the prober from the probe.
+        let (probe, reply) = tokio::join!(
+            next_ping(&target),
+            timeout(Duration::from_millis(300), prober.next())
+        );
+
+        assert_eq!(probe.0, prober_env.state.local_id());
+        assert!(reply.is_err());
+    }
+
+    #[tokio::test]
+    async fn unsolicited_replies_are_ignored() {
+        let (_env, adapter) = adapter(vec![]).await;
+        let reply = ProbeMessage {
+            kind: ProbeKind::Reply,
+            id: Uuid::new_v4(),
+            instance_id: Uuid::new_v4(),
+            transport_port: 42882,
+            cluster: None,
+        };
+        let datagram = serde_json::to_vec(&reply).unwrap();
+
+        let source = "10.0.0.9:42881".parse().unwrap();
+        assert!(adapter.handle_datagram(&datagram, source).is_none());
+        assert!(adapter.handle_datagram(b"not json", source).is_none());
+    }
+}
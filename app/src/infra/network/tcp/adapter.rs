@@ -3,79 +3,199 @@ use crate::{
     application::network::transport::interface::{
         TransportError, TransportInterface, TransportResult,
     },
-    domain::{TransportData, TransportEvent, TransportMetadata},
-    infra::network::tcp::{kind::TcpStreamKind, receiver::TcpReceiver, sender::TcpSender},
+    domain::{TransportData, TransportEvent},
+    infra::network::{
+        socket,
+        tcp::{
+            chunk::SECURE_HANDSHAKE_TIMEOUT,
+            inbox::{Inbox, Lanes, read_stream_header, wire_format},
+            kind::TcpStreamKind,
+            pool::{ConnectionPool, InboundStreams, MUX_READY},
+            secure::SecureStream,
+            sender::TcpSender,
+            stream::PeerStream,
+        },
+    },
+};
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
+use tokio::{
+    io::AsyncWriteExt,
+    net::{TcpListener, TcpStream},
+    sync::{broadcast::error::RecvError, mpsc},
+    task::JoinHandle,
+    time::timeout,
 };
-use std::{net::IpAddr, sync::Arc};
-use tokio::{io::AsyncReadExt, net::TcpListener};
 use tracing::{trace, warn};
-use uuid::Uuid;
 
 /// TCP adapter implementing `TransportInterface`.
 ///
-/// Owns the listening socket plus a `TcpSender` / `TcpReceiver` pair
-/// that implement the wire format. Listener bind/accept failures are
-/// fatal; framing errors on an accepted connection are logged and the
-/// loop continues so a single bad peer cannot stop the synchronizer.
+/// Owns the listening socket, bound to IPv4 and IPv6 alike, the
+/// `ConnectionPool` of long-lived connections to peers, and a
+/// `TcpSender` / `TcpReceiver` pair that implement the wire format.
+/// Every accepted connection must complete a Noise handshake before
+/// any frame is read, and the source id reported upward is the one
+/// proven by that handshake. A connection either
+/// carries one message or, after a `Multiplex` frame, joins the pool
+/// and carries a stream per message. Devices the user has not approved
+/// may only send handshakes; any other stream is rejected before its
+/// payload is read.
+///
+/// Inbound streams are read by an `Inbox`, whose separate lane for bulk
+/// transfers keeps a large file from holding up a metadata update or a
+/// handshake. Listener bind/accept failures are fatal; handshake and
+/// framing errors on a connection or stream are logged and dropped so a
+/// single bad peer cannot stop the synchronizer.
 pub struct TcpAdapter {
     sender: TcpSender,
-    receiver: TcpReceiver,
     listener: TcpListener,
+    state: Arc<AppState>,
+    pool: Arc<ConnectionPool>,
+    inbox: Inbox,
+    background: Vec<JoinHandle<()>>,
 }
 
 impl TcpAdapter {
     pub async fn new(state: Arc<AppState>) -> Self {
-        let addr = format!("0.0.0.0:{}", state.ports().transport);
-        let listener = TcpListener::bind(addr).await.unwrap();
+        let listener = socket::tcp_listener(state.ports().transport).unwrap();
 
-        let receiver = TcpReceiver::new(state.clone());
-        let sender = TcpSender::new(state);
+        let (pool, inbound) = ConnectionPool::new(state.clone());
+        let pool = Arc::new(pool);
+        let (sender, inbox) = wire_format(state.clone(), pool.clone()).await;
+
+        let background = vec![
+            tokio::spawn(Self::route_connections(inbound, inbox.lanes())),
+            tokio::spawn(Self::evict_removed_peers(state.clone(), pool.clone())),
+        ];
 
         Self {
             sender,
-            receiver,
             listener,
+            state,
+            pool,
+            inbox,
+            background,
         }
     }
-}
 
-impl TransportInterface for TcpAdapter {
-    async fn send(&self, target: IpAddr, data: TransportData) -> TransportResult<()> {
-        self.sender.send_data(target, data).await
+    /// Authenticates an accepted connection, then either hands its one
+    /// message to a lane or adds it to the pool.
+    async fn accept(
+        stream: TcpStream,
+        source_ip: IpAddr,
+        state: Arc<AppState>,
+        pool: Arc<ConnectionPool>,
+        lanes: Lanes,
+    ) -> TransportResult<()> {
+        let mut stream = timeout(
+            SECURE_HANDSHAKE_TIMEOUT,
+            SecureStream::accept(stream, state.device_key()),
+        )
+        .await
+        .map_err(|_| TransportError::new("Noise handshake timed out"))??;
+        let source_id = stream.remote_id();
+        let kind = read_stream_header(&mut stream, source_id).await?;
+
+        if let TcpStreamKind::Multiplex = kind {
+            stream.write_all(&[MUX_READY]).await?;
+            stream.flush().await?;
+            pool.attach(source_ip, stream, source_id, false);
+            return Ok(());
+        }
+
+        lanes
+            .route(PeerStream::new(stream, source_id), kind, source_ip)
+            .await
     }
 
-    async fn recv(&self) -> TransportResult<TransportEvent> {
-        loop {
-            let (mut stream, addr) = self.listener.accept().await?;
+    /// Hands the streams each pooled connection's peer opens to the
+    /// lanes, in the order they were opened.
+    async fn route_connections(mut inbound: mpsc::UnboundedReceiver<InboundStreams>, lanes: Lanes) {
+        while let Some((mut streams, source_ip)) = inbound.recv().await {
+            let lanes = lanes.clone();
 
-            let source_ip = addr.ip();
-            trace!(peer = %source_ip, "tcp listener accepted");
+            tokio::spawn(async move {
+                while let Some(mut stream) = streams.recv().await {
+                    let source_id = stream.remote_id();
 
-            let result: TransportResult<TransportEvent> = async {
-                let mut source_id_buf = [0u8; 16];
-                stream.read_exact(&mut source_id_buf).await?;
-                let source_id = Uuid::from_bytes(source_id_buf);
+                    let result = async {
+                        let kind = read_stream_header(&mut stream, source_id).await?;
+                        if let TcpStreamKind::Multiplex = kind {
+                            return Err(TransportError::new(
+                                "Multiplex requested on a multiplexed stream",
+                            ));
+                        }
 
-                let mut kind_buf = [0u8; 1];
-                stream.read_exact(&mut kind_buf).await?;
-                let kind = TcpStreamKind::try_from(kind_buf[0])?;
+                        lanes
+                            .route(PeerStream::new(stream, source_id), kind, source_ip)
+                            .await
+                    }
+                    .await;
 
-                let payload = self.receiver.read_data(stream, kind, source_id).await?;
+                    if let Err(TransportError::Failure(message)) = result {
+                        warn!(peer = ?source_ip, "Ignoring invalid TCP transport message: {message}");
+                    }
+                }
+            });
+        }
+    }
 
-                Ok(TransportEvent {
-                    metadata: TransportMetadata {
-                        source_id,
-                        source_ip,
-                    },
-                    payload,
-                })
+    async fn evict_removed_peers(state: Arc<AppState>, pool: Arc<ConnectionPool>) {
+        let mut removals = state.subscribe_peer_removals();
+        loop {
+            match removals.recv().await {
+                Ok(id) => {
+                    trace!(peer_id = %id, "closing connections to removed peer");
+                    pool.evict(id);
+                }
+                Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => return,
             }
-            .await;
+        }
+    }
+}
+
+impl Drop for TcpAdapter {
+    fn drop(&mut self) {
+        for task in &self.background {
+            task.abort();
+        }
+        self.pool.close_all();
+    }
+}
 
-            match result {
-                Ok(event) => return Ok(event),
-                Err(TransportError::Failure(message)) => {
-                    warn!(peer = ?source_ip, "Ignoring invalid TCP transport message: {message}");
+impl TransportInterface for TcpAdapter {
+    async fn send(&self, target: SocketAddr, data: TransportData) -> TransportResult<()> {
+        self.sender.send_data(target, data).await
+    }
+
+    /// Accepts connections while waiting for the next message, so the
+    /// listener is only served while the application is listening.
+    async fn recv(&self) -> TransportResult<TransportEvent> {
+        let mut events = self.inbox.events().rx.lock().await;
+
+        loop {
+            tokio::select! {
+                Some(event) = events.recv() => return Ok(event),
+                accepted = self.listener.accept() => {
+                    let (stream, addr) = accepted?;
+                    let source_ip = addr.ip().to_canonical();
+                    trace!(peer = %source_ip, "tcp listener accepted");
+
+                    let accept = Self::accept(
+                        stream,
+                        source_ip,
+                        self.state.clone(),
+                        self.pool.clone(),
+                        self.inbox.lanes(),
+                    );
+                    tokio::spawn(async move {
+                        if let Err(TransportError::Failure(message)) = accept.await {
+                            warn!(peer = ?source_ip, "Ignoring invalid TCP transport message: {message}");
+                        }
+                    });
                 }
             }
         }
@@ -85,11 +205,31 @@ impl TransportInterface for TcpAdapter {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::domain::{EntryInfo, EntryKind};
-    use std::{collections::HashMap, time::Duration};
-    use tokio::{io::AsyncWriteExt, net::TcpStream, time::timeout};
+    use crate::{
+        application::PeerManager,
+        domain::{DeviceKey, EntryInfo, EntryKind},
+        infra::network::tcp::{
+            handshake::{HandshakeHeader, InlineHandshake},
+            mux::MuxConnection,
+        },
+    };
+    use std::{collections::HashMap, net::SocketAddr, time::Duration};
+    use tokio::io::{AsyncReadExt, AsyncWrite};
+    use uuid::Uuid;
 
-    async fn write_metadata(stream: &mut TcpStream, source_id: Uuid, entry: &EntryInfo) {
+    async fn connect(addr: SocketAddr, key: &DeviceKey) -> SecureStream<TcpStream> {
+        let stream = TcpStream::connect(addr).await.unwrap();
+        match SecureStream::connect(stream, key).await {
+            Ok(stream) => stream,
+            Err(TransportError::Failure(message)) => panic!("handshake failed: {message}"),
+        }
+    }
+
+    async fn write_metadata<S: AsyncWrite + Unpin>(
+        stream: &mut S,
+        source_id: Uuid,
+        entry: &EntryInfo,
+    ) {
         let contents = serde_json::to_vec(entry).unwrap();
 
         stream.write_all(source_id.as_bytes()).await.unwrap();
@@ -102,9 +242,14 @@ mod tests {
             .await
             .unwrap();
         stream.write_all(&contents).await.unwrap();
+        stream.flush().await.unwrap();
     }
 
-    async fn write_corrupt_transfer(stream: &mut TcpStream, source_id: Uuid, entry: &EntryInfo) {
+    async fn write_corrupt_transfer(
+        stream: &mut SecureStream<TcpStream>,
+        source_id: Uuid,
+        entry: &EntryInfo,
+    ) {
         let contents = b"not the advertised hash";
         let entry_json = serde_json::to_vec(entry).unwrap();
 
@@ -123,6 +268,7 @@ mod tests {
             .await
             .unwrap();
         stream.write_all(contents).await.unwrap();
+        stream.flush().await.unwrap();
     }
 
     fn file_entry(name: &str, hash: &str) -> EntryInfo {
@@ -131,6 +277,27 @@ mod tests {
             kind: EntryKind::File,
             hash: Some(hash.to_string()),
             version: HashMap::from([(Uuid::new_v4(), 1)]),
+            modified: None,
+            removed_at: None,
+        }
+    }
+
+    async fn trust(state: &Arc<AppState>, key: &DeviceKey) {
+        PeerManager::new(state.clone())
+            .load_trusted(vec![key.device_id()])
+            .await;
+    }
+
+    async fn recv_event(adapter: &TcpAdapter) -> TransportEvent {
+        let result = timeout(Duration::from_secs(5), adapter.recv())
+            .await
+            .expect("adapter should keep listening");
+
+        match result {
+            Ok(event) => event,
+            Err(TransportError::Failure(message)) => {
+                panic!("unexpected transport error: {message}")
+            }
         }
     }
 
@@ -139,36 +306,200 @@ mod tests {
         let env = crate::utils::test_support::test_env_with_dirs(&["bad"]).await;
         let adapter = TcpAdapter::new(env.state.clone()).await;
         let addr = adapter.listener.local_addr().unwrap();
-        let source_id = Uuid::new_v4();
+        let key = DeviceKey::generate();
+        trust(&env.state, &key).await;
+        let source_id = key.device_id();
         let corrupt_entry = file_entry("bad/payload.bin", "deadbeef");
         let metadata_entry = file_entry("ok/payload.bin", "hash");
 
         let metadata_entry_clone = metadata_entry.clone();
         let writer = tokio::spawn(async move {
-            let mut bad_stream = TcpStream::connect(addr).await.unwrap();
+            let mut bad_stream = connect(addr, &key).await;
             write_corrupt_transfer(&mut bad_stream, source_id, &corrupt_entry).await;
             drop(bad_stream);
 
-            let mut good_stream = TcpStream::connect(addr).await.unwrap();
+            let mut good_stream = connect(addr, &key).await;
             write_metadata(&mut good_stream, source_id, &metadata_entry_clone).await;
         });
 
-        let result = timeout(Duration::from_secs(2), adapter.recv())
-            .await
-            .expect("adapter should keep listening after corrupt transfer");
+        let event = recv_event(&adapter).await;
         writer.await.unwrap();
 
-        let event = match result {
-            Ok(event) => event,
-            Err(TransportError::Failure(message)) => {
-                panic!("unexpected transport error: {message}")
-            }
-        };
-
         assert_eq!(event.metadata.source_id, source_id);
         match event.payload {
             TransportData::Metadata(entry) => assert_eq!(entry.name, metadata_entry.name),
             _ => panic!("expected metadata after corrupt transfer"),
         }
     }
+
+    /// A peer that authenticates with its own key but writes somebody
+    /// else's id into the frame must be dropped; its next honest frame
+    /// is still accepted under the authenticated id.
+    #[tokio::test]
+    async fn recv_rejects_forged_source_id() {
+        let env = crate::utils::test_support::test_env_with_dirs(&["sync"]).await;
+        let adapter = TcpAdapter::new(env.state.clone()).await;
+        let addr = adapter.listener.local_addr().unwrap();
+        let key = DeviceKey::generate();
+        trust(&env.state, &key).await;
+        let forged_id = Uuid::new_v4();
+        let forged_entry = file_entry("sync/forged.bin", "hash");
+        let honest_entry = file_entry("sync/honest.bin", "hash");
+
+        let honest_entry_clone = honest_entry.clone();
+        let writer = tokio::spawn(async move {
+            let mut forged = connect(addr, &key).await;
+            write_metadata(&mut forged, forged_id, &forged_entry).await;
+            drop(forged);
+
+            let mut honest = connect(addr, &key).await;
+            write_metadata(&mut honest, key.device_id(), &honest_entry_clone).await;
+            key.device_id()
+        });
+
+        let event = recv_event(&adapter).await;
+        let authenticated_id = writer.await.unwrap();
+
+        assert_eq!(event.metadata.source_id, authenticated_id);
+        assert_ne!(event.metadata.source_id, forged_id);
+        match event.payload {
+            TransportData::Metadata(entry) => assert_eq!(entry.name, honest_entry.name),
+            _ => panic!("expected the honest metadata frame"),
+        }
+    }
+
+    /// A plaintext client speaking the old unauthenticated framing never
+    /// gets a frame through.
+    #[tokio::test]
+    async fn recv_rejects_unauthenticated_plaintext_frames() {
+        let env = crate::utils::test_support::test_env_with_dirs(&["sync"]).await;
+        let adapter = TcpAdapter::new(env.state.clone()).await;
+        let addr = adapter.listener.local_addr().unwrap();
+        let key = DeviceKey::generate();
+        trust(&env.state, &key).await;
+        let entry = file_entry("sync/plain.bin", "hash");
+        let contents = serde_json::to_vec(&entry).unwrap();
+
+        let writer = tokio::spawn(async move {
+            let mut plain = TcpStream::connect(addr).await.unwrap();
+            plain.write_all(Uuid::new_v4().as_bytes()).await.unwrap();
+            plain
+                .write_all(&[TcpStreamKind::Metadata as u8])
+                .await
+                .unwrap();
+            plain
+                .write_all(&(contents.len() as u32).to_be_bytes())
+                .await
+                .unwrap();
+            plain.write_all(&contents).await.unwrap();
+            drop(plain);
+
+            let mut honest = connect(addr, &key).await;
+            write_metadata(&mut honest, key.device_id(), &entry).await;
+            key.device_id()
+        });
+
+        let event = recv_event(&adapter).await;
+        let authenticated_id = writer.await.unwrap();
+
+        assert_eq!(event.metadata.source_id, authenticated_id);
+    }
+
+    /// An authenticated but unapproved device gets no data frame
+    /// through; its handshake still arrives so it can be paired.
+    #[tokio::test]
+    async fn recv_drops_data_frames_from_unapproved_device() {
+        let env = crate::utils::test_support::test_env_with_dirs(&["sync"]).await;
+        let adapter = TcpAdapter::new(env.state.clone()).await;
+        let addr = adapter.listener.local_addr().unwrap();
+        let key = DeviceKey::generate();
+        let entry = file_entry("sync/stranger.bin", "hash");
+        let hs_data = InlineHandshake {
+            header: HandshakeHeader {
+                hostname: "stranger".into(),
+                instance_id: Uuid::new_v4(),
+                transport_port: None,
+                cluster_proof: None,
+                sync_dirs: vec![],
+                protocol: Default::default(),
+            },
+            entries: HashMap::new(),
+        };
+
+        let writer = tokio::spawn(async move {
+            let mut data = connect(addr, &key).await;
+            write_metadata(&mut data, key.device_id(), &entry).await;
+            drop(data);
+
+            let mut hello = connect(addr, &key).await;
+            let contents = serde_json::to_vec(&hs_data).unwrap();
+            hello.write_all(key.device_id().as_bytes()).await.unwrap();
+            hello
+                .write_all(&[TcpStreamKind::HandshakeSyn as u8])
+                .await
+                .unwrap();
+            hello
+                .write_all(&(contents.len() as u32).to_be_bytes())
+                .await
+                .unwrap();
+            hello.write_all(&contents).await.unwrap();
+            hello.flush().await.unwrap();
+        });
+
+        let event = recv_event(&adapter).await;
+        writer.await.unwrap();
+
+        assert!(matches!(event.payload, TransportData::HandshakeSyn(_)));
+    }
+
+    /// Every stream of a multiplexed connection is read under the id
+    /// the Noise handshake proved, in the order the streams were opened.
+    #[tokio::test]
+    async fn recv_reads_every_stream_of_a_multiplexed_connection() {
+        let env = crate::utils::test_support::test_env_with_dirs(&["sync"]).await;
+        let adapter = TcpAdapter::new(env.state.clone()).await;
+        let addr = adapter.listener.local_addr().unwrap();
+        let key = DeviceKey::generate();
+        trust(&env.state, &key).await;
+        let source_id = key.device_id();
+        let entries: Vec<_> = (0..3)
+            .map(|i| file_entry(&format!("sync/{i}.bin"), "hash"))
+            .collect();
+
+        let sent = entries.clone();
+        let writer = tokio::spawn(async move {
+            let mut stream = connect(addr, &key).await;
+            stream.write_all(source_id.as_bytes()).await.unwrap();
+            stream
+                .write_all(&[TcpStreamKind::Multiplex as u8])
+                .await
+                .unwrap();
+            stream.flush().await.unwrap();
+
+            let mut answer = [0u8; 1];
+            stream.read_exact(&mut answer).await.unwrap();
+            assert_eq!(answer[0], MUX_READY);
+
+            let (conn, _) = MuxConnection::start(stream, source_id, true);
+            for entry in &sent {
+                let mut stream = conn.open().unwrap();
+                write_metadata(&mut stream, source_id, entry).await;
+            }
+            conn
+        });
+
+        let mut received = Vec::new();
+        for _ in 0..entries.len() {
+            let event = recv_event(&adapter).await;
+            assert_eq!(event.metadata.source_id, source_id);
+            match event.payload {
+                TransportData::Metadata(entry) => received.push(entry.name),
+                _ => panic!("expected metadata"),
+            }
+        }
+        let _conn = writer.await.unwrap();
+
+        let expected: Vec<_> = entries.into_iter().map(|e| e.name).collect();
+        assert_eq!(received, expected);
+    }
 }
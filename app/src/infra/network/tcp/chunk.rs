@@ -1,12 +1,86 @@
+use std::time::Duration;
+
 pub(super) const TRANSFER_CHUNK_SIZE: usize = 1024 * 1024;
 pub(super) const MAX_TRANSFER_SIZE: u64 = 16 * 1024 * 1024 * 1024;
 
+/// Bytes read from the start of a transfer to decide whether it is
+/// worth compressing.
+pub(super) const COMPRESSION_SAMPLE_SIZE: usize = 64 * 1024;
+
+/// A sample that compresses to more than this share of its size, in
+/// percent, is sent raw.
+pub(super) const MAX_COMPRESSED_PERCENT: usize = 90;
+
+/// zstd level used for `Transfer` payloads; fast enough to keep up
+/// with a LAN link on modest hardware.
+pub(super) const ZSTD_LEVEL: i32 = 3;
+
+/// Block size used when offering a file as a `DeltaTransfer`. Files no
+/// larger than one block are always sent whole.
+pub(super) const DELTA_BLOCK_SIZE: usize = 128 * 1024;
+
+/// Smallest block size accepted from a peer's delta manifest. Together
+/// with `MAX_TRANSFER_SIZE` it caps the manifest at 8 MiB of digests.
+pub(super) const MIN_DELTA_BLOCK_SIZE: usize = 64 * 1024;
+
 /// Upper bound on a handshake JSON payload length advertised by a peer.
-/// Handshakes carry the full entry map, so the cap is generous, but a
-/// `u32` length field with no ceiling lets any LAN host force a ~4 GiB
-/// allocation per connection.
+/// Inline handshakes carry the full entry map, so the cap is generous,
+/// but a `u32` length field with no ceiling lets any LAN host force a
+/// ~4 GiB allocation per connection. Trees whose map does not fit are
+/// only synced with peers that take it in pages.
 pub(super) const MAX_HANDSHAKE_JSON_SIZE: usize = 8 * 1024 * 1024;
 
+/// Upper bound on one page of a paged handshake's entry map. Always
+/// fits at least one `MAX_ENTRY_JSON_SIZE` entry.
+pub(super) const MAX_HANDSHAKE_PAGE_SIZE: usize = 1024 * 1024;
+
+/// Pages of an inbound entry map read ahead of the application. Once
+/// they are buffered, the sender waits until the oldest is processed.
+pub(super) const HANDSHAKE_PAGE_BUFFER: usize = 4;
+
+/// How long a paged handshake may go without delivering its next page
+/// before the stream is dropped.
+pub(super) const HANDSHAKE_PAGE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How long a control stream (a handshake, metadata or a request) may
+/// take to deliver its whole message before it is dropped.
+pub(super) const CONTROL_READ_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How long a transfer may go without delivering a byte before it is
+/// dropped. The transfer as a whole may take as long as it needs.
+pub(super) const TRANSFER_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Most directories one descent step may ask the sender to summarize,
+/// so a reply stays well under `MAX_HANDSHAKE_JSON_SIZE`.
+pub(super) const MAX_DESCENT_DIRS: usize = 64;
+
+/// Most scopes a descent may end up asking for. Past this the receiver
+/// asks for the whole map instead, which is cheaper than naming every
+/// differing directory.
+pub(super) const MAX_DESCENT_SCOPES: usize = 4096;
+
 /// Upper bound on a single `EntryInfo` JSON payload length advertised
 /// by a peer. One entry should be well under 64 KiB.
 pub(super) const MAX_ENTRY_JSON_SIZE: usize = 64 * 1024;
+
+/// How long an accepted connection may take to finish the Noise or
+/// QUIC handshake, and a stream may take to name its sender and kind, so a
+/// host that connects and stays silent does not hold a task forever.
+pub const SECURE_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long an interrupted inbound transfer is kept for resuming.
+/// Older partials are removed on startup.
+pub(super) const PARTIAL_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Bytes a multiplexed stream may have in flight before its reader
+/// consumes them. Caps what one stream can buffer on the receiving
+/// side and keeps a bulk transfer from crowding out the rest.
+pub(super) const MUX_WINDOW: usize = 256 * 1024;
+
+/// Largest payload of one multiplexed frame. Streams take turns on the
+/// connection at this granularity.
+pub(super) const MUX_MAX_FRAME: usize = 16 * 1024;
+
+/// Most streams a peer may keep open on one multiplexed connection.
+/// Streams opened past this are reset straight away.
+pub(super) const MUX_MAX_STREAMS: usize = 256;
@@ -0,0 +1,141 @@
+use crate::{
+    application::network::transport::interface::{TransportError, TransportResult},
+    domain::RelativePath,
+    infra::network::tcp::chunk::{COMPRESSION_SAMPLE_SIZE, MAX_COMPRESSED_PERCENT, ZSTD_LEVEL},
+};
+use serde::{Deserialize, Serialize};
+use std::io::SeekFrom;
+use tokio::io::{self, AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt};
+
+/// Extensions of formats that are compressed already. Compressing them
+/// again costs CPU and saves next to nothing.
+const COMPRESSED_EXTENSIONS: &[&str] = &[
+    "7z", "apk", "avi", "avif", "br", "bz2", "docx", "flac", "gif", "gz", "heic", "jar", "jpeg",
+    "jpg", "lz4", "m4a", "mkv", "mov", "mp3", "mp4", "ogg", "opus", "png", "pptx", "rar", "tgz",
+    "webm", "webp", "woff2", "xlsx", "xz", "zip", "zst",
+];
+
+/// Encoding of a `Transfer` payload, named in the frame's JSON.
+///
+/// A compressed payload is a sequence of chunks, each written as the
+/// raw length (u32), the frame length (u32) and one zstd frame that
+/// decompresses to exactly the raw length. Only the raw bytes count
+/// towards the advertised entry size and the SHA-256.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(super) enum Compression {
+    Zstd,
+}
+
+/// Picks the encoding for a transfer of `name` starting at the current
+/// position of `file`. Files with a compressed format's extension are
+/// sent raw, as is anything whose first `COMPRESSION_SAMPLE_SIZE` bytes
+/// do not shrink below `MAX_COMPRESSED_PERCENT`. Leaves `file` where it
+/// was.
+pub(super) async fn choose<R>(name: &RelativePath, file: &mut R) -> io::Result<Option<Compression>>
+where
+    R: AsyncRead + AsyncSeek + Unpin,
+{
+    if has_compressed_extension(name) {
+        return Ok(None);
+    }
+
+    let start = file.stream_position().await?;
+    let mut sample = Vec::with_capacity(COMPRESSION_SAMPLE_SIZE);
+    (&mut *file)
+        .take(COMPRESSION_SAMPLE_SIZE as u64)
+        .read_to_end(&mut sample)
+        .await?;
+    file.seek(SeekFrom::Start(start)).await?;
+
+    Ok(compresses_well(&sample)?.then_some(Compression::Zstd))
+}
+
+fn has_compressed_extension(name: &RelativePath) -> bool {
+    let file_name = name.rsplit('/').next().unwrap_or_default();
+    match file_name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => COMPRESSED_EXTENSIONS
+            .iter()
+            .any(|known| known.eq_ignore_ascii_case(ext)),
+        _ => false,
+    }
+}
+
+fn compresses_well(sample: &[u8]) -> io::Result<bool> {
+    if sample.is_empty() {
+        return Ok(false);
+    }
+    let compressed = compress_chunk(sample)?;
+    Ok(compressed.len() * 100 <= sample.len() * MAX_COMPRESSED_PERCENT)
+}
+
+pub(super) fn compress_chunk(raw: &[u8]) -> io::Result<Vec<u8>> {
+    zstd::bulk::compress(raw, ZSTD_LEVEL)
+}
+
+/// Largest frame a well-behaved sender produces for `raw_len` bytes.
+pub(super) fn max_frame_len(raw_len: usize) -> usize {
+    zstd::zstd_safe::compress_bound(raw_len)
+}
+
+/// Decompresses one frame that must hold exactly `raw_len` bytes.
+pub(super) fn decompress_chunk(frame: &[u8], raw_len: usize) -> TransportResult<Vec<u8>> {
+    let raw = zstd::bulk::decompress(frame, raw_len)
+        .map_err(|err| TransportError::new(&format!("Invalid zstd frame: {err}")))?;
+    if raw.len() != raw_len {
+        return Err(TransportError::new(&format!(
+            "zstd frame holds {} bytes, expected {raw_len}",
+            raw.len()
+        )));
+    }
+    Ok(raw)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[tokio::test]
+    async fn choose_compresses_text_and_leaves_the_file_in_place() {
+        let text = "fn main() { println!(\"hello\"); }\n".repeat(2000);
+        let mut file = Cursor::new(text.into_bytes());
+        file.set_position(10);
+
+        let chosen = choose(&"sync/src/main.rs".into(), &mut file).await.unwrap();
+
+        assert_eq!(chosen, Some(Compression::Zstd));
+        assert_eq!(file.position(), 10);
+    }
+
+    #[tokio::test]
+    async fn choose_skips_compressed_formats_and_incompressible_samples() {
+        let text = "a".repeat(4096).into_bytes();
+        let mut seed = 0x9e37_79b9_7f4a_7c15u64;
+        let noise: Vec<u8> = (0..4096)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+                (seed >> 56) as u8
+            })
+            .collect();
+
+        let archive = choose(&"sync/backup.TAR.GZ".into(), &mut Cursor::new(text)).await;
+        let random = choose(&"sync/blob.bin".into(), &mut Cursor::new(noise)).await;
+        let empty = choose(&"sync/empty.txt".into(), &mut Cursor::new(Vec::new())).await;
+
+        assert_eq!(archive.unwrap(), None);
+        assert_eq!(random.unwrap(), None);
+        assert_eq!(empty.unwrap(), None);
+    }
+
+    #[test]
+    fn decompress_chunk_rejects_frames_of_the_wrong_size() {
+        let frame = compress_chunk(&[7u8; 100]).unwrap();
+
+        assert!(decompress_chunk(&frame, 100).is_ok());
+        assert!(decompress_chunk(&frame, 99).is_err());
+        assert!(decompress_chunk(&frame, 200).is_err());
+    }
+}
@@ -0,0 +1,252 @@
+use crate::{
+    application::network::transport::interface::{TransportError, TransportResult},
+    infra::network::tcp::chunk::{MIN_DELTA_BLOCK_SIZE, TRANSFER_CHUNK_SIZE},
+};
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, io::SeekFrom, path::Path};
+use tokio::{
+    fs::File,
+    io::{AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWrite, AsyncWriteExt},
+};
+
+pub(super) type BlockDigest = [u8; 32];
+
+/// Per-block SHA-256 digests of a file split into fixed-size blocks.
+///
+/// Sent by the holder at the start of a `DeltaTransfer` so the
+/// receiver can answer with the blocks its local copy lacks. Every
+/// block is `block_size` bytes except the last, which holds the
+/// remainder of `entry_size`.
+pub(super) struct BlockManifest {
+    pub entry_size: u64,
+    pub block_size: usize,
+    pub digests: Vec<BlockDigest>,
+}
+
+impl BlockManifest {
+    pub fn block_len(&self, index: usize) -> usize {
+        let start = index as u64 * self.block_size as u64;
+        (self.entry_size - start).min(self.block_size as u64) as usize
+    }
+
+    pub async fn write<W: AsyncWrite + Unpin>(&self, writer: &mut W) -> TransportResult<()> {
+        writer
+            .write_all(&(self.block_size as u32).to_be_bytes())
+            .await?;
+        writer
+            .write_all(&(self.digests.len() as u32).to_be_bytes())
+            .await?;
+        for digest in &self.digests {
+            writer.write_all(digest).await?;
+        }
+        Ok(())
+    }
+
+    /// Reads the manifest that follows `entry_size` on the wire. The
+    /// block size is bounded on both sides and the block count must
+    /// cover `entry_size` exactly, so a peer cannot make us allocate
+    /// more digests than the transfer size cap allows.
+    pub async fn read<R: AsyncRead + Unpin>(
+        reader: &mut R,
+        entry_size: u64,
+    ) -> TransportResult<Self> {
+        let mut u32_buf = [0u8; 4];
+        reader.read_exact(&mut u32_buf).await?;
+        let block_size = u32::from_be_bytes(u32_buf) as usize;
+
+        if !(MIN_DELTA_BLOCK_SIZE..=TRANSFER_CHUNK_SIZE).contains(&block_size) {
+            return Err(TransportError::new(&format!(
+                "Delta block size {block_size} outside {MIN_DELTA_BLOCK_SIZE}..={TRANSFER_CHUNK_SIZE}",
+            )));
+        }
+
+        reader.read_exact(&mut u32_buf).await?;
+        let block_count = u32::from_be_bytes(u32_buf) as u64;
+
+        if block_count != entry_size.div_ceil(block_size as u64) {
+            return Err(TransportError::new(&format!(
+                "Delta block count {block_count} does not cover entry_size {entry_size}",
+            )));
+        }
+
+        let mut digests = vec![[0u8; 32]; block_count as usize];
+        for digest in &mut digests {
+            reader.read_exact(digest).await?;
+        }
+
+        Ok(Self {
+            entry_size,
+            block_size,
+            digests,
+        })
+    }
+}
+
+/// Reads exactly `total` bytes from `reader` in `block_size` blocks,
+/// returning each block's digest and the hex-encoded SHA-256 of the
+/// whole stream. A reader that ends early is zero-padded, as in
+/// `TcpSender::stream_file_to`, so the digests still cover `total`.
+pub(super) async fn hash_blocks<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    total: u64,
+    block_size: usize,
+) -> TransportResult<(Vec<BlockDigest>, String)> {
+    let mut digests = Vec::with_capacity(total.div_ceil(block_size as u64) as usize);
+    let mut file_hasher = Sha256::new();
+    let mut buf = vec![0u8; block_size];
+    let mut remaining = total;
+
+    while remaining > 0 {
+        let want = remaining.min(block_size as u64) as usize;
+        read_padded(reader, &mut buf[..want]).await?;
+        file_hasher.update(&buf[..want]);
+        digests.push(Sha256::digest(&buf[..want]).into());
+        remaining -= want as u64;
+    }
+
+    Ok((digests, format!("{:x}", file_hasher.finalize())))
+}
+
+/// Fills `buf` from `reader`, zero-padding whatever the reader could
+/// not supply.
+pub(super) async fn read_padded<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    buf: &mut [u8],
+) -> TransportResult<()> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]).await? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    buf[filled..].fill(0);
+    Ok(())
+}
+
+/// Packs one bit per block, least significant bit first.
+pub(super) fn encode_needed(needed: &[bool]) -> Vec<u8> {
+    let mut bitmap = vec![0u8; needed.len().div_ceil(8)];
+    for (i, _) in needed.iter().enumerate().filter(|(_, n)| **n) {
+        bitmap[i / 8] |= 1 << (i % 8);
+    }
+    bitmap
+}
+
+pub(super) fn decode_needed(bitmap: &[u8], block_count: usize) -> Vec<bool> {
+    (0..block_count)
+        .map(|i| bitmap[i / 8] & (1 << (i % 8)) != 0)
+        .collect()
+}
+
+/// The receiver's existing copy of an entry, indexed by block digest
+/// so matching blocks can be copied instead of sent. A missing or
+/// unreadable file indexes to nothing and every block is requested.
+pub(super) struct LocalBlocks {
+    file: Option<File>,
+    offsets: HashMap<BlockDigest, u64>,
+}
+
+impl LocalBlocks {
+    pub async fn index(path: &Path, block_size: usize) -> Self {
+        let empty = Self {
+            file: None,
+            offsets: HashMap::new(),
+        };
+
+        let Ok(mut file) = File::open(path).await else {
+            return empty;
+        };
+        let Ok(size) = file.metadata().await.map(|m| m.len()) else {
+            return empty;
+        };
+        let Ok((digests, _)) = hash_blocks(&mut file, size, block_size).await else {
+            return empty;
+        };
+
+        let mut offsets = HashMap::with_capacity(digests.len());
+        for (i, digest) in digests.into_iter().enumerate() {
+            offsets
+                .entry(digest)
+                .or_insert(i as u64 * block_size as u64);
+        }
+
+        Self {
+            file: Some(file),
+            offsets,
+        }
+    }
+
+    pub fn contains(&self, digest: &BlockDigest) -> bool {
+        self.offsets.contains_key(digest)
+    }
+
+    /// Copies the local block with `digest` into `buf`. Zero-fills if
+    /// the block is gone or the file changed since it was indexed; the
+    /// final whole-file hash check then rejects the transfer.
+    pub async fn read(&mut self, digest: &BlockDigest, buf: &mut [u8]) -> TransportResult<()> {
+        match (self.file.as_mut(), self.offsets.get(digest)) {
+            (Some(file), Some(offset)) => {
+                file.seek(SeekFrom::Start(*offset)).await?;
+                read_padded(file, buf).await
+            }
+            _ => {
+                buf.fill(0);
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn ok<T>(res: TransportResult<T>) -> T {
+        match res {
+            Ok(v) => v,
+            Err(TransportError::Failure(m)) => panic!("{m}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn hash_blocks_splits_on_block_size_and_hashes_whole_stream() {
+        let payload: Vec<u8> = (0..40u32).map(|i| i as u8).collect();
+
+        let (digests, hash) = ok(hash_blocks(&mut Cursor::new(&payload), 40, 16).await);
+
+        assert_eq!(digests.len(), 3);
+        assert_eq!(
+            digests[2],
+            <BlockDigest>::from(Sha256::digest(&payload[32..]))
+        );
+        assert_eq!(hash, format!("{:x}", Sha256::digest(&payload)));
+    }
+
+    #[test]
+    fn needed_bitmap_round_trips() {
+        let needed = vec![true, false, false, true, false, false, false, false, true];
+
+        let bitmap = encode_needed(&needed);
+
+        assert_eq!(bitmap, vec![0b0000_1001, 0b0000_0001]);
+        assert_eq!(decode_needed(&bitmap, needed.len()), needed);
+    }
+
+    #[tokio::test]
+    async fn manifest_read_rejects_block_count_that_does_not_cover_size() {
+        let manifest = BlockManifest {
+            entry_size: 3 * MIN_DELTA_BLOCK_SIZE as u64,
+            block_size: MIN_DELTA_BLOCK_SIZE,
+            digests: vec![[0u8; 32]; 2],
+        };
+        let mut wire = Vec::new();
+        ok(manifest.write(&mut wire).await);
+
+        match BlockManifest::read(&mut Cursor::new(wire), manifest.entry_size).await {
+            Err(TransportError::Failure(m)) => assert!(m.contains("block count"), "{m}"),
+            Ok(_) => panic!("expected block count rejection"),
+        }
+    }
+}
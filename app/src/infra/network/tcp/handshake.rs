@@ -0,0 +1,131 @@
+use crate::{
+    application::network::transport::interface::{TransportError, TransportResult},
+    domain::{
+        EntryInfo, EntryScope, HandshakeData, HandshakeEntries, ProtocolInfo, RelativePath,
+        SyncDirectory,
+    },
+    infra::network::tcp::chunk::{HANDSHAKE_PAGE_TIMEOUT, MAX_HANDSHAKE_JSON_SIZE},
+};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use std::collections::HashMap;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    time::timeout,
+};
+use uuid::Uuid;
+
+/// Handshake JSON without the entry map.
+///
+/// A `PagedHandshakeSyn` / `PagedHandshakeAck` frame carries only this
+/// header; the entry map follows as pages once the receiver asks for
+/// it.
+#[derive(Serialize, Deserialize)]
+pub(super) struct HandshakeHeader {
+    pub hostname: String,
+    pub instance_id: Uuid,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transport_port: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cluster_proof: Option<String>,
+    pub sync_dirs: Vec<SyncDirectory>,
+    #[serde(default)]
+    pub protocol: ProtocolInfo,
+}
+
+/// Handshake JSON as every build reads it: the header and the whole
+/// entry map in one payload. Only sent to peers that cannot take
+/// pages.
+#[derive(Serialize, Deserialize)]
+pub(super) struct InlineHandshake {
+    #[serde(flatten)]
+    pub header: HandshakeHeader,
+    pub entries: HashMap<RelativePath, EntryInfo>,
+}
+
+impl HandshakeHeader {
+    /// Splits `data` into the header and its entry map.
+    pub fn split(data: HandshakeData) -> (Self, HandshakeEntries) {
+        let header = Self {
+            hostname: data.hostname,
+            instance_id: data.instance_id,
+            transport_port: data.transport_port,
+            cluster_proof: data.cluster_proof,
+            sync_dirs: data.sync_dirs,
+            protocol: data.protocol,
+        };
+        (header, data.entries)
+    }
+
+    pub fn with_entries(self, entries: HandshakeEntries) -> HandshakeData {
+        HandshakeData {
+            hostname: self.hostname,
+            instance_id: self.instance_id,
+            transport_port: self.transport_port,
+            cluster_proof: self.cluster_proof,
+            sync_dirs: self.sync_dirs,
+            entries,
+            protocol: self.protocol,
+        }
+    }
+}
+
+/// Byte the receiver of a paged handshake header answers with when it
+/// wants the entry pages. Any other byte means it does not — a device
+/// that is not approved yet has no use for our entry map.
+pub(super) const SEND_PAGES: u8 = 1;
+
+/// Byte the receiver answers with instead of `SEND_PAGES` when the
+/// sender advertised `SubtreeDigests`: it first descends the sender's
+/// digest tree through `DescentRequest`s, then asks for only the pages
+/// it still needs.
+pub(super) const DESCEND: u8 = 2;
+
+/// One step of a receiver's descent through the sender's digest tree.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(super) enum DescentRequest {
+    /// Asks for the `DirSummary` of each directory, answered in the
+    /// same order with `null` for one the sender holds nothing in.
+    Expand(Vec<RelativePath>),
+    /// Ends the descent. The sender pages through the entries in these
+    /// scopes and nothing else.
+    Fetch(Vec<EntryScope>),
+}
+
+/// Writes one length-prefixed JSON step of a descent.
+pub(super) async fn write_descent_json<W: AsyncWrite + Unpin, T: Serialize>(
+    writer: &mut W,
+    value: &T,
+) -> TransportResult<()> {
+    let json = serde_json::to_vec(value)?;
+    writer.write_all(&(json.len() as u32).to_be_bytes()).await?;
+    writer.write_all(&json).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+/// Reads one length-prefixed JSON step of a descent, giving up after
+/// `HANDSHAKE_PAGE_TIMEOUT`.
+pub(super) async fn read_descent_json<R: AsyncRead + Unpin, T: DeserializeOwned>(
+    reader: &mut R,
+) -> TransportResult<T> {
+    let read = async {
+        let mut len_buf = [0u8; 4];
+        reader.read_exact(&mut len_buf).await?;
+        let len = u32::from_be_bytes(len_buf) as usize;
+
+        if len > MAX_HANDSHAKE_JSON_SIZE {
+            return Err(TransportError::new(&format!(
+                "Descent JSON size {len} exceeds MAX_HANDSHAKE_JSON_SIZE {MAX_HANDSHAKE_JSON_SIZE}",
+            )));
+        }
+
+        let mut buf = vec![0u8; len];
+        reader.read_exact(&mut buf).await?;
+        Ok(serde_json::from_slice(&buf)?)
+    };
+
+    timeout(HANDSHAKE_PAGE_TIMEOUT, read)
+        .await
+        .map_err(|_| TransportError::new("Timed out waiting for the next descent step"))?
+}
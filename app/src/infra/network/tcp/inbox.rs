@@ -0,0 +1,338 @@
+use crate::{
+    application::AppState,
+    application::network::transport::interface::{TransportError, TransportResult},
+    domain::{MutexChannel, TransportEvent, TransportMetadata},
+    infra::network::tcp::{
+        chunk::{CONTROL_READ_TIMEOUT, SECURE_HANDSHAKE_TIMEOUT, TRANSFER_IDLE_TIMEOUT},
+        kind::TcpStreamKind,
+        receiver::TcpReceiver,
+        resume::PartialStore,
+        sender::TcpSender,
+        stream::{IdleTimeout, PeerStream, StreamOpener},
+    },
+};
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt},
+    sync::mpsc,
+    task::JoinHandle,
+    time::timeout,
+};
+use tracing::warn;
+use uuid::Uuid;
+
+/// Inbound messages read ahead of the application, per lane and in
+/// total.
+const LANE_BUFFER: usize = 64;
+
+/// How long a lane may sit empty before its task ends. The next stream
+/// for it starts a new one.
+const LANE_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Reads inbound streams into `TransportEvent`s for a transport
+/// adapter.
+///
+/// Each approved device gets two lanes, one for bulk transfers and one
+/// for everything else, so a large file never holds up a metadata
+/// update or a handshake, and a slow device never holds up another.
+/// Handshakes from devices the user has not approved share one lane.
+/// Each lane reads its streams in the order they were handed to it, on
+/// a task of its own. A stream that fails to parse, or stalls, is
+/// logged and dropped without stopping its lane.
+pub struct Inbox {
+    lanes: Lanes,
+    events: MutexChannel<TransportEvent>,
+}
+
+/// Sending side of an `Inbox`'s lanes, cheap to clone into the tasks
+/// that accept streams.
+#[derive(Clone)]
+pub struct Lanes {
+    state: Arc<AppState>,
+    receiver: Arc<TcpReceiver>,
+    events: mpsc::Sender<TransportEvent>,
+    queues: Arc<Mutex<HashMap<LaneKey, Queue>>>,
+}
+
+/// Which of a device's lanes a stream is read on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Lane {
+    Control,
+    Bulk,
+}
+
+/// A lane's device, or `None` for the one shared by unapproved devices,
+/// and which of its lanes it is.
+type LaneKey = (Option<Uuid>, Lane);
+
+/// The streams waiting on one lane, and the task reading them.
+struct Queue {
+    streams: mpsc::Sender<Inbound>,
+    reader: JoinHandle<()>,
+}
+
+/// An inbound stream whose sender and kind are known.
+struct Inbound {
+    stream: PeerStream,
+    kind: TcpStreamKind,
+    source_ip: IpAddr,
+}
+
+/// Both halves of the wire format for a transport adapter: a
+/// `TcpSender` writing to the streams `opener` hands out, and an
+/// `Inbox` reading the streams the adapter accepts. They share the
+/// `PartialStore`, so requests resume the transfers the inbox kept.
+pub async fn wire_format(
+    state: Arc<AppState>,
+    opener: Arc<dyn StreamOpener>,
+) -> (TcpSender, Inbox) {
+    let _ = tokio::fs::remove_dir_all(state.dirs().legacy_partials_dir()).await;
+    let partials = PartialStore::in_home(state.home_path()).await.unwrap();
+    let partials = Arc::new(partials);
+
+    let receiver = Arc::new(TcpReceiver::new(state.clone(), partials.clone()));
+    let sender = TcpSender::new(state.clone(), partials, opener);
+    (sender, Inbox::new(state, receiver))
+}
+
+impl Inbox {
+    fn new(state: Arc<AppState>, receiver: Arc<TcpReceiver>) -> Self {
+        let events = MutexChannel::new(LANE_BUFFER);
+
+        Self {
+            lanes: Lanes {
+                state,
+                receiver,
+                events: events.tx.clone(),
+                queues: Default::default(),
+            },
+            events,
+        }
+    }
+
+    pub fn lanes(&self) -> Lanes {
+        self.lanes.clone()
+    }
+
+    pub fn events(&self) -> &MutexChannel<TransportEvent> {
+        &self.events
+    }
+}
+
+impl Drop for Inbox {
+    fn drop(&mut self) {
+        for queue in self.lanes.queues.lock().unwrap().values() {
+            queue.reader.abort();
+        }
+    }
+}
+
+impl Lanes {
+    /// Hands a stream whose header has been read to its lane. Devices
+    /// the user has not approved may only send handshakes; any other
+    /// stream from them is rejected before its payload is read.
+    pub async fn route(
+        &self,
+        stream: PeerStream,
+        kind: TcpStreamKind,
+        source_ip: IpAddr,
+    ) -> TransportResult<()> {
+        let source_id = stream.remote_id();
+        let trusted = self.state.is_trusted_device(&source_id).await;
+        if !kind.is_handshake() && !trusted {
+            return Err(TransportError::new(&format!(
+                "{kind} from unapproved device {source_id}"
+            )));
+        }
+
+        let lane = match kind {
+            TcpStreamKind::Transfer | TcpStreamKind::DeltaTransfer => Lane::Bulk,
+            _ => Lane::Control,
+        };
+        let key = (trusted.then_some(source_id), lane);
+        let mut inbound = Inbound {
+            stream,
+            kind,
+            source_ip,
+        };
+
+        // A lane whose task ended while idle refuses the stream; it is
+        // then handed to a new one.
+        loop {
+            match self.queue(key).send(inbound).await {
+                Ok(()) => return Ok(()),
+                Err(mpsc::error::SendError(refused)) => inbound = refused,
+            }
+        }
+    }
+
+    /// The sending side of the lane for `key`, starting its task if it
+    /// has none.
+    fn queue(&self, key: LaneKey) -> mpsc::Sender<Inbound> {
+        let mut queues = self.queues.lock().unwrap();
+        queues.retain(|_, queue| !queue.streams.is_closed());
+
+        let queue = queues.entry(key).or_insert_with(|| {
+            let (streams, streams_rx) = mpsc::channel(LANE_BUFFER);
+            let reader = tokio::spawn(Self::read_lane(
+                self.receiver.clone(),
+                key.1,
+                streams_rx,
+                self.events.clone(),
+            ));
+            Queue { streams, reader }
+        });
+        queue.streams.clone()
+    }
+
+    async fn read_lane(
+        receiver: Arc<TcpReceiver>,
+        lane: Lane,
+        mut streams: mpsc::Receiver<Inbound>,
+        events: mpsc::Sender<TransportEvent>,
+    ) {
+        loop {
+            let inbound = match timeout(LANE_IDLE_TIMEOUT, streams.recv()).await {
+                Ok(Some(inbound)) => inbound,
+                Ok(None) => return,
+                // Refuse new streams, but read those already queued.
+                Err(_) => {
+                    streams.close();
+                    continue;
+                }
+            };
+            let source_ip = inbound.source_ip;
+            let source_id = inbound.stream.remote_id();
+
+            let read = match lane {
+                Lane::Control => timeout(
+                    CONTROL_READ_TIMEOUT,
+                    receiver.read_data(inbound.stream, inbound.kind, source_id),
+                )
+                .await
+                .unwrap_or_else(|_| Err(TransportError::new("Timed out reading the message"))),
+                Lane::Bulk => {
+                    let stream = IdleTimeout::new(inbound.stream, TRANSFER_IDLE_TIMEOUT);
+                    receiver.read_data(stream, inbound.kind, source_id).await
+                }
+            };
+
+            match read {
+                Ok(payload) => {
+                    let event = TransportEvent {
+                        metadata: TransportMetadata {
+                            source_id,
+                            source_ip,
+                        },
+                        payload,
+                    };
+                    if events.send(event).await.is_err() {
+                        return;
+                    }
+                }
+                Err(TransportError::Failure(message)) => {
+                    warn!(peer = ?source_ip, "Ignoring invalid transport message: {message}");
+                }
+            }
+        }
+    }
+}
+
+/// Reads the device id and kind every stream starts with. The id must
+/// be the one the transport's own handshake proved.
+pub async fn read_stream_header<S: AsyncRead + Unpin>(
+    stream: &mut S,
+    source_id: Uuid,
+) -> TransportResult<TcpStreamKind> {
+    let read = async {
+        let mut claimed_id_buf = [0u8; 16];
+        stream.read_exact(&mut claimed_id_buf).await?;
+        if Uuid::from_bytes(claimed_id_buf) != source_id {
+            return Err(TransportError::new(
+                "Claimed source id does not match the authenticated device key",
+            ));
+        }
+
+        let mut kind_buf = [0u8; 1];
+        stream.read_exact(&mut kind_buf).await?;
+        TcpStreamKind::try_from(kind_buf[0])
+    };
+
+    timeout(SECURE_HANDSHAKE_TIMEOUT, read)
+        .await
+        .map_err(|_| TransportError::new("Timed out waiting for the stream header"))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        application::PeerManager,
+        domain::{EntryInfo, EntryKind, TransportData},
+        utils::test_support::test_env,
+    };
+    use tokio::io::{AsyncWriteExt, duplex};
+
+    #[tokio::test]
+    async fn a_stalled_device_does_not_hold_up_another() {
+        let env = test_env().await;
+        let (stalled_id, live_id) = (Uuid::new_v4(), Uuid::new_v4());
+        PeerManager::new(env.state.clone())
+            .load_trusted(vec![stalled_id, live_id])
+            .await;
+
+        let partials = PartialStore::in_home(env.home_path()).await.unwrap();
+        let receiver = TcpReceiver::new(env.state.clone(), Arc::new(partials));
+        let inbox = Inbox::new(env.state.clone(), Arc::new(receiver));
+        let lanes = inbox.lanes();
+        let source_ip = IpAddr::from([127, 0, 0, 1]);
+
+        // Stays open without a byte written.
+        let (_stalled, stream) = duplex(1024);
+        let routed = lanes
+            .route(
+                PeerStream::new(stream, stalled_id),
+                TcpStreamKind::Metadata,
+                source_ip,
+            )
+            .await;
+        assert!(routed.is_ok());
+
+        let entry = EntryInfo {
+            name: "Docs/a.txt".into(),
+            kind: EntryKind::File,
+            hash: Some("hash".to_string()),
+            version: HashMap::from([(live_id, 1)]),
+            modified: None,
+            removed_at: None,
+        };
+        let contents = serde_json::to_vec(&entry).unwrap();
+        let (mut live, stream) = duplex(1024);
+        live.write_all(&(contents.len() as u32).to_be_bytes())
+            .await
+            .unwrap();
+        live.write_all(&contents).await.unwrap();
+        let routed = lanes
+            .route(
+                PeerStream::new(stream, live_id),
+                TcpStreamKind::Metadata,
+                source_ip,
+            )
+            .await;
+        assert!(routed.is_ok());
+
+        let event = timeout(Duration::from_secs(5), inbox.events().recv())
+            .await
+            .expect("the live device's message should not wait on the stalled one")
+            .unwrap();
+        assert_eq!(event.metadata.source_id, live_id);
+        assert!(
+            matches!(event.payload, TransportData::Metadata(received) if received.name == entry.name)
+        );
+    }
+}
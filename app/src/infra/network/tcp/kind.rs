@@ -5,6 +5,15 @@ use crate::{application::network::transport::interface::TransportError, domain::
 ///
 /// The discriminants are part of the wire format — renumbering them
 /// would break compatibility with peers running older builds.
+/// New kinds must be gated behind a `Capability` so they are only sent
+/// to peers that advertised them. `DeltaTransfer` is never derived
+/// from a `TransportData`: the sender picks it for large files when
+/// the peer advertised `Capability::Delta`. The paged handshake kinds
+/// likewise replace `HandshakeSyn` / `HandshakeAck` for peers that
+/// take the entry map in pages. `Multiplex` carries no message: it
+/// turns the connection into a `MuxConnection` whose streams each
+/// start with their own id and kind.
+#[derive(Clone, Copy)]
 #[repr(u8)]
 pub enum TcpStreamKind {
     HandshakeSyn = 1,
@@ -12,6 +21,33 @@ pub enum TcpStreamKind {
     Metadata = 3,
     Request = 4,
     Transfer = 5,
+    DeltaTransfer = 6,
+    PagedHandshakeSyn = 7,
+    PagedHandshakeAck = 8,
+    Multiplex = 9,
+}
+
+impl TcpStreamKind {
+    /// Handshakes are the only frames accepted from devices that are
+    /// not yet approved — they are how a device asks to be paired.
+    pub fn is_handshake(&self) -> bool {
+        matches!(
+            self,
+            Self::HandshakeSyn
+                | Self::HandshakeAck
+                | Self::PagedHandshakeSyn
+                | Self::PagedHandshakeAck
+        )
+    }
+
+    /// The paged form of a handshake kind.
+    pub fn paged(self) -> Self {
+        match self {
+            Self::HandshakeSyn => Self::PagedHandshakeSyn,
+            Self::HandshakeAck => Self::PagedHandshakeAck,
+            other => other,
+        }
+    }
 }
 
 impl TryFrom<u8> for TcpStreamKind {
@@ -24,7 +60,13 @@ impl TryFrom<u8> for TcpStreamKind {
             3 => Ok(Self::Metadata),
             4 => Ok(Self::Request),
             5 => Ok(Self::Transfer),
-            _ => Err(TransportError::new("Invalid Tcp Stream kind")),
+            6 => Ok(Self::DeltaTransfer),
+            7 => Ok(Self::PagedHandshakeSyn),
+            8 => Ok(Self::PagedHandshakeAck),
+            9 => Ok(Self::Multiplex),
+            _ => Err(TransportError::new(&format!(
+                "Unknown Tcp Stream kind {value}; the peer may speak a newer protocol"
+            ))),
         }
     }
 }
@@ -49,6 +91,10 @@ impl std::fmt::Display for TcpStreamKind {
             TcpStreamKind::Metadata => f.write_str("Metadata"),
             TcpStreamKind::Request => f.write_str("Request"),
             TcpStreamKind::Transfer => f.write_str("Transfer"),
+            TcpStreamKind::DeltaTransfer => f.write_str("Delta Transfer"),
+            TcpStreamKind::PagedHandshakeSyn => f.write_str("Paged Handshake SYN"),
+            TcpStreamKind::PagedHandshakeAck => f.write_str("Paged Handshake ACK"),
+            TcpStreamKind::Multiplex => f.write_str("Multiplex"),
         }
     }
 }
@@ -1,7 +1,23 @@
 mod adapter;
 mod chunk;
+mod compress;
+mod delta;
+mod handshake;
+mod inbox;
 mod kind;
+mod mux;
+mod pool;
 mod receiver;
+mod resume;
+mod secure;
 mod sender;
+mod stream;
 
 pub use adapter::TcpAdapter;
+
+// The wire format is shared with the QUIC transport.
+pub(super) use chunk::SECURE_HANDSHAKE_TIMEOUT;
+pub(super) use inbox::{Inbox, Lanes, read_stream_header, wire_format};
+pub(super) use kind::TcpStreamKind;
+pub(super) use sender::TcpSender;
+pub(super) use stream::{PeerStream, StreamOpener};
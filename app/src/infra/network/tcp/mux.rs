@@ -0,0 +1,595 @@
+use crate::infra::network::tcp::chunk::{MUX_MAX_FRAME, MUX_MAX_STREAMS, MUX_WINDOW};
+use std::{
+    collections::{HashMap, VecDeque},
+    io,
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    task::{Context, Poll, Waker},
+};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf, ReadHalf, WriteHalf},
+    sync::{mpsc, watch},
+};
+use tracing::trace;
+use uuid::Uuid;
+
+/// Opens a stream. The id is chosen by the side that opens it: odd on
+/// the connection's dialer, even on its acceptor, so ids never clash.
+const OPEN: u8 = 0;
+/// Stream bytes, at most `MUX_MAX_FRAME` per frame.
+const DATA: u8 = 1;
+/// A `u32` of credit: how many more bytes the sender may write before
+/// its peer has read them.
+const WINDOW: u8 = 2;
+/// The sender will write nothing more on the stream.
+const CLOSE: u8 = 3;
+/// The stream is gone on the sender's side; anything sent to it is
+/// dropped.
+const RESET: u8 = 4;
+
+/// Header of every frame: stream id, frame kind and payload length.
+const FRAME_HEADER_LEN: usize = 9;
+
+enum Frame {
+    Stream { id: u32, kind: u8, payload: Vec<u8> },
+    Goodbye,
+}
+
+/// Many independent byte streams over one connection to a peer.
+///
+/// Every stream is framed as `stream id | kind | length | payload` and
+/// gets `MUX_WINDOW` bytes of credit, replenished as its reader
+/// consumes them, so a bulk transfer can never fill the connection
+/// ahead of a small control message. A writer task sends frames in the
+/// order streams queue them and a reader task hands each frame to its
+/// stream; streams the peer opens arrive on the receiver returned by
+/// `start`. Once the underlying connection fails or ends, every stream
+/// on it reads what it already holds and then fails.
+#[derive(Clone)]
+pub(super) struct MuxConnection {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    remote_id: Uuid,
+    dialed: bool,
+    out: mpsc::UnboundedSender<Frame>,
+    closing: watch::Sender<bool>,
+    inner: Mutex<Inner>,
+}
+
+struct Inner {
+    streams: HashMap<u32, StreamState>,
+    next_id: u32,
+    closed: bool,
+    retired: bool,
+}
+
+struct StreamState {
+    received: VecDeque<u8>,
+    unacked: usize,
+    credit: usize,
+    local_closed: bool,
+    remote_closed: bool,
+    reset: bool,
+    read_waker: Option<Waker>,
+    write_waker: Option<Waker>,
+}
+
+impl StreamState {
+    fn new() -> Self {
+        Self {
+            received: VecDeque::new(),
+            unacked: 0,
+            credit: MUX_WINDOW,
+            local_closed: false,
+            remote_closed: false,
+            reset: false,
+            read_waker: None,
+            write_waker: None,
+        }
+    }
+
+    fn wake(&mut self) {
+        if let Some(waker) = self.read_waker.take() {
+            waker.wake();
+        }
+        if let Some(waker) = self.write_waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl MuxConnection {
+    /// Starts multiplexing over `stream`, an authenticated connection
+    /// to `remote_id`. `dialed` tells whether this side opened it.
+    pub fn start<S>(
+        stream: S,
+        remote_id: Uuid,
+        dialed: bool,
+    ) -> (Self, mpsc::UnboundedReceiver<MuxStream>)
+    where
+        S: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        let (reader, writer) = tokio::io::split(stream);
+        let (out, out_rx) = mpsc::unbounded_channel();
+        let (incoming_tx, incoming_rx) = mpsc::unbounded_channel();
+
+        let shared = Arc::new(Shared {
+            remote_id,
+            dialed,
+            out,
+            closing: watch::Sender::new(false),
+            inner: Mutex::new(Inner {
+                streams: HashMap::new(),
+                next_id: if dialed { 1 } else { 2 },
+                closed: false,
+                retired: false,
+            }),
+        });
+
+        tokio::spawn(write_frames(writer, out_rx, shared.clone()));
+        tokio::spawn(read_frames(reader, shared.clone(), incoming_tx));
+
+        (Self { shared }, incoming_rx)
+    }
+
+    pub fn remote_id(&self) -> Uuid {
+        self.shared.remote_id
+    }
+
+    pub fn dialed(&self) -> bool {
+        self.shared.dialed
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.shared.lock().closed
+    }
+
+    /// Opens a new stream to the peer. Fails once the connection is
+    /// closed or retired.
+    pub fn open(&self) -> io::Result<MuxStream> {
+        let mut inner = self.shared.lock();
+        if inner.closed || inner.retired {
+            return Err(io::Error::new(
+                io::ErrorKind::NotConnected,
+                "Multiplexed connection is closed",
+            ));
+        }
+
+        let id = inner.next_id;
+        inner.next_id = inner.next_id.wrapping_add(2);
+        inner.streams.insert(id, StreamState::new());
+        self.shared.send(id, OPEN, Vec::new());
+
+        Ok(MuxStream {
+            shared: self.shared.clone(),
+            id,
+        })
+    }
+
+    /// Stops opening streams on this connection and closes it once the
+    /// streams already on it are done.
+    pub fn retire(&self) {
+        let mut inner = self.shared.lock();
+        inner.retired = true;
+        if inner.streams.is_empty() {
+            self.shared.shut_down(&mut inner);
+        }
+    }
+
+    /// Closes the connection now, failing every stream on it.
+    pub fn close(&self) {
+        self.shared.shut_down(&mut self.shared.lock());
+    }
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn send(&self, id: u32, kind: u8, payload: Vec<u8>) {
+        let _ = self.out.send(Frame::Stream { id, kind, payload });
+    }
+
+    fn shut_down(&self, inner: &mut Inner) {
+        if inner.closed {
+            return;
+        }
+        inner.closed = true;
+        for stream in inner.streams.values_mut() {
+            stream.reset = true;
+            stream.wake();
+        }
+        let _ = self.out.send(Frame::Goodbye);
+        self.closing.send_replace(true);
+    }
+
+    fn owns_remote_id(&self, id: u32) -> bool {
+        // The dialer opens odd ids, so the acceptor's are even.
+        id.is_multiple_of(2) == self.dialed
+    }
+
+    /// Hands one inbound frame to its stream. Errors are protocol
+    /// violations that end the connection.
+    fn dispatch(
+        self: &Arc<Self>,
+        id: u32,
+        kind: u8,
+        payload: Vec<u8>,
+        incoming: &mpsc::UnboundedSender<MuxStream>,
+    ) -> io::Result<()> {
+        let mut inner = self.lock();
+
+        if kind == OPEN {
+            if !self.owns_remote_id(id) || inner.streams.contains_key(&id) {
+                return Err(invalid_data(&format!("peer opened invalid stream {id}")));
+            }
+            if inner.streams.len() >= MUX_MAX_STREAMS {
+                self.send(id, RESET, Vec::new());
+                return Ok(());
+            }
+
+            inner.streams.insert(id, StreamState::new());
+            drop(inner);
+            let _ = incoming.send(MuxStream {
+                shared: self.clone(),
+                id,
+            });
+            return Ok(());
+        }
+
+        let Some(stream) = inner.streams.get_mut(&id) else {
+            // The stream was dropped on our side; tell the peer to stop.
+            if kind == DATA {
+                self.send(id, RESET, Vec::new());
+            }
+            return Ok(());
+        };
+
+        match kind {
+            DATA => {
+                if stream.remote_closed {
+                    return Err(invalid_data(&format!("data after close on stream {id}")));
+                }
+                stream.received.extend(payload);
+                if stream.received.len() > MUX_WINDOW {
+                    return Err(invalid_data(&format!("stream {id} overran its window")));
+                }
+            }
+            WINDOW => {
+                let credit: [u8; 4] = payload
+                    .try_into()
+                    .map_err(|_| invalid_data("malformed window frame"))?;
+                stream.credit += u32::from_be_bytes(credit) as usize;
+            }
+            CLOSE => stream.remote_closed = true,
+            RESET => stream.reset = true,
+            other => return Err(invalid_data(&format!("unknown frame kind {other}"))),
+        }
+        stream.wake();
+        Ok(())
+    }
+}
+
+async fn write_frames<S: AsyncWrite>(
+    mut writer: WriteHalf<S>,
+    mut out: mpsc::UnboundedReceiver<Frame>,
+    shared: Arc<Shared>,
+) {
+    let result: io::Result<()> = async {
+        while let Some(frame) = out.recv().await {
+            let mut next = Some(frame);
+            // Drain whatever is queued before flushing, so a burst of
+            // small frames leaves in as few messages as possible.
+            while let Some(frame) = next {
+                match frame {
+                    Frame::Goodbye => return writer.shutdown().await,
+                    Frame::Stream { id, kind, payload } => {
+                        let mut buf = Vec::with_capacity(FRAME_HEADER_LEN + payload.len());
+                        buf.extend_from_slice(&id.to_be_bytes());
+                        buf.push(kind);
+                        buf.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+                        buf.extend_from_slice(&payload);
+                        writer.write_all(&buf).await?;
+                    }
+                }
+                next = out.try_recv().ok();
+            }
+            writer.flush().await?;
+        }
+        Ok(())
+    }
+    .await;
+
+    if let Err(err) = result {
+        trace!(peer_id = %shared.remote_id, "multiplexed connection write failed: {err}");
+    }
+    shared.shut_down(&mut shared.lock());
+}
+
+async fn read_frames<S: AsyncRead>(
+    mut reader: ReadHalf<S>,
+    shared: Arc<Shared>,
+    incoming: mpsc::UnboundedSender<MuxStream>,
+) {
+    let mut closing = shared.closing.subscribe();
+    let result: io::Result<()> = async {
+        loop {
+            let frame = tokio::select! {
+                frame = read_frame(&mut reader) => frame?,
+                _ = closing.wait_for(|closing| *closing) => None,
+            };
+            let Some((id, kind, payload)) = frame else {
+                return Ok(());
+            };
+            shared.dispatch(id, kind, payload, &incoming)?;
+        }
+    }
+    .await;
+
+    match result {
+        Ok(()) => trace!(peer_id = %shared.remote_id, "multiplexed connection ended"),
+        Err(err) => trace!(peer_id = %shared.remote_id, "multiplexed connection failed: {err}"),
+    }
+    shared.shut_down(&mut shared.lock());
+}
+
+/// Reads the next frame, or `None` once the peer has closed the
+/// connection between frames.
+async fn read_frame<S: AsyncRead>(
+    reader: &mut ReadHalf<S>,
+) -> io::Result<Option<(u32, u8, Vec<u8>)>> {
+    let mut header = [0u8; FRAME_HEADER_LEN];
+    match reader.read_exact(&mut header).await {
+        Ok(_) => {}
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err),
+    }
+
+    let id = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+    let kind = header[4];
+    let len = u32::from_be_bytes([header[5], header[6], header[7], header[8]]) as usize;
+    if len > MUX_MAX_FRAME {
+        return Err(invalid_data(&format!(
+            "frame of {len} bytes exceeds MUX_MAX_FRAME {MUX_MAX_FRAME}"
+        )));
+    }
+
+    let mut payload = vec![0u8; len];
+    reader.read_exact(&mut payload).await?;
+    Ok(Some((id, kind, payload)))
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// One stream of a `MuxConnection`.
+///
+/// Writes go out as soon as the stream has credit, so `flush` has
+/// nothing to do; `shutdown` tells the peer nothing more is coming.
+/// Dropping the stream closes it, and resets it if the peer was still
+/// sending.
+pub(super) struct MuxStream {
+    shared: Arc<Shared>,
+    id: u32,
+}
+
+impl MuxStream {
+    pub fn remote_id(&self) -> Uuid {
+        self.shared.remote_id
+    }
+}
+
+impl AsyncRead for MuxStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let mut inner = self.shared.lock();
+        let Some(stream) = inner.streams.get_mut(&self.id) else {
+            return Poll::Ready(Err(io::ErrorKind::ConnectionReset.into()));
+        };
+
+        if !stream.received.is_empty() {
+            let n = buf.remaining().min(stream.received.len());
+            let (front, back) = stream.received.as_slices();
+            let from_front = n.min(front.len());
+            buf.put_slice(&front[..from_front]);
+            buf.put_slice(&back[..n - from_front]);
+            stream.received.drain(..n);
+
+            stream.unacked += n;
+            if stream.unacked >= MUX_WINDOW / 2 && !stream.remote_closed {
+                let credit = std::mem::take(&mut stream.unacked) as u32;
+                self.shared
+                    .send(self.id, WINDOW, credit.to_be_bytes().to_vec());
+            }
+            return Poll::Ready(Ok(()));
+        }
+
+        if stream.remote_closed {
+            return Poll::Ready(Ok(()));
+        }
+        if stream.reset {
+            return Poll::Ready(Err(io::ErrorKind::ConnectionReset.into()));
+        }
+
+        stream.read_waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl AsyncWrite for MuxStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+
+        let mut inner = self.shared.lock();
+        let Some(stream) = inner.streams.get_mut(&self.id) else {
+            return Poll::Ready(Err(io::ErrorKind::ConnectionReset.into()));
+        };
+        if stream.reset {
+            return Poll::Ready(Err(io::ErrorKind::ConnectionReset.into()));
+        }
+        if stream.local_closed {
+            return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()));
+        }
+        if stream.credit == 0 {
+            stream.write_waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+
+        let n = buf.len().min(stream.credit).min(MUX_MAX_FRAME);
+        stream.credit -= n;
+        self.shared.send(self.id, DATA, buf[..n].to_vec());
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let mut inner = self.shared.lock();
+        if let Some(stream) = inner.streams.get_mut(&self.id)
+            && !stream.local_closed
+            && !stream.reset
+        {
+            stream.local_closed = true;
+            self.shared.send(self.id, CLOSE, Vec::new());
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Drop for MuxStream {
+    fn drop(&mut self) {
+        let mut inner = self.shared.lock();
+        if let Some(stream) = inner.streams.remove(&self.id)
+            && !stream.reset
+            && !inner.closed
+        {
+            if !stream.local_closed {
+                self.shared.send(self.id, CLOSE, Vec::new());
+            }
+            if !stream.remote_closed {
+                self.shared.send(self.id, RESET, Vec::new());
+            }
+        }
+
+        if inner.retired && inner.streams.is_empty() {
+            self.shared.shut_down(&mut inner);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::time::timeout;
+
+    fn pair() -> (
+        (MuxConnection, mpsc::UnboundedReceiver<MuxStream>),
+        (MuxConnection, mpsc::UnboundedReceiver<MuxStream>),
+    ) {
+        let (a, b) = tokio::io::duplex(64 * 1024);
+        (
+            MuxConnection::start(a, Uuid::new_v4(), true),
+            MuxConnection::start(b, Uuid::new_v4(), false),
+        )
+    }
+
+    async fn accept(incoming: &mut mpsc::UnboundedReceiver<MuxStream>) -> MuxStream {
+        timeout(Duration::from_secs(5), incoming.recv())
+            .await
+            .expect("stream should be opened")
+            .expect("connection should be open")
+    }
+
+    #[tokio::test]
+    async fn streams_carry_their_own_bytes_in_both_directions() {
+        let ((dialer, _), (_acceptor, mut incoming)) = pair();
+
+        let mut first = dialer.open().unwrap();
+        let mut second = dialer.open().unwrap();
+        first.write_all(b"first").await.unwrap();
+        second.write_all(b"second").await.unwrap();
+        first.write_all(b" again").await.unwrap();
+        first.shutdown().await.unwrap();
+        second.shutdown().await.unwrap();
+
+        let mut remote_first = accept(&mut incoming).await;
+        let mut remote_second = accept(&mut incoming).await;
+        let mut buf = Vec::new();
+        remote_second.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf, b"second");
+        buf.clear();
+        remote_first.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf, b"first again");
+
+        remote_first.write_all(b"reply").await.unwrap();
+        drop(remote_first);
+        let mut reply = [0u8; 5];
+        first.read_exact(&mut reply).await.unwrap();
+        assert_eq!(&reply, b"reply");
+    }
+
+    /// A stream whose reader stalls stops at one window, and the other
+    /// streams on the connection keep flowing past it.
+    #[tokio::test]
+    async fn a_stalled_stream_does_not_hold_up_the_others() {
+        let ((dialer, _), (_acceptor, mut incoming)) = pair();
+
+        let mut bulk = dialer.open().unwrap();
+        let writer = tokio::spawn(async move {
+            bulk.write_all(&vec![7u8; MUX_WINDOW * 3]).await.unwrap();
+            bulk
+        });
+        let mut remote_bulk = accept(&mut incoming).await;
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!writer.is_finished());
+
+        let mut control = dialer.open().unwrap();
+        control.write_all(b"ping").await.unwrap();
+        let mut remote_control = accept(&mut incoming).await;
+        let mut ping = [0u8; 4];
+        timeout(Duration::from_secs(5), remote_control.read_exact(&mut ping))
+            .await
+            .expect("control stream should not wait behind the bulk one")
+            .unwrap();
+        assert_eq!(&ping, b"ping");
+
+        let mut received = vec![0u8; MUX_WINDOW * 3];
+        remote_bulk.read_exact(&mut received).await.unwrap();
+        assert!(received.iter().all(|b| *b == 7));
+        writer.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn dropping_a_connection_fails_its_streams() {
+        let ((dialer, _), (acceptor, mut incoming)) = pair();
+
+        let mut stream = dialer.open().unwrap();
+        stream.write_all(b"x").await.unwrap();
+        let _remote = accept(&mut incoming).await;
+        acceptor.close();
+
+        let mut buf = [0u8; 1];
+        let read = timeout(Duration::from_secs(5), stream.read(&mut buf))
+            .await
+            .expect("the stream should notice the connection ended");
+        assert!(read.is_err());
+        assert!(dialer.open().is_err());
+    }
+}
@@ -0,0 +1,466 @@
+use crate::{
+    application::AppState,
+    application::network::transport::interface::{TransportError, TransportResult},
+    domain::Capability,
+    infra::network::tcp::{
+        chunk::SECURE_HANDSHAKE_TIMEOUT,
+        kind::TcpStreamKind,
+        mux::{MuxConnection, MuxStream},
+        secure::SecureStream,
+        stream::{PeerStream, StreamOpener},
+    },
+};
+use std::{
+    collections::{HashMap, HashSet},
+    io,
+    net::{IpAddr, SocketAddr},
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    task::{Context, Poll},
+};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf},
+    net::TcpStream,
+    sync::{RwLock, mpsc},
+    time::timeout,
+};
+use tracing::{trace, warn};
+use uuid::Uuid;
+
+/// Byte the acceptor of a `Multiplex` frame answers with once it takes
+/// streams on the connection. Devices that predate multiplexing drop
+/// the connection instead.
+pub(super) const MUX_READY: u8 = 1;
+
+/// Streams a connection's peer opens on it, with the address it came
+/// from.
+pub(super) type InboundStreams = (mpsc::UnboundedReceiver<MuxStream>, IpAddr);
+
+/// A stream to one authenticated device: a stream of the device's
+/// shared connection, or a connection of its own for devices that
+/// predate multiplexing.
+pub(super) enum PooledStream {
+    Mux(MuxStream),
+    Direct(Box<SecureStream<TcpStream>>),
+}
+
+impl PooledStream {
+    pub fn remote_id(&self) -> Uuid {
+        match self {
+            Self::Mux(stream) => stream.remote_id(),
+            Self::Direct(stream) => stream.remote_id(),
+        }
+    }
+}
+
+impl AsyncRead for PooledStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Mux(stream) => Pin::new(stream).poll_read(cx, buf),
+            Self::Direct(stream) => Pin::new(stream.as_mut()).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for PooledStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Self::Mux(stream) => Pin::new(stream).poll_write(cx, buf),
+            Self::Direct(stream) => Pin::new(stream.as_mut()).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Mux(stream) => Pin::new(stream).poll_flush(cx),
+            Self::Direct(stream) => Pin::new(stream.as_mut()).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Mux(stream) => Pin::new(stream).poll_shutdown(cx),
+            Self::Direct(stream) => Pin::new(stream.as_mut()).poll_shutdown(cx),
+        }
+    }
+}
+
+/// One long-lived `MuxConnection` per peer device.
+///
+/// Connections are dialed on first use and shared by every message to
+/// the peer after that, whichever side dialed them. They are kept by
+/// the device id the connection proved rather than by address, since
+/// several devices may share a host, and a target address is matched to
+/// its device through the peers `PeerManager` knows; until a device's
+/// handshake arrives, each message to it dials anew. A connection that
+/// fails is dropped and the next message dials a new one. When both
+/// devices dial each other at once, each keeps the connection dialed by
+/// the lower device id, so they settle on the same one; the other
+/// closes once its streams are done. Devices that drop the `Multiplex`
+/// frame predate it; they are remembered in `no_mux` and get a
+/// connection per message until restart. Connections to a peer
+/// `PeerManager` removes are closed through `evict`.
+pub(super) struct ConnectionPool {
+    state: Arc<AppState>,
+    connections: Mutex<HashMap<Uuid, MuxConnection>>,
+    no_mux: RwLock<HashSet<Uuid>>,
+    inbound: mpsc::UnboundedSender<InboundStreams>,
+}
+
+impl ConnectionPool {
+    /// Builds the pool and the receiver on which it hands over the
+    /// inbound streams of every connection it takes in.
+    pub fn new(state: Arc<AppState>) -> (Self, mpsc::UnboundedReceiver<InboundStreams>) {
+        let (inbound, inbound_rx) = mpsc::unbounded_channel();
+        let pool = Self {
+            state,
+            connections: Mutex::new(HashMap::new()),
+            no_mux: RwLock::new(HashSet::new()),
+            inbound,
+        };
+        (pool, inbound_rx)
+    }
+
+    /// Opens a stream to the device at `target`, dialing it first if
+    /// there is no live connection. Refuses to continue if the device
+    /// answering at `target` is not an approved one, so a stranger that
+    /// takes over a peer's address receives nothing.
+    pub async fn open(&self, target: SocketAddr) -> TransportResult<PooledStream> {
+        let shared = match self.state.peer_at(target).await {
+            Some(id) => self
+                .lock()
+                .get(&id)
+                .filter(|conn| !conn.is_closed())
+                .cloned(),
+            None => None,
+        };
+        if let Some(conn) = shared {
+            self.ensure_trusted(conn.remote_id(), target).await?;
+            if let Ok(stream) = conn.open() {
+                return Ok(PooledStream::Mux(stream));
+            }
+        }
+
+        let stream = self.dial(target).await?;
+        let remote_id = stream.remote_id();
+
+        let mux_known = self
+            .state
+            .peer_supports(&remote_id, Capability::Multiplex)
+            .await;
+        if !mux_known && self.no_mux.read().await.contains(&remote_id) {
+            return Ok(PooledStream::Direct(Box::new(stream)));
+        }
+
+        match self.upgrade(stream).await {
+            Ok(stream) => {
+                let conn = self.attach(target.ip(), stream, remote_id, true);
+                Ok(PooledStream::Mux(conn.open()?))
+            }
+            Err(err) if mux_known => Err(err),
+            Err(_) => {
+                warn!(peer_id = %remote_id, "peer dropped multiplex request; opening a connection per message");
+                self.no_mux.write().await.insert(remote_id);
+                Ok(PooledStream::Direct(Box::new(self.dial(target).await?)))
+            }
+        }
+    }
+
+    /// Starts multiplexing over an authenticated connection to
+    /// `remote_id` at `addr` and offers it as the shared one. Returns
+    /// whichever connection the pool keeps for `remote_id`.
+    pub fn attach(
+        &self,
+        addr: IpAddr,
+        stream: SecureStream<TcpStream>,
+        remote_id: Uuid,
+        dialed: bool,
+    ) -> MuxConnection {
+        let (conn, streams) = MuxConnection::start(stream, remote_id, dialed);
+        let _ = self.inbound.send((streams, addr));
+        trace!(peer_id = %remote_id, dialed, "multiplexed connection up");
+
+        let mut connections = self.lock();
+        let kept = match connections.get(&remote_id) {
+            Some(current) if !current.is_closed() && self.keeps(current, &conn) => current.clone(),
+            _ => {
+                if let Some(replaced) = connections.insert(remote_id, conn.clone()) {
+                    replaced.retire();
+                }
+                return conn;
+            }
+        };
+        conn.retire();
+        kept
+    }
+
+    /// Closes every connection to `id`.
+    pub fn evict(&self, id: Uuid) {
+        if let Some(conn) = self.lock().remove(&id) {
+            conn.close();
+        }
+    }
+
+    /// Closes every connection.
+    pub fn close_all(&self) {
+        for (_, conn) in self.lock().drain() {
+            conn.close();
+        }
+    }
+
+    /// Whether `current` stays the shared connection when `new` comes
+    /// up next to it.
+    fn keeps(&self, current: &MuxConnection, new: &MuxConnection) -> bool {
+        if current.dialed() == new.dialed() {
+            return true;
+        }
+
+        let dialer = |conn: &MuxConnection| match conn.dialed() {
+            true => self.state.local_id(),
+            false => conn.remote_id(),
+        };
+        dialer(current) < dialer(new)
+    }
+
+    async fn dial(&self, target: SocketAddr) -> TransportResult<SecureStream<TcpStream>> {
+        let stream = TcpStream::connect(target).await?;
+        stream.set_nodelay(true)?;
+
+        let stream = SecureStream::connect(stream, self.state.device_key()).await?;
+        self.ensure_trusted(stream.remote_id(), target).await?;
+
+        trace!(target = ?target, peer_id = %stream.remote_id(), "secure session established");
+        Ok(stream)
+    }
+
+    async fn ensure_trusted(&self, remote_id: Uuid, target: SocketAddr) -> TransportResult<()> {
+        if self.state.is_trusted_device(&remote_id).await {
+            return Ok(());
+        }
+        Err(TransportError::new(&format!(
+            "Refusing to send to unapproved device {remote_id} at {target}"
+        )))
+    }
+
+    /// Asks the peer to multiplex the connection and waits for it to
+    /// agree.
+    async fn upgrade(
+        &self,
+        mut stream: SecureStream<TcpStream>,
+    ) -> TransportResult<SecureStream<TcpStream>> {
+        stream.write_all(self.state.local_id().as_bytes()).await?;
+        stream.write_all(&[TcpStreamKind::Multiplex as u8]).await?;
+        stream.flush().await?;
+
+        let mut answer = [0u8; 1];
+        timeout(SECURE_HANDSHAKE_TIMEOUT, stream.read_exact(&mut answer))
+            .await
+            .map_err(|_| TransportError::new("Timed out waiting for the multiplex answer"))??;
+
+        match answer[0] {
+            MUX_READY => Ok(stream),
+            other => Err(TransportError::new(&format!(
+                "Unexpected multiplex answer {other}"
+            ))),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<Uuid, MuxConnection>> {
+        self.connections
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[async_trait::async_trait]
+impl StreamOpener for ConnectionPool {
+    async fn open_stream(&self, target: SocketAddr) -> TransportResult<PeerStream> {
+        let stream = self.open(target).await?;
+        let remote_id = stream.remote_id();
+        Ok(PeerStream::new(stream, remote_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        application::PeerManager,
+        domain::{AppPorts, DeviceKey, Peer},
+        utils::test_support::{TestEnv, test_env_with_ports},
+    };
+    use std::{
+        net::Ipv4Addr,
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+    use tokio::net::TcpListener;
+
+    const LOCALHOST: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+    /// A peer on a local listener that counts the connections it
+    /// accepts and collects what every message carries. With `mux`
+    /// unset it behaves like a build from before multiplexing and drops
+    /// the `Multiplex` frame.
+    struct FakePeer {
+        key: DeviceKey,
+        port: u16,
+        accepted: Arc<AtomicUsize>,
+        messages: mpsc::UnboundedReceiver<Vec<u8>>,
+    }
+
+    impl FakePeer {
+        fn addr(&self) -> SocketAddr {
+            SocketAddr::new(LOCALHOST, self.port)
+        }
+    }
+
+    async fn fake_peer(mux: bool) -> FakePeer {
+        let listener = TcpListener::bind((LOCALHOST, 0)).await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let key = DeviceKey::generate();
+        let accepted = Arc::new(AtomicUsize::new(0));
+        let (tx, messages) = mpsc::unbounded_channel();
+
+        let peer_key = key.clone();
+        let count = accepted.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                count.fetch_add(1, Ordering::SeqCst);
+                let Ok(mut stream) = SecureStream::accept(stream, &peer_key).await else {
+                    continue;
+                };
+                let mut header = [0u8; 17];
+                stream.read_exact(&mut header).await.unwrap();
+
+                if header[16] != TcpStreamKind::Multiplex as u8 {
+                    let mut message = Vec::new();
+                    stream.read_to_end(&mut message).await.unwrap();
+                    tx.send(message).unwrap();
+                    continue;
+                }
+                if !mux {
+                    continue;
+                }
+
+                stream.write_all(&[MUX_READY]).await.unwrap();
+                stream.flush().await.unwrap();
+                let (conn, mut streams) = MuxConnection::start(stream, Uuid::nil(), false);
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    let _conn = conn;
+                    while let Some(mut stream) = streams.recv().await {
+                        let mut message = Vec::new();
+                        stream.read_to_end(&mut message).await.unwrap();
+                        tx.send(message[17..].to_vec()).unwrap();
+                    }
+                });
+            }
+        });
+
+        FakePeer {
+            key,
+            port,
+            accepted,
+            messages,
+        }
+    }
+
+    async fn pool_for(peer: &FakePeer) -> (TestEnv, ConnectionPool) {
+        let env = test_env_with_ports(
+            &["sync"],
+            AppPorts {
+                http: 0,
+                presence: 0,
+                transport: peer.port,
+                broadcast: 0,
+            },
+        )
+        .await;
+        let peers = PeerManager::new(env.state.clone());
+        peers.load_trusted(vec![peer.key.device_id()]).await;
+        peers
+            .insert(Peer::new(
+                peer.key.device_id(),
+                peer.addr(),
+                "peer".into(),
+                Uuid::new_v4(),
+                vec![],
+            ))
+            .await;
+
+        let (pool, _) = ConnectionPool::new(env.state.clone());
+        (env, pool)
+    }
+
+    async fn send(pool: &ConnectionPool, peer: &FakePeer, local_id: Uuid, message: &[u8]) -> bool {
+        let mut stream = match pool.open(peer.addr()).await {
+            Ok(stream) => stream,
+            Err(TransportError::Failure(message)) => panic!("open failed: {message}"),
+        };
+        stream.write_all(local_id.as_bytes()).await.unwrap();
+        stream
+            .write_all(&[TcpStreamKind::Metadata as u8])
+            .await
+            .unwrap();
+        stream.write_all(message).await.unwrap();
+        stream.shutdown().await.unwrap();
+        matches!(stream, PooledStream::Mux(_))
+    }
+
+    async fn next_message(peer: &mut FakePeer) -> Vec<u8> {
+        tokio::time::timeout(Duration::from_secs(5), peer.messages.recv())
+            .await
+            .expect("peer should receive the message")
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn messages_to_a_peer_share_one_connection() {
+        let mut peer = fake_peer(true).await;
+        let (env, pool) = pool_for(&peer).await;
+
+        for message in [&b"one"[..], b"two", b"three"] {
+            assert!(send(&pool, &peer, env.state.local_id(), message).await);
+            assert_eq!(next_message(&mut peer).await, message);
+        }
+
+        assert_eq!(peer.accepted.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn peers_without_multiplexing_get_a_connection_per_message() {
+        let mut peer = fake_peer(false).await;
+        let (env, pool) = pool_for(&peer).await;
+
+        assert!(!send(&pool, &peer, env.state.local_id(), b"one").await);
+        assert_eq!(next_message(&mut peer).await, b"one");
+        assert!(!send(&pool, &peer, env.state.local_id(), b"two").await);
+        assert_eq!(next_message(&mut peer).await, b"two");
+
+        // One refused upgrade, then one connection per message.
+        assert_eq!(peer.accepted.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn evicted_peers_are_dialed_again() {
+        let mut peer = fake_peer(true).await;
+        let (env, pool) = pool_for(&peer).await;
+
+        assert!(send(&pool, &peer, env.state.local_id(), b"one").await);
+        next_message(&mut peer).await;
+        pool.evict(peer.key.device_id());
+        assert!(send(&pool, &peer, env.state.local_id(), b"two").await);
+        assert_eq!(next_message(&mut peer).await, b"two");
+
+        assert_eq!(peer.accepted.load(Ordering::SeqCst), 2);
+    }
+}
@@ -1,70 +1,158 @@
 use crate::{
-    application::AppState,
     application::network::transport::interface::{TransportError, TransportResult},
+    application::{AppState, VersionStore},
     domain::{
-        EntryInfo, EntryKind, HandshakeData, MAX_TRUSTED_COUNTER, RelativePath, ServerEvent,
-        SyncDirectory, TransportData,
+        Capability, DirSummary, EntryInfo, EntryKind, EntryScope, HandshakeData, HandshakeEntries,
+        MAX_TRUSTED_COUNTER, RelativePath, ServerEvent, SyncDirectory, TransportData, TreeDigest,
     },
     infra::network::tcp::{
         chunk::{
-            MAX_ENTRY_JSON_SIZE, MAX_HANDSHAKE_JSON_SIZE, MAX_TRANSFER_SIZE, TRANSFER_CHUNK_SIZE,
+            HANDSHAKE_PAGE_BUFFER, HANDSHAKE_PAGE_TIMEOUT, MAX_DESCENT_DIRS, MAX_DESCENT_SCOPES,
+            MAX_ENTRY_JSON_SIZE, MAX_HANDSHAKE_JSON_SIZE, MAX_HANDSHAKE_PAGE_SIZE,
+            MAX_TRANSFER_SIZE, TRANSFER_CHUNK_SIZE,
+        },
+        compress::{self, Compression},
+        delta::{self, BlockManifest, LocalBlocks},
+        handshake::{
+            DESCEND, DescentRequest, HandshakeHeader, InlineHandshake, SEND_PAGES,
+            read_descent_json, write_descent_json,
         },
         kind::TcpStreamKind,
+        resume::{EntryAtOffset, Partial, PartialStore, TransferHasher},
     },
-    utils::fs::is_git_path,
+    utils::fs::{is_git_path, is_synche_path},
 };
-use sha2::{Digest, Sha256};
-use std::{env, path::PathBuf, sync::Arc};
+use std::{collections::HashMap, sync::Arc};
 use tokio::{
-    fs::{self, File},
-    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
-    net::TcpStream,
+    fs,
+    io::{self, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    sync::mpsc,
+    time::timeout,
 };
 use uuid::Uuid;
 
 /// Inbound side of the TCP wire format.
 ///
 /// Decodes a `TcpStreamKind`-tagged payload back into a
-/// `TransportData`. For bulk transfers, the bytes are written to a
-/// per-transfer staging file in the OS temp directory and only moved
-/// to their final location after the streamed SHA-256 matches the
-/// advertised hash and safety validation passes; corrupt or unsafe
-/// transfers are dropped without touching the user's home tree.
+/// `TransportData`. For bulk transfers, the bytes are written to the
+/// entry's partial file in the `PartialStore` and only moved to their
+/// final location after the streamed SHA-256 matches the advertised
+/// hash and safety validation passes; corrupt or unsafe transfers are
+/// dropped without touching the user's home tree. A transfer cut off
+/// mid-stream leaves its partial behind, so a later `Transfer` that
+/// starts at an offset, or a `DeltaTransfer` whose leading blocks the
+/// partial already holds, only has to carry the rest. Delta transfers
+/// are rebuilt from blocks of the existing local copy plus the blocks
+/// the sender was asked for, and pass through the same hash check.
+/// Compressed transfers are decompressed chunk by chunk before they
+/// are hashed, so the check always covers the file's own bytes. The
+/// local copy a transfer replaces is kept in the `VersionStore`.
+///
+/// Paged handshakes return as soon as their header is read; the entry
+/// pages keep arriving on the same connection while the application
+/// works through them. With a sender that advertises
+/// `SubtreeDigests`, those pages only cover the subtrees whose digests
+/// differ from ours.
 pub struct TcpReceiver {
     state: Arc<AppState>,
-}
-
-struct Staging {
-    root: PathBuf,
-    tmp_path: PathBuf,
-    file: File,
+    partials: Arc<PartialStore>,
+    versions: VersionStore,
 }
 
 impl TcpReceiver {
-    pub fn new(state: Arc<AppState>) -> Self {
-        Self { state }
+    pub fn new(state: Arc<AppState>, partials: Arc<PartialStore>) -> Self {
+        Self {
+            versions: VersionStore::new(state.clone()),
+            state,
+            partials,
+        }
     }
 
-    pub async fn read_data(
+    pub async fn read_data<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(
         &self,
-        mut stream: TcpStream,
+        mut stream: S,
         kind: TcpStreamKind,
         source_id: Uuid,
     ) -> TransportResult<TransportData> {
         match kind {
             TcpStreamKind::HandshakeSyn => self.read_handshake(&mut stream, true).await,
             TcpStreamKind::HandshakeAck => self.read_handshake(&mut stream, false).await,
+            TcpStreamKind::PagedHandshakeSyn => {
+                self.read_paged_handshake(stream, true, source_id).await
+            }
+            TcpStreamKind::PagedHandshakeAck => {
+                self.read_paged_handshake(stream, false, source_id).await
+            }
             TcpStreamKind::Metadata => self.read_metadata(&mut stream).await,
-            TcpStreamKind::Request => self.read_request(&mut stream).await,
-            TcpStreamKind::Transfer => self.read_transfer(&mut stream, source_id).await,
+            TcpStreamKind::Request => self.read_request(&mut stream, source_id).await,
+            TcpStreamKind::Transfer => self.read_transfer(&mut stream, source_id, false).await,
+            TcpStreamKind::DeltaTransfer => self.read_transfer(&mut stream, source_id, true).await,
+            TcpStreamKind::Multiplex => Err(TransportError::new(
+                "Multiplex sets up a connection and carries no message",
+            )),
         }
     }
 
-    async fn read_handshake(
+    async fn read_handshake<S: AsyncRead + Unpin>(
         &self,
-        stream: &mut TcpStream,
+        stream: &mut S,
         is_syn: bool,
     ) -> TransportResult<TransportData> {
+        let json = Self::read_handshake_json(stream).await?;
+        let data = Self::validate_handshake_data(serde_json::from_slice(&json)?)?;
+
+        let entries = HandshakeEntries::from_map(data.entries);
+        Ok(Self::handshake(data.header.with_entries(entries), is_syn))
+    }
+
+    /// Reads a paged handshake header, then asks for the entry pages
+    /// and hands them over as they arrive. The stream moves to a task
+    /// that reads ahead at most `HANDSHAKE_PAGE_BUFFER` pages, so a
+    /// large map neither stalls the accept loop nor piles up in memory.
+    /// Devices that are not approved are told to keep their pages;
+    /// devices that advertise `SubtreeDigests` are descended first.
+    async fn read_paged_handshake<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(
+        &self,
+        mut stream: S,
+        is_syn: bool,
+        source_id: Uuid,
+    ) -> TransportResult<TransportData> {
+        let json = Self::read_handshake_json(&mut stream).await?;
+        let header = Self::validate_handshake_header(serde_json::from_slice(&json)?)?;
+
+        let entries = if !self.state.is_trusted_device(&source_id).await {
+            stream.write_all(&[0]).await?;
+            stream.flush().await?;
+            HandshakeEntries::from_map(HashMap::new())
+        } else if header
+            .protocol
+            .capabilities
+            .contains(&Capability::SubtreeDigests)
+        {
+            stream.write_all(&[DESCEND]).await?;
+            stream.flush().await?;
+            HandshakeEntries::from_channel(Self::spawn_page_reader(
+                stream,
+                Some(self.state.clone()),
+            ))
+        } else {
+            stream.write_all(&[SEND_PAGES]).await?;
+            stream.flush().await?;
+            HandshakeEntries::from_channel(Self::spawn_page_reader(stream, None))
+        };
+
+        Ok(Self::handshake(header.with_entries(entries), is_syn))
+    }
+
+    fn handshake(data: HandshakeData, is_syn: bool) -> TransportData {
+        if is_syn {
+            TransportData::HandshakeSyn(data)
+        } else {
+            TransportData::HandshakeAck(data)
+        }
+    }
+
+    async fn read_handshake_json<S: AsyncRead + Unpin>(stream: &mut S) -> TransportResult<Vec<u8>> {
         let mut len_buf = [0u8; 4];
         stream.read_exact(&mut len_buf).await?;
         let len = u32::from_be_bytes(len_buf) as usize;
@@ -77,41 +165,182 @@ impl TcpReceiver {
 
         let mut buf = vec![0u8; len];
         stream.read_exact(&mut buf).await?;
+        Ok(buf)
+    }
 
-        let data_str = String::from_utf8(buf).map_err(|e| TransportError::new(&e.to_string()))?;
-        let data = Self::validate_handshake_data(serde_json::from_str(&data_str)?)?;
+    /// Reads pages in a task of their own, after descending the
+    /// sender's digest tree when `descent` holds our state.
+    fn spawn_page_reader<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(
+        mut stream: S,
+        descent: Option<Arc<AppState>>,
+    ) -> mpsc::Receiver<io::Result<Vec<EntryInfo>>> {
+        let (tx, rx) = mpsc::channel(HANDSHAKE_PAGE_BUFFER);
+
+        tokio::spawn(async move {
+            if let Some(state) = descent
+                && let Err(err) = Self::descend(&mut stream, &state).await
+            {
+                let _ = tx.send(Err(err.into())).await;
+                return;
+            }
 
-        if is_syn {
-            Ok(TransportData::HandshakeSyn(data))
-        } else {
-            Ok(TransportData::HandshakeAck(data))
+            loop {
+                let page = match timeout(HANDSHAKE_PAGE_TIMEOUT, Self::read_page(&mut stream)).await
+                {
+                    Ok(Ok(Some(page))) => Ok(page),
+                    Ok(Ok(None)) => return,
+                    Ok(Err(err)) => Err(err.into()),
+                    Err(_) => Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        "Timed out waiting for the next handshake page",
+                    )),
+                };
+
+                let failed = page.is_err();
+                if tx.send(page).await.is_err() || failed {
+                    return;
+                }
+            }
+        });
+
+        rx
+    }
+
+    /// Walks the sender's digest tree down from the root, one level
+    /// per round trip, then asks for the entries of every scope where
+    /// it differs from ours. Top-level directories we do not sync are
+    /// never entered.
+    async fn descend<S: AsyncRead + AsyncWrite + Unpin>(
+        stream: &mut S,
+        state: &AppState,
+    ) -> TransportResult<()> {
+        let mut pending = vec![RelativePath::from("")];
+        let mut fetch = Vec::new();
+
+        while !pending.is_empty() {
+            let batch: Vec<_> = pending
+                .drain(..pending.len().min(MAX_DESCENT_DIRS))
+                .collect();
+            write_descent_json(stream, &DescentRequest::Expand(batch.clone())).await?;
+
+            let summaries: Vec<Option<DirSummary>> = read_descent_json(stream).await?;
+            if summaries.len() != batch.len() {
+                return Err(TransportError::new(&format!(
+                    "Asked for {} directory summaries, got {}",
+                    batch.len(),
+                    summaries.len()
+                )));
+            }
+
+            for (dir, summary) in batch.into_iter().zip(summaries) {
+                let Some(summary) = summary else {
+                    continue;
+                };
+                let summary = Self::validate_summary(summary)?;
+                let summary = if dir.is_empty() {
+                    Self::synced_top_level(summary, state).await
+                } else {
+                    summary
+                };
+
+                let diff = state.digest_tree().await.diff(&dir, &summary);
+                pending.extend(diff.descend);
+                fetch.extend(diff.fetch);
+            }
+        }
+
+        if fetch.len() > MAX_DESCENT_SCOPES {
+            fetch = vec![EntryScope::All];
+        }
+        write_descent_json(stream, &DescentRequest::Fetch(fetch)).await
+    }
+
+    /// Keeps only the top-level directories we sync. Entries outside
+    /// them are dropped on arrival anyway.
+    async fn synced_top_level(summary: DirSummary, state: &AppState) -> DirSummary {
+        let mut dirs = summary.dirs;
+        for name in dirs.keys().cloned().collect::<Vec<_>>() {
+            if !state.contains_sync_dir(&name.as_str().into()).await {
+                dirs.remove(&name);
+            }
         }
+
+        DirSummary {
+            own: None,
+            files: TreeDigest::default(),
+            dirs,
+        }
+    }
+
+    /// Reads one entry page, or `None` at the zero length that ends
+    /// the map.
+    pub(super) async fn read_page<S: AsyncRead + Unpin>(
+        stream: &mut S,
+    ) -> TransportResult<Option<Vec<EntryInfo>>> {
+        let mut len_buf = [0u8; 4];
+        stream.read_exact(&mut len_buf).await?;
+        let len = u32::from_be_bytes(len_buf) as usize;
+
+        if len == 0 {
+            return Ok(None);
+        }
+        if len > MAX_HANDSHAKE_PAGE_SIZE {
+            return Err(TransportError::new(&format!(
+                "Handshake page size {len} exceeds MAX_HANDSHAKE_PAGE_SIZE {MAX_HANDSHAKE_PAGE_SIZE}",
+            )));
+        }
+
+        let mut buf = vec![0u8; len];
+        stream.read_exact(&mut buf).await?;
+
+        let page: Vec<EntryInfo> = serde_json::from_slice(&buf)?;
+        page.into_iter()
+            .map(Self::validate_entry_info)
+            .collect::<TransportResult<_>>()
+            .map(Some)
     }
 
-    async fn read_metadata(&self, stream: &mut TcpStream) -> TransportResult<TransportData> {
+    async fn read_metadata<S: AsyncRead + Unpin>(
+        &self,
+        stream: &mut S,
+    ) -> TransportResult<TransportData> {
         let entry = self.read_entry_info(stream).await?;
 
         Ok(TransportData::Metadata(entry))
     }
 
-    async fn read_request(&self, stream: &mut TcpStream) -> TransportResult<TransportData> {
-        let entry = self.read_entry_info(stream).await?;
+    /// Reads a `Request` and remembers the offset the peer asked to
+    /// resume from, for the `Transfer` that answers it.
+    async fn read_request<S: AsyncRead + Unpin>(
+        &self,
+        stream: &mut S,
+        source_id: Uuid,
+    ) -> TransportResult<TransportData> {
+        let EntryAtOffset { entry, offset, .. } = self.read_entry_at_offset(stream).await?;
+        self.partials
+            .set_requested(source_id, &entry.name, offset)
+            .await;
 
         Ok(TransportData::Request(entry))
     }
 
-    async fn read_transfer(
+    async fn read_transfer<S: AsyncRead + AsyncWrite + Unpin>(
         &self,
-        stream: &mut TcpStream,
+        stream: &mut S,
         source_id: Uuid,
+        delta: bool,
     ) -> TransportResult<TransportData> {
-        let entry = self.read_entry_info(stream).await?;
+        let EntryAtOffset {
+            entry,
+            offset,
+            compression,
+        } = self.read_entry_at_offset(stream).await?;
 
         // Header parsed — any failure from here on can be attributed to this
         // specific entry, so emit `EntrySyncFailed` before propagating the
         // error (the adapter then swallows the error itself).
         match self
-            .read_transfer_after_header(stream, &entry, source_id)
+            .read_transfer_after_header(stream, &entry, source_id, offset, compression, delta)
             .await
         {
             Ok(()) => Ok(TransportData::Transfer(entry)),
@@ -122,11 +351,14 @@ impl TcpReceiver {
         }
     }
 
-    async fn read_transfer_after_header(
+    async fn read_transfer_after_header<S: AsyncRead + AsyncWrite + Unpin>(
         &self,
-        stream: &mut TcpStream,
+        stream: &mut S,
         entry: &EntryInfo,
         source_id: Uuid,
+        offset: u64,
+        compression: Option<Compression>,
+        delta: bool,
     ) -> TransportResult<()> {
         let mut entry_size_buf = [0u8; 8];
         stream.read_exact(&mut entry_size_buf).await?;
@@ -138,40 +370,163 @@ impl TcpReceiver {
             )));
         }
 
+        if offset > entry_size {
+            return Err(TransportError::new(&format!(
+                "Transfer offset {offset} exceeds entry_size {entry_size}",
+            )));
+        }
+
+        if delta && compression.is_some() {
+            return Err(TransportError::new(
+                "DeltaTransfer payloads are never compressed",
+            ));
+        }
+
+        let manifest = match delta {
+            true => Some(BlockManifest::read(stream, entry_size).await?),
+            false => None,
+        };
+
         if self
             .should_drop_transfer_before_disk_write(entry, source_id)
             .await
         {
-            // Drain the payload from the wire without writing to disk.
-            Self::discard_bytes(stream, entry_size, TRANSFER_CHUNK_SIZE).await?;
+            match manifest {
+                // Ask for no blocks so nothing follows on the wire.
+                Some(manifest) => {
+                    Self::write_needed(stream, &vec![false; manifest.digests.len()]).await?
+                }
+                // Drain the payload from the wire without writing to disk.
+                None if compression.is_none() => {
+                    Self::discard_bytes(stream, entry_size - offset, TRANSFER_CHUNK_SIZE).await?
+                }
+                // Compressed chunks must be parsed to find their end.
+                None => {
+                    Self::stream_to_file(
+                        &mut TransferHasher::new(),
+                        stream,
+                        &mut io::sink(),
+                        entry_size - offset,
+                        TRANSFER_CHUNK_SIZE,
+                        compression,
+                    )
+                    .await?;
+                }
+            }
             return Ok(());
         }
 
-        let mut staging = self.create_staging(entry).await?;
+        let mut partial = self.partials.open_partial(entry).await?;
+
+        let (written, hasher) = match manifest {
+            Some(manifest) => {
+                let (kept, mut hasher) = partial.resume_blocks(&manifest).await?;
+                let written = self
+                    .rebuild_from_blocks(
+                        stream,
+                        &mut partial.file,
+                        entry,
+                        &manifest,
+                        kept,
+                        &mut hasher,
+                    )
+                    .await;
+                (written, hasher)
+            }
+            None => {
+                let mut hasher = partial.resume_at(offset).await?;
+                let written = Self::stream_to_file(
+                    &mut hasher,
+                    stream,
+                    &mut partial.file,
+                    entry_size - offset,
+                    TRANSFER_CHUNK_SIZE,
+                    compression,
+                )
+                .await;
+                (written, hasher)
+            }
+        };
 
-        let computed_hash =
-            match Self::stream_to_file(stream, &mut staging.file, entry_size, TRANSFER_CHUNK_SIZE)
-                .await
-            {
-                Ok(h) => h,
-                Err(e) => {
-                    let _ = fs::remove_dir_all(&staging.root).await;
-                    return Err(e);
+        let computed_hash = match written {
+            Ok(h) => h,
+            Err(e) => {
+                // Keep what arrived, and the hash over it, so the next
+                // attempt can resume.
+                if partial.file.flush().await.is_ok() {
+                    partial.checkpoint(&hasher).await;
                 }
-            };
+                return Err(e);
+            }
+        };
 
         if let Some(hash) = &entry.hash
             && !entry.is_removed()
             && matches!(entry.kind, EntryKind::File)
             && computed_hash != *hash
         {
-            let _ = fs::remove_dir_all(&staging.root).await;
+            partial.discard().await;
             return Err(TransportError::new(
                 "Hash mismatch: data corruption detected",
             ));
         }
 
-        self.finalise_staging(entry, staging).await
+        self.finalise_partial(entry, partial).await
+    }
+
+    /// Answers a block manifest with the blocks our current copy of
+    /// `entry` lacks, then writes every block after the first `kept`
+    /// to `writer` in order — copied locally or read off the wire. The
+    /// `kept` leading blocks are already in `writer` from an earlier,
+    /// interrupted attempt and `hasher` has consumed them. Returns the
+    /// hex-encoded SHA-256 of the rebuilt file.
+    async fn rebuild_from_blocks<S, W>(
+        &self,
+        stream: &mut S,
+        writer: &mut W,
+        entry: &EntryInfo,
+        manifest: &BlockManifest,
+        kept: usize,
+        hasher: &mut TransferHasher,
+    ) -> TransportResult<String>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        let local_path = entry.name.to_canonical(self.state.home_path());
+        let mut local = LocalBlocks::index(&local_path, manifest.block_size).await;
+
+        let needed: Vec<bool> = manifest
+            .digests
+            .iter()
+            .enumerate()
+            .map(|(i, digest)| i >= kept && !local.contains(digest))
+            .collect();
+        Self::write_needed(stream, &needed).await?;
+
+        let mut buf = vec![0u8; manifest.block_size];
+
+        for (i, digest) in manifest.digests.iter().enumerate().skip(kept) {
+            let len = manifest.block_len(i);
+            if needed[i] {
+                stream.read_exact(&mut buf[..len]).await?;
+            } else {
+                local.read(digest, &mut buf[..len]).await?;
+            }
+            writer.write_all(&buf[..len]).await?;
+            hasher.update(&buf[..len]);
+        }
+
+        Ok(format!("{:x}", hasher.finalize()))
+    }
+
+    async fn write_needed<S: AsyncWrite + Unpin>(
+        stream: &mut S,
+        needed: &[bool],
+    ) -> TransportResult<()> {
+        stream.write_all(&delta::encode_needed(needed)).await?;
+        stream.flush().await?;
+        Ok(())
     }
 
     async fn should_drop_transfer_before_disk_write(
@@ -179,7 +534,15 @@ impl TcpReceiver {
         entry: &EntryInfo,
         source_id: Uuid,
     ) -> bool {
-        if is_git_path(&entry.name) || !self.state.contains_sync_dir(&entry.get_sync_dir()).await {
+        if is_git_path(&entry.name)
+            || is_synche_path(&entry.name)
+            || !self.state.contains_sync_dir(&entry.get_sync_dir()).await
+            || self
+                .state
+                .directory_mode(&entry.get_sync_dir())
+                .await
+                .is_send_only()
+        {
             return true;
         }
 
@@ -199,7 +562,17 @@ impl TcpReceiver {
         });
     }
 
-    async fn read_entry_info(&self, stream: &mut TcpStream) -> TransportResult<EntryInfo> {
+    async fn read_entry_info<S: AsyncRead + Unpin>(
+        &self,
+        stream: &mut S,
+    ) -> TransportResult<EntryInfo> {
+        Ok(self.read_entry_at_offset(stream).await?.entry)
+    }
+
+    async fn read_entry_at_offset<S: AsyncRead + Unpin>(
+        &self,
+        stream: &mut S,
+    ) -> TransportResult<EntryAtOffset> {
         let mut json_len_buf = [0u8; 4];
         stream.read_exact(&mut json_len_buf).await?;
         let json_len = u32::from_be_bytes(json_len_buf) as usize;
@@ -213,14 +586,32 @@ impl TcpReceiver {
         let mut json_buf = vec![0u8; json_len];
         stream.read_exact(&mut json_buf).await?;
 
-        let entry = Self::validate_entry_info(serde_json::from_slice::<EntryInfo>(&json_buf)?)?;
-        Ok(entry)
+        let mut parsed: EntryAtOffset = serde_json::from_slice(&json_buf)?;
+        parsed.entry = Self::validate_entry_info(parsed.entry)?;
+        Ok(parsed)
     }
 
-    fn validate_handshake_data(data: HandshakeData) -> TransportResult<HandshakeData> {
-        for SyncDirectory { name } in &data.sync_dirs {
+    fn validate_handshake_header(header: HandshakeHeader) -> TransportResult<HandshakeHeader> {
+        for SyncDirectory { name, .. } in &header.sync_dirs {
             Self::validate_relative_path(name)?;
         }
+        Ok(header)
+    }
+
+    fn validate_summary(summary: DirSummary) -> TransportResult<DirSummary> {
+        for child in summary.dirs.keys() {
+            if child.contains('/') {
+                return Err(TransportError::new(&format!(
+                    "Directory summary names a nested child: {child}"
+                )));
+            }
+            Self::validate_relative_path(&child.as_str().into())?;
+        }
+        Ok(summary)
+    }
+
+    fn validate_handshake_data(data: InlineHandshake) -> TransportResult<InlineHandshake> {
+        let header = Self::validate_handshake_header(data.header)?;
 
         for (name, entry) in &data.entries {
             Self::validate_relative_path(name)?;
@@ -233,7 +624,10 @@ impl TcpReceiver {
             }
         }
 
-        Ok(data)
+        Ok(InlineHandshake {
+            header,
+            entries: data.entries,
+        })
     }
 
     fn validate_entry_info(entry: EntryInfo) -> TransportResult<EntryInfo> {
@@ -249,80 +643,107 @@ impl TcpReceiver {
         }
     }
 
-    async fn create_staging(&self, entry: &EntryInfo) -> TransportResult<Staging> {
-        // Stage into a per-transfer subdirectory inside the OS temp dir.
-        // Without the Uuid suffix, two concurrent transfers of the same
-        // `entry.name` would race on the same `/tmp/<name>` staging file.
-        let root = env::temp_dir().join(format!("synche-{}", Uuid::new_v4()));
-        let tmp_path = root.join(&entry.name);
-
-        if let Some(parent) = tmp_path.parent() {
-            fs::create_dir_all(parent).await?;
-        }
-        let file = File::create(&tmp_path).await?;
-
-        Ok(Staging {
-            root,
-            tmp_path,
-            file,
-        })
-    }
-
-    async fn finalise_staging(
+    async fn finalise_partial(
         &self,
         entry: &EntryInfo,
-        mut staging: Staging,
+        mut partial: Partial,
     ) -> TransportResult<()> {
-        staging.file.flush().await?;
-        drop(staging.file);
+        partial.file.flush().await?;
 
         let original_path = entry.name.to_canonical(self.state.home_path());
         if let Some(parent) = original_path.parent() {
             fs::create_dir_all(parent).await?;
         }
+        self.versions.keep(&entry.name).await?;
 
-        match fs::rename(&staging.tmp_path, &original_path).await {
+        match fs::rename(&partial.path, &original_path).await {
             Ok(_) => {}
             Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => {
-                fs::copy(&staging.tmp_path, &original_path).await?;
-                fs::remove_file(&staging.tmp_path).await?;
-            }
-            Err(e) => {
-                let _ = fs::remove_dir_all(&staging.root).await;
-                return Err(e.into());
+                fs::copy(&partial.path, &original_path).await?;
             }
+            Err(e) => return Err(e.into()),
         }
-        let _ = fs::remove_dir_all(&staging.root).await;
+        partial.finish().await;
         Ok(())
     }
 
     /// Stream exactly `total` bytes from `reader` into `writer` in `chunk_size`
-    /// chunks, returning the hex-encoded SHA-256 of the bytes streamed.
+    /// chunks, returning the hex-encoded SHA-256 of everything `hasher` has
+    /// consumed: any resumed prefix, then the bytes streamed. With
+    /// `compression`, `total` counts decompressed bytes and each chunk is
+    /// decompressed before it is hashed and written.
     pub(super) async fn stream_to_file<R, W>(
+        hasher: &mut TransferHasher,
         reader: &mut R,
         writer: &mut W,
         total: u64,
         chunk_size: usize,
+        compression: Option<Compression>,
     ) -> TransportResult<String>
     where
         R: AsyncRead + Unpin,
         W: AsyncWrite + Unpin,
     {
-        let mut hasher = Sha256::new();
         let mut buf = vec![0u8; chunk_size];
         let mut remaining = total;
 
         while remaining > 0 {
             let want = remaining.min(chunk_size as u64) as usize;
-            reader.read_exact(&mut buf[..want]).await?;
-            hasher.update(&buf[..want]);
-            writer.write_all(&buf[..want]).await?;
-            remaining -= want as u64;
+            if let Some(Compression::Zstd) = compression {
+                let raw = Self::read_compressed_chunk(reader, want).await?;
+                writer.write_all(&raw).await?;
+                hasher.update(&raw);
+                remaining -= raw.len() as u64;
+                continue;
+            }
+
+            // Write whatever arrives rather than waiting for a full chunk,
+            // so a dropped connection leaves every received byte in the
+            // partial.
+            let n = reader.read(&mut buf[..want]).await?;
+            if n == 0 {
+                return Err(TransportError::new(&format!(
+                    "Connection closed with {remaining} bytes left to receive",
+                )));
+            }
+            writer.write_all(&buf[..n]).await?;
+            hasher.update(&buf[..n]);
+            remaining -= n as u64;
         }
 
         Ok(format!("{:x}", hasher.finalize()))
     }
 
+    /// Reads one compressed chunk of at most `max_len` raw bytes and
+    /// returns it decompressed. Both lengths are checked before the
+    /// frame is read, so a peer cannot make us buffer more than one
+    /// chunk's worth.
+    async fn read_compressed_chunk<R: AsyncRead + Unpin>(
+        reader: &mut R,
+        max_len: usize,
+    ) -> TransportResult<Vec<u8>> {
+        let mut len_buf = [0u8; 4];
+        reader.read_exact(&mut len_buf).await?;
+        let raw_len = u32::from_be_bytes(len_buf) as usize;
+        reader.read_exact(&mut len_buf).await?;
+        let frame_len = u32::from_be_bytes(len_buf) as usize;
+
+        if raw_len == 0 || raw_len > max_len {
+            return Err(TransportError::new(&format!(
+                "Compressed chunk of {raw_len} bytes, expected 1 to {max_len}",
+            )));
+        }
+        if frame_len > compress::max_frame_len(raw_len) {
+            return Err(TransportError::new(&format!(
+                "Compressed frame of {frame_len} bytes for a {raw_len}-byte chunk",
+            )));
+        }
+
+        let mut frame = vec![0u8; frame_len];
+        reader.read_exact(&mut frame).await?;
+        compress::decompress_chunk(&frame, raw_len)
+    }
+
     /// Read and discard exactly `total` bytes from `reader` in `chunk_size`
     /// chunks. Used when an incoming Transfer is filtered out (e.g. .git path)
     /// but the wire framing still requires consuming the advertised payload.
@@ -348,6 +769,8 @@ impl TcpReceiver {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::infra::network::tcp::chunk::MIN_DELTA_BLOCK_SIZE;
+    use sha2::{Digest, Sha256};
     use std::collections::HashMap;
     use std::io::Cursor;
     use tokio::{
@@ -364,12 +787,19 @@ mod tests {
         }
     }
 
+    async fn receiver_for(state: &Arc<AppState>) -> TcpReceiver {
+        let partials = PartialStore::in_home(state.home_path()).await.unwrap();
+        TcpReceiver::new(state.clone(), Arc::new(partials))
+    }
+
     fn file_entry(name: &str, hash: Option<String>) -> EntryInfo {
         EntryInfo {
             name: name.into(),
             kind: EntryKind::File,
             hash,
             version: HashMap::from([(Uuid::new_v4(), 1)]),
+            modified: None,
+            removed_at: None,
         }
     }
 
@@ -396,15 +826,22 @@ mod tests {
         }
     }
 
+    fn header(sync_dir: &str) -> HandshakeHeader {
+        HandshakeHeader {
+            hostname: "peer".to_string(),
+            instance_id: Uuid::new_v4(),
+            transport_port: None,
+            cluster_proof: None,
+            sync_dirs: vec![SyncDirectory::new(sync_dir.into())],
+            protocol: Default::default(),
+        }
+    }
+
     #[test]
     fn validate_handshake_data_rejects_unsafe_remote_paths() {
         let entry = file_entry("../payload.bin", Some("hash".to_string()));
-        let data = HandshakeData {
-            hostname: "peer".to_string(),
-            instance_id: Uuid::new_v4(),
-            sync_dirs: vec![SyncDirectory {
-                name: "sync".into(),
-            }],
+        let data = InlineHandshake {
+            header: header("sync"),
             entries: HashMap::from([(entry.name.clone(), entry)]),
         };
 
@@ -417,12 +854,8 @@ mod tests {
     #[test]
     fn validate_handshake_data_rejects_mismatched_entry_keys() {
         let entry = file_entry("sync/payload.bin", Some("hash".to_string()));
-        let data = HandshakeData {
-            hostname: "peer".to_string(),
-            instance_id: Uuid::new_v4(),
-            sync_dirs: vec![SyncDirectory {
-                name: "sync".into(),
-            }],
+        let data = InlineHandshake {
+            header: header("sync"),
             entries: HashMap::from([("sync/other.bin".into(), entry)]),
         };
 
@@ -438,8 +871,15 @@ mod tests {
         let mut src = Cursor::new(payload.clone());
         let mut dst: Vec<u8> = Vec::new();
 
-        let hash =
-            ok(TcpReceiver::stream_to_file(&mut src, &mut dst, payload.len() as u64, 16).await);
+        let hash = ok(TcpReceiver::stream_to_file(
+            &mut TransferHasher::new(),
+            &mut src,
+            &mut dst,
+            payload.len() as u64,
+            16,
+            None,
+        )
+        .await);
 
         assert_eq!(dst, payload);
         assert_eq!(hash, format!("{:x}", Sha256::digest(&payload)));
@@ -451,7 +891,15 @@ mod tests {
         let mut src = Cursor::new(payload.clone());
         let mut dst: Vec<u8> = Vec::new();
 
-        ok(TcpReceiver::stream_to_file(&mut src, &mut dst, payload.len() as u64, 16).await);
+        ok(TcpReceiver::stream_to_file(
+            &mut TransferHasher::new(),
+            &mut src,
+            &mut dst,
+            payload.len() as u64,
+            16,
+            None,
+        )
+        .await);
 
         assert_eq!(dst, payload);
     }
@@ -462,7 +910,15 @@ mod tests {
         let mut src = Cursor::new(payload.clone());
         let mut dst: Vec<u8> = Vec::new();
 
-        ok(TcpReceiver::stream_to_file(&mut src, &mut dst, payload.len() as u64, 1024).await);
+        ok(TcpReceiver::stream_to_file(
+            &mut TransferHasher::new(),
+            &mut src,
+            &mut dst,
+            payload.len() as u64,
+            1024,
+            None,
+        )
+        .await);
 
         assert_eq!(dst, payload);
     }
@@ -515,7 +971,7 @@ mod tests {
         });
 
         let (stream, _) = listener.accept().await.unwrap();
-        let receiver = TcpReceiver::new(state.clone());
+        let receiver = receiver_for(&state).await;
         let data = ok(receiver
             .read_data(stream, TcpStreamKind::Transfer, Uuid::new_v4())
             .await);
@@ -552,7 +1008,7 @@ mod tests {
         });
 
         let (stream, _) = listener.accept().await.unwrap();
-        let receiver = TcpReceiver::new(state.clone());
+        let receiver = receiver_for(&state).await;
         let result = receiver
             .read_data(stream, TcpStreamKind::Transfer, peer)
             .await;
@@ -613,7 +1069,7 @@ mod tests {
         });
 
         let (stream, _) = listener.accept().await.unwrap();
-        let receiver = TcpReceiver::new(state.clone());
+        let receiver = receiver_for(&state).await;
         let result = receiver
             .read_data(stream, TcpStreamKind::Transfer, Uuid::new_v4())
             .await;
@@ -658,7 +1114,7 @@ mod tests {
         });
 
         let (stream, _) = listener.accept().await.unwrap();
-        let receiver = TcpReceiver::new(state.clone());
+        let receiver = receiver_for(&state).await;
         let data = ok(receiver
             .read_data(stream, TcpStreamKind::Transfer, Uuid::new_v4())
             .await);
@@ -693,7 +1149,7 @@ mod tests {
         });
 
         let (stream, _) = listener.accept().await.unwrap();
-        let receiver = TcpReceiver::new(state.clone());
+        let receiver = receiver_for(&state).await;
         let data = ok(receiver
             .read_data(stream, TcpStreamKind::Transfer, Uuid::new_v4())
             .await);
@@ -704,6 +1160,135 @@ mod tests {
         assert!(!state.home_path().join("other").exists());
     }
 
+    fn transfer_frame(entry: &EntryInfo, offset: u64, entry_size: u64, body: &[u8]) -> Vec<u8> {
+        let entry_json = serde_json::to_vec(&EntryAtOffset {
+            entry: entry.clone(),
+            offset,
+            compression: None,
+        })
+        .unwrap();
+        let mut wire = Vec::new();
+        wire.extend_from_slice(&(entry_json.len() as u32).to_be_bytes());
+        wire.extend_from_slice(&entry_json);
+        wire.extend_from_slice(&entry_size.to_be_bytes());
+        wire.extend_from_slice(body);
+        wire
+    }
+
+    /// A transfer cut off mid-stream keeps what arrived; the next one
+    /// starts at that offset and only carries the rest.
+    #[tokio::test]
+    async fn read_transfer_resumes_from_partial_kept_after_drop() {
+        let env = crate::utils::test_support::test_env_with_dirs(&["sync"]).await;
+        let state = env.state.clone();
+        let receiver = receiver_for(&state).await;
+        let contents: Vec<u8> = (0..5000u32).map(|i| (i % 253) as u8).collect();
+        let size = contents.len() as u64;
+        let entry = file_entry(
+            "sync/video.bin",
+            Some(format!("{:x}", Sha256::digest(&contents))),
+        );
+
+        let cut = transfer_frame(&entry, 0, size, &contents[..3000]);
+        let result = receiver
+            .read_data(Cursor::new(cut), TcpStreamKind::Transfer, Uuid::new_v4())
+            .await;
+        assert!(result.is_err());
+        assert_eq!(receiver.partials.resume_offset(&entry).await, 3000);
+
+        let rest = transfer_frame(&entry, 3000, size, &contents[3000..]);
+        let data = ok(receiver
+            .read_data(Cursor::new(rest), TcpStreamKind::Transfer, Uuid::new_v4())
+            .await);
+
+        assert!(matches!(data, TransportData::Transfer(_)));
+        let original_path = state.home_path().join("sync/video.bin");
+        assert_eq!(fs::read(&original_path).await.unwrap(), contents);
+        assert_eq!(receiver.partials.resume_offset(&entry).await, 0);
+    }
+
+    fn compressed_transfer_frame(entry: &EntryInfo, raw_len: u32, frame: &[u8]) -> Vec<u8> {
+        let entry_json = serde_json::to_vec(&EntryAtOffset {
+            entry: entry.clone(),
+            offset: 0,
+            compression: Some(Compression::Zstd),
+        })
+        .unwrap();
+        let mut wire = Vec::new();
+        wire.extend_from_slice(&(entry_json.len() as u32).to_be_bytes());
+        wire.extend_from_slice(&entry_json);
+        wire.extend_from_slice(&(raw_len as u64).to_be_bytes());
+        wire.extend_from_slice(&raw_len.to_be_bytes());
+        wire.extend_from_slice(&(frame.len() as u32).to_be_bytes());
+        wire.extend_from_slice(frame);
+        wire
+    }
+
+    #[tokio::test]
+    async fn read_transfer_decompresses_and_checks_the_raw_hash() {
+        let env = crate::utils::test_support::test_env_with_dirs(&["sync"]).await;
+        let receiver = receiver_for(&env.state).await;
+        let contents = "line of a log file\n".repeat(500).into_bytes();
+        let entry = file_entry(
+            "sync/app.log",
+            Some(format!("{:x}", Sha256::digest(&contents))),
+        );
+        let frame = compress::compress_chunk(&contents).unwrap();
+
+        let wire = compressed_transfer_frame(&entry, contents.len() as u32, &frame);
+        let data = ok(receiver
+            .read_data(Cursor::new(wire), TcpStreamKind::Transfer, Uuid::new_v4())
+            .await);
+
+        assert!(matches!(data, TransportData::Transfer(_)));
+        let path = env.state.home_path().join("sync/app.log");
+        assert_eq!(fs::read(&path).await.unwrap(), contents);
+    }
+
+    #[tokio::test]
+    async fn read_transfer_rejects_frames_that_claim_more_than_remains() {
+        let env = crate::utils::test_support::test_env_with_dirs(&["sync"]).await;
+        let receiver = receiver_for(&env.state).await;
+        let contents = vec![b'x'; 4096];
+        let entry = file_entry("sync/big.txt", Some("hash".into()));
+        let frame = compress::compress_chunk(&contents).unwrap();
+
+        let mut wire = compressed_transfer_frame(&entry, contents.len() as u32, &frame);
+        // Advertise a smaller file than the chunk decompresses to.
+        let size_at = wire.len() - frame.len() - 16;
+        wire[size_at..size_at + 8].copy_from_slice(&100u64.to_be_bytes());
+
+        assert_transport_error(
+            receiver
+                .read_data(Cursor::new(wire), TcpStreamKind::Transfer, Uuid::new_v4())
+                .await,
+            "Compressed chunk of 4096 bytes",
+        );
+    }
+
+    #[tokio::test]
+    async fn read_request_records_offset_for_the_answering_transfer() {
+        let env = crate::utils::test_support::test_env_with_dirs(&["sync"]).await;
+        let receiver = receiver_for(&env.state).await;
+        let peer = Uuid::new_v4();
+        let entry = file_entry("sync/video.bin", Some("hash".into()));
+        let json = serde_json::to_vec(&EntryAtOffset {
+            entry: entry.clone(),
+            offset: 4096,
+            compression: None,
+        })
+        .unwrap();
+        let mut wire = (json.len() as u32).to_be_bytes().to_vec();
+        wire.extend_from_slice(&json);
+
+        let data = ok(receiver
+            .read_data(Cursor::new(wire), TcpStreamKind::Request, peer)
+            .await);
+
+        assert!(matches!(data, TransportData::Request(e) if e.name == entry.name));
+        assert_eq!(receiver.partials.requested(peer, &entry.name).await, 4096);
+    }
+
     #[tokio::test]
     async fn read_transfer_with_poisoned_peer_counter_never_overwrites_home() {
         let env = crate::utils::test_support::test_env_with_dirs(&["sync"]).await;
@@ -723,6 +1308,8 @@ mod tests {
             kind: EntryKind::File,
             hash: Some(hash),
             version: HashMap::from([(peer, u64::MAX)]),
+            modified: None,
+            removed_at: None,
         };
 
         let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
@@ -735,7 +1322,7 @@ mod tests {
         });
 
         let (stream, _) = listener.accept().await.unwrap();
-        let receiver = TcpReceiver::new(state.clone());
+        let receiver = receiver_for(&state).await;
         let data = ok(receiver
             .read_data(stream, TcpStreamKind::Transfer, peer)
             .await);
@@ -745,6 +1332,91 @@ mod tests {
         assert_eq!(fs::read(&original_path).await.unwrap(), b"original");
     }
 
+    #[tokio::test]
+    async fn read_delta_transfer_reuses_local_blocks_and_fetches_only_changed_ones() {
+        use crate::infra::network::tcp::{chunk::DELTA_BLOCK_SIZE, sender::TcpSender};
+
+        let env = crate::utils::test_support::test_env_with_dirs(&["sync"]).await;
+        let state = env.state.clone();
+        let entry_name = "sync/image.bin";
+        let original_path = state.home_path().join(entry_name);
+        let old: Vec<u8> = (0..(3 * DELTA_BLOCK_SIZE + 100))
+            .map(|i| (i % 251) as u8)
+            .collect();
+        let mut new = old.clone();
+        new[DELTA_BLOCK_SIZE + 7] ^= 0xFF;
+        fs::create_dir_all(original_path.parent().unwrap())
+            .await
+            .unwrap();
+        fs::write(&original_path, &old).await.unwrap();
+        let entry = file_entry(entry_name, Some(format!("{:x}", Sha256::digest(&new))));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let entry_json = serde_json::to_vec(&entry).unwrap();
+        let new_clone = new.clone();
+        let holder = tokio::spawn(async move {
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            stream
+                .write_all(&(entry_json.len() as u32).to_be_bytes())
+                .await
+                .unwrap();
+            stream.write_all(&entry_json).await.unwrap();
+
+            let mut file = Cursor::new(new_clone);
+            let size = file.get_ref().len() as u64;
+            let (digests, _) = ok(delta::hash_blocks(&mut file, size, DELTA_BLOCK_SIZE).await);
+            let manifest = BlockManifest {
+                entry_size: size,
+                block_size: DELTA_BLOCK_SIZE,
+                digests,
+            };
+            let needed = ok(TcpSender::offer_blocks(&mut stream, &manifest).await).unwrap();
+            ok(TcpSender::send_blocks(&mut stream, &mut file, &manifest, &needed).await)
+        });
+
+        let (stream, _) = listener.accept().await.unwrap();
+        let receiver = receiver_for(&state).await;
+        let data = ok(receiver
+            .read_data(stream, TcpStreamKind::DeltaTransfer, Uuid::new_v4())
+            .await);
+        let sent = holder.await.unwrap();
+
+        assert!(matches!(data, TransportData::Transfer(_)));
+        assert_eq!(sent, 1, "only the modified block crosses the wire");
+        assert_eq!(fs::read(&original_path).await.unwrap(), new);
+    }
+
+    #[tokio::test]
+    async fn read_delta_transfer_outside_sync_dir_requests_no_blocks() {
+        let env = crate::utils::test_support::test_env_with_dirs(&["sync"]).await;
+        let state = env.state.clone();
+        let entry = file_entry("other/image.bin", Some("hash".into()));
+        let manifest = BlockManifest {
+            entry_size: 20 * MIN_DELTA_BLOCK_SIZE as u64,
+            block_size: MIN_DELTA_BLOCK_SIZE,
+            digests: vec![[7u8; 32]; 20],
+        };
+        let mut wire = Vec::new();
+        let entry_json = serde_json::to_vec(&entry).unwrap();
+        wire.extend_from_slice(&(entry_json.len() as u32).to_be_bytes());
+        wire.extend_from_slice(&entry_json);
+        wire.extend_from_slice(&manifest.entry_size.to_be_bytes());
+        ok(manifest.write(&mut wire).await);
+
+        let (mut holder, receiver_end) = tokio::io::duplex(64 * 1024);
+        holder.write_all(&wire).await.unwrap();
+        let receiver = receiver_for(&state).await;
+        ok(receiver
+            .read_data(receiver_end, TcpStreamKind::DeltaTransfer, Uuid::new_v4())
+            .await);
+
+        let mut bitmap = Vec::new();
+        holder.read_to_end(&mut bitmap).await.unwrap();
+        assert_eq!(bitmap, vec![0u8; 3]);
+        assert!(!state.home_path().join("other").exists());
+    }
+
     #[tokio::test]
     async fn read_handshake_rejects_oversized_advertised_length() {
         let env = crate::utils::test_support::test_env().await;
@@ -761,7 +1433,7 @@ mod tests {
         });
 
         let (stream, _) = listener.accept().await.unwrap();
-        let receiver = TcpReceiver::new(state.clone());
+        let receiver = receiver_for(&state).await;
         let result = receiver
             .read_data(stream, TcpStreamKind::HandshakeSyn, Uuid::new_v4())
             .await;
@@ -778,6 +1450,115 @@ mod tests {
         }
     }
 
+    async fn write_paged_header<W: AsyncWrite + Unpin>(writer: &mut W) {
+        let json = serde_json::to_vec(&header("sync")).unwrap();
+        writer
+            .write_all(&(json.len() as u32).to_be_bytes())
+            .await
+            .unwrap();
+        writer.write_all(&json).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn read_paged_handshake_hands_over_pages_as_they_arrive() {
+        let env = crate::utils::test_support::test_env_with_dirs(&["sync"]).await;
+        let state = env.state.clone();
+        let peer = Uuid::new_v4();
+        crate::application::PeerManager::new(state.clone())
+            .load_trusted(vec![peer])
+            .await;
+
+        let (mut remote, local) = tokio::io::duplex(64 * 1024);
+        let receiver = receiver_for(&state).await;
+        write_paged_header(&mut remote).await;
+
+        let data = ok(receiver
+            .read_data(local, TcpStreamKind::PagedHandshakeSyn, peer)
+            .await);
+        let TransportData::HandshakeSyn(mut data) = data else {
+            panic!("expected HandshakeSyn");
+        };
+
+        let mut answer = [0u8; 1];
+        remote.read_exact(&mut answer).await.unwrap();
+        assert_eq!(answer[0], SEND_PAGES);
+
+        let sent = [
+            file_entry("sync/a.txt", Some("a".into())),
+            file_entry("sync/b.txt", Some("b".into())),
+        ];
+        let mut entries =
+            HandshakeEntries::from_map(sent.iter().map(|e| (e.name.clone(), e.clone())).collect());
+        ok(
+            crate::infra::network::tcp::sender::TcpSender::send_pages(&mut remote, &mut entries)
+                .await,
+        );
+
+        let mut received = Vec::new();
+        while let Some(page) = data.entries.next_page().await {
+            received.extend(page.unwrap());
+        }
+        received.sort_by(|a, b| a.name.cmp(&b.name));
+        assert_eq!(received.len(), 2);
+        assert_eq!(received[0].name, sent[0].name);
+        assert_eq!(received[1].name, sent[1].name);
+    }
+
+    #[tokio::test]
+    async fn read_paged_handshake_declines_pages_from_unapproved_device() {
+        let env = crate::utils::test_support::test_env().await;
+        let state = env.state.clone();
+
+        let (mut remote, local) = tokio::io::duplex(64 * 1024);
+        let receiver = receiver_for(&state).await;
+        write_paged_header(&mut remote).await;
+
+        let data = ok(receiver
+            .read_data(local, TcpStreamKind::PagedHandshakeAck, Uuid::new_v4())
+            .await);
+        let TransportData::HandshakeAck(mut data) = data else {
+            panic!("expected HandshakeAck");
+        };
+
+        let mut answer = [0u8; 1];
+        remote.read_exact(&mut answer).await.unwrap();
+        assert_ne!(answer[0], SEND_PAGES);
+        assert!(data.entries.next_page().await.unwrap().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn read_paged_handshake_reports_a_map_cut_short() {
+        let env = crate::utils::test_support::test_env_with_dirs(&["sync"]).await;
+        let state = env.state.clone();
+        let peer = Uuid::new_v4();
+        crate::application::PeerManager::new(state.clone())
+            .load_trusted(vec![peer])
+            .await;
+
+        let (mut remote, local) = tokio::io::duplex(64 * 1024);
+        let receiver = receiver_for(&state).await;
+        write_paged_header(&mut remote).await;
+
+        let data = ok(receiver
+            .read_data(local, TcpStreamKind::PagedHandshakeSyn, peer)
+            .await);
+        let TransportData::HandshakeSyn(mut data) = data else {
+            panic!("expected HandshakeSyn");
+        };
+
+        let page = serde_json::to_vec(&[file_entry("sync/a.txt", None)]).unwrap();
+        remote
+            .write_all(&(page.len() as u32).to_be_bytes())
+            .await
+            .unwrap();
+        remote.write_all(&page).await.unwrap();
+        drop(remote);
+
+        assert_eq!(data.entries.next_page().await.unwrap().unwrap().len(), 1);
+        assert!(data.entries.next_page().await.unwrap().is_err());
+        assert!(data.entries.next_page().await.is_none());
+    }
+
     #[tokio::test]
     async fn read_entry_info_rejects_oversized_advertised_length() {
         let env = crate::utils::test_support::test_env().await;
@@ -792,7 +1573,7 @@ mod tests {
         });
 
         let (stream, _) = listener.accept().await.unwrap();
-        let receiver = TcpReceiver::new(state.clone());
+        let receiver = receiver_for(&state).await;
         let result = receiver
             .read_data(stream, TcpStreamKind::Metadata, Uuid::new_v4())
             .await;
@@ -805,4 +1586,127 @@ mod tests {
             Ok(_) => panic!("expected oversize rejection"),
         }
     }
+
+    async fn manager_with(
+        state: &Arc<AppState>,
+        entries: &[EntryInfo],
+    ) -> Arc<crate::application::EntryManager<crate::infra::persistence::sqlite::SqliteDb>> {
+        let db = crate::infra::persistence::sqlite::SqliteDb::new(":memory:")
+            .await
+            .unwrap();
+        let manager = crate::application::EntryManager::new(db, state.clone());
+        for entry in entries {
+            manager.insert_entry(entry.clone()).await.unwrap();
+        }
+        manager
+    }
+
+    /// Runs a paged handshake from `remote_entries` with a sender that
+    /// advertises `SubtreeDigests`, against a receiver holding
+    /// `local_entries`, and returns the names the receiver was sent.
+    async fn descend_and_collect(
+        local_entries: &[EntryInfo],
+        remote_entries: &[EntryInfo],
+    ) -> Vec<String> {
+        let local_env = crate::utils::test_support::test_env_with_dirs(&["sync"]).await;
+        let remote_env = crate::utils::test_support::test_env_with_dirs(&["sync"]).await;
+        let peer = Uuid::new_v4();
+        crate::application::PeerManager::new(local_env.state.clone())
+            .load_trusted(vec![peer])
+            .await;
+        manager_with(&local_env.state, local_entries).await;
+        let remote_manager = manager_with(&remote_env.state, remote_entries).await;
+
+        let (mut remote, local) = tokio::io::duplex(64 * 1024);
+        let receiver = receiver_for(&local_env.state).await;
+        let mut hs_header = header("sync");
+        hs_header.protocol = crate::domain::ProtocolInfo::local();
+        let json = serde_json::to_vec(&hs_header).unwrap();
+        remote
+            .write_all(&(json.len() as u32).to_be_bytes())
+            .await
+            .unwrap();
+        remote.write_all(&json).await.unwrap();
+
+        let data = ok(receiver
+            .read_data(local, TcpStreamKind::PagedHandshakeSyn, peer)
+            .await);
+        let TransportData::HandshakeSyn(mut data) = data else {
+            panic!("expected HandshakeSyn");
+        };
+
+        let mut answer = [0u8; 1];
+        remote.read_exact(&mut answer).await.unwrap();
+        assert_eq!(answer[0], DESCEND);
+
+        let mut entries = HandshakeEntries::from_pager(remote_manager);
+        ok(
+            crate::infra::network::tcp::sender::TcpSender::serve_descent(
+                &remote_env.state,
+                &mut remote,
+                &mut entries,
+            )
+            .await,
+        );
+
+        let mut received = Vec::new();
+        while let Some(page) = data.entries.next_page().await {
+            received.extend(page.unwrap().into_iter().map(|e| e.name.to_string()));
+        }
+        received
+    }
+
+    fn dir_entry(name: &str) -> EntryInfo {
+        EntryInfo {
+            name: name.into(),
+            kind: EntryKind::Directory,
+            hash: None,
+            version: HashMap::from([(Uuid::nil(), 1)]),
+            modified: None,
+            removed_at: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn descent_between_converged_devices_sends_no_pages() {
+        let entries = [
+            dir_entry("sync"),
+            dir_entry("sync/a"),
+            file_entry("sync/a/1.txt", Some("x".into())),
+            file_entry("sync/top.txt", Some("y".into())),
+        ];
+
+        assert!(descend_and_collect(&entries, &entries).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn descent_fetches_only_subtrees_that_differ() {
+        let shared = [
+            dir_entry("sync"),
+            dir_entry("sync/same"),
+            file_entry("sync/same/1.txt", Some("x".into())),
+            dir_entry("sync/changed"),
+        ];
+        let old = file_entry("sync/changed/2.txt", Some("old".into()));
+        let mut new = old.clone();
+        new.hash = Some("new".into());
+        let remote_only = [
+            new,
+            dir_entry("sync/added"),
+            file_entry("sync/added/3.txt", Some("z".into())),
+            dir_entry("unsynced"),
+            file_entry("unsynced/4.txt", Some("w".into())),
+        ];
+
+        let received = descend_and_collect(
+            &[&shared[..], &[old]].concat(),
+            &[&shared[..], &remote_only[..]].concat(),
+        )
+        .await;
+
+        assert_eq!(
+            received,
+            ["sync/added", "sync/added/3.txt", "sync/changed/2.txt"]
+        );
+    }
 }
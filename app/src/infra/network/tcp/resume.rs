@@ -0,0 +1,560 @@
+use crate::{
+    application::network::transport::interface::{TransportError, TransportResult},
+    domain::{EntryInfo, RelativePath},
+    infra::network::tcp::{
+        chunk::{PARTIAL_MAX_AGE, TRANSFER_CHUNK_SIZE},
+        compress::Compression,
+        delta::{self, BlockManifest},
+    },
+    utils::fs::SYNCHE_DIR,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{
+    Digest, Sha256,
+    digest::{Output, generic_array::GenericArray},
+};
+use std::{
+    collections::HashMap,
+    io::SeekFrom,
+    path::{Path, PathBuf},
+    slice,
+    time::SystemTime,
+};
+use tokio::{
+    fs::{self, File, OpenOptions},
+    io::{self, AsyncReadExt, AsyncSeekExt},
+    sync::Mutex,
+};
+use tracing::{trace, warn};
+use uuid::Uuid;
+
+/// Directory of `SYNCHE_DIR` in the home directory holding partials.
+const PARTIALS_DIR: &str = "partials";
+
+/// `EntryInfo` JSON as carried by `Request` and `Transfer` frames, plus
+/// the byte offset a resumed transfer starts at and how its payload is
+/// compressed. Both fields are omitted when unused, so the JSON is
+/// unchanged for raw whole-file transfers and peers that predate them
+/// ignore them.
+#[derive(Serialize, Deserialize)]
+pub(super) struct EntryAtOffset {
+    #[serde(flatten)]
+    pub entry: EntryInfo,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub offset: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression: Option<Compression>,
+}
+
+fn is_zero(offset: &u64) -> bool {
+    *offset == 0
+}
+
+/// What a partial was started for. A transfer advertising a different
+/// hash for the same name cannot continue it.
+#[derive(Serialize, Deserialize)]
+struct PartialMeta {
+    name: RelativePath,
+    hash: Option<String>,
+}
+
+/// Interrupted inbound transfers, kept on disk so they can resume.
+///
+/// Each entry name gets its own directory under `root` holding the
+/// received prefix (`content`), the advertised hash it belongs to
+/// (`entry.json`) and, once a transfer was cut off, the SHA-256 state
+/// over the prefix (`hasher.json`), so a resumed transfer only hashes
+/// the bytes after it. `root` sits in the home directory, so a finished
+/// partial is renamed into place on the same filesystem. Partials older than
+/// `PARTIAL_MAX_AGE` or without readable metadata are removed on
+/// startup, and a partial is reset as soon as a different hash is
+/// advertised for its entry.
+///
+/// Also remembers, per peer, the offsets that peer asked us to resume
+/// from so the matching outbound `Transfer` can skip what it holds.
+pub(super) struct PartialStore {
+    root: PathBuf,
+    requested: Mutex<HashMap<(Uuid, RelativePath), u64>>,
+}
+
+/// An open partial: the file positioned at the end of the kept prefix.
+pub(super) struct Partial {
+    pub path: PathBuf,
+    pub file: File,
+    dir: PathBuf,
+}
+
+impl PartialStore {
+    /// Opens the store in `SYNCHE_DIR` of the home directory `home`.
+    pub async fn in_home(home: &Path) -> io::Result<Self> {
+        Self::open(&home.join(SYNCHE_DIR).join(PARTIALS_DIR)).await
+    }
+
+    pub async fn open(root: &Path) -> io::Result<Self> {
+        fs::create_dir_all(root).await?;
+        let store = Self {
+            root: root.to_path_buf(),
+            requested: Mutex::new(HashMap::new()),
+        };
+        store.remove_stale().await?;
+        Ok(store)
+    }
+
+    async fn remove_stale(&self) -> io::Result<()> {
+        let mut dirs = fs::read_dir(&self.root).await?;
+        while let Some(dir) = dirs.next_entry().await? {
+            let path = dir.path();
+            if Self::is_stale(&path).await {
+                trace!(partial = ?path, "removing stale partial");
+                let _ = fs::remove_dir_all(&path).await;
+            }
+        }
+        Ok(())
+    }
+
+    async fn is_stale(dir: &Path) -> bool {
+        if Self::read_meta(dir).await.is_none() {
+            return true;
+        }
+
+        match fs::metadata(dir.join("content"))
+            .await
+            .and_then(|m| m.modified())
+        {
+            Ok(modified) => SystemTime::now()
+                .duration_since(modified)
+                .is_ok_and(|age| age > PARTIAL_MAX_AGE),
+            Err(_) => true,
+        }
+    }
+
+    async fn read_meta(dir: &Path) -> Option<PartialMeta> {
+        let bytes = fs::read(dir.join("entry.json")).await.ok()?;
+        serde_json::from_slice(&bytes).ok()
+    }
+
+    fn dir_for(&self, name: &RelativePath) -> PathBuf {
+        self.root
+            .join(format!("{:x}", Sha256::digest(name.as_bytes())))
+    }
+
+    /// Bytes already held for `entry`, or 0 when there is no partial
+    /// for its advertised hash. A partial for another hash is removed.
+    pub async fn resume_offset(&self, entry: &EntryInfo) -> u64 {
+        let dir = self.dir_for(&entry.name);
+        match Self::read_meta(&dir).await {
+            Some(meta) if meta.hash == entry.hash => fs::metadata(dir.join("content"))
+                .await
+                .map(|m| m.len())
+                .unwrap_or(0),
+            Some(_) => {
+                let _ = fs::remove_dir_all(&dir).await;
+                0
+            }
+            None => 0,
+        }
+    }
+
+    /// Opens the partial for `entry`, starting an empty one when none
+    /// exists for its advertised hash.
+    pub async fn open_partial(&self, entry: &EntryInfo) -> TransportResult<Partial> {
+        let dir = self.dir_for(&entry.name);
+        let path = dir.join("content");
+
+        let matches = Self::read_meta(&dir)
+            .await
+            .is_some_and(|meta| meta.hash == entry.hash);
+        if !matches {
+            let _ = fs::remove_dir_all(&dir).await;
+            fs::create_dir_all(&dir).await?;
+            let meta = PartialMeta {
+                name: entry.name.clone(),
+                hash: entry.hash.clone(),
+            };
+            fs::write(dir.join("entry.json"), serde_json::to_vec(&meta)?).await?;
+        }
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .await?;
+
+        Ok(Partial { path, file, dir })
+    }
+
+    pub async fn set_requested(&self, peer: Uuid, name: &RelativePath, offset: u64) {
+        let mut requested = self.requested.lock().await;
+        if offset == 0 {
+            requested.remove(&(peer, name.clone()));
+        } else {
+            requested.insert((peer, name.clone()), offset);
+        }
+    }
+
+    /// Offset `peer` last asked us to resume `name` from. Kept until a
+    /// transfer succeeds so retries resume from the same place.
+    pub async fn requested(&self, peer: Uuid, name: &RelativePath) -> u64 {
+        let requested = self.requested.lock().await;
+        requested.get(&(peer, name.clone())).copied().unwrap_or(0)
+    }
+}
+
+impl Partial {
+    /// Keeps the first `offset` bytes and returns a hasher primed with
+    /// them. Fails when fewer bytes are held than the sender skipped.
+    pub async fn resume_at(&mut self, offset: u64) -> TransportResult<TransferHasher> {
+        let held = self.file.metadata().await?.len();
+        if held < offset {
+            return Err(TransportError::new(&format!(
+                "Resume offset {offset} beyond the {held} bytes held",
+            )));
+        }
+
+        let hasher = self
+            .read_checkpoint()
+            .await
+            .filter(|hasher| hasher.len <= offset)
+            .unwrap_or_default();
+        self.keep_prefix(hasher, offset).await
+    }
+
+    /// Keeps the leading blocks that match `manifest` and returns how
+    /// many there are, with a hasher primed over them.
+    pub async fn resume_blocks(
+        &mut self,
+        manifest: &BlockManifest,
+    ) -> TransportResult<(usize, TransferHasher)> {
+        let held = self.file.metadata().await?.len();
+        let mut buf = vec![0u8; manifest.block_size];
+        let mut hasher = TransferHasher::default();
+        let (mut kept, mut offset) = (0, 0u64);
+
+        self.file.seek(SeekFrom::Start(0)).await?;
+        for (i, digest) in manifest.digests.iter().enumerate() {
+            let len = manifest.block_len(i);
+            if offset + len as u64 > held {
+                break;
+            }
+            self.file.read_exact(&mut buf[..len]).await?;
+            if Sha256::digest(&buf[..len]).as_slice() != digest {
+                break;
+            }
+            hasher.update(&buf[..len]);
+            kept += 1;
+            offset += len as u64;
+        }
+
+        Ok((kept, self.keep_prefix(hasher, offset).await?))
+    }
+
+    /// Cuts the file to `offset` and brings `hasher`, which has
+    /// consumed a prefix of it, up to `offset`.
+    async fn keep_prefix(
+        &mut self,
+        mut hasher: TransferHasher,
+        offset: u64,
+    ) -> TransportResult<TransferHasher> {
+        self.file.set_len(offset).await?;
+        self.file.seek(SeekFrom::Start(hasher.len())).await?;
+
+        let mut buf = vec![0u8; TRANSFER_CHUNK_SIZE];
+        let mut remaining = offset - hasher.len();
+        while remaining > 0 {
+            let want = remaining.min(TRANSFER_CHUNK_SIZE as u64) as usize;
+            delta::read_padded(&mut self.file, &mut buf[..want]).await?;
+            hasher.update(&buf[..want]);
+            remaining -= want as u64;
+        }
+
+        if offset > 0 {
+            trace!(partial = ?self.path, offset, "resuming partial transfer");
+        }
+        Ok(hasher)
+    }
+
+    /// Saves the state of `hasher`, which has consumed a prefix of the
+    /// file, for the next attempt to resume from. Bytes past the last
+    /// whole SHA-256 block are left for it to hash again.
+    pub async fn checkpoint(&self, hasher: &TransferHasher) {
+        let checkpoint = Checkpoint {
+            state: hasher.state,
+            len: hasher.len - hasher.pending.len() as u64,
+        };
+        let Ok(bytes) = serde_json::to_vec(&checkpoint) else {
+            return;
+        };
+        if let Err(err) = fs::write(self.dir.join("hasher.json"), bytes).await {
+            warn!(partial = ?self.dir, "Failed to save hasher state: {err}");
+        }
+    }
+
+    async fn read_checkpoint(&self) -> Option<TransferHasher> {
+        let bytes = fs::read(self.dir.join("hasher.json")).await.ok()?;
+        let checkpoint: Checkpoint = serde_json::from_slice(&bytes).ok()?;
+        checkpoint
+            .len
+            .is_multiple_of(BLOCK_LEN as u64)
+            .then(|| TransferHasher {
+                state: checkpoint.state,
+                len: checkpoint.len,
+                pending: Vec::new(),
+            })
+    }
+
+    /// Drops the partial, e.g. after its content failed the hash check.
+    pub async fn discard(self) {
+        drop(self.file);
+        if let Err(err) = fs::remove_dir_all(&self.dir).await {
+            warn!(partial = ?self.dir, "Failed to remove partial: {err}");
+        }
+    }
+
+    /// Removes what is left of the partial once `content` has been
+    /// moved into place.
+    pub async fn finish(self) {
+        drop(self.file);
+        let _ = fs::remove_dir_all(&self.dir).await;
+    }
+}
+
+/// Bytes SHA-256 compresses at a time.
+const BLOCK_LEN: usize = 64;
+
+/// SHA-256 initial hash value.
+const INITIAL_STATE: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+/// The SHA-256 of a transfer being received. Unlike `Sha256`, its state
+/// can be saved with the partial, so a resumed transfer picks up where
+/// the last attempt stopped instead of hashing the prefix again.
+#[derive(Clone)]
+pub(super) struct TransferHasher {
+    state: [u32; 8],
+    /// Bytes consumed, including `pending`.
+    len: u64,
+    /// Bytes that do not fill a block yet.
+    pending: Vec<u8>,
+}
+
+/// A `TransferHasher` as saved in `hasher.json`, at a block boundary.
+#[derive(Serialize, Deserialize)]
+struct Checkpoint {
+    state: [u32; 8],
+    len: u64,
+}
+
+impl Default for TransferHasher {
+    fn default() -> Self {
+        Self {
+            state: INITIAL_STATE,
+            len: 0,
+            pending: Vec::with_capacity(BLOCK_LEN),
+        }
+    }
+}
+
+impl TransferHasher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bytes consumed so far.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        self.len += data.len() as u64;
+
+        if !self.pending.is_empty() {
+            let take = data.len().min(BLOCK_LEN - self.pending.len());
+            self.pending.extend_from_slice(&data[..take]);
+            data = &data[take..];
+            if self.pending.len() < BLOCK_LEN {
+                return;
+            }
+            let block = std::mem::take(&mut self.pending);
+            self.compress(&block);
+            self.pending = block;
+            self.pending.clear();
+        }
+
+        let whole = data.len() - data.len() % BLOCK_LEN;
+        for block in data[..whole].chunks_exact(BLOCK_LEN) {
+            self.compress(block);
+        }
+        self.pending.extend_from_slice(&data[whole..]);
+    }
+
+    /// The digest of everything consumed, which the hasher keeps.
+    pub fn finalize(&self) -> Output<Sha256> {
+        let mut hasher = self.clone();
+        let bit_len = self.len.wrapping_mul(8);
+
+        let mut tail = std::mem::take(&mut hasher.pending);
+        tail.push(0x80);
+        let padded = (tail.len() + 8).div_ceil(BLOCK_LEN) * BLOCK_LEN;
+        tail.resize(padded - 8, 0);
+        tail.extend_from_slice(&bit_len.to_be_bytes());
+        for block in tail.chunks_exact(BLOCK_LEN) {
+            hasher.compress(block);
+        }
+
+        let mut digest = Output::<Sha256>::default();
+        for (bytes, word) in digest.chunks_exact_mut(4).zip(hasher.state) {
+            bytes.copy_from_slice(&word.to_be_bytes());
+        }
+        digest
+    }
+
+    fn compress(&mut self, block: &[u8]) {
+        sha2::compress256(
+            &mut self.state,
+            slice::from_ref(GenericArray::from_slice(block)),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::EntryKind;
+    use tempfile::TempDir;
+    use tokio::io::AsyncWriteExt;
+
+    fn ok<T>(res: TransportResult<T>) -> T {
+        match res {
+            Ok(v) => v,
+            Err(TransportError::Failure(m)) => panic!("{m}"),
+        }
+    }
+
+    fn entry(hash: &str) -> EntryInfo {
+        EntryInfo {
+            name: "sync/big.bin".into(),
+            kind: EntryKind::File,
+            hash: Some(hash.into()),
+            version: HashMap::from([(Uuid::new_v4(), 1)]),
+            modified: None,
+            removed_at: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn partial_survives_reopen_and_resets_when_hash_changes() {
+        let temp = TempDir::new().unwrap();
+        let store = PartialStore::open(&temp.path().join("partials"))
+            .await
+            .unwrap();
+
+        let mut partial = ok(store.open_partial(&entry("v1")).await);
+        partial.file.write_all(b"0123456789").await.unwrap();
+        partial.file.flush().await.unwrap();
+        drop(partial);
+
+        assert_eq!(store.resume_offset(&entry("v1")).await, 10);
+
+        let mut partial = ok(store.open_partial(&entry("v1")).await);
+        let hasher = ok(partial.resume_at(4).await);
+        assert_eq!(hasher.finalize(), Sha256::digest(b"0123"));
+        drop(partial);
+
+        assert_eq!(store.resume_offset(&entry("v2")).await, 0);
+        assert_eq!(store.resume_offset(&entry("v1")).await, 0);
+    }
+
+    #[tokio::test]
+    async fn open_removes_partials_without_metadata() {
+        let temp = TempDir::new().unwrap();
+        let root = temp.path().join("partials");
+        let orphan = root.join("orphan");
+        std::fs::create_dir_all(&orphan).unwrap();
+        std::fs::write(orphan.join("content"), b"bytes").unwrap();
+
+        let store = PartialStore::open(&root).await.unwrap();
+        let mut partial = ok(store.open_partial(&entry("v1")).await);
+        partial.file.write_all(b"kept").await.unwrap();
+        drop(partial);
+        drop(store);
+
+        let store = PartialStore::open(&temp.path().join("partials"))
+            .await
+            .unwrap();
+
+        assert!(!orphan.exists());
+        assert_eq!(store.resume_offset(&entry("v1")).await, 4);
+    }
+
+    #[test]
+    fn transfer_hasher_matches_sha256_however_it_is_fed() {
+        let data: Vec<u8> = (0..1000u32).map(|i| (i * 7 % 251) as u8).collect();
+
+        for len in [0, 1, 55, 56, 63, 64, 65, 119, 128, 1000] {
+            for step in [1, 13, 64, 1000] {
+                let mut hasher = TransferHasher::new();
+                for chunk in data[..len].chunks(step) {
+                    hasher.update(chunk);
+                }
+                assert_eq!(
+                    hasher.finalize(),
+                    Sha256::digest(&data[..len]),
+                    "{len}/{step}"
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn resume_continues_from_the_saved_hasher_state() {
+        let temp = TempDir::new().unwrap();
+        let store = PartialStore::open(&temp.path().join("partials"))
+            .await
+            .unwrap();
+        let data: Vec<u8> = (0..300u32).map(|i| i as u8).collect();
+
+        let mut partial = ok(store.open_partial(&entry("v1")).await);
+        let mut hasher = TransferHasher::new();
+        partial.file.write_all(&data[..200]).await.unwrap();
+        partial.file.flush().await.unwrap();
+        hasher.update(&data[..200]);
+        partial.checkpoint(&hasher).await;
+        drop(partial);
+
+        // The prefix the checkpoint covers is not read again, so a byte
+        // changed there does not show in the hash.
+        let mut partial = ok(store.open_partial(&entry("v1")).await);
+        let mut content = std::fs::read(&partial.path).unwrap();
+        content[0] ^= 0xff;
+        std::fs::write(&partial.path, &content).unwrap();
+
+        let mut hasher = ok(partial.resume_at(200).await);
+        assert_eq!(hasher.len(), 200);
+        hasher.update(&data[200..]);
+        assert_eq!(hasher.finalize(), Sha256::digest(&data));
+
+        // A checkpoint past the offset is ignored.
+        let hasher = ok(partial.resume_at(100).await);
+        assert_eq!(hasher.finalize(), Sha256::digest(&content[..100]));
+    }
+
+    #[test]
+    fn entry_at_offset_omits_zero_offset_and_reads_plain_entries() {
+        let plain = serde_json::to_value(entry("v1")).unwrap();
+        let wrapped = serde_json::to_value(EntryAtOffset {
+            entry: entry("v1"),
+            offset: 0,
+            compression: None,
+        })
+        .unwrap();
+        assert_eq!(wrapped.as_object().unwrap().keys().len(), 4);
+        assert!(wrapped.get("offset").is_none());
+        assert!(wrapped.get("compression").is_none());
+
+        let parsed: EntryAtOffset = serde_json::from_value(plain).unwrap();
+        assert_eq!(parsed.offset, 0);
+    }
+}
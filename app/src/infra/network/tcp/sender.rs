@@ -1,38 +1,91 @@
 use crate::{
     application::AppState,
-    application::network::transport::interface::TransportResult,
-    domain::{EntryInfo, HandshakeData, TransportData},
-    infra::network::tcp::{chunk::TRANSFER_CHUNK_SIZE, kind::TcpStreamKind},
+    application::network::transport::interface::{TransportError, TransportResult},
+    domain::{Capability, EntryInfo, HandshakeData, HandshakeEntries, TransportData},
+    infra::network::tcp::{
+        chunk::{DELTA_BLOCK_SIZE, MAX_DESCENT_DIRS, MAX_HANDSHAKE_PAGE_SIZE, TRANSFER_CHUNK_SIZE},
+        compress::{self, Compression},
+        delta::{self, BlockManifest},
+        handshake::{
+            DESCEND, DescentRequest, HandshakeHeader, InlineHandshake, SEND_PAGES,
+            read_descent_json, write_descent_json,
+        },
+        kind::TcpStreamKind,
+        resume::{EntryAtOffset, PartialStore},
+        stream::{PeerStream, StreamOpener},
+    },
 };
 use sha2::{Digest, Sha256};
 use std::{
-    net::{IpAddr, SocketAddr},
+    collections::{HashMap, HashSet},
+    io::{self, SeekFrom},
+    net::SocketAddr,
     sync::Arc,
 };
 use tokio::{
     fs::File,
-    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
-    net::TcpStream,
+    io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, AsyncWrite, AsyncWriteExt},
+    sync::RwLock,
 };
-use tracing::{info, warn};
+use tracing::{info, trace, warn};
+use uuid::Uuid;
 
 /// Outbound side of the TCP wire format.
 ///
-/// Each `send_*` method opens a fresh connection to the target peer,
-/// writes the device id, a `TcpStreamKind` tag, and the kind-specific
-/// payload. Bulk transfers use `stream_file_to` to chunk content with
-/// a streaming SHA-256 so the receiver can detect mid-transfer
-/// changes.
+/// Each `send_*` method opens a stream to the target peer through its
+/// `StreamOpener` — with `TcpAdapter`, a stream of the peer's
+/// long-lived multiplexed connection, or a Noise connection of its own
+/// for peers that predate multiplexing; with `QuicAdapter`, a QUIC
+/// stream — then writes the device id, a `TcpStreamKind` tag, and the
+/// kind-specific payload. Bulk transfers use `stream_file_to` to chunk
+/// content with a streaming SHA-256 so the receiver can detect
+/// mid-transfer changes, compressing the chunks with zstd for peers
+/// that support it unless the file looks compressed already. Optional
+/// features are only used with peers that negotiated the matching
+/// `Capability` in their handshake. Files larger than one
+/// `DELTA_BLOCK_SIZE` block are first offered as a `DeltaTransfer`; a
+/// device that closes the stream instead of answering is remembered in
+/// `no_delta` and gets whole files until restart, while any other
+/// failed offer falls back for that file only. Handshakes are the
+/// exception to negotiating first, since they are how capabilities are
+/// learned: the entry map is always offered in bounded pages, and
+/// devices that predate paging are remembered in `no_paging`. A device
+/// that reads our `SubtreeDigests` capability in the header may walk
+/// our digest tree first and ask for only part of the map.
+///
+/// Requests carry the size of any partial the `PartialStore` holds for
+/// the entry, and whole-file transfers start at the offset the peer
+/// last asked for, so an interrupted transfer does not start over.
 pub struct TcpSender {
     state: Arc<AppState>,
+    partials: Arc<PartialStore>,
+    opener: Arc<dyn StreamOpener>,
+    no_delta: RwLock<HashSet<Uuid>>,
+    no_paging: RwLock<HashSet<Uuid>>,
 }
 
 impl TcpSender {
-    pub fn new(state: Arc<AppState>) -> Self {
-        Self { state }
+    pub(super) fn new(
+        state: Arc<AppState>,
+        partials: Arc<PartialStore>,
+        opener: Arc<dyn StreamOpener>,
+    ) -> Self {
+        Self {
+            state,
+            partials,
+            opener,
+            no_delta: RwLock::new(HashSet::new()),
+            no_paging: RwLock::new(HashSet::new()),
+        }
+    }
+
+    /// Opens a stream to `target` through the opener, which only hands
+    /// out streams to approved devices.
+    async fn connect(&self, target: SocketAddr) -> TransportResult<PeerStream> {
+        self.opener.open_stream(target).await
     }
 
-    pub async fn send_data(&self, target: IpAddr, data: TransportData) -> TransportResult<()> {
+    pub async fn send_data(&self, target: SocketAddr, data: TransportData) -> TransportResult<()> {
         let kind = TcpStreamKind::from(&data);
 
         match data {
@@ -45,18 +98,78 @@ impl TcpSender {
         }
     }
 
+    /// Sends our handshake as a paged header followed by the entry map
+    /// in pages. A device that drops the paged frame unanswered
+    /// predates paging; it is remembered in `no_paging` and gets the
+    /// whole map inline, on a new stream, from then on.
     async fn send_handshake(
         &self,
-        target: IpAddr,
+        target: SocketAddr,
         hs_data: HandshakeData,
         kind: TcpStreamKind,
     ) -> TransportResult<()> {
-        let socket = SocketAddr::new(target, self.state.ports().transport);
-        let mut stream = TcpStream::connect(socket).await?;
+        let mut stream = self.connect(target).await?;
+        let remote_id = stream.remote_id();
+        let (header, mut entries) = HandshakeHeader::split(hs_data);
+
+        let pages_known = self
+            .state
+            .peer_supports(&remote_id, Capability::PagedHandshake)
+            .await;
+        if !pages_known && self.no_paging.read().await.contains(&remote_id) {
+            return self
+                .send_inline_handshake(&mut stream, header, entries, kind)
+                .await;
+        }
 
-        let contents = serde_json::to_vec(&hs_data)?;
+        let paged = kind.paged();
+        info!(kind = paged.to_string(), target = ?target, "sending");
 
-        info!(kind = kind.to_string(), target = ?target, "sending");
+        let contents = serde_json::to_vec(&header)?;
+        stream.write_all(self.state.local_id().as_bytes()).await?;
+        stream.write_all(&[paged as u8]).await?;
+        stream
+            .write_all(&(contents.len() as u32).to_be_bytes())
+            .await?;
+        stream.write_all(&contents).await?;
+        stream.flush().await?;
+
+        let mut answer = [0u8; 1];
+        match stream.read_exact(&mut answer).await {
+            Ok(_) if answer[0] == SEND_PAGES => Self::send_pages(&mut stream, &mut entries).await,
+            Ok(_) if answer[0] == DESCEND => {
+                Self::serve_descent(&self.state, &mut stream, &mut entries).await
+            }
+            Ok(_) => Ok(()),
+            Err(err) if pages_known => Err(err.into()),
+            Err(_) => {
+                warn!(peer_id = %remote_id, "peer dropped paged handshake; sending entry map inline");
+                self.no_paging.write().await.insert(remote_id);
+
+                let mut stream = self.connect(target).await?;
+                self.send_inline_handshake(&mut stream, header, entries, kind)
+                    .await
+            }
+        }
+    }
+
+    async fn send_inline_handshake<S: AsyncWrite + Unpin>(
+        &self,
+        stream: &mut S,
+        header: HandshakeHeader,
+        mut entries: HandshakeEntries,
+        kind: TcpStreamKind,
+    ) -> TransportResult<()> {
+        let mut map = HashMap::new();
+        while let Some(page) = entries.next_page().await {
+            map.extend(page?.into_iter().map(|e| (e.name.clone(), e)));
+        }
+        let contents = serde_json::to_vec(&InlineHandshake {
+            header,
+            entries: map,
+        })?;
+
+        info!(kind = kind.to_string(), "sending");
 
         stream.write_all(self.state.local_id().as_bytes()).await?;
         stream.write_all(&[kind as u8]).await?;
@@ -64,13 +177,83 @@ impl TcpSender {
             .write_all(&(contents.len() as u32).to_be_bytes())
             .await?;
         stream.write_all(&contents).await?;
+        stream.flush().await?;
 
         Ok(())
     }
 
-    async fn send_metadata(&self, target: IpAddr, entry: EntryInfo) -> TransportResult<()> {
-        let socket = SocketAddr::new(target, self.state.ports().transport);
-        let mut stream = TcpStream::connect(socket).await?;
+    /// Answers a receiver walking our digest tree until it names the
+    /// scopes it still needs, then pages through those.
+    pub(super) async fn serve_descent<S: AsyncRead + AsyncWrite + Unpin>(
+        state: &AppState,
+        stream: &mut S,
+        entries: &mut HandshakeEntries,
+    ) -> TransportResult<()> {
+        loop {
+            match read_descent_json(stream).await? {
+                DescentRequest::Expand(dirs) => {
+                    if dirs.len() > MAX_DESCENT_DIRS {
+                        return Err(TransportError::new(&format!(
+                            "Descent step asks for {} directories, over MAX_DESCENT_DIRS {MAX_DESCENT_DIRS}",
+                            dirs.len()
+                        )));
+                    }
+
+                    let summaries: Vec<_> = {
+                        let tree = state.digest_tree().await;
+                        dirs.iter().map(|dir| tree.summary(dir)).collect()
+                    };
+                    write_descent_json(stream, &summaries).await?;
+                }
+                DescentRequest::Fetch(scopes) => {
+                    trace!(scopes = scopes.len(), "descent done");
+                    entries.restrict(scopes);
+                    return Self::send_pages(stream, entries).await;
+                }
+            }
+        }
+    }
+
+    /// Writes `entries` as length-prefixed JSON arrays of at most
+    /// `MAX_HANDSHAKE_PAGE_SIZE` bytes, then a zero length.
+    pub(super) async fn send_pages<W: AsyncWrite + Unpin>(
+        writer: &mut W,
+        entries: &mut HandshakeEntries,
+    ) -> TransportResult<()> {
+        let mut page: Vec<u8> = Vec::new();
+
+        while let Some(entries) = entries.next_page().await {
+            for entry in entries? {
+                let json = serde_json::to_vec(&entry)?;
+                if !page.is_empty() && page.len() + json.len() + 2 > MAX_HANDSHAKE_PAGE_SIZE {
+                    Self::write_page(writer, &mut page).await?;
+                }
+                page.push(if page.is_empty() { b'[' } else { b',' });
+                page.extend_from_slice(&json);
+            }
+        }
+        if !page.is_empty() {
+            Self::write_page(writer, &mut page).await?;
+        }
+
+        writer.write_all(&0u32.to_be_bytes()).await?;
+        writer.flush().await?;
+        Ok(())
+    }
+
+    async fn write_page<W: AsyncWrite + Unpin>(
+        writer: &mut W,
+        page: &mut Vec<u8>,
+    ) -> TransportResult<()> {
+        page.push(b']');
+        writer.write_all(&(page.len() as u32).to_be_bytes()).await?;
+        writer.write_all(page).await?;
+        page.clear();
+        Ok(())
+    }
+
+    async fn send_metadata(&self, target: SocketAddr, entry: EntryInfo) -> TransportResult<()> {
+        let mut stream = self.connect(target).await?;
 
         let kind = TcpStreamKind::Metadata;
         let contents = serde_json::to_vec(&entry)?;
@@ -83,17 +266,31 @@ impl TcpSender {
             .write_all(&u32::to_be_bytes(contents.len() as u32))
             .await?;
         stream.write_all(&contents).await?;
+        stream.flush().await?;
         Ok(())
     }
 
-    async fn send_request(&self, target: IpAddr, entry: EntryInfo) -> TransportResult<()> {
-        let socket = SocketAddr::new(target, self.state.ports().transport);
-        let mut stream = TcpStream::connect(socket).await?;
+    /// Requests `entry`, asking to resume after the bytes of a partial
+    /// kept from an interrupted transfer of the same hash.
+    async fn send_request(&self, target: SocketAddr, entry: EntryInfo) -> TransportResult<()> {
+        let mut stream = self.connect(target).await?;
 
         let kind = TcpStreamKind::Request;
-        let contents = serde_json::to_vec(&entry)?;
+        let offset = match self
+            .state
+            .peer_supports(&stream.remote_id(), Capability::Resume)
+            .await
+        {
+            true => self.partials.resume_offset(&entry).await,
+            false => 0,
+        };
+        let contents = serde_json::to_vec(&EntryAtOffset {
+            entry,
+            offset,
+            compression: None,
+        })?;
 
-        info!(kind = kind.to_string(), target = ?target, entry_name = ?&entry.name, "sending");
+        info!(kind = kind.to_string(), target = ?target, offset, "sending");
 
         stream.write_all(self.state.local_id().as_bytes()).await?;
         stream.write_all(&[kind as u8]).await?;
@@ -101,17 +298,23 @@ impl TcpSender {
             .write_all(&u32::to_be_bytes(contents.len() as u32))
             .await?;
         stream.write_all(&contents).await?;
+        stream.flush().await?;
         Ok(())
     }
 
     /// Streams an entry's bytes to `target`, framed by the wire
     /// protocol: device id, kind tag, metadata-length + JSON, entry
-    /// size, then `entry_size` bytes of content. Logs a warning (but
-    /// still completes the transfer) if the file changes during
-    /// streaming so the receiver can reject by hash mismatch.
-    async fn send_entry(&self, target: IpAddr, entry: EntryInfo) -> TransportResult<()> {
-        let socket = SocketAddr::new(target, self.state.ports().transport);
-        let mut stream = TcpStream::connect(socket).await?;
+    /// size, then `entry_size - offset` bytes of content, where
+    /// `offset` is where the peer asked to resume and is echoed in the
+    /// JSON. Logs a warning (but still completes the transfer) if the
+    /// file changes during streaming so the receiver can reject by hash
+    /// mismatch.
+    ///
+    /// Large files are offered as a `DeltaTransfer` first when the peer
+    /// supports it. If the offer fails, the file is resent whole on a
+    /// new stream.
+    async fn send_entry(&self, target: SocketAddr, entry: EntryInfo) -> TransportResult<()> {
+        let mut stream = self.connect(target).await?;
 
         let path = entry.name.to_canonical(self.state.home_path());
         // Open the file first, then derive the wire size from the same handle
@@ -122,10 +325,113 @@ impl TcpSender {
         let mut file = File::open(&path).await?;
         let entry_size = file.metadata().await?.len();
 
-        let kind = TcpStreamKind::Transfer;
-        let metadata_json = serde_json::to_vec(&entry)?;
+        let remote_id = stream.remote_id();
+        if entry_size > DELTA_BLOCK_SIZE as u64
+            && self
+                .state
+                .peer_supports(&remote_id, Capability::Delta)
+                .await
+            && !self.no_delta.read().await.contains(&remote_id)
+        {
+            let (digests, computed_hash) =
+                delta::hash_blocks(&mut file, entry_size, DELTA_BLOCK_SIZE).await?;
+            let manifest = BlockManifest {
+                entry_size,
+                block_size: DELTA_BLOCK_SIZE,
+                digests,
+            };
+
+            self.write_entry_header(
+                &mut stream,
+                TcpStreamKind::DeltaTransfer,
+                target,
+                &entry,
+                0,
+                None,
+            )
+            .await?;
 
-        info!(kind = kind.to_string(), target = ?target, entry_name = ?&entry.name, "sending");
+            match Self::offer_blocks(&mut stream, &manifest).await {
+                Ok(Some(needed)) => {
+                    let sent =
+                        Self::send_blocks(&mut stream, &mut file, &manifest, &needed).await?;
+                    stream.flush().await?;
+
+                    trace!(entry_name = ?&entry.name, sent, total = needed.len(), "delta blocks sent");
+                    Self::warn_if_changed(&entry, &computed_hash);
+                    self.partials.set_requested(remote_id, &entry.name, 0).await;
+                    return Ok(());
+                }
+                Ok(None) => {
+                    warn!(peer_id = %remote_id, "Delta transfer refused, sending whole files");
+                    self.no_delta.write().await.insert(remote_id);
+                }
+                Err(TransportError::Failure(message)) => {
+                    warn!(peer_id = %remote_id, entry_name = ?&entry.name, "Delta offer failed, sending the file whole: {message}");
+                }
+            }
+            stream = self.connect(target).await?;
+            file.seek(SeekFrom::Start(0)).await?;
+        }
+
+        // The offset stays recorded until a transfer succeeds, so the
+        // application's retries resume from the same place.
+        let offset = match self.partials.requested(remote_id, &entry.name).await {
+            offset if offset <= entry_size => offset,
+            _ => 0,
+        };
+        file.seek(SeekFrom::Start(offset)).await?;
+
+        let compression = match self.state.peer_supports(&remote_id, Capability::Zstd).await {
+            true => compress::choose(&entry.name, &mut file).await?,
+            false => None,
+        };
+
+        self.write_entry_header(
+            &mut stream,
+            TcpStreamKind::Transfer,
+            target,
+            &entry,
+            offset,
+            compression,
+        )
+        .await?;
+        stream.write_all(&u64::to_be_bytes(entry_size)).await?;
+
+        let computed_hash = Self::stream_file_to(
+            &mut file,
+            &mut stream,
+            entry_size - offset,
+            TRANSFER_CHUNK_SIZE,
+            compression,
+        )
+        .await?;
+        stream.flush().await?;
+
+        // Only a whole-file stream hashes to the advertised value.
+        if offset == 0 {
+            Self::warn_if_changed(&entry, &computed_hash);
+        }
+        self.partials.set_requested(remote_id, &entry.name, 0).await;
+        Ok(())
+    }
+
+    async fn write_entry_header(
+        &self,
+        stream: &mut PeerStream,
+        kind: TcpStreamKind,
+        target: SocketAddr,
+        entry: &EntryInfo,
+        offset: u64,
+        compression: Option<Compression>,
+    ) -> TransportResult<()> {
+        let metadata_json = serde_json::to_vec(&EntryAtOffset {
+            entry: entry.clone(),
+            offset,
+            compression,
+        })?;
+
+        info!(kind = kind.to_string(), target = ?target, entry_name = ?&entry.name, offset, ?compression, "sending");
 
         stream.write_all(self.state.local_id().as_bytes()).await?;
         stream.write_all(&[kind as u8]).await?;
@@ -133,11 +439,10 @@ impl TcpSender {
             .write_all(&u32::to_be_bytes(metadata_json.len() as u32))
             .await?;
         stream.write_all(&metadata_json).await?;
-        stream.write_all(&u64::to_be_bytes(entry_size)).await?;
-
-        let computed_hash =
-            Self::stream_file_to(&mut file, &mut stream, entry_size, TRANSFER_CHUNK_SIZE).await?;
+        Ok(())
+    }
 
+    fn warn_if_changed(entry: &EntryInfo, computed_hash: &str) {
         if let Some(expected) = entry.hash.as_deref()
             && computed_hash != expected
         {
@@ -146,19 +451,69 @@ impl TcpSender {
                 "file changed during transfer; receiver will reject by hash mismatch",
             );
         }
+    }
 
-        Ok(())
+    /// Writes `entry_size` and the block manifest, then waits for the
+    /// receiver's bitmap of the blocks it lacks. Returns `None` if the
+    /// receiver closes the stream instead of answering, which is how a
+    /// device that predates delta transfers refuses the offer.
+    pub(super) async fn offer_blocks<S: AsyncRead + AsyncWrite + Unpin>(
+        stream: &mut S,
+        manifest: &BlockManifest,
+    ) -> TransportResult<Option<Vec<bool>>> {
+        stream
+            .write_all(&u64::to_be_bytes(manifest.entry_size))
+            .await?;
+        manifest.write(stream).await?;
+        stream.flush().await?;
+
+        let mut bitmap = vec![0u8; manifest.digests.len().div_ceil(8)];
+        match stream.read_exact(&mut bitmap).await {
+            Ok(_) => Ok(Some(delta::decode_needed(&bitmap, manifest.digests.len()))),
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Writes the bytes of every block marked in `needed`, in index
+    /// order. Returns how many blocks were sent.
+    pub(super) async fn send_blocks<W, R>(
+        writer: &mut W,
+        file: &mut R,
+        manifest: &BlockManifest,
+        needed: &[bool],
+    ) -> TransportResult<usize>
+    where
+        W: AsyncWrite + Unpin,
+        R: AsyncRead + AsyncSeek + Unpin,
+    {
+        let mut buf = vec![0u8; manifest.block_size];
+        let mut sent = 0;
+
+        for (i, _) in needed.iter().enumerate().filter(|(_, n)| **n) {
+            let len = manifest.block_len(i);
+            file.seek(SeekFrom::Start(i as u64 * manifest.block_size as u64))
+                .await?;
+            delta::read_padded(file, &mut buf[..len]).await?;
+            writer.write_all(&buf[..len]).await?;
+            sent += 1;
+        }
+
+        Ok(sent)
     }
 
     /// Stream exactly `total` bytes from `file` to `writer` in `chunk_size` chunks,
     /// returning the hex-encoded SHA-256 of the bytes streamed. If the file is
     /// shorter than `total`, the remainder is zero-padded so the wire framing
-    /// matches the size advertised in the header.
+    /// matches the size advertised in the header. With `compression`, each
+    /// chunk goes out as its raw length, frame length and zstd frame, and the
+    /// hash still covers the raw bytes.
     pub(super) async fn stream_file_to<R, W>(
         file: &mut R,
         writer: &mut W,
         total: u64,
         chunk_size: usize,
+        compression: Option<Compression>,
     ) -> TransportResult<String>
     where
         R: AsyncRead + Unpin,
@@ -184,7 +539,17 @@ impl TcpSender {
                 buf[filled..want].fill(0);
             }
             hasher.update(&buf[..want]);
-            writer.write_all(&buf[..want]).await?;
+            match compression {
+                Some(Compression::Zstd) => {
+                    let frame = compress::compress_chunk(&buf[..want])?;
+                    writer.write_all(&u32::to_be_bytes(want as u32)).await?;
+                    writer
+                        .write_all(&u32::to_be_bytes(frame.len() as u32))
+                        .await?;
+                    writer.write_all(&frame).await?;
+                }
+                None => writer.write_all(&buf[..want]).await?,
+            }
             remaining -= want as u64;
         }
 
@@ -195,7 +560,6 @@ impl TcpSender {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::application::network::transport::interface::TransportError;
     use std::io::Cursor;
 
     fn ok<T>(res: TransportResult<T>) -> T {
@@ -212,7 +576,7 @@ mod tests {
         let mut dst: Vec<u8> = Vec::new();
 
         let hash =
-            ok(TcpSender::stream_file_to(&mut src, &mut dst, payload.len() as u64, 16).await);
+            ok(TcpSender::stream_file_to(&mut src, &mut dst, payload.len() as u64, 16, None).await);
 
         assert_eq!(dst, payload);
         assert_eq!(hash, format!("{:x}", Sha256::digest(&payload)));
@@ -224,7 +588,7 @@ mod tests {
         let mut src = Cursor::new(payload.clone());
         let mut dst: Vec<u8> = Vec::new();
 
-        ok(TcpSender::stream_file_to(&mut src, &mut dst, payload.len() as u64, 16).await);
+        ok(TcpSender::stream_file_to(&mut src, &mut dst, payload.len() as u64, 16, None).await);
 
         assert_eq!(dst, payload);
     }
@@ -235,10 +599,118 @@ mod tests {
         let mut src = Cursor::new(payload);
         let mut dst: Vec<u8> = Vec::new();
 
-        ok(TcpSender::stream_file_to(&mut src, &mut dst, 24, 8).await);
+        ok(TcpSender::stream_file_to(&mut src, &mut dst, 24, 8, None).await);
 
         assert_eq!(dst.len(), 24);
         assert_eq!(&dst[..10], &[0xAB; 10]);
         assert_eq!(&dst[10..], &[0u8; 14]);
     }
+
+    #[tokio::test]
+    async fn compressed_stream_round_trips_through_stream_to_file() {
+        let payload = "{\"level\":\"info\",\"msg\":\"synced\"}\n"
+            .repeat(100)
+            .into_bytes();
+        let mut wire: Vec<u8> = Vec::new();
+
+        let sent = ok(TcpSender::stream_file_to(
+            &mut Cursor::new(payload.clone()),
+            &mut wire,
+            payload.len() as u64,
+            1024,
+            Some(Compression::Zstd),
+        )
+        .await);
+        assert!(wire.len() < payload.len() / 4);
+
+        let mut dst: Vec<u8> = Vec::new();
+        let received = ok(
+            crate::infra::network::tcp::receiver::TcpReceiver::stream_to_file(
+                &mut crate::infra::network::tcp::resume::TransferHasher::new(),
+                &mut Cursor::new(wire),
+                &mut dst,
+                payload.len() as u64,
+                1024,
+                Some(Compression::Zstd),
+            )
+            .await,
+        );
+
+        assert_eq!(dst, payload);
+        assert_eq!(sent, received);
+        assert_eq!(sent, format!("{:x}", Sha256::digest(&payload)));
+    }
+
+    /// A peer that predates delta transfers drops the stream on the
+    /// unknown kind; the offer reports the refusal so `send_entry`
+    /// stops offering deltas to it.
+    #[tokio::test]
+    async fn offer_blocks_is_refused_when_peer_hangs_up_without_answering() {
+        let (mut holder, mut peer) = tokio::io::duplex(64 * 1024);
+        let manifest = BlockManifest {
+            entry_size: 2 * DELTA_BLOCK_SIZE as u64,
+            block_size: DELTA_BLOCK_SIZE,
+            digests: vec![[0u8; 32]; 2],
+        };
+        let entry_size = manifest.entry_size;
+        let reader = tokio::spawn(async move {
+            peer.read_u64().await.unwrap();
+            assert!(BlockManifest::read(&mut peer, entry_size).await.is_ok());
+        });
+
+        let needed = TcpSender::offer_blocks(&mut holder, &manifest).await;
+        reader.await.unwrap();
+        assert!(matches!(needed, Ok(None)));
+    }
+
+    /// A connection lost mid-offer is an error, not a refusal, so the
+    /// peer keeps getting delta offers.
+    #[tokio::test]
+    async fn offer_blocks_fails_when_the_connection_is_lost() {
+        let (mut holder, peer) = tokio::io::duplex(64 * 1024);
+        drop(peer);
+        let manifest = BlockManifest {
+            entry_size: 2 * DELTA_BLOCK_SIZE as u64,
+            block_size: DELTA_BLOCK_SIZE,
+            digests: vec![[0u8; 32]; 2],
+        };
+
+        assert!(
+            TcpSender::offer_blocks(&mut holder, &manifest)
+                .await
+                .is_err()
+        );
+    }
+
+    /// Pages never exceed `MAX_HANDSHAKE_PAGE_SIZE`, however large the
+    /// entries, and the map ends with a zero length.
+    #[tokio::test]
+    async fn send_pages_splits_the_map_under_the_page_cap() {
+        use crate::{domain::EntryKind, infra::network::tcp::receiver::TcpReceiver};
+
+        let entries: HashMap<_, _> = (0..40)
+            .map(|i| EntryInfo {
+                name: format!("sync/{i}.bin").into(),
+                kind: EntryKind::File,
+                hash: Some("h".repeat(60 * 1024)),
+                version: HashMap::from([(Uuid::new_v4(), 1)]),
+                modified: None,
+                removed_at: None,
+            })
+            .map(|e| (e.name.clone(), e))
+            .collect();
+        let mut wire = Vec::new();
+        ok(TcpSender::send_pages(&mut wire, &mut HandshakeEntries::from_map(entries)).await);
+
+        let mut reader = Cursor::new(wire);
+        let (mut pages, mut total) = (0, 0);
+        while let Some(page) = ok(TcpReceiver::read_page(&mut reader).await) {
+            pages += 1;
+            total += page.len();
+        }
+
+        assert!(pages > 1);
+        assert_eq!(total, 40);
+        assert_eq!(reader.position() as usize, reader.get_ref().len());
+    }
 }
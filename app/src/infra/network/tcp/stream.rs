@@ -0,0 +1,171 @@
+use crate::application::network::transport::interface::TransportResult;
+use std::{
+    io,
+    net::SocketAddr,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    time::{Instant, Sleep, sleep},
+};
+use uuid::Uuid;
+
+/// Anything a message can be read from and written to.
+pub trait PeerIo: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> PeerIo for T {}
+
+/// A stream carrying one message to or from an authenticated device,
+/// whichever transport it belongs to.
+pub struct PeerStream {
+    io: Box<dyn PeerIo>,
+    remote_id: Uuid,
+}
+
+impl PeerStream {
+    /// Wraps `io`, whose other end has proved it owns `remote_id`.
+    pub fn new(io: impl PeerIo + 'static, remote_id: Uuid) -> Self {
+        Self {
+            io: Box::new(io),
+            remote_id,
+        }
+    }
+
+    pub fn remote_id(&self) -> Uuid {
+        self.remote_id
+    }
+}
+
+/// Hands out outbound streams for `TcpSender` to write messages on.
+#[async_trait::async_trait]
+pub trait StreamOpener: Send + Sync {
+    /// Opens a stream to the device at `target`. Implementations refuse
+    /// devices the user has not approved.
+    async fn open_stream(&self, target: SocketAddr) -> TransportResult<PeerStream>;
+}
+
+impl AsyncRead for PeerStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut *self.io).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for PeerStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut *self.io).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut *self.io).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut *self.io).poll_shutdown(cx)
+    }
+}
+
+/// Wraps a stream so that it fails with `TimedOut` once `idle` passes
+/// without any bytes read or written, however long it stays busy.
+pub struct IdleTimeout<S> {
+    io: S,
+    idle: Duration,
+    deadline: Pin<Box<Sleep>>,
+}
+
+impl<S> IdleTimeout<S> {
+    pub fn new(io: S, idle: Duration) -> Self {
+        Self {
+            io,
+            idle,
+            deadline: Box::pin(sleep(idle)),
+        }
+    }
+
+    /// Pushes the deadline back after progress, or reports whether it
+    /// passed while the stream was waiting.
+    fn poll_idle<T>(
+        &mut self,
+        cx: &mut Context<'_>,
+        poll: Poll<io::Result<T>>,
+    ) -> Poll<io::Result<T>> {
+        match poll {
+            Poll::Ready(result) => {
+                let idle = self.idle;
+                self.deadline.as_mut().reset(Instant::now() + idle);
+                Poll::Ready(result)
+            }
+            Poll::Pending => match self.deadline.as_mut().poll(cx) {
+                Poll::Ready(()) => Poll::Ready(Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "Stream went idle",
+                ))),
+                Poll::Pending => Poll::Pending,
+            },
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for IdleTimeout<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let poll = Pin::new(&mut self.io).poll_read(cx, buf);
+        self.poll_idle(cx, poll)
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for IdleTimeout<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.io).poll_write(cx, buf);
+        self.poll_idle(cx, poll)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let poll = Pin::new(&mut self.io).poll_flush(cx);
+        self.poll_idle(cx, poll)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.io).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt, duplex};
+
+    #[tokio::test]
+    async fn idle_timeout_fails_a_stalled_stream_but_not_a_slow_one() {
+        let idle = Duration::from_millis(300);
+        let (mut remote, local) = duplex(64);
+        let mut stream = IdleTimeout::new(local, idle);
+        let mut buf = [0u8; 1];
+
+        // Slower in total than `idle`, but never idle for that long.
+        for _ in 0..4 {
+            tokio::time::sleep(idle / 3).await;
+            remote.write_all(b"x").await.unwrap();
+            stream.read_exact(&mut buf).await.unwrap();
+        }
+
+        let err = stream.read_exact(&mut buf).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    }
+}
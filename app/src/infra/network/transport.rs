@@ -0,0 +1,39 @@
+use crate::{
+    application::AppState,
+    application::network::transport::interface::{TransportInterface, TransportResult},
+    domain::{TransportData, TransportEvent, TransportProtocol},
+    infra::network::{quic::QuicAdapter, tcp::TcpAdapter},
+};
+use std::{net::SocketAddr, sync::Arc};
+
+/// The transport adapter `config.toml` selects, behind one type so the
+/// production `Synchronizer` does not depend on the choice.
+pub enum TransportAdapter {
+    Tcp(TcpAdapter),
+    Quic(QuicAdapter),
+}
+
+impl TransportAdapter {
+    pub async fn new(state: Arc<AppState>) -> Self {
+        match state.transport() {
+            TransportProtocol::Tcp => Self::Tcp(TcpAdapter::new(state).await),
+            TransportProtocol::Quic => Self::Quic(QuicAdapter::new(state).await),
+        }
+    }
+}
+
+impl TransportInterface for TransportAdapter {
+    async fn recv(&self) -> TransportResult<TransportEvent> {
+        match self {
+            Self::Tcp(adapter) => adapter.recv().await,
+            Self::Quic(adapter) => adapter.recv().await,
+        }
+    }
+
+    async fn send(&self, target: SocketAddr, data: TransportData) -> TransportResult<()> {
+        match self {
+            Self::Tcp(adapter) => adapter.send(target, data).await,
+            Self::Quic(adapter) => adapter.send(target, data).await,
+        }
+    }
+}
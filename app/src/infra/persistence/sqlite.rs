@@ -2,19 +2,28 @@ use crate::{
     application::persistence::interface::{
         PersistenceError, PersistenceInterface, PersistenceResult,
     },
-    domain::{EntryInfo, EntryKind},
+    domain::{
+        ConflictRecord, EntryInfo, EntryKind, MergeBase, Snapshot, TrashRecord, VersionVector,
+    },
 };
 use sqlx::{
     Error, Executor, FromRow, Pool, Row, Sqlite, SqlitePool,
     sqlite::{SqliteConnectOptions, SqliteRow},
 };
 use std::path::Path;
+use uuid::Uuid;
 
 /// `sqlx`-backed SQLite adapter for `PersistenceInterface`.
 ///
 /// Stores one row per `EntryInfo` and serializes the `VersionVector`
-/// inline. Accepts `:memory:` as a path so tests can run against an
-/// in-process database without touching disk.
+/// inline; approved device ids live in a separate `trusted_devices`
+/// table, per-device tombstone acknowledgements in `tombstone_acks`,
+/// unresolved conflicts in `conflicts`, trashed entries in `trash`,
+/// snapshots in `snapshots` with their entries in `snapshot_entries`,
+/// merge base hashes and versions in `merge_bases`, and conflicts
+/// waiting on a peer's version in `pending_merges`. Accepts `:memory:` as a path
+/// so tests can run against an in-process database without touching
+/// disk.
 pub struct SqliteDb {
     pool: Pool<Sqlite>,
 }
@@ -39,7 +48,100 @@ impl SqliteDb {
                 name TEXT PRIMARY KEY,
                 kind TEXT NOT NULL,
                 hash TEXT,
-                version TEXT NOT NULL
+                version TEXT NOT NULL,
+                modified INTEGER,
+                removed_at INTEGER
+            )",
+        )
+        .await?;
+
+        // Stores created before `modified` and `removed_at` were
+        // tracked lack the columns.
+        add_missing_column(&pool, "entries", "modified", "INTEGER").await?;
+        add_missing_column(&pool, "entries", "removed_at", "INTEGER").await?;
+
+        pool.execute(
+            "CREATE TABLE IF NOT EXISTS trusted_devices (
+                id TEXT PRIMARY KEY
+            )",
+        )
+        .await?;
+
+        pool.execute(
+            "CREATE TABLE IF NOT EXISTS tombstone_acks (
+                name TEXT NOT NULL,
+                device_id TEXT NOT NULL,
+                PRIMARY KEY (name, device_id)
+            )",
+        )
+        .await?;
+
+        pool.execute(
+            "CREATE TABLE IF NOT EXISTS conflicts (
+                id TEXT PRIMARY KEY,
+                path TEXT NOT NULL,
+                conflict_path TEXT NOT NULL,
+                peer_id TEXT NOT NULL,
+                local_version TEXT NOT NULL,
+                remote_version TEXT NOT NULL,
+                detected_at INTEGER NOT NULL
+            )",
+        )
+        .await?;
+
+        pool.execute(
+            "CREATE TABLE IF NOT EXISTS trash (
+                id TEXT PRIMARY KEY,
+                path TEXT NOT NULL,
+                kind TEXT NOT NULL,
+                peer_id TEXT NOT NULL,
+                deleted_at INTEGER NOT NULL
+            )",
+        )
+        .await?;
+
+        pool.execute(
+            "CREATE TABLE IF NOT EXISTS snapshots (
+                id TEXT PRIMARY KEY,
+                name TEXT NOT NULL,
+                dir TEXT NOT NULL,
+                taken_at INTEGER NOT NULL
+            )",
+        )
+        .await?;
+
+        pool.execute(
+            "CREATE TABLE IF NOT EXISTS snapshot_entries (
+                snapshot_id TEXT NOT NULL,
+                name TEXT NOT NULL,
+                kind TEXT NOT NULL,
+                hash TEXT,
+                version TEXT NOT NULL,
+                modified INTEGER,
+                removed_at INTEGER,
+                PRIMARY KEY (snapshot_id, name)
+            )",
+        )
+        .await?;
+        add_missing_column(&pool, "snapshot_entries", "removed_at", "INTEGER").await?;
+
+        pool.execute(
+            "CREATE TABLE IF NOT EXISTS merge_bases (
+                name TEXT PRIMARY KEY,
+                hash TEXT NOT NULL,
+                version TEXT
+            )",
+        )
+        .await?;
+
+        // Stores created before merge base versions were kept lack the
+        // column; their bases stay without one.
+        add_missing_column(&pool, "merge_bases", "version", "TEXT").await?;
+
+        pool.execute(
+            "CREATE TABLE IF NOT EXISTS pending_merges (
+                name TEXT PRIMARY KEY,
+                conflict_id TEXT NOT NULL
             )",
         )
         .await?;
@@ -48,19 +150,42 @@ impl SqliteDb {
     }
 }
 
+/// Adds `column` to `table` unless a store created by an older build
+/// already has it.
+async fn add_missing_column(
+    pool: &Pool<Sqlite>,
+    table: &str,
+    column: &str,
+    kind: &str,
+) -> Result<(), Error> {
+    let exists: bool =
+        sqlx::query_scalar("SELECT COUNT(*) > 0 FROM pragma_table_info(?) WHERE name = ?")
+            .bind(table)
+            .bind(column)
+            .fetch_one(pool)
+            .await?;
+    if !exists {
+        pool.execute(format!("ALTER TABLE {table} ADD COLUMN {column} {kind}").as_str())
+            .await?;
+    }
+    Ok(())
+}
+
 #[async_trait::async_trait]
 impl PersistenceInterface for SqliteDb {
     async fn insert_or_replace_entry(&self, entry: &EntryInfo) -> PersistenceResult<()> {
         let version_json = serde_json::to_string(&entry.version)?;
 
         sqlx::query(
-            "INSERT OR REPLACE INTO entries (name, kind, hash, version)
-                VALUES (?, ?, ?, ?)",
+            "INSERT OR REPLACE INTO entries (name, kind, hash, version, modified, removed_at)
+                VALUES (?, ?, ?, ?, ?, ?)",
         )
         .bind(&*entry.name)
         .bind(entry.kind.to_string())
         .bind(entry.hash.clone())
         .bind(version_json)
+        .bind(entry.modified.map(|modified| modified as i64))
+        .bind(entry.removed_at.map(|removed_at| removed_at as i64))
         .execute(&self.pool)
         .await?;
         Ok(())
@@ -83,6 +208,52 @@ impl PersistenceInterface for SqliteDb {
         Ok(entries)
     }
 
+    async fn list_entries_after(
+        &self,
+        after: Option<&str>,
+        limit: usize,
+    ) -> PersistenceResult<Vec<EntryInfo>> {
+        let entries = match after {
+            Some(after) => {
+                sqlx::query_as("SELECT * FROM entries WHERE name > ? ORDER BY name LIMIT ?")
+                    .bind(after)
+                    .bind(limit as i64)
+                    .fetch_all(&self.pool)
+                    .await?
+            }
+            None => {
+                sqlx::query_as("SELECT * FROM entries ORDER BY name LIMIT ?")
+                    .bind(limit as i64)
+                    .fetch_all(&self.pool)
+                    .await?
+            }
+        };
+
+        Ok(entries)
+    }
+
+    async fn list_entries_within(
+        &self,
+        dir: &str,
+        after: Option<&str>,
+        limit: usize,
+    ) -> PersistenceResult<Vec<EntryInfo>> {
+        // Names below `dir` sort between `dir/` and `dir0`, since '0'
+        // follows '/' in byte order.
+        let entries = sqlx::query_as(
+            "SELECT * FROM entries WHERE (name = ? OR (name > ? AND name < ?)) AND name > ? ORDER BY name LIMIT ?",
+        )
+        .bind(dir)
+        .bind(format!("{dir}/"))
+        .bind(format!("{dir}0"))
+        .bind(after.unwrap_or_default())
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(entries)
+    }
+
     async fn delete_entry(&self, name: &str) -> PersistenceResult<()> {
         sqlx::query("DELETE FROM entries WHERE name = ?")
             .bind(name)
@@ -90,6 +261,252 @@ impl PersistenceInterface for SqliteDb {
             .await?;
         Ok(())
     }
+
+    async fn insert_trusted_device(&self, id: &Uuid) -> PersistenceResult<()> {
+        sqlx::query("INSERT OR IGNORE INTO trusted_devices (id) VALUES (?)")
+            .bind(id.to_string())
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn list_trusted_devices(&self) -> PersistenceResult<Vec<Uuid>> {
+        let rows: Vec<String> = sqlx::query_scalar("SELECT id FROM trusted_devices")
+            .fetch_all(&self.pool)
+            .await?;
+
+        parse_device_ids(&rows)
+    }
+
+    async fn insert_tombstone_ack(&self, name: &str, device_id: &Uuid) -> PersistenceResult<()> {
+        sqlx::query("INSERT OR IGNORE INTO tombstone_acks (name, device_id) VALUES (?, ?)")
+            .bind(name)
+            .bind(device_id.to_string())
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn list_tombstone_acks(&self, name: &str) -> PersistenceResult<Vec<Uuid>> {
+        let rows: Vec<String> =
+            sqlx::query_scalar("SELECT device_id FROM tombstone_acks WHERE name = ?")
+                .bind(name)
+                .fetch_all(&self.pool)
+                .await?;
+
+        parse_device_ids(&rows)
+    }
+
+    async fn delete_tombstone_acks(&self, name: &str) -> PersistenceResult<()> {
+        sqlx::query("DELETE FROM tombstone_acks WHERE name = ?")
+            .bind(name)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn insert_conflict(&self, conflict: &ConflictRecord) -> PersistenceResult<()> {
+        sqlx::query(
+            "INSERT OR REPLACE INTO conflicts
+                (id, path, conflict_path, peer_id, local_version, remote_version, detected_at)
+                VALUES (?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(conflict.id.to_string())
+        .bind(&*conflict.path)
+        .bind(&*conflict.conflict_path)
+        .bind(conflict.peer.to_string())
+        .bind(serde_json::to_string(&conflict.local_version)?)
+        .bind(serde_json::to_string(&conflict.remote_version)?)
+        .bind(conflict.detected_at as i64)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn list_conflicts(&self) -> PersistenceResult<Vec<ConflictRecord>> {
+        let conflicts = sqlx::query_as("SELECT * FROM conflicts ORDER BY detected_at, rowid")
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(conflicts)
+    }
+
+    async fn delete_conflict(&self, id: &Uuid) -> PersistenceResult<()> {
+        sqlx::query("DELETE FROM conflicts WHERE id = ?")
+            .bind(id.to_string())
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn insert_trash(&self, record: &TrashRecord) -> PersistenceResult<()> {
+        sqlx::query(
+            "INSERT OR REPLACE INTO trash (id, path, kind, peer_id, deleted_at)
+                VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(record.id.to_string())
+        .bind(&*record.path)
+        .bind(record.kind.to_string())
+        .bind(record.peer.to_string())
+        .bind(record.deleted_at as i64)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn list_trash(&self) -> PersistenceResult<Vec<TrashRecord>> {
+        let trash = sqlx::query_as("SELECT * FROM trash ORDER BY deleted_at, rowid")
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(trash)
+    }
+
+    async fn delete_trash(&self, id: &Uuid) -> PersistenceResult<()> {
+        sqlx::query("DELETE FROM trash WHERE id = ?")
+            .bind(id.to_string())
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn insert_snapshot(
+        &self,
+        snapshot: &Snapshot,
+        entries: &[EntryInfo],
+    ) -> PersistenceResult<()> {
+        let id = snapshot.id.to_string();
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("INSERT INTO snapshots (id, name, dir, taken_at) VALUES (?, ?, ?, ?)")
+            .bind(&id)
+            .bind(&snapshot.name)
+            .bind(&*snapshot.dir)
+            .bind(snapshot.taken_at as i64)
+            .execute(&mut *tx)
+            .await?;
+        for entry in entries {
+            sqlx::query(
+                "INSERT INTO snapshot_entries
+                    (snapshot_id, name, kind, hash, version, modified, removed_at)
+                    VALUES (?, ?, ?, ?, ?, ?, ?)",
+            )
+            .bind(&id)
+            .bind(&*entry.name)
+            .bind(entry.kind.to_string())
+            .bind(entry.hash.clone())
+            .bind(serde_json::to_string(&entry.version)?)
+            .bind(entry.modified.map(|modified| modified as i64))
+            .bind(entry.removed_at.map(|removed_at| removed_at as i64))
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn list_snapshots(&self) -> PersistenceResult<Vec<Snapshot>> {
+        let snapshots = sqlx::query_as("SELECT * FROM snapshots ORDER BY taken_at, rowid")
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(snapshots)
+    }
+
+    async fn list_snapshot_entries(&self, id: &Uuid) -> PersistenceResult<Vec<EntryInfo>> {
+        let entries =
+            sqlx::query_as("SELECT * FROM snapshot_entries WHERE snapshot_id = ? ORDER BY name")
+                .bind(id.to_string())
+                .fetch_all(&self.pool)
+                .await?;
+
+        Ok(entries)
+    }
+
+    async fn delete_snapshot(&self, id: &Uuid) -> PersistenceResult<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM snapshot_entries WHERE snapshot_id = ?")
+            .bind(id.to_string())
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM snapshots WHERE id = ?")
+            .bind(id.to_string())
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn set_merge_base(
+        &self,
+        name: &str,
+        hash: &str,
+        version: &VersionVector,
+    ) -> PersistenceResult<()> {
+        sqlx::query("INSERT OR REPLACE INTO merge_bases (name, hash, version) VALUES (?, ?, ?)")
+            .bind(name)
+            .bind(hash)
+            .bind(serde_json::to_string(version)?)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_merge_base(&self, name: &str) -> PersistenceResult<Option<MergeBase>> {
+        let base = sqlx::query_as("SELECT hash, version FROM merge_bases WHERE name = ?")
+            .bind(name)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(base)
+    }
+
+    async fn delete_merge_base(&self, name: &str) -> PersistenceResult<()> {
+        sqlx::query("DELETE FROM merge_bases WHERE name = ?")
+            .bind(name)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn is_merge_base(&self, hash: &str) -> PersistenceResult<bool> {
+        let found: Option<i64> = sqlx::query_scalar("SELECT 1 FROM merge_bases WHERE hash = ?")
+            .bind(hash)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(found.is_some())
+    }
+
+    async fn set_pending_merge(&self, name: &str, id: &Uuid) -> PersistenceResult<()> {
+        sqlx::query("INSERT OR REPLACE INTO pending_merges (name, conflict_id) VALUES (?, ?)")
+            .bind(name)
+            .bind(id.to_string())
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn take_pending_merge(&self, name: &str) -> PersistenceResult<Option<Uuid>> {
+        let id: Option<String> =
+            sqlx::query_scalar("DELETE FROM pending_merges WHERE name = ? RETURNING conflict_id")
+                .bind(name)
+                .fetch_optional(&self.pool)
+                .await?;
+
+        id.map(|id| Uuid::parse_str(&id).map_err(|err| PersistenceError::Failure(err.to_string())))
+            .transpose()
+    }
+}
+
+fn parse_device_ids(rows: &[String]) -> PersistenceResult<Vec<Uuid>> {
+    rows.iter()
+        .map(|id| {
+            Uuid::parse_str(id)
+                .map_err(|err| PersistenceError::Failure(format!("Invalid device id: {err}")))
+        })
+        .collect()
 }
 
 impl std::fmt::Display for EntryKind {
@@ -101,31 +518,113 @@ impl std::fmt::Display for EntryKind {
     }
 }
 
+fn parse_kind(row: &SqliteRow) -> sqlx::Result<EntryKind> {
+    let kind_str: String = row.try_get("kind")?;
+    match kind_str.as_str() {
+        "F" => Ok(EntryKind::File),
+        "D" => Ok(EntryKind::Directory),
+        other => Err(Error::Decode(
+            format!("Unknown entry kind: {}", other).into(),
+        )),
+    }
+}
+
 impl FromRow<'_, SqliteRow> for EntryInfo {
     fn from_row(row: &SqliteRow) -> sqlx::Result<Self> {
         let name: String = row.try_get("name")?;
         let hash: Option<String> = row.try_get("hash")?;
+        let modified: Option<i64> = row.try_get("modified")?;
+        let removed_at: Option<i64> = row.try_get("removed_at")?;
 
         let version_json: String = row.try_get("version")?;
         let version =
             serde_json::from_str(&version_json).map_err(|err| Error::Decode(Box::new(err)))?;
 
-        let kind_str: String = row.try_get("kind")?;
-        let kind = match kind_str.as_str() {
-            "F" => EntryKind::File,
-            "D" => EntryKind::Directory,
-            other => {
-                return Err(Error::Decode(
-                    format!("Unknown entry kind: {}", other).into(),
-                ));
-            }
-        };
+        let kind = parse_kind(row)?;
 
         Ok(EntryInfo {
             name: name.into(),
             kind,
             version,
             hash,
+            modified: modified.map(|modified| modified as u64),
+            removed_at: removed_at.map(|removed_at| removed_at as u64),
+        })
+    }
+}
+
+impl FromRow<'_, SqliteRow> for ConflictRecord {
+    fn from_row(row: &SqliteRow) -> sqlx::Result<Self> {
+        let uuid = |column: &str| -> sqlx::Result<Uuid> {
+            let id: String = row.try_get(column)?;
+            Uuid::parse_str(&id).map_err(|err| Error::Decode(Box::new(err)))
+        };
+        let version = |column: &str| -> sqlx::Result<_> {
+            let json: String = row.try_get(column)?;
+            serde_json::from_str(&json).map_err(|err| Error::Decode(Box::new(err)))
+        };
+
+        let path: String = row.try_get("path")?;
+        let conflict_path: String = row.try_get("conflict_path")?;
+        let detected_at: i64 = row.try_get("detected_at")?;
+
+        Ok(ConflictRecord {
+            id: uuid("id")?,
+            path: path.into(),
+            conflict_path: conflict_path.into(),
+            peer: uuid("peer_id")?,
+            local_version: version("local_version")?,
+            remote_version: version("remote_version")?,
+            detected_at: detected_at as u64,
+        })
+    }
+}
+
+impl FromRow<'_, SqliteRow> for MergeBase {
+    fn from_row(row: &SqliteRow) -> sqlx::Result<Self> {
+        let version: Option<String> = row.try_get("version")?;
+
+        Ok(MergeBase {
+            hash: row.try_get("hash")?,
+            version: version
+                .map(|json| serde_json::from_str(&json))
+                .transpose()
+                .map_err(|err| Error::Decode(Box::new(err)))?,
+        })
+    }
+}
+
+impl FromRow<'_, SqliteRow> for TrashRecord {
+    fn from_row(row: &SqliteRow) -> sqlx::Result<Self> {
+        let uuid = |column: &str| -> sqlx::Result<Uuid> {
+            let id: String = row.try_get(column)?;
+            Uuid::parse_str(&id).map_err(|err| Error::Decode(Box::new(err)))
+        };
+
+        let path: String = row.try_get("path")?;
+        let deleted_at: i64 = row.try_get("deleted_at")?;
+
+        Ok(TrashRecord {
+            id: uuid("id")?,
+            path: path.into(),
+            kind: parse_kind(row)?,
+            peer: uuid("peer_id")?,
+            deleted_at: deleted_at as u64,
+        })
+    }
+}
+
+impl FromRow<'_, SqliteRow> for Snapshot {
+    fn from_row(row: &SqliteRow) -> sqlx::Result<Self> {
+        let id: String = row.try_get("id")?;
+        let dir: String = row.try_get("dir")?;
+        let taken_at: i64 = row.try_get("taken_at")?;
+
+        Ok(Snapshot {
+            id: Uuid::parse_str(&id).map_err(|err| Error::Decode(Box::new(err)))?,
+            name: row.try_get("name")?,
+            dir: dir.into(),
+            taken_at: taken_at as u64,
         })
     }
 }
@@ -148,7 +647,6 @@ mod tests {
     use crate::domain::{EntryKind, VersionVector};
     use std::collections::HashMap;
     use tempfile::tempdir;
-    use uuid::Uuid;
 
     async fn create_test_db() -> SqliteDb {
         SqliteDb::new(":memory:").await.unwrap()
@@ -164,6 +662,8 @@ mod tests {
             kind,
             hash,
             version,
+            modified: None,
+            removed_at: None,
         }
     }
 
@@ -209,6 +709,65 @@ mod tests {
         assert_eq!(retrieved.version, entry.version);
     }
 
+    #[tokio::test]
+    async fn test_modified_and_removed_at_survive_a_store_that_predates_them() {
+        let dir = tempdir().unwrap();
+        let db_path = dir.path().join("old.db");
+        let old = SqlitePool::connect_with(
+            SqliteConnectOptions::new()
+                .filename(&db_path)
+                .create_if_missing(true),
+        )
+        .await
+        .unwrap();
+        old.execute(
+            "CREATE TABLE entries (name TEXT PRIMARY KEY, kind TEXT NOT NULL, hash TEXT, version TEXT NOT NULL);
+             INSERT INTO entries VALUES ('old.txt', 'F', 'hash', '{}');",
+        )
+        .await
+        .unwrap();
+        old.close().await;
+
+        let db = SqliteDb::new(&db_path).await.unwrap();
+        let mut entry = create_test_entry("new.txt", EntryKind::File, Some("hash".into()));
+        entry.modified = Some(1_700_000_000);
+        entry.removed_at = Some(1_700_000_100);
+        db.insert_or_replace_entry(&entry).await.unwrap();
+
+        let old = db.get_entry("old.txt").await.unwrap().unwrap();
+        let new = db.get_entry("new.txt").await.unwrap().unwrap();
+        assert_eq!(old.modified, None);
+        assert_eq!(old.removed_at, None);
+        assert_eq!(new.modified, Some(1_700_000_000));
+        assert_eq!(new.removed_at, Some(1_700_000_100));
+    }
+
+    #[tokio::test]
+    async fn test_merge_bases_from_before_versions_were_kept_have_none() {
+        let dir = tempdir().unwrap();
+        let db_path = dir.path().join("old.db");
+        let old = SqlitePool::connect_with(
+            SqliteConnectOptions::new()
+                .filename(&db_path)
+                .create_if_missing(true),
+        )
+        .await
+        .unwrap();
+        old.execute(
+            "CREATE TABLE merge_bases (name TEXT PRIMARY KEY, hash TEXT NOT NULL);
+             INSERT INTO merge_bases VALUES ('old.txt', 'hash');",
+        )
+        .await
+        .unwrap();
+        old.close().await;
+
+        let db = SqliteDb::new(&db_path).await.unwrap();
+
+        let base = db.get_merge_base("old.txt").await.unwrap().unwrap();
+        assert_eq!(base.hash, "hash");
+        assert_eq!(base.version, None);
+    }
+
     #[tokio::test]
     async fn test_replace_entry() {
         let db = create_test_db().await;
@@ -258,6 +817,49 @@ mod tests {
         assert!(names.contains(&"dir2"));
     }
 
+    #[tokio::test]
+    async fn test_list_entries_after_walks_names_in_order() {
+        let db = create_test_db().await;
+
+        for name in ["b/2.txt", "a/1.txt", "c", "b/1.txt", "a"] {
+            let entry = create_test_entry(name, EntryKind::File, Some("h".to_string()));
+            db.insert_or_replace_entry(&entry).await.unwrap();
+        }
+
+        let first = db.list_entries_after(None, 2).await.unwrap();
+        let second = db.list_entries_after(Some("a/1.txt"), 2).await.unwrap();
+        let last = db.list_entries_after(Some("b/2.txt"), 2).await.unwrap();
+
+        let names = |entries: &[EntryInfo]| -> Vec<String> {
+            entries.iter().map(|e| e.name.to_string()).collect()
+        };
+        assert_eq!(names(&first), ["a", "a/1.txt"]);
+        assert_eq!(names(&second), ["b/1.txt", "b/2.txt"]);
+        assert_eq!(names(&last), ["c"]);
+    }
+
+    #[tokio::test]
+    async fn test_list_entries_within_stays_inside_the_directory() {
+        let db = create_test_db().await;
+
+        for name in ["a", "a.txt", "a/1.txt", "a/b/2.txt", "a0", "ab/3.txt"] {
+            let entry = create_test_entry(name, EntryKind::File, Some("h".to_string()));
+            db.insert_or_replace_entry(&entry).await.unwrap();
+        }
+
+        let first = db.list_entries_within("a", None, 2).await.unwrap();
+        let rest = db
+            .list_entries_within("a", Some("a/1.txt"), 2)
+            .await
+            .unwrap();
+
+        let names = |entries: &[EntryInfo]| -> Vec<String> {
+            entries.iter().map(|e| e.name.to_string()).collect()
+        };
+        assert_eq!(names(&first), ["a", "a/1.txt"]);
+        assert_eq!(names(&rest), ["a/b/2.txt"]);
+    }
+
     #[tokio::test]
     async fn test_delete_entry() {
         let db = create_test_db().await;
@@ -322,6 +924,8 @@ mod tests {
             kind: EntryKind::File,
             hash: Some("hash".to_string()),
             version: version.clone(),
+            modified: None,
+            removed_at: None,
         };
 
         db.insert_or_replace_entry(&entry).await.unwrap();
@@ -387,6 +991,8 @@ mod tests {
             kind: EntryKind::File,
             hash: Some("hash".to_string()),
             version: HashMap::new(),
+            modified: None,
+            removed_at: None,
         };
 
         db.insert_or_replace_entry(&entry).await.unwrap();
@@ -394,4 +1000,161 @@ mod tests {
         let retrieved = db.get_entry("test.txt").await.unwrap().unwrap();
         assert_eq!(retrieved.version.len(), 0);
     }
+
+    #[tokio::test]
+    async fn test_trusted_devices_round_trip_and_ignore_duplicates() {
+        let db = create_test_db().await;
+        let id = Uuid::new_v4();
+
+        assert!(db.list_trusted_devices().await.unwrap().is_empty());
+
+        db.insert_trusted_device(&id).await.unwrap();
+        db.insert_trusted_device(&id).await.unwrap();
+
+        assert_eq!(db.list_trusted_devices().await.unwrap(), vec![id]);
+    }
+
+    #[tokio::test]
+    async fn test_tombstone_acks_are_scoped_by_name() {
+        let db = create_test_db().await;
+        let device = Uuid::new_v4();
+
+        db.insert_tombstone_ack("a.txt", &device).await.unwrap();
+        db.insert_tombstone_ack("a.txt", &device).await.unwrap();
+        db.insert_tombstone_ack("b.txt", &device).await.unwrap();
+
+        assert_eq!(db.list_tombstone_acks("a.txt").await.unwrap(), vec![device]);
+
+        db.delete_tombstone_acks("a.txt").await.unwrap();
+        assert!(db.list_tombstone_acks("a.txt").await.unwrap().is_empty());
+        assert_eq!(db.list_tombstone_acks("b.txt").await.unwrap(), vec![device]);
+    }
+
+    #[tokio::test]
+    async fn test_conflicts_round_trip_oldest_first() {
+        let db = create_test_db().await;
+        let (local, peer) = (Uuid::new_v4(), Uuid::new_v4());
+        let conflict = |name: &str, detected_at| ConflictRecord {
+            id: Uuid::new_v4(),
+            path: format!("{name}.txt").into(),
+            conflict_path: format!("{name}_CONFLICT_{detected_at}_{local}.txt").into(),
+            peer,
+            local_version: HashMap::from([(local, 2), (peer, 1)]),
+            remote_version: HashMap::from([(peer, 2)]),
+            detected_at,
+        };
+        let (newer, older) = (conflict("b", 200), conflict("a", 100));
+
+        db.insert_conflict(&newer).await.unwrap();
+        db.insert_conflict(&older).await.unwrap();
+        assert_eq!(
+            db.list_conflicts().await.unwrap(),
+            vec![older.clone(), newer]
+        );
+
+        db.delete_conflict(&older.id).await.unwrap();
+        db.delete_conflict(&older.id).await.unwrap();
+        assert_eq!(db.list_conflicts().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_trash_round_trips_oldest_first() {
+        let db = create_test_db().await;
+        let trashed = |name: &str, kind, deleted_at| TrashRecord {
+            id: Uuid::new_v4(),
+            path: name.into(),
+            kind,
+            peer: Uuid::new_v4(),
+            deleted_at,
+        };
+        let newer = trashed("sync/b.txt", EntryKind::File, 200);
+        let older = trashed("sync/a", EntryKind::Directory, 100);
+
+        db.insert_trash(&newer).await.unwrap();
+        db.insert_trash(&older).await.unwrap();
+        assert_eq!(db.list_trash().await.unwrap(), vec![older.clone(), newer]);
+
+        db.delete_trash(&older.id).await.unwrap();
+        db.delete_trash(&older.id).await.unwrap();
+        assert_eq!(db.list_trash().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_snapshots_keep_their_entries_until_deleted() {
+        let db = create_test_db().await;
+        let snapshot = |name: &str, taken_at| Snapshot {
+            id: Uuid::new_v4(),
+            name: name.into(),
+            dir: "sync".into(),
+            taken_at,
+        };
+        let newer = snapshot("after", 200);
+        let older = snapshot("before", 100);
+        let entries = [
+            create_test_entry("sync/b.txt", EntryKind::File, Some("h".into())),
+            create_test_entry("sync/a", EntryKind::Directory, None),
+        ];
+
+        db.insert_snapshot(&newer, &entries[..1]).await.unwrap();
+        db.insert_snapshot(&older, &entries).await.unwrap();
+        assert_eq!(
+            db.list_snapshots().await.unwrap(),
+            vec![older.clone(), newer.clone()]
+        );
+        let names: Vec<String> = db
+            .list_snapshot_entries(&older.id)
+            .await
+            .unwrap()
+            .iter()
+            .map(|e| e.name.to_string())
+            .collect();
+        assert_eq!(names, ["sync/a", "sync/b.txt"]);
+
+        db.delete_snapshot(&older.id).await.unwrap();
+        db.delete_snapshot(&older.id).await.unwrap();
+        assert_eq!(db.list_snapshots().await.unwrap(), vec![newer.clone()]);
+        assert!(
+            db.list_snapshot_entries(&older.id)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(db.list_snapshot_entries(&newer.id).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_merge_bases_are_replaced_per_name() {
+        let db = create_test_db().await;
+
+        let version = VersionVector::from([(Uuid::new_v4(), 3)]);
+        db.set_merge_base("a.txt", "h1", &version).await.unwrap();
+        db.set_merge_base("b.txt", "h1", &version).await.unwrap();
+        db.set_merge_base("a.txt", "h2", &version).await.unwrap();
+
+        assert_eq!(
+            db.get_merge_base("a.txt").await.unwrap().unwrap(),
+            MergeBase {
+                hash: "h2".into(),
+                version: Some(version),
+            }
+        );
+        assert!(db.is_merge_base("h1").await.unwrap());
+
+        db.delete_merge_base("b.txt").await.unwrap();
+        db.delete_merge_base("b.txt").await.unwrap();
+        assert!(!db.is_merge_base("h1").await.unwrap());
+        assert_eq!(db.get_merge_base("b.txt").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_pending_merges_are_taken_once() {
+        let db = create_test_db().await;
+        let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+
+        db.set_pending_merge("a.txt", &first).await.unwrap();
+        db.set_pending_merge("a.txt", &second).await.unwrap();
+
+        assert_eq!(db.take_pending_merge("a.txt").await.unwrap(), Some(second));
+        assert_eq!(db.take_pending_merge("a.txt").await.unwrap(), None);
+    }
 }
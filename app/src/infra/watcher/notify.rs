@@ -1,7 +1,7 @@
 use crate::{
     application::{AppState, watcher::interface::FileWatcherInterface},
     domain::{CanonicalPath, ConfigWatcherEvent, HomeWatcherEvent, RelativePath, WatcherEventPath},
-    utils::fs::{is_ds_store, is_git_path},
+    utils::fs::{is_ds_store, is_git_path, is_synche_path},
 };
 use notify::{
     Config, Error, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher,
@@ -90,6 +90,7 @@ impl FileWatcherInterface for NotifyFileWatcher {
                         && let Ok(relative) = RelativePath::new(&canonical, self.state.home_path())
                         && !is_ds_store(&canonical)
                         && !is_git_path(&relative)
+                        && !is_synche_path(&relative)
                     {
                         match self.classify_path(&relative).await {
                             PathClassification::Ignored => continue,
@@ -64,6 +64,25 @@ impl SyncheDirs {
         self.data.join("device_id")
     }
 
+    /// Path of the persistent `device_key` file holding the device's
+    /// secret key seed. The device id is derived from this key, so the
+    /// two files always agree.
+    pub fn device_key_file(&self) -> CanonicalPath {
+        self.data.join("device_key")
+    }
+
+    /// Directory partially received files were kept in before they
+    /// moved under the home directory. Removed on startup.
+    pub fn legacy_partials_dir(&self) -> CanonicalPath {
+        self.data.join("partials")
+    }
+
+    /// Directory holding the text contents last held in common with a
+    /// peer, by hash, as the ancestors of three-way merges.
+    pub fn merge_bases_dir(&self) -> CanonicalPath {
+        self.data.join("merge_bases")
+    }
+
     /// Directory where the rolling log appender writes daily files.
     pub fn log_dir(&self) -> &CanonicalPath {
         &self.logs
@@ -176,10 +195,12 @@ mod tests {
         assert!(dirs.config().exists());
         assert!(dirs.logs().exists());
         assert_eq!(dirs.device_id_file().file_name().unwrap(), "device_id");
+        assert_eq!(dirs.device_key_file().file_name().unwrap(), "device_key");
         assert_eq!(dirs.config_file().file_name().unwrap(), "config.toml");
         assert_eq!(dirs.data_db_file().file_name().unwrap(), "data.db");
 
         assert!(dirs.device_id_file().starts_with(dirs.data().as_ref()));
+        assert!(dirs.device_key_file().starts_with(dirs.data().as_ref()));
         assert!(dirs.config_file().starts_with(dirs.config().as_ref()));
         assert!(dirs.log_dir().starts_with(dirs.logs().as_ref()));
     }
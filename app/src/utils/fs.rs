@@ -1,11 +1,15 @@
 use crate::domain::CanonicalPath;
 use sha2::{Digest, Sha256};
-use std::path::Path;
+use std::{path::Path, time::UNIX_EPOCH};
 use tokio::{
-    fs::File,
+    fs::{self, File},
     io::{self, AsyncReadExt},
 };
 
+/// Directory at the root of each sync directory where Synche keeps its
+/// own files, such as replaced versions. It is never synced.
+pub const SYNCHE_DIR: &str = ".synche";
+
 /// Returns the default platform-appropriate home directory for Synche,
 /// creating it if necessary.
 ///
@@ -54,6 +58,36 @@ pub async fn compute_hash(path: &CanonicalPath) -> io::Result<String> {
     Ok(hash)
 }
 
+/// Returns the lowercase hex SHA-256 of `bytes`, as `compute_hash`
+/// does for a file.
+pub fn hash_bytes(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+/// Returns when the file at `path` was last modified, in seconds since
+/// UNIX epoch, or `None` if the platform or the file cannot tell.
+pub async fn modified_secs(path: &CanonicalPath) -> Option<u64> {
+    let modified = fs::metadata(path).await.ok()?.modified().ok()?;
+    Some(modified.duration_since(UNIX_EPOCH).ok()?.as_secs())
+}
+
+/// Copies the file at `from` to `to`, replacing any file there, like
+/// `fs::copy`. The file is cloned when the filesystem can (a reflink on
+/// Btrfs, XFS or APFS, a block clone on ReFS), so the copy takes no
+/// space until either file changes; otherwise, e.g. across filesystems
+/// or on ext4, it is copied byte for byte.
+pub async fn clone_file(from: impl AsRef<Path>, to: impl AsRef<Path>) -> io::Result<()> {
+    let (from, to) = (from.as_ref().to_owned(), to.as_ref().to_owned());
+    match fs::remove_file(&to).await {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+        _ => {}
+    }
+    tokio::task::spawn_blocking(move || reflink_copy::reflink_or_copy(from, to))
+        .await
+        .map_err(io::Error::other)??;
+    Ok(())
+}
+
 /// Returns `true` if `path`'s final component is the macOS metadata
 /// file `.DS_Store`. These files are filtered out by the watcher and
 /// the entry scanner because syncing them is never useful.
@@ -69,10 +103,42 @@ pub fn is_git_path(path: &str) -> bool {
     path.split('/').any(|seg| seg == ".git")
 }
 
+/// Returns true if `path` is `SYNCHE_DIR` of its sync directory or
+/// inside it.
+///
+/// Matches `Docs/.synche` and `Docs/.synche/versions/a.txt~1700000000`,
+/// but not `Docs/notes/.synche`.
+pub fn is_synche_path(path: &str) -> bool {
+    path.split('/').nth(1) == Some(SYNCHE_DIR)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[tokio::test]
+    async fn clone_file_makes_an_independent_copy_over_any_existing_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let (from, to) = (dir.path().join("from"), dir.path().join("to"));
+        std::fs::write(&from, "original").unwrap();
+        std::fs::write(&to, "stale").unwrap();
+
+        clone_file(&from, &to).await.unwrap();
+        std::fs::write(&to, "edited").unwrap();
+
+        assert_eq!(std::fs::read_to_string(&from).unwrap(), "original");
+        assert_eq!(std::fs::read_to_string(&to).unwrap(), "edited");
+    }
+
+    #[test]
+    fn is_synche_path_matches_only_the_sync_dir_root() {
+        assert!(is_synche_path("Docs/.synche"));
+        assert!(is_synche_path("Docs/.synche/versions/a.txt~1700000000"));
+        assert!(!is_synche_path(".synche"));
+        assert!(!is_synche_path("Docs/notes/.synche"));
+        assert!(!is_synche_path("Docs/.synchers"));
+    }
+
     #[test]
     fn is_git_path_matches_exact_component() {
         assert!(is_git_path(".git"));
@@ -50,6 +50,21 @@ pub async fn test_env() -> TestEnv {
 
 /// Like [`test_env`] but seeds the config with the given sync directory names.
 pub async fn test_env_with_dirs(dirs: &[&str]) -> TestEnv {
+    test_env_with_ports(
+        dirs,
+        AppPorts {
+            http: 0,
+            presence: 0,
+            transport: 0,
+            broadcast: 0,
+        },
+    )
+    .await
+}
+
+/// Like [`test_env_with_dirs`] but with explicit ports, for tests that
+/// dial a listener another test env bound.
+pub async fn test_env_with_ports(dirs: &[&str], ports: AppPorts) -> TestEnv {
     let temp = TempDir::new().expect("create test temp dir");
 
     let home_path = temp.path().join("home");
@@ -60,20 +75,17 @@ pub async fn test_env_with_dirs(dirs: &[&str]) -> TestEnv {
 
     let seeded = Config {
         home_path: home.clone(),
+        cluster: None,
+        transport: Default::default(),
+        liveness: Default::default(),
+        discovery: Default::default(),
         directory: dirs.iter().map(|name| ConfigDirectory::new(name)).collect(),
+        peer: Vec::new(),
     };
     let contents = toml::to_string_pretty(&seeded).expect("serialize seeded config");
     std::fs::write(dirs_struct.config_file(), contents).expect("write seeded config");
 
-    let state = AppState::new(
-        dirs_struct.clone(),
-        AppPorts {
-            http: 0,
-            presence: 0,
-            transport: 0,
-        },
-    )
-    .await;
+    let state = AppState::new(dirs_struct.clone(), ports).await;
 
     TestEnv {
         temp,
@@ -55,7 +55,16 @@ export function dirListItem(name) {
           </details>`;
 }
 
-export function peerListItem({ id, addr, hostname, instance_id, last_seen, sync_dirs }) {
+export function peerListItem({
+  id,
+  addr,
+  hostname,
+  instance_id,
+  last_seen,
+  sync_dirs,
+  protocol_version,
+  capabilities,
+}) {
   const tsLabel = last_seen
     ? new Date(last_seen * 1000).toLocaleString()
     : "unknown";
@@ -63,6 +72,10 @@ export function peerListItem({ id, addr, hostname, instance_id, last_seen, sync_
     sync_dirs && sync_dirs.length
       ? `<ul>${sync_dirs.map((d) => `<li>${escapeHtml(d)}</li>`).join("")}</ul>`
       : "None";
+  const features =
+    capabilities && capabilities.length
+      ? capabilities.map(escapeHtml).join(", ")
+      : "none";
 
   return `<details class="list-item" id="peer-${id}">
             <summary><strong><svg class="lucide lucide-laptop-minimal-icon lucide-laptop-minimal" fill="none" height="20" stroke="currentColor" stroke-linecap="round"
@@ -86,6 +99,66 @@ export function peerListItem({ id, addr, hostname, instance_id, last_seen, sync_
             <p><strong>Instance ID:</strong> ${instance_id ?? "unknown"}</p>
             <p><strong>Last Seen:</strong> ${tsLabel}</p>
             <p><strong>Sync Directories:</strong> ${dirsList}</p>
+            <p><strong>Protocol:</strong> v${protocol_version ?? 0} (${features})</p>
+          </details>`;
+}
+
+export function incompatiblePeerListItem({ id, addr, hostname, protocol_version, reason }) {
+  return `<details class="list-item" id="peer-${id}" open>
+            <summary><strong><svg class="lucide lucide-laptop-minimal-icon lucide-laptop-minimal" fill="none" height="20" stroke="currentColor" stroke-linecap="round"
+                                 stroke-linejoin="round" stroke-width="2" viewBox="0 0 24 24" width="20"
+                                 xmlns="http://www.w3.org/2000/svg">
+                        <rect height="12" rx="2" ry="2" width="18" x="3" y="4"/>
+                        <line x1="2" x2="22" y1="20" y2="20"/>
+                    </svg><span>${escapeHtml(hostname)}</span></strong
+                    ><small class="peer-status">${peerIncompatibleStatus()}</small></summary>
+            <p><strong>IP:</strong> ${addr}</p>
+            <p><strong>ID:</strong> ${id}</p>
+            <p><strong>Protocol:</strong> v${protocol_version}</p>
+            <p class="peer-error"><strong>Error:</strong> ${escapeHtml(reason)}</p>
+          </details>`;
+}
+
+export function pendingListItem({ id, addr, hostname }) {
+  const name = hostname ? escapeHtml(hostname) : "Unknown device";
+
+  return `<details class="list-item pending-item" id="pending-${id}" open>
+            <summary><strong><svg class="lucide lucide-laptop-minimal-icon lucide-laptop-minimal" fill="none" height="20" stroke="currentColor" stroke-linecap="round"
+                                 stroke-linejoin="round" stroke-width="2" viewBox="0 0 24 24" width="20"
+                                 xmlns="http://www.w3.org/2000/svg">
+                        <rect height="12" rx="2" ry="2" width="18" x="3" y="4"/>
+                        <line x1="2" x2="22" y1="20" y2="20"/>
+                    </svg><span>${name}</span></strong
+                    ><small class="peer-status"><span>Awaiting approval</span></small></summary>
+            <p><strong>IP:</strong> ${addr}</p>
+            <p><strong>ID:</strong> ${id}</p>
+            <div class="device-actions">
+              <button class="btn btn-success approve-device-btn">Approve</button>
+              <button class="btn btn-danger reject-device-btn">Reject</button>
+            </div>
+          </details>`;
+}
+
+export function trashListItem({ id, path, kind, peer, deleted_at }) {
+  const deletedAt = new Date(deleted_at * 1000).toLocaleString();
+
+  return `<details class="list-item trash-item" id="trash-${id}">
+            <summary><strong><svg class="lucide lucide-trash-2-icon lucide-trash-2" fill="none" height="20" stroke="currentColor" stroke-linecap="round"
+                                 stroke-linejoin="round" stroke-width="2" viewBox="0 0 24 24" width="20"
+                                 xmlns="http://www.w3.org/2000/svg">
+                        <path d="M3 6h18"/>
+                        <path d="M19 6v14c0 1-1 2-2 2H7c-1 0-2-1-2-2V6"/>
+                        <path d="M8 6V4c0-1 1-2 2-2h4c1 0 2 1 2 2v2"/>
+                        <line x1="10" x2="10" y1="11" y2="17"/>
+                        <line x1="14" x2="14" y1="11" y2="17"/>
+                    </svg><span>${escapeHtml(path)}</span></strong></summary>
+            <p><strong>Kind:</strong> ${kind}</p>
+            <p><strong>Deleted by:</strong> ${peer}</p>
+            <p><strong>Deleted at:</strong> <span class="trash-deleted-at">${deletedAt}</span></p>
+            <div class="trash-actions">
+              <button class="btn btn-success restore-trash-btn">Restore</button>
+              <button class="btn btn-danger purge-trash-btn">Delete Forever</button>
+            </div>
           </details>`;
 }
 
@@ -94,6 +167,11 @@ export function peerDisconnectedStatus() {
                     <svg xmlns="http://www.w3.org/2000/svg" width="17" height="17" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="2" stroke-linecap="round" stroke-linejoin="round" class="lucide lucide-cloud-alert-icon lucide-cloud-alert disconnected"><path d="M12 12v4"/><path d="M12 20h.01"/><path d="M17 18h.5a1 1 0 0 0 0-9h-1.79A7 7 0 1 0 7 17.708"/></svg>`;
 }
 
+export function peerIncompatibleStatus() {
+  return `<span>Incompatible version</span>
+                    <svg xmlns="http://www.w3.org/2000/svg" width="17" height="17" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="2" stroke-linecap="round" stroke-linejoin="round" class="lucide lucide-cloud-alert-icon lucide-cloud-alert disconnected"><path d="M12 12v4"/><path d="M12 20h.01"/><path d="M17 18h.5a1 1 0 0 0 0-9h-1.79A7 7 0 1 0 7 17.708"/></svg>`;
+}
+
 export function addDirToList(dirName, listElement) {
   document.getElementById(`dir-${dirName}`)?.remove();
   listElement.insertAdjacentHTML("beforeend", dirListItem(dirName));
@@ -108,6 +186,29 @@ export function addPeerToList(peer, listElement) {
   listElement.insertAdjacentHTML("beforeend", peerListItem(peer));
 }
 
+export function addIncompatibleToList(peer, listElement) {
+  document.getElementById(`peer-${peer.id}`)?.remove();
+  listElement.insertAdjacentHTML("beforeend", incompatiblePeerListItem(peer));
+}
+
+export function addPendingToList(device, listElement) {
+  document.getElementById(`pending-${device.id}`)?.remove();
+  listElement.insertAdjacentHTML("beforeend", pendingListItem(device));
+}
+
+export function removePendingFromList(id) {
+  document.getElementById(`pending-${id}`)?.remove();
+}
+
+export function addTrashToList(record, listElement) {
+  document.getElementById(`trash-${record.id}`)?.remove();
+  listElement.insertAdjacentHTML("afterbegin", trashListItem(record));
+}
+
+export function removeTrashFromList(id) {
+  document.getElementById(`trash-${id}`)?.remove();
+}
+
 export function setPeerAsDisconnected(peer) {
   const el = document.getElementById(`peer-${peer.id}`);
   const status = el?.querySelector(".peer-status");
@@ -1,4 +1,4 @@
-import { addDirToList, removeDirFromList } from './components.js';
+import { addDirToList, removeDirFromList, removePendingFromList, removeTrashFromList } from './components.js';
 
 const el_dir_form = document.getElementById("add-dir-form");
 const el_dir_list = document.getElementById("dir-list");
@@ -6,6 +6,8 @@ const el_remove_dialog = document.getElementById("remove-dir-dialog");
 const el_remove_dir_name = document.getElementById("remove-dir-name");
 const el_confirm_remove_btn = document.getElementById("confirm-remove-btn");
 const el_home_path_form = document.getElementById("home-path-form");
+const el_pending_list = document.getElementById("pending-list");
+const el_trash_list = document.getElementById("trash-list");
 
 el_dir_form.addEventListener("submit", async (e) => {
   e.preventDefault();
@@ -25,6 +27,17 @@ el_dir_form.addEventListener("submit", async (e) => {
 });
 
 el_dir_list.addEventListener("click", async (e) => {
+  const revertBtn = e.target.closest(".revert-local-btn");
+  if (revertBtn) {
+    const dir_id = revertBtn.closest("details")?.id ?? null;
+    const prefix = "dir-";
+
+    if (dir_id && dir_id.startsWith(prefix)) {
+      await revert_local_changes(dir_id.slice(prefix.length), revertBtn);
+    }
+    return;
+  }
+
   const removeBtn = e.target.closest(".remove-dir-btn");
   if (removeBtn) {
     const dir_id = removeBtn.closest("details")?.id ?? null;
@@ -36,6 +49,18 @@ el_dir_list.addEventListener("click", async (e) => {
   }
 });
 
+async function revert_local_changes(dir_name, btn) {
+  const res = await fetch(`/api/revert-local-changes?dir=${encodeURIComponent(dir_name)}`, {
+    method: "POST",
+  });
+
+  if (res.status == 200) {
+    const panel = btn.closest(".dir-local-changes");
+    panel.querySelector("ul")?.remove();
+    btn.outerHTML = "<p>No local changes.</p>";
+  }
+}
+
 async function delete_dir(dir_name) {
   el_remove_dir_name.textContent = dir_name;
   el_remove_dialog.showModal();
@@ -52,6 +77,48 @@ async function delete_dir(dir_name) {
   };
 }
 
+el_pending_list.addEventListener("click", async (e) => {
+  const btn = e.target.closest(".approve-device-btn, .reject-device-btn");
+  const device_id = btn?.closest("details")?.id ?? null;
+  const prefix = "pending-";
+
+  if (!device_id || !device_id.startsWith(prefix)) {
+    return;
+  }
+
+  const id = device_id.slice(prefix.length);
+  const action = btn.classList.contains("approve-device-btn") ? "approve" : "reject";
+
+  const res = await fetch(`/api/${action}-device?id=${id}`, {
+    method: "POST",
+  });
+
+  if (res.status == 200 || res.status == 404) {
+    removePendingFromList(id);
+  }
+});
+
+el_trash_list.addEventListener("click", async (e) => {
+  const btn = e.target.closest(".restore-trash-btn, .purge-trash-btn");
+  const trash_id = btn?.closest("details")?.id ?? null;
+  const prefix = "trash-";
+
+  if (!trash_id || !trash_id.startsWith(prefix)) {
+    return;
+  }
+
+  const id = trash_id.slice(prefix.length);
+  const action = btn.classList.contains("restore-trash-btn") ? "restore" : "purge";
+
+  const res = await fetch(`/api/${action}-trash?id=${id}`, {
+    method: "POST",
+  });
+
+  if (res.status == 200 || res.status == 404) {
+    removeTrashFromList(id);
+  }
+});
+
 el_home_path_form.addEventListener("submit", async (e) => {
   e.preventDefault();
 
@@ -1,15 +1,21 @@
 import {
   addPeerToList,
   setPeerAsDisconnected,
+  addIncompatibleToList,
+  addPendingToList,
+  removePendingFromList,
   addDirToList,
   removeDirFromList,
   setSyncStarted,
   setSyncCompleted,
-  setSyncFailed
+  setSyncFailed,
+  addTrashToList
 } from './components.js';
 
 const el_peer_list = document.getElementById("peer-list");
+const el_pending_list = document.getElementById("pending-list");
 const el_dir_list = document.getElementById("dir-list");
+const el_trash_list = document.getElementById("trash-list");
 
 const es = new EventSource("/api/events");
 
@@ -36,6 +42,19 @@ es.onmessage = (event) => {
       setPeerAsDisconnected(payload);
       break;
 
+    case "PeerIncompatible":
+      addIncompatibleToList(payload, el_peer_list);
+      break;
+
+    case "DevicePending":
+      addPendingToList(payload, el_pending_list);
+      break;
+
+    case "DeviceApproved":
+    case "DeviceRejected":
+      removePendingFromList(payload);
+      break;
+
     case "SyncDirectoryAdded":
       addDirToList(payload, el_dir_list);
       break;
@@ -55,6 +74,10 @@ es.onmessage = (event) => {
     case "EntrySyncFailed":
       setSyncFailed(payload);
       break;
+
+    case "EntryTrashed":
+      addTrashToList(payload, el_trash_list);
+      break;
   }
 };
 